@@ -1,4 +1,5 @@
 mod client;
+pub mod openai;
 pub(crate) mod shared;
 mod streaming_client;
 
@@ -0,0 +1,814 @@
+//! Client for model servers implementing the OpenAI chat completions API with tool calling, such as
+//! Ollama or vLLM.
+//!
+//! See https://platform.openai.com/docs/api-reference/chat/create
+
+use std::collections::VecDeque;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use reqwest::StatusCode;
+use reqwest::header::{
+    AUTHORIZATION,
+    RETRY_AFTER,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::api_client::ApiClientError;
+use crate::api_client::model::{
+    AssistantResponseMessage,
+    ChatMessage,
+    ChatResponseStream,
+    ConversationState,
+    FigDocument,
+    ImageBlock,
+    ImageFormat,
+    ImageSource,
+    Tool,
+    ToolResult,
+    ToolResultContentBlock,
+    ToolResultStatus,
+    UserInputMessage,
+};
+use crate::api_client::retry::parse_retry_after;
+use crate::mcp_client::transport::http::SseParser;
+
+/// Environment variable holding the API key sent to the model endpoint, if it requires one.
+const API_KEY_ENV_VAR: &str = "OPENAI_API_KEY";
+
+#[derive(Clone, Debug)]
+pub struct OpenAiClient {
+    client: reqwest::Client,
+    /// Base url of the API, e.g. `http://localhost:11434/v1`.
+    base_url: String,
+    model: String,
+    api_key: Option<String>,
+}
+
+impl OpenAiClient {
+    /// Creates a client for the endpoint at `base_url`. If `model` is not given, the first model
+    /// listed by the endpoint is used.
+    pub async fn new(base_url: &str, model: Option<String>) -> Result<Self, ApiClientError> {
+        let mut client = Self {
+            client: crate::request::new_client()?,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            model: model.unwrap_or_default(),
+            api_key: std::env::var(API_KEY_ENV_VAR).ok().filter(|key| !key.is_empty()),
+        };
+        if client.model.is_empty() {
+            client.model = match client.list_models().await?.into_iter().next() {
+                Some(model) => model,
+                None => {
+                    return Err(ApiClientError::ModelEndpoint(format!(
+                        "no models are available from {}",
+                        client.base_url
+                    )));
+                },
+            };
+        }
+        Ok(client)
+    }
+
+    /// Lists the ids of the models served by the endpoint.
+    pub async fn list_models(&self) -> Result<Vec<String>, ApiClientError> {
+        #[derive(Deserialize)]
+        struct Models {
+            data: Vec<Model>,
+        }
+
+        #[derive(Deserialize)]
+        struct Model {
+            id: String,
+        }
+
+        let mut request = self.client.get(format!("{}/models", self.base_url));
+        if let Some(api_key) = &self.api_key {
+            request = request.header(AUTHORIZATION, format!("Bearer {api_key}"));
+        }
+        let response = error_for_status(request.send().await?).await?;
+        Ok(response
+            .json::<Models>()
+            .await?
+            .data
+            .into_iter()
+            .map(|model| model.id)
+            .collect())
+    }
+
+    pub async fn send_message(
+        &self,
+        conversation_state: ConversationState,
+    ) -> Result<ChatCompletionStream, ApiClientError> {
+        let body = ChatCompletionRequest::new(&self.model, conversation_state);
+        let mut request = self
+            .client
+            .post(format!("{}/chat/completions", self.base_url))
+            .json(&body);
+        if let Some(api_key) = &self.api_key {
+            request = request.header(AUTHORIZATION, format!("Bearer {api_key}"));
+        }
+        let response = error_for_status(request.send().await?).await?;
+        let request_id = response
+            .headers()
+            .get("x-request-id")
+            .and_then(|id| id.to_str().ok())
+            .map(str::to_string);
+
+        Ok(ChatCompletionStream {
+            response,
+            request_id,
+            parser: SseParser::default(),
+            events: VecDeque::new(),
+            tool_calls: Vec::new(),
+            done: false,
+        })
+    }
+}
+
+/// Maps error responses to [ApiClientError], so that throttling, unavailability and context window
+/// overflows are handled like they are for the Q service.
+async fn error_for_status(response: reqwest::Response) -> Result<reqwest::Response, ApiClientError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let retry_after = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_retry_after);
+    let body = response.text().await.unwrap_or_default();
+    let message = serde_json::from_str::<ErrorResponse>(&body)
+        .map(|response| response.error.message)
+        .unwrap_or(body);
+    let lower = message.to_lowercase();
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        Err(ApiClientError::QuotaBreach {
+            message: "the model endpoint is throttling requests",
+            retry_after,
+        })
+    } else if matches!(
+        status,
+        StatusCode::REQUEST_TIMEOUT
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    ) {
+        Err(ApiClientError::ModelEndpointUnavailable {
+            message: format!("{status}: {message}"),
+            retry_after,
+        })
+    } else if status == StatusCode::BAD_REQUEST && (lower.contains("context length") || lower.contains("too long")) {
+        Err(ApiClientError::ContextWindowOverflow)
+    } else {
+        Err(ApiClientError::ModelEndpoint(format!("{status}: {message}")))
+    }
+}
+
+/// A streamed chat completion, converted to [ChatResponseStream] events.
+#[derive(Debug)]
+pub struct ChatCompletionStream {
+    response: reqwest::Response,
+    request_id: Option<String>,
+    parser: SseParser,
+    /// Events converted from the chunks received so far.
+    events: VecDeque<ChatResponseStream>,
+    /// Tool calls are streamed in pieces that can be interleaved, so they are only emitted once
+    /// the completion is finished.
+    tool_calls: Vec<ToolCallBuilder>,
+    done: bool,
+}
+
+impl ChatCompletionStream {
+    pub fn request_id(&self) -> Option<&str> {
+        self.request_id.as_deref()
+    }
+
+    pub async fn recv(&mut self) -> Result<Option<ChatResponseStream>, ApiClientError> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(Some(event));
+            }
+            if self.done {
+                return Ok(None);
+            }
+            match self.response.chunk().await? {
+                Some(chunk) => {
+                    for event in self.parser.feed(&chunk) {
+                        if event.data == "[DONE]" {
+                            self.finish();
+                            break;
+                        }
+                        let chunk = serde_json::from_str::<ChatCompletionChunk>(&event.data)?;
+                        self.push_chunk(chunk)?;
+                    }
+                },
+                None => self.finish(),
+            }
+        }
+    }
+
+    fn push_chunk(&mut self, chunk: ChatCompletionChunk) -> Result<(), ApiClientError> {
+        if let Some(error) = chunk.error {
+            return Err(ApiClientError::ModelEndpoint(error.message));
+        }
+        for choice in chunk.choices {
+            if let Some(content) = choice.delta.content.filter(|content| !content.is_empty()) {
+                self.events
+                    .push_back(ChatResponseStream::AssistantResponseEvent { content });
+            }
+            for delta in choice.delta.tool_calls {
+                self.push_tool_call_delta(delta);
+            }
+        }
+        Ok(())
+    }
+
+    fn push_tool_call_delta(&mut self, delta: ToolCallDelta) {
+        let existing = self.tool_calls.iter().rposition(|call| call.index == delta.index);
+        let position = match (existing, &delta.id) {
+            // Some servers don't send indices, in which case a new id starts a new tool call.
+            (Some(i), Some(id)) if !self.tool_calls[i].id.is_empty() && self.tool_calls[i].id != *id => None,
+            (existing, _) => existing,
+        };
+        let call = match position {
+            Some(i) => &mut self.tool_calls[i],
+            None => {
+                self.tool_calls.push(ToolCallBuilder {
+                    index: delta.index,
+                    id: String::new(),
+                    name: String::new(),
+                    arguments: String::new(),
+                });
+                self.tool_calls.last_mut().expect("a tool call was just pushed")
+            },
+        };
+        if let Some(id) = delta.id {
+            call.id = id;
+        }
+        if let Some(function) = delta.function {
+            call.name.push_str(&function.name.unwrap_or_default());
+            call.arguments.push_str(&function.arguments.unwrap_or_default());
+        }
+    }
+
+    /// Emits the buffered tool calls, in the same sequence of events as the Q service.
+    fn finish(&mut self) {
+        self.done = true;
+        for call in std::mem::take(&mut self.tool_calls) {
+            let id = match call.id.is_empty() {
+                true => format!("tooluse_{}", uuid::Uuid::new_v4().simple()),
+                false => call.id,
+            };
+            let arguments = match call.arguments.trim().is_empty() {
+                true => "{}".to_string(),
+                false => call.arguments,
+            };
+            for (input, stop) in [(None, None), (Some(arguments), None), (None, Some(true))] {
+                self.events.push_back(ChatResponseStream::ToolUseEvent {
+                    tool_use_id: id.clone(),
+                    name: call.name.clone(),
+                    input,
+                    stop,
+                });
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ToolCallBuilder {
+    index: usize,
+    id: String,
+    name: String,
+    arguments: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatCompletionRequest {
+    model: String,
+    messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<FunctionTool>,
+    stream: bool,
+}
+
+impl ChatCompletionRequest {
+    /// Creates a request for the model selected in the conversation, falling back to
+    /// `default_model`.
+    fn new(default_model: &str, conversation_state: ConversationState) -> Self {
+        let ConversationState {
+            user_input_message,
+            history,
+            model_id,
+            ..
+        } = conversation_state;
+        let model = model_id.unwrap_or_else(|| default_model.to_string());
+
+        let tools = user_input_message
+            .user_input_message_context
+            .as_ref()
+            .and_then(|context| context.tools.clone())
+            .unwrap_or_default()
+            .into_iter()
+            .map(FunctionTool::from)
+            .collect();
+
+        let mut messages = Vec::new();
+        for message in history.unwrap_or_default() {
+            match message {
+                ChatMessage::UserInputMessage(message) => push_user_message(&mut messages, message),
+                ChatMessage::AssistantResponseMessage(message) => messages.push(message.into()),
+            }
+        }
+        push_user_message(&mut messages, user_input_message);
+
+        Self {
+            model,
+            messages,
+            tools,
+            stream: true,
+        }
+    }
+}
+
+/// Tool results are sent as messages of their own, before the content of the user message.
+fn push_user_message(messages: &mut Vec<Message>, message: UserInputMessage) {
+    let tool_results = message
+        .user_input_message_context
+        .and_then(|context| context.tool_results)
+        .unwrap_or_default();
+    let has_tool_results = !tool_results.is_empty();
+    messages.extend(tool_results.into_iter().map(Message::from));
+
+    let images = message.images.unwrap_or_default();
+    if has_tool_results && message.content.trim().is_empty() && images.is_empty() {
+        return;
+    }
+    let content = match images.is_empty() {
+        true => UserContent::Text(message.content),
+        false => UserContent::Parts(
+            std::iter::once(ContentPart::Text { text: message.content })
+                .chain(images.into_iter().filter_map(ContentPart::image))
+                .collect(),
+        ),
+    };
+    messages.push(Message::User { content });
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "role", rename_all = "lowercase")]
+enum Message {
+    User {
+        content: UserContent,
+    },
+    Assistant {
+        content: String,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        tool_calls: Vec<ToolCall>,
+    },
+    Tool {
+        tool_call_id: String,
+        content: String,
+    },
+}
+
+impl From<AssistantResponseMessage> for Message {
+    fn from(value: AssistantResponseMessage) -> Self {
+        Self::Assistant {
+            content: value.content,
+            tool_calls: value
+                .tool_uses
+                .unwrap_or_default()
+                .into_iter()
+                .map(|tool_use| ToolCall {
+                    id: tool_use.tool_use_id,
+                    kind: "function",
+                    function: FunctionCall {
+                        name: tool_use.name,
+                        arguments: serde_json::to_string(&tool_use.input).unwrap_or_default(),
+                    },
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<ToolResult> for Message {
+    fn from(value: ToolResult) -> Self {
+        let mut content = value
+            .content
+            .into_iter()
+            .map(|block| match block {
+                ToolResultContentBlock::Text(text) => text,
+                ToolResultContentBlock::Json(document) => {
+                    serde_json::to_string(&FigDocument::from(document)).unwrap_or_default()
+                },
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        if let ToolResultStatus::Error = value.status {
+            content = format!("Error: {content}");
+        }
+        Self::Tool {
+            tool_call_id: value.tool_use_id,
+            content,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum UserContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+impl ContentPart {
+    /// Images are sent inline as data urls.
+    fn image(image: ImageBlock) -> Option<Self> {
+        let ImageSource::Bytes(bytes) = image.source else {
+            return None;
+        };
+        let media_type = match image.format {
+            ImageFormat::Gif => "image/gif",
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Png => "image/png",
+            ImageFormat::Webp => "image/webp",
+        };
+        Some(Self::ImageUrl {
+            image_url: ImageUrl {
+                url: format!("data:{media_type};base64,{}", STANDARD.encode(bytes)),
+            },
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ImageUrl {
+    url: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ToolCall {
+    id: String,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    function: FunctionCall,
+}
+
+#[derive(Debug, Serialize)]
+struct FunctionCall {
+    name: String,
+    /// JSON encoded arguments.
+    arguments: String,
+}
+
+#[derive(Debug, Serialize)]
+struct FunctionTool {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    function: FunctionDefinition,
+}
+
+impl From<Tool> for FunctionTool {
+    fn from(value: Tool) -> Self {
+        let Tool::ToolSpecification(spec) = value;
+        Self {
+            kind: "function",
+            function: FunctionDefinition {
+                name: spec.name,
+                description: spec.description,
+                parameters: spec
+                    .input_schema
+                    .json
+                    .and_then(|schema| serde_json::to_value(schema).ok())
+                    .unwrap_or_else(|| serde_json::json!({ "type": "object" })),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct FunctionDefinition {
+    name: String,
+    description: String,
+    parameters: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletionChunk {
+    #[serde(default)]
+    choices: Vec<ChunkChoice>,
+    error: Option<ErrorBody>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChunkChoice {
+    #[serde(default)]
+    delta: Delta,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Delta {
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ToolCallDelta>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ToolCallDelta {
+    #[serde(default)]
+    index: usize,
+    id: Option<String>,
+    function: Option<FunctionDelta>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FunctionDelta {
+    name: Option<String>,
+    arguments: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ErrorBody,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorBody {
+    message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use bytes::Bytes;
+    use http_body_util::{
+        BodyExt,
+        Full,
+    };
+    use hyper::body::Incoming;
+    use hyper::server::conn::http1;
+    use hyper::service::service_fn;
+    use hyper::{
+        Method,
+        Request,
+        Response,
+    };
+    use hyper_util::rt::TokioIo;
+    use serde_json::json;
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::api_client::model::{
+        ToolInputSchema,
+        ToolSpecification,
+        ToolUse,
+        UserInputMessageContext,
+    };
+
+    fn user_message(content: &str, context: Option<UserInputMessageContext>) -> UserInputMessage {
+        UserInputMessage {
+            content: content.to_string(),
+            user_input_message_context: context,
+            user_intent: None,
+            images: None,
+        }
+    }
+
+    /// A mock model server that replies to chat completions with `status` and `body`, and sends
+    /// the requests it receives through the returned channel. Returns the base url of the server.
+    async fn spawn_mock_server(
+        status: u16,
+        body: &'static str,
+    ) -> (String, mpsc::UnboundedReceiver<serde_json::Value>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |req: Request<Incoming>| {
+                        let tx = tx.clone();
+                        async move {
+                            let method = req.method().clone();
+                            let path = req.uri().path().to_string();
+                            let request_body = req.into_body().collect().await.unwrap().to_bytes();
+                            let (status, body) = match (method, path.as_str()) {
+                                (Method::GET, "/v1/models") => (
+                                    200,
+                                    json!({ "data": [{ "id": "llama" }, { "id": "qwen" }] }).to_string(),
+                                ),
+                                (Method::POST, "/v1/chat/completions") => {
+                                    tx.send(serde_json::from_slice(&request_body).unwrap()).unwrap();
+                                    (status, body.to_string())
+                                },
+                                _ => (404, String::new()),
+                            };
+                            Ok::<_, Infallible>(
+                                Response::builder()
+                                    .status(status)
+                                    .header("x-request-id", "request-1")
+                                    .body(Full::new(Bytes::from(body)))
+                                    .unwrap(),
+                            )
+                        }
+                    });
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+        (format!("http://{addr}/v1/"), rx)
+    }
+
+    #[test]
+    fn test_request_from_conversation_state() {
+        let tools = vec![Tool::ToolSpecification(ToolSpecification {
+            name: "fs_read".to_string(),
+            description: "Reads files".to_string(),
+            input_schema: ToolInputSchema {
+                json: Some(serde_json::from_value(json!({ "type": "object" })).unwrap()),
+            },
+        })];
+        let tool_results = vec![ToolResult {
+            tool_use_id: "call_1".to_string(),
+            content: vec![ToolResultContentBlock::Text("hello".to_string())],
+            status: ToolResultStatus::Success,
+        }];
+        let state = ConversationState {
+            conversation_id: None,
+            user_input_message: user_message(
+                "",
+                Some(UserInputMessageContext {
+                    tool_results: Some(tool_results),
+                    tools: Some(tools),
+                    ..Default::default()
+                }),
+            ),
+            history: Some(vec![
+                ChatMessage::UserInputMessage(user_message("read the file", None)),
+                ChatMessage::AssistantResponseMessage(AssistantResponseMessage {
+                    message_id: None,
+                    content: "Reading it".to_string(),
+                    tool_uses: Some(vec![ToolUse {
+                        tool_use_id: "call_1".to_string(),
+                        name: "fs_read".to_string(),
+                        input: serde_json::from_value(json!({ "path": "a.txt" })).unwrap(),
+                    }]),
+                }),
+            ]),
+            model_id: None,
+        };
+
+        assert_eq!(
+            serde_json::to_value(ChatCompletionRequest::new("llama", state)).unwrap(),
+            json!({
+                "model": "llama",
+                "messages": [
+                    { "role": "user", "content": "read the file" },
+                    {
+                        "role": "assistant",
+                        "content": "Reading it",
+                        "tool_calls": [{
+                            "id": "call_1",
+                            "type": "function",
+                            "function": { "name": "fs_read", "arguments": "{\"path\":\"a.txt\"}" },
+                        }],
+                    },
+                    { "role": "tool", "tool_call_id": "call_1", "content": "hello" },
+                ],
+                "tools": [{
+                    "type": "function",
+                    "function": { "name": "fs_read", "description": "Reads files", "parameters": { "type": "object" } },
+                }],
+                "stream": true,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_events() {
+        // Text followed by two tool calls whose arguments are interleaved.
+        let body = concat!(
+            "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\",\"content\":\"Hello\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\" world\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"id\":\"call_1\",\"function\":{\"name\":\"fs_read\",\"arguments\":\"{\\\"path\\\":\"}}]}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":1,\"id\":\"call_2\",\"function\":{\"name\":\"use_aws\"}}]}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\"\\\"a\\\"}\"}}]}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{},\"finish_reason\":\"tool_calls\"}]}\n\n",
+            "data: [DONE]\n\n",
+        );
+        let (url, mut requests) = spawn_mock_server(200, body).await;
+        let client = OpenAiClient::new(&url, None).await.unwrap();
+        let mut stream = client
+            .send_message(ConversationState {
+                conversation_id: None,
+                user_input_message: user_message("hi", None),
+                history: None,
+                model_id: None,
+            })
+            .await
+            .unwrap();
+
+        let request = requests.recv().await.unwrap();
+        assert_eq!(request["model"], "llama");
+        assert_eq!(stream.request_id(), Some("request-1"));
+
+        let mut events = Vec::new();
+        while let Some(event) = stream.recv().await.unwrap() {
+            events.push(event);
+        }
+        let tool_use =
+            |id: &str, name: &str, input: Option<&str>, stop: Option<bool>| ChatResponseStream::ToolUseEvent {
+                tool_use_id: id.to_string(),
+                name: name.to_string(),
+                input: input.map(str::to_string),
+                stop,
+            };
+        assert_eq!(events, vec![
+            ChatResponseStream::AssistantResponseEvent {
+                content: "Hello".to_string()
+            },
+            ChatResponseStream::AssistantResponseEvent {
+                content: " world".to_string()
+            },
+            tool_use("call_1", "fs_read", None, None),
+            tool_use("call_1", "fs_read", Some("{\"path\":\"a\"}"), None),
+            tool_use("call_1", "fs_read", None, Some(true)),
+            tool_use("call_2", "use_aws", None, None),
+            tool_use("call_2", "use_aws", Some("{}"), None),
+            tool_use("call_2", "use_aws", None, Some(true)),
+        ]);
+    }
+
+    #[test]
+    fn test_request_uses_conversation_model() {
+        let state = ConversationState {
+            conversation_id: None,
+            user_input_message: user_message("hi", None),
+            history: None,
+            model_id: Some("qwen".to_string()),
+        };
+        assert_eq!(ChatCompletionRequest::new("llama", state).model, "qwen");
+    }
+
+    #[tokio::test]
+    async fn test_error_responses() {
+        let state = ConversationState {
+            conversation_id: None,
+            user_input_message: user_message("hi", None),
+            history: None,
+            model_id: None,
+        };
+
+        let (url, _requests) = spawn_mock_server(429, "").await;
+        let client = OpenAiClient::new(&url, Some("qwen".to_string())).await.unwrap();
+        assert!(matches!(
+            client.send_message(state.clone()).await,
+            Err(ApiClientError::QuotaBreach { .. })
+        ));
+
+        let (url, _requests) = spawn_mock_server(
+            400,
+            r#"{"error":{"message":"This model's maximum context length is 8192 tokens"}}"#,
+        )
+        .await;
+        let client = OpenAiClient::new(&url, Some("qwen".to_string())).await.unwrap();
+        assert!(matches!(
+            client.send_message(state.clone()).await,
+            Err(ApiClientError::ContextWindowOverflow)
+        ));
+
+        let (url, _requests) = spawn_mock_server(503, "loading model").await;
+        let client = OpenAiClient::new(&url, Some("qwen".to_string())).await.unwrap();
+        match client.send_message(state.clone()).await {
+            Err(err @ ApiClientError::ModelEndpointUnavailable { .. }) => assert!(err.is_retryable()),
+            other => panic!("unexpected result: {other:?}"),
+        }
+
+        let (url, _requests) = spawn_mock_server(500, r#"{"error":{"message":"model not loaded"}}"#).await;
+        let client = OpenAiClient::new(&url, Some("qwen".to_string())).await.unwrap();
+        match client.send_message(state).await {
+            Err(err @ ApiClientError::ModelEndpoint(_)) => assert!(err.to_string().contains("model not loaded")),
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+}
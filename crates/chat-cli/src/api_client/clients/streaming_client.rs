@@ -9,8 +9,13 @@ use aws_types::request_id::RequestId;
 use tracing::{
     debug,
     error,
+    warn,
 };
 
+use super::openai::{
+    ChatCompletionStream,
+    OpenAiClient,
+};
 use super::shared::{
     bearer_sdk_config,
     sigv4_sdk_config,
@@ -21,9 +26,11 @@ use crate::api_client::model::{
     ChatResponseStream,
     ConversationState,
 };
+use crate::api_client::retry::parse_retry_after;
 use crate::api_client::{
     ApiClientError,
     Endpoint,
+    RetryPolicy,
 };
 use crate::auth::builder_id::BearerResolver;
 use crate::aws_common::{
@@ -44,12 +51,14 @@ mod inner {
     use amzn_codewhisperer_streaming_client::Client as CodewhispererStreamingClient;
     use amzn_qdeveloper_streaming_client::Client as QDeveloperStreamingClient;
 
+    use crate::api_client::clients::openai::OpenAiClient;
     use crate::api_client::model::ChatResponseStream;
 
     #[derive(Clone, Debug)]
     pub enum Inner {
         Codewhisperer(CodewhispererStreamingClient),
         QDeveloper(QDeveloperStreamingClient),
+        OpenAi(OpenAiClient),
         Mock(Arc<Mutex<std::vec::IntoIter<Vec<ChatResponseStream>>>>),
     }
 }
@@ -58,6 +67,7 @@ mod inner {
 pub struct StreamingClient {
     inner: inner::Inner,
     profile: Option<AuthProfile>,
+    retry_policy: RetryPolicy,
 }
 
 impl StreamingClient {
@@ -77,6 +87,7 @@ impl StreamingClient {
         Self {
             inner: inner::Inner::Mock(Arc::new(Mutex::new(events.into_iter()))),
             profile: None,
+            retry_policy: RetryPolicy::default(),
         }
     }
 
@@ -105,7 +116,11 @@ impl StreamingClient {
             },
         };
 
-        Ok(Self { inner, profile })
+        Ok(Self {
+            inner,
+            profile,
+            retry_policy: RetryPolicy::default(),
+        })
     }
 
     pub async fn new_qdeveloper_client(database: &Database, endpoint: &Endpoint) -> Result<Self, ApiClientError> {
@@ -123,18 +138,71 @@ impl StreamingClient {
         Ok(Self {
             inner: inner::Inner::QDeveloper(client),
             profile: None,
+            retry_policy: RetryPolicy::default(),
+        })
+    }
+
+    /// Creates a client for a model server implementing the OpenAI chat completions API, selected
+    /// with the `api.modelEndpoint.url` setting or `--model-endpoint`.
+    pub async fn new_openai_client(base_url: &str, model: Option<String>) -> Result<Self, ApiClientError> {
+        Ok(Self {
+            inner: inner::Inner::OpenAi(OpenAiClient::new(base_url, model).await?),
+            profile: None,
+            retry_policy: RetryPolicy::default(),
         })
     }
 
+    /// Sets how [Self::send_message] retries requests that failed with a retryable error.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    /// Lists the ids of the models that can be selected with [ConversationState::model_id].
+    pub async fn list_models(&self) -> Result<Vec<String>, ApiClientError> {
+        match &self.inner {
+            inner::Inner::OpenAi(client) => client.list_models().await,
+            _ => Err(ApiClientError::ModelSelectionUnsupported),
+        }
+    }
+
+    /// Sends the conversation, retrying according to the [RetryPolicy] of the client.
     pub async fn send_message(
         &self,
         conversation_state: ConversationState,
+    ) -> Result<SendMessageOutput, ApiClientError> {
+        let mut attempt = 1;
+        loop {
+            match self.send_message_once(conversation_state.clone()).await {
+                Err(err)
+                    if err.is_retryable()
+                        && attempt < self.retry_policy.max_attempts
+                        && self.retry_policy.can_wait(err.retry_after()) =>
+                {
+                    let delay = self.retry_policy.delay(attempt, err.retry_after());
+                    warn!(?err, attempt, ?delay, "Retrying a failed request");
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                },
+                result => return result,
+            }
+        }
+    }
+
+    async fn send_message_once(
+        &self,
+        conversation_state: ConversationState,
     ) -> Result<SendMessageOutput, ApiClientError> {
         debug!("Sending conversation: {:#?}", conversation_state);
         let ConversationState {
             conversation_id,
             user_input_message,
             history,
+            model_id,
         } = conversation_state;
 
         match &self.inner {
@@ -171,7 +239,13 @@ impl StreamingClient {
                         });
 
                         if is_quota_breach {
-                            Err(ApiClientError::QuotaBreach("quota has reached its limit"))
+                            Err(ApiClientError::QuotaBreach {
+                                message: "quota has reached its limit",
+                                retry_after: e
+                                    .raw_response()
+                                    .and_then(|resp| resp.headers().get("retry-after"))
+                                    .and_then(parse_retry_after),
+                            })
                         } else if is_context_window_overflow {
                             Err(ApiClientError::ContextWindowOverflow)
                         } else {
@@ -201,6 +275,16 @@ impl StreamingClient {
                         .await?,
                 ))
             },
+            inner::Inner::OpenAi(client) => Ok(SendMessageOutput::OpenAi(
+                client
+                    .send_message(ConversationState {
+                        conversation_id,
+                        user_input_message,
+                        history,
+                        model_id,
+                    })
+                    .await?,
+            )),
             inner::Inner::Mock(events) => {
                 let mut new_events = events.lock().unwrap().next().unwrap_or_default().clone();
                 new_events.reverse();
@@ -216,6 +300,7 @@ pub enum SendMessageOutput {
         amzn_codewhisperer_streaming_client::operation::generate_assistant_response::GenerateAssistantResponseOutput,
     ),
     QDeveloper(amzn_qdeveloper_streaming_client::operation::send_message::SendMessageOutput),
+    OpenAi(ChatCompletionStream),
     Mock(Vec<ChatResponseStream>),
 }
 
@@ -224,6 +309,7 @@ impl SendMessageOutput {
         match self {
             SendMessageOutput::Codewhisperer(output) => output.request_id(),
             SendMessageOutput::QDeveloper(output) => output.request_id(),
+            SendMessageOutput::OpenAi(output) => output.request_id(),
             SendMessageOutput::Mock(_) => None,
         }
     }
@@ -236,6 +322,7 @@ impl SendMessageOutput {
                 .await?
                 .map(|s| s.into())),
             SendMessageOutput::QDeveloper(output) => Ok(output.send_message_response.recv().await?.map(|s| s.into())),
+            SendMessageOutput::OpenAi(output) => output.recv().await,
             SendMessageOutput::Mock(vec) => Ok(vec.pop()),
         }
     }
@@ -246,6 +333,7 @@ impl RequestId for SendMessageOutput {
         match self {
             SendMessageOutput::Codewhisperer(output) => output.request_id(),
             SendMessageOutput::QDeveloper(output) => output.request_id(),
+            SendMessageOutput::OpenAi(output) => output.request_id(),
             SendMessageOutput::Mock(_) => Some("<mock-request-id>"),
         }
     }
@@ -293,6 +381,7 @@ mod tests {
                     user_intent: None,
                 },
                 history: None,
+                model_id: None,
             })
             .await
             .unwrap();
@@ -304,6 +393,95 @@ mod tests {
         assert_eq!(output_content, "Hello! How can I assist you today?");
     }
 
+    #[tokio::test]
+    async fn test_send_message_retries() {
+        use std::convert::Infallible;
+        use std::sync::atomic::{
+            AtomicUsize,
+            Ordering,
+        };
+        use std::time::Duration;
+
+        use bytes::Bytes;
+        use http_body_util::Full;
+        use hyper::server::conn::http1;
+        use hyper::service::service_fn;
+        use hyper_util::rt::TokioIo;
+        use tokio::net::TcpListener;
+
+        // A model endpoint that is unavailable for the first two requests.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let counter = Arc::clone(&counter);
+                tokio::spawn(async move {
+                    let service = service_fn(move |_| {
+                        let (status, body) = match counter.fetch_add(1, Ordering::SeqCst) {
+                            0 | 1 => (503, ""),
+                            _ => (
+                                200,
+                                "data: {\"choices\":[{\"delta\":{\"content\":\"Hello\"}}]}\n\ndata: [DONE]\n\n",
+                            ),
+                        };
+                        async move {
+                            Ok::<_, Infallible>(
+                                hyper::Response::builder()
+                                    .status(status)
+                                    .header("retry-after", "0")
+                                    .body(Full::new(Bytes::from(body)))
+                                    .unwrap(),
+                            )
+                        }
+                    });
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        let state = ConversationState {
+            conversation_id: None,
+            user_input_message: UserInputMessage {
+                images: None,
+                content: "Hello".into(),
+                user_input_message_context: None,
+                user_intent: None,
+            },
+            history: None,
+            model_id: None,
+        };
+        let client = StreamingClient::new_openai_client(&format!("http://{addr}/v1"), Some("llama".to_string()))
+            .await
+            .unwrap();
+        let policy = RetryPolicy {
+            max_attempts: 2,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(1),
+            ..Default::default()
+        };
+
+        let client = client.with_retry_policy(policy);
+        assert!(matches!(
+            client.send_message(state.clone()).await,
+            Err(ApiClientError::ModelEndpointUnavailable { .. })
+        ));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        let mut output = client.send_message(state).await.unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+        assert_eq!(
+            output.recv().await.unwrap(),
+            Some(ChatResponseStream::AssistantResponseEvent {
+                content: "Hello".to_string()
+            })
+        );
+    }
+
     #[ignore]
     #[tokio::test]
     async fn assistant_response() {
@@ -331,6 +509,7 @@ mod tests {
                         tool_uses: None,
                     }),
                 ]),
+                model_id: None,
             })
             .await
             .unwrap();
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use amzn_codewhisperer_client::operation::generate_completions::GenerateCompletionsError;
 use amzn_codewhisperer_client::operation::list_available_customizations::ListAvailableCustomizationsError;
 use amzn_codewhisperer_client::operation::list_available_profiles::ListAvailableProfilesError;
@@ -10,6 +12,7 @@ use amzn_qdeveloper_streaming_client::types::error::ChatResponseStreamError as Q
 use aws_credential_types::provider::error::CredentialsError;
 use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
 pub use aws_smithy_runtime_api::client::result::SdkError;
+use aws_smithy_types::error::metadata::ProvideErrorMetadata;
 use aws_smithy_types::event_stream::RawMessage;
 use thiserror::Error;
 
@@ -47,7 +50,11 @@ pub enum ApiClientError {
 
     // quota breach
     #[error("quota has reached its limit")]
-    QuotaBreach(&'static str),
+    QuotaBreach {
+        message: &'static str,
+        /// How long the service asked to wait before sending another request.
+        retry_after: Option<Duration>,
+    },
 
     /// Returned from the backend when the user input is too large to fit within the model context
     /// window.
@@ -65,6 +72,66 @@ pub enum ApiClientError {
 
     #[error(transparent)]
     AuthError(#[from] AuthError),
+
+    #[error(
+        "the Amazon Q service does not support choosing a model, use --model-endpoint to chat with a model endpoint"
+    )]
+    ModelSelectionUnsupported,
+
+    // OpenAI compatible model endpoint errors
+    #[error("the model endpoint returned an error: {}", .0)]
+    ModelEndpoint(String),
+    /// The model endpoint is overloaded or down for a moment, e.g. while loading a model.
+    #[error("the model endpoint is unavailable: {}", .message)]
+    ModelEndpointUnavailable {
+        message: String,
+        retry_after: Option<Duration>,
+    },
+    #[error(transparent)]
+    Request(#[from] crate::request::RequestError),
+    #[error(transparent)]
+    Reqwest(#[from] reqwest::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+impl ApiClientError {
+    /// Whether sending the same request again may succeed, e.g. after throttling or a network
+    /// failure.
+    ///
+    /// [ApiClientError::ContextWindowOverflow] is not retryable, the history has to be compacted
+    /// first.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::QuotaBreach { .. } | Self::ModelEndpointUnavailable { .. } => true,
+            Self::CodewhispererGenerateAssistantResponse(err) => sdk_error_is_retryable(err),
+            Self::QDeveloperSendMessage(err) => sdk_error_is_retryable(err),
+            Self::CodewhispererChatResponseStream(err) => sdk_error_is_retryable(err),
+            Self::QDeveloperChatResponseStream(err) => sdk_error_is_retryable(err),
+            Self::Reqwest(err) => err.is_timeout() || err.is_connect() || err.is_request() || err.is_body(),
+            _ => false,
+        }
+    }
+
+    /// How long the service asked to wait before retrying, from the `Retry-After` header.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::QuotaBreach { retry_after, .. } | Self::ModelEndpointUnavailable { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+fn sdk_error_is_retryable<E: ProvideErrorMetadata, R>(err: &SdkError<E, R>) -> bool {
+    match err {
+        SdkError::TimeoutError(_) | SdkError::ResponseError(_) => true,
+        SdkError::DispatchFailure(failure) => !failure.is_user(),
+        SdkError::ServiceError(err) => matches!(
+            err.err().code(),
+            Some("ThrottlingException" | "InternalServerException" | "ServiceUnavailableException")
+        ),
+        _ => false,
+    }
 }
 
 #[cfg(test)]
@@ -124,6 +191,28 @@ mod tests {
         ]
     }
 
+    #[test]
+    fn test_is_retryable() {
+        let quota_breach = ApiClientError::QuotaBreach {
+            message: "quota has reached its limit",
+            retry_after: Some(Duration::from_secs(3)),
+        };
+        assert!(quota_breach.is_retryable());
+        assert_eq!(quota_breach.retry_after(), Some(Duration::from_secs(3)));
+        assert!(!ApiClientError::ContextWindowOverflow.is_retryable());
+
+        let throttled = ApiClientError::CodewhispererChatResponseStream(SdkError::service_error(
+            CodewhispererChatResponseStreamError::generic(
+                aws_smithy_types::error::ErrorMetadata::builder()
+                    .code("ThrottlingException")
+                    .build(),
+            ),
+            raw_message(),
+        ));
+        assert!(throttled.is_retryable());
+        assert!(all_errors().iter().all(|err| !err.is_retryable()));
+    }
+
     #[test]
     fn test_errors() {
         for error in all_errors() {
@@ -7,6 +7,7 @@ mod error;
 pub(crate) mod interceptor;
 pub mod model;
 pub mod profile;
+mod retry;
 
 pub use clients::{
     Client,
@@ -15,3 +16,4 @@ pub use clients::{
 pub use endpoints::Endpoint;
 pub use error::ApiClientError;
 pub use profile::list_available_profiles;
+pub use retry::RetryPolicy;
@@ -97,6 +97,8 @@ pub struct ConversationState {
     pub conversation_id: Option<String>,
     pub user_input_message: UserInputMessage,
     pub history: Option<Vec<ChatMessage>>,
+    /// Model to answer with, if the backend supports choosing one.
+    pub model_id: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,160 @@
+use std::time::Duration;
+
+use rand::Rng;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc2822;
+
+use crate::database::settings::{
+    Setting,
+    Settings,
+};
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(20);
+const DEFAULT_MAX_RETRY_AFTER: Duration = Duration::from_secs(120);
+
+/// How requests that fail with a retryable [ApiClientError](super::ApiClientError) are retried,
+/// set with the `api.retry.*` settings.
+///
+/// Retries are delayed with an exponential backoff with jitter, unless the service said how long
+/// to wait with a `Retry-After` header. Delays asked for by the service are honored up to
+/// `max_retry_after`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one. `1` disables retries.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for every following retry.
+    pub initial_backoff: Duration,
+    /// Upper bound of the exponential backoff between two attempts.
+    pub max_backoff: Duration,
+    /// Longest delay asked for with a `Retry-After` header that is waited for. Requests asked to
+    /// wait longer are not retried, the error is returned instead.
+    pub max_retry_after: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            max_retry_after: DEFAULT_MAX_RETRY_AFTER,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the retry policy configured in `settings`. Missing or negative values are replaced
+    /// with the defaults.
+    pub fn from_settings(settings: &Settings) -> Self {
+        let millis = |setting| {
+            settings
+                .get_int(setting)
+                .and_then(|v| u64::try_from(v).ok())
+                .map(Duration::from_millis)
+        };
+        let max_backoff = millis(Setting::ApiRetryMaxBackoff).unwrap_or(DEFAULT_MAX_BACKOFF);
+        Self {
+            max_attempts: settings
+                .get_int(Setting::ApiRetryMaxAttempts)
+                .map_or(DEFAULT_MAX_ATTEMPTS, |v| v.clamp(1, u32::MAX as i64) as u32),
+            initial_backoff: millis(Setting::ApiRetryInitialBackoff)
+                .unwrap_or(DEFAULT_INITIAL_BACKOFF)
+                .min(max_backoff),
+            max_backoff,
+            max_retry_after: DEFAULT_MAX_RETRY_AFTER,
+        }
+    }
+
+    /// Returns whether a request can be retried after waiting for `retry_after`, the delay asked
+    /// for by the service. Delays longer than `max_retry_after` are not waited for.
+    pub fn can_wait(&self, retry_after: Option<Duration>) -> bool {
+        retry_after.is_none_or(|retry_after| retry_after <= self.max_retry_after)
+    }
+
+    /// Returns how long to wait before the given retry, starting at `1`.
+    ///
+    /// `retry_after` is the delay asked for by the service, if any, see [Self::can_wait].
+    /// Otherwise the delay is picked at random between half and all of the exponential backoff, so
+    /// that clients failing at the same time do not retry at the same time.
+    pub fn delay(&self, retry: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after.min(self.max_retry_after);
+        }
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+            .min(self.max_backoff);
+        let half = backoff / 2;
+        half + half.mul_f64(rand::rng().random::<f64>())
+    }
+}
+
+/// Parses the value of a `Retry-After` header, given either in seconds or as an HTTP date such as
+/// `Wed, 21 Oct 2015 07:28:00 GMT`. Dates in the past are a delay of zero.
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = OffsetDateTime::parse(value, &Rfc2822).ok()?;
+    Some((date - OffsetDateTime::now_utc()).try_into().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(3),
+            max_retry_after: Duration::from_secs(60),
+        };
+        for _ in 0..100 {
+            let first = policy.delay(1, None);
+            assert!(first >= Duration::from_millis(500) && first <= Duration::from_secs(1));
+            let second = policy.delay(2, None);
+            assert!(second >= Duration::from_secs(1) && second <= Duration::from_secs(2));
+            let capped = policy.delay(10, None);
+            assert!(capped >= Duration::from_millis(1500) && capped <= Duration::from_secs(3));
+        }
+        // Delays asked for by the service are not capped by the backoff.
+        assert_eq!(policy.delay(1, Some(Duration::from_secs(2))), Duration::from_secs(2));
+        assert_eq!(policy.delay(1, Some(Duration::from_secs(60))), Duration::from_secs(60));
+        assert!(policy.can_wait(None));
+        assert!(policy.can_wait(Some(Duration::from_secs(60))));
+        assert!(!policy.can_wait(Some(Duration::from_secs(61))));
+    }
+
+    #[tokio::test]
+    async fn test_from_settings() {
+        let mut settings = Settings::new().await.unwrap();
+        assert_eq!(RetryPolicy::from_settings(&settings), RetryPolicy::default());
+
+        settings.set(Setting::ApiRetryMaxAttempts, 0).await.unwrap();
+        settings.set(Setting::ApiRetryInitialBackoff, 5000).await.unwrap();
+        settings.set(Setting::ApiRetryMaxBackoff, 2000).await.unwrap();
+        assert_eq!(RetryPolicy::from_settings(&settings), RetryPolicy {
+            max_attempts: 1,
+            initial_backoff: Duration::from_secs(2),
+            max_backoff: Duration::from_secs(2),
+            max_retry_after: DEFAULT_MAX_RETRY_AFTER,
+        });
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after(" 7"), Some(Duration::from_secs(7)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::ZERO));
+        let date = (OffsetDateTime::now_utc() + Duration::from_secs(30))
+            .format(&Rfc2822)
+            .unwrap();
+        let delay = parse_retry_after(&date).unwrap();
+        assert!(delay > Duration::from_secs(25) && delay <= Duration::from_secs(30));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+}
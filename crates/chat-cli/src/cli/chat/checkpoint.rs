@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::time::Instant;
+
+use crossterm::style::Color;
+use crossterm::{
+    queue,
+    style,
+};
+use eyre::{
+    Result,
+    bail,
+    eyre,
+};
+use tracing::error;
+
+use crate::platform::Context;
+
+/// The content of a file before a write was applied to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileSnapshot {
+    pub path: PathBuf,
+    /// [None] if the file did not exist at the time of the snapshot.
+    pub content: Option<Vec<u8>>,
+}
+
+/// A set of pre-image snapshots recorded right before a single accepted write.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub id: usize,
+    /// The user turn that the write belongs to.
+    pub turn: usize,
+    pub created_at: Instant,
+    pub files: Vec<FileSnapshot>,
+}
+
+/// Tracks file snapshots taken over the course of a chat session so that writes made by the
+/// model can be reverted without relying on version control.
+#[derive(Debug, Default)]
+pub struct CheckpointManager {
+    checkpoints: Vec<Checkpoint>,
+    next_id: usize,
+    turn: usize,
+}
+
+impl CheckpointManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Marks the start of a new user turn. Every checkpoint recorded afterwards is grouped under
+    /// this turn for the purposes of `/undo`.
+    pub fn start_turn(&mut self) {
+        self.turn += 1;
+    }
+
+    /// Snapshots the current content of `paths` and stores it as a new checkpoint.
+    ///
+    /// Returns the id of the checkpoint created.
+    pub async fn record(&mut self, ctx: &Context, paths: Vec<PathBuf>) -> Result<usize> {
+        let mut files = Vec::with_capacity(paths.len());
+        for path in paths {
+            let content = if ctx.fs().exists(&path) {
+                Some(ctx.fs().read(&path).await?)
+            } else {
+                None
+            };
+            files.push(FileSnapshot { path, content });
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.checkpoints.push(Checkpoint {
+            id,
+            turn: self.turn,
+            created_at: Instant::now(),
+            files,
+        });
+        Ok(id)
+    }
+
+    /// Drops a checkpoint without restoring it, e.g. when the write it guarded has failed.
+    pub fn discard(&mut self, id: usize) {
+        self.checkpoints.retain(|c| c.id != id);
+    }
+
+    /// Reverts every write made during the last `n` turns that performed writes.
+    ///
+    /// Returns the paths that were restored.
+    pub async fn undo(&mut self, ctx: &Context, n: usize) -> Result<Vec<PathBuf>> {
+        if n == 0 {
+            bail!("Number of turns to undo must be greater than 0");
+        }
+        let mut turns = self.checkpoints.iter().map(|c| c.turn).collect::<Vec<_>>();
+        turns.dedup();
+        let Some(&first_turn) = turns.iter().rev().take(n).last() else {
+            bail!("There are no changes to undo");
+        };
+        let split_at = self
+            .checkpoints
+            .iter()
+            .position(|c| c.turn >= first_turn)
+            .unwrap_or(self.checkpoints.len());
+        self.revert_from(ctx, split_at).await
+    }
+
+    /// Restores files to the state they were in right before the checkpoint with the given id,
+    /// reverting that checkpoint along with every checkpoint recorded after it.
+    ///
+    /// Returns the paths that were restored.
+    pub async fn restore(&mut self, ctx: &Context, id: usize) -> Result<Vec<PathBuf>> {
+        let split_at = self
+            .checkpoints
+            .iter()
+            .position(|c| c.id == id)
+            .ok_or(eyre!("No checkpoint with id {id} exists"))?;
+        self.revert_from(ctx, split_at).await
+    }
+
+    /// Reverts all checkpoints starting from the index `split_at`.
+    ///
+    /// The revert is all or nothing: if any file fails to be restored, every file that has
+    /// already been restored is put back to its current content and the checkpoints are kept.
+    async fn revert_from(&mut self, ctx: &Context, split_at: usize) -> Result<Vec<PathBuf>> {
+        // The oldest snapshot of a given path is the state we need to go back to.
+        let mut targets = Vec::<FileSnapshot>::new();
+        for checkpoint in &self.checkpoints[split_at..] {
+            for file in &checkpoint.files {
+                if !targets.iter().any(|t| t.path == file.path) {
+                    targets.push(file.clone());
+                }
+            }
+        }
+
+        let mut current = HashMap::<PathBuf, Option<Vec<u8>>>::new();
+        for target in &targets {
+            let content = if ctx.fs().exists(&target.path) {
+                Some(ctx.fs().read(&target.path).await?)
+            } else {
+                None
+            };
+            current.insert(target.path.clone(), content);
+        }
+
+        let mut applied = Vec::<&Path>::new();
+        for target in &targets {
+            if let Err(err) = apply_snapshot(ctx, &target.path, target.content.as_deref()).await {
+                for path in applied {
+                    let content = current.get(path).and_then(|c| c.as_deref());
+                    if let Err(rollback_err) = apply_snapshot(ctx, path, content).await {
+                        error!(?rollback_err, "Failed to roll back {}", path.display());
+                    }
+                }
+                bail!("Failed to restore {}: {}", target.path.display(), err);
+            }
+            applied.push(&target.path);
+        }
+
+        self.checkpoints.truncate(split_at);
+        Ok(targets.into_iter().map(|t| t.path).collect())
+    }
+
+    pub fn print_list(&self, output: &mut impl Write, cwd: &Path) -> std::io::Result<()> {
+        if self.checkpoints.is_empty() {
+            queue!(
+                output,
+                style::SetForegroundColor(Color::DarkGrey),
+                style::Print("\nNo checkpoints recorded for this session.\n\n"),
+                style::SetForegroundColor(Color::Reset),
+            )?;
+            return Ok(());
+        }
+
+        queue!(output, style::Print("\n"))?;
+        for checkpoint in self.checkpoints.iter().rev() {
+            let elapsed = checkpoint.created_at.elapsed().as_secs();
+            queue!(
+                output,
+                style::SetForegroundColor(Color::Green),
+                style::Print(format!("{:>4}", checkpoint.id)),
+                style::SetForegroundColor(Color::DarkGrey),
+                style::Print(format!("  turn {}  ({}s ago)\n", checkpoint.turn, elapsed)),
+                style::SetForegroundColor(Color::Reset),
+            )?;
+            for file in &checkpoint.files {
+                let path = file.path.strip_prefix(cwd).unwrap_or(&file.path);
+                let label = if file.content.is_some() { "modified" } else { "created" };
+                queue!(
+                    output,
+                    style::Print(format!("        {} ({})\n", path.display(), label))
+                )?;
+            }
+        }
+        queue!(output, style::Print("\n"))?;
+        Ok(())
+    }
+}
+
+async fn apply_snapshot(ctx: &Context, path: &Path, content: Option<&[u8]>) -> Result<()> {
+    match content {
+        Some(content) => {
+            if let Some(parent) = path.parent() {
+                ctx.fs().create_dir_all(parent).await?;
+            }
+            ctx.fs().write(path, content).await?;
+        },
+        None => {
+            if ctx.fs().exists(path) {
+                ctx.fs().remove_file(path).await?;
+            }
+        },
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    async fn setup() -> Arc<Context> {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        ctx.fs().write("/a.txt", "a0").await.unwrap();
+        ctx.fs().write("/b.txt", "b0").await.unwrap();
+        ctx
+    }
+
+    #[tokio::test]
+    async fn test_undo_reverts_last_turn() {
+        let ctx = setup().await;
+        let mut manager = CheckpointManager::new();
+
+        manager.start_turn();
+        manager.record(&ctx, vec!["/a.txt".into()]).await.unwrap();
+        ctx.fs().write("/a.txt", "a1").await.unwrap();
+
+        manager.start_turn();
+        manager.record(&ctx, vec!["/a.txt".into()]).await.unwrap();
+        ctx.fs().write("/a.txt", "a2").await.unwrap();
+        manager.record(&ctx, vec!["/b.txt".into()]).await.unwrap();
+        ctx.fs().write("/b.txt", "b1").await.unwrap();
+        manager.record(&ctx, vec!["/c.txt".into()]).await.unwrap();
+        ctx.fs().write("/c.txt", "c1").await.unwrap();
+
+        let restored = manager.undo(&ctx, 1).await.unwrap();
+        assert_eq!(restored.len(), 3);
+        assert_eq!(ctx.fs().read_to_string("/a.txt").await.unwrap(), "a1");
+        assert_eq!(ctx.fs().read_to_string("/b.txt").await.unwrap(), "b0");
+        assert!(!ctx.fs().exists("/c.txt"));
+        assert_eq!(manager.checkpoints.len(), 1);
+
+        manager.undo(&ctx, 1).await.unwrap();
+        assert_eq!(ctx.fs().read_to_string("/a.txt").await.unwrap(), "a0");
+        assert!(manager.checkpoints.is_empty());
+        assert!(manager.undo(&ctx, 1).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_restore_by_id() {
+        let ctx = setup().await;
+        let mut manager = CheckpointManager::new();
+
+        manager.start_turn();
+        manager.record(&ctx, vec!["/a.txt".into()]).await.unwrap();
+        ctx.fs().write("/a.txt", "a1").await.unwrap();
+        manager.start_turn();
+        let id = manager.record(&ctx, vec!["/a.txt".into()]).await.unwrap();
+        ctx.fs().write("/a.txt", "a2").await.unwrap();
+        manager.start_turn();
+        manager.record(&ctx, vec!["/a.txt".into()]).await.unwrap();
+        ctx.fs().write("/a.txt", "a3").await.unwrap();
+
+        manager.restore(&ctx, id).await.unwrap();
+        assert_eq!(ctx.fs().read_to_string("/a.txt").await.unwrap(), "a1");
+        assert_eq!(manager.checkpoints.len(), 1);
+        assert!(manager.restore(&ctx, id).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_undo_multiple_turns_and_discard() {
+        let ctx = setup().await;
+        let mut manager = CheckpointManager::new();
+
+        manager.start_turn();
+        manager.record(&ctx, vec!["/a.txt".into()]).await.unwrap();
+        ctx.fs().write("/a.txt", "a1").await.unwrap();
+        manager.start_turn();
+        let failed = manager.record(&ctx, vec!["/b.txt".into()]).await.unwrap();
+        manager.discard(failed);
+        manager.start_turn();
+        manager.record(&ctx, vec!["/b.txt".into()]).await.unwrap();
+        ctx.fs().write("/b.txt", "b1").await.unwrap();
+
+        manager.undo(&ctx, 5).await.unwrap();
+        assert_eq!(ctx.fs().read_to_string("/a.txt").await.unwrap(), "a0");
+        assert_eq!(ctx.fs().read_to_string("/b.txt").await.unwrap(), "b0");
+        assert!(manager.checkpoints.is_empty());
+    }
+}
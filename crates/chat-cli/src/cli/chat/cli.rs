@@ -17,9 +17,25 @@ pub struct Chat {
     /// prompt requests permissions to use a tool, unless --trust-all-tools is also used.
     #[arg(long)]
     pub no_interactive: bool,
-    /// Resumes the previous conversation from this directory.
-    #[arg(short, long)]
-    pub resume: bool,
+    /// The format of the output. Anything other than text implies --no-interactive and prints
+    /// machine readable events to STDOUT.
+    #[arg(long, value_enum, default_value_t, value_name = "FORMAT")]
+    pub output_format: ChatOutputFormat,
+    /// The format of the input read from STDIN. With stream-json, every line is a JSON object
+    /// such as {"type":"user_message","content":"..."} that is sent as the next prompt.
+    #[arg(long, value_enum, default_value_t, value_name = "FORMAT")]
+    pub input_format: ChatInputFormat,
+    /// Resumes the previous conversation from this directory, or the conversation with the given
+    /// id from the conversation history, e.g. --resume=<ID>.
+    #[arg(short, long, value_name = "ID", require_equals = true)]
+    #[allow(clippy::option_option)]
+    pub resume: Option<Option<String>>,
+    /// List the conversations in the conversation history.
+    #[arg(long, conflicts_with_all = ["resume", "input"])]
+    pub list: bool,
+    /// Search the transcripts of the conversations in the conversation history.
+    #[arg(long, value_name = "QUERY", conflicts_with_all = ["resume", "input"])]
+    pub search: Option<String>,
     /// The first question to ask
     pub input: Option<String>,
     /// Context profile to use
@@ -29,9 +45,40 @@ pub struct Chat {
     #[arg(long)]
     pub trust_all_tools: bool,
     /// Trust only this set of tools. Example: trust some tools:
-    /// '--trust-tools=fs_read,fs_write', trust no tools: '--trust-tools='
+    /// '--trust-tools=fs_read,fs_write', trust no tools: '--trust-tools='. Rules in
+    /// permissions.json files still apply.
     #[arg(long, value_delimiter = ',', value_name = "TOOL_NAMES")]
     pub trust_tools: Option<Vec<String>>,
+    /// Chat with a model server implementing the OpenAI chat completions API instead of Amazon Q,
+    /// e.g. 'http://localhost:11434/v1' for Ollama. Defaults to the api.modelEndpoint.url setting.
+    /// The model is set with api.modelEndpoint.model, and an API key can be set with
+    /// OPENAI_API_KEY.
+    #[arg(long, value_name = "URL")]
+    pub model_endpoint: Option<String>,
+    /// Model to chat with, overriding the model of a resumed conversation and the profile's
+    /// default. Only available with a model endpoint.
+    #[arg(long, value_name = "MODEL")]
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ChatOutputFormat {
+    /// Formatted text for humans
+    #[default]
+    Text,
+    /// A single JSON object printed once the chat ends
+    Json,
+    /// A JSON object per line, printed as events happen
+    StreamJson,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ChatInputFormat {
+    /// STDIN is appended to the first question
+    #[default]
+    Text,
+    /// STDIN is read as a JSON object per line, one per prompt
+    StreamJson,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
@@ -58,8 +105,14 @@ pub struct McpAdd {
     #[arg(long)]
     pub name: String,
     /// The command used to launch the server
+    #[arg(long, required_unless_present = "url", conflicts_with = "url")]
+    pub command: Option<String>,
+    /// Url of a remote server using the streamable HTTP or SSE transport
     #[arg(long)]
-    pub command: String,
+    pub url: Option<String>,
+    /// Header to send with every request to a remote server, e.g. 'Authorization: Bearer <token>'
+    #[arg(long, value_parser = parse_header, requires = "url")]
+    pub header: Vec<(String, String)>,
     /// Where to add the server to.
     #[arg(long, value_enum)]
     pub scope: Option<Scope>,
@@ -146,3 +199,10 @@ fn parse_env_vars(arg: &str) -> Result<HashMap<String, String>, EnvVarParseError
 
     Ok(vars)
 }
+
+fn parse_header(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once(':') {
+        Some((name, value)) if !name.trim().is_empty() => Ok((name.trim().to_string(), value.trim().to_string())),
+        _ => Err(format!("Invalid header '{}'. Expected 'name: value'", arg)),
+    }
+}
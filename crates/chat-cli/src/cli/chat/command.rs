@@ -16,6 +16,13 @@ use serde::{
     Serialize,
 };
 
+use super::tools::mcp_resource::ResourceMention;
+use super::tools::permissions::{
+    PermissionAction,
+    PermissionRule,
+    RuleScope,
+};
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum Command {
     Ask {
@@ -44,12 +51,26 @@ pub enum Command {
         show_summary: bool,
         help: bool,
     },
+    /// Pin the `turn`th most recent prompt, 1 being the last, so that compaction keeps it.
+    Pin {
+        turn: usize,
+    },
+    /// Unpin the `turn`th most recent prompt, or every prompt if [None].
+    Unpin {
+        turn: Option<usize>,
+    },
     Tools {
         subcommand: Option<ToolsSubcommand>,
     },
     Prompts {
         subcommand: Option<PromptsSubcommand>,
     },
+    Resources {
+        subcommand: Option<ResourcesSubcommand>,
+    },
+    History {
+        subcommand: Option<HistorySubcommand>,
+    },
     Usage,
     Load {
         path: String,
@@ -58,6 +79,21 @@ pub enum Command {
         path: String,
         force: bool,
     },
+    Undo {
+        count: usize,
+    },
+    Checkpoint {
+        subcommand: CheckpointSubcommand,
+    },
+    Knowledge {
+        subcommand: KnowledgeSubcommand,
+    },
+    Jobs {
+        subcommand: JobsSubcommand,
+    },
+    Model {
+        subcommand: ModelSubcommand,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -119,12 +155,15 @@ pub enum HooksSubcommand {
     Add {
         name: String,
 
-        #[arg(long, value_parser = ["per_prompt", "conversation_start"])]
+        #[arg(long, value_parser = ["per_prompt", "conversation_start", "pre_tool_use", "post_tool_use", "stop"])]
         trigger: String,
 
         #[arg(long, value_parser = clap::value_parser!(String))]
         command: String,
 
+        #[arg(long)]
+        matcher: Option<String>,
+
         #[arg(long)]
         global: bool,
     },
@@ -208,8 +247,10 @@ impl ContextSubcommand {
 
   <em>hooks add [--global] <<name>></em>        <black!>Add a new command context hook</black!>
                                          <black!>--global: Add to global hooks</black!>
-         <em>--trigger <<trigger>></em>           <black!>When to trigger the hook, valid options: `per_prompt` or `conversation_start`</black!>
+         <em>--trigger <<trigger>></em>           <black!>When to trigger the hook, valid options: `per_prompt`, `conversation_start`,</black!>
+                                         <black!>`pre_tool_use`, `post_tool_use` or `stop`</black!>
          <em>--command <<command>></em>             <black!>Shell command to execute</black!>
+         <em>--matcher <<glob>></em>                <black!>Only run tool use hooks for tools whose name matches</black!>
 
   <em>hooks rm [--global] <<name>></em>         <black!>Remove an existing context hook</black!>
                                          <black!>--global: Remove from global hooks</black!>
@@ -279,9 +320,18 @@ in global or local profiles.
 {}
 
 <cyan!>Notes</cyan!>
-• Hooks are executed in parallel
+• Context hooks are executed in parallel
 • 'conversation_start' hooks run on the first user prompt and are attached once to the conversation history sent to Amazon Q
 • 'per_prompt' hooks run on each user prompt and are attached to the prompt, but are not stored in conversation history
+
+<cyan!>Lifecycle hooks</cyan!>
+• 'pre_tool_use', 'post_tool_use' and 'stop' hooks run one after another, and receive the event as JSON on stdin,
+  including 'tool_name' and 'tool_input' for tool use hooks
+• A hook blocks by exiting with code 2, with stderr as the reason, or by printing {{"decision": "block", "reason": "..."}}
+• A blocked 'pre_tool_use' hook prevents the tool from running, and a 'pre_tool_use' hook can replace the
+  tool input by printing {{"tool_input": {{...}}}}
+• A blocked 'post_tool_use' hook sends its reason to Amazon Q along with the tool result
+• A blocked 'stop' hook sends its reason to Amazon Q to keep the turn going
 "#,
             Self::HOOKS_AVAILABLE_COMMANDS
         )
@@ -296,10 +346,14 @@ pub enum ToolsSubcommand {
     TrustAll,
     Reset,
     ResetSingle { tool_name: String },
+    Rules,
+    AddRule { rule: PermissionRule, scope: RuleScope },
+    RemoveRule { index: usize },
     Help,
 }
 
 impl ToolsSubcommand {
+    const ADD_RULE_USAGE: &str = "/tools <allow|deny|ask> <tool> [--path <glob>] [--command <prefix>] [--regex <regex>] [--service <name>] [--operation <name>] [--server <name>] [--workspace|--profile]";
     const AVAILABLE_COMMANDS: &str = color_print::cstr! {"<cyan!>Available subcommands</cyan!>
   <em>help</em>                           <black!>Show an explanation for the tools command</black!>
   <em>schema</em>                         <black!>Show the input schema for all available tools</black!>
@@ -307,7 +361,18 @@ impl ToolsSubcommand {
   <em>untrust <<tools...>></em>             <black!>Revert a tool or tools to per-request confirmation</black!>
   <em>trustall</em>                       <black!>Trust all tools (equivalent to deprecated /acceptall)</black!>
   <em>reset</em>                          <black!>Reset all tools to default permission levels</black!>
-  <em>reset <<tool name>></em>              <black!>Reset a single tool to default permission level</black!>"};
+  <em>reset <<tool name>></em>              <black!>Reset a single tool to default permission level</black!>
+  <em>rules</em>                          <black!>Show the permission rules and where they come from</black!>
+  <em>rules rm <<number>></em>              <black!>Remove a permission rule</black!>
+  <em>allow|deny|ask <<tool>> [OPTIONS]</em> <black!>Add a permission rule matching the tool's arguments</black!>
+    <em>--path <<glob>></em>                <black!>Paths written or read by fs_write and fs_read</black!>
+    <em>--command <<prefix>></em>           <black!>Commands run by execute_bash</black!>
+    <em>--regex <<regex>></em>              <black!>Commands run by execute_bash</black!>
+    <em>--service <<name>></em>             <black!>Service called by use_aws</black!>
+    <em>--operation <<name>></em>           <black!>Operation called by use_aws</black!>
+    <em>--server <<name>></em>              <black!>MCP server providing the tool</black!>
+    <em>--workspace</em>                  <black!>Save a deny or ask rule to .amazonq/permissions.json</black!>
+    <em>--profile</em>                    <black!>Save the rule to the current profile</black!>"};
     const BASE_COMMAND: &str = color_print::cstr! {"<cyan!>Usage: /tools [SUBCOMMAND]</cyan!>
 
 <cyan!>Description</cyan!>
@@ -332,6 +397,12 @@ impl ToolsSubcommand {
 By default, Amazon Q will ask for your permission to use certain tools. You can control which tools you
 trust so that no confirmation is required. These settings will last only for this session.
 
+Rules can also match on the arguments of a tool use, e.g. to allow <em>git status</em> while still
+asking before any other command. Deny rules always win, otherwise the most specific scope decides:
+session rules first, then <em>.amazonq/permissions.json</em> in the workspace, then <em>permissions.json</em>
+in the profile directory. Allow rules in the workspace are ignored, since a repository must not be
+able to trust its own tool uses.
+
 {}
 
 {}"#,
@@ -388,6 +459,306 @@ Or if you prefer the long way:
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResourcesSubcommand {
+    List { search_word: Option<String> },
+    Help,
+}
+
+impl ResourcesSubcommand {
+    const AVAILABLE_COMMANDS: &str = color_print::cstr! {"<cyan!>Available subcommands</cyan!>
+  <em>help</em>                                                   <black!>Show an explanation for the resources command</black!>
+  <em>list [search word]</em>                                     <black!>List the resources and resource templates of the mcp servers</black!>"};
+    const BASE_COMMAND: &str = color_print::cstr! {"<cyan!>Usage: /resources [SUBCOMMAND]</cyan!>
+
+<cyan!>Description</cyan!>
+  Show the resources exposed by the current fleet of mcp servers."};
+
+    fn usage_msg(header: impl AsRef<str>) -> String {
+        format!(
+            "{}\n\n{}\n\n{}",
+            header.as_ref(),
+            Self::BASE_COMMAND,
+            Self::AVAILABLE_COMMANDS
+        )
+    }
+
+    pub fn help_text() -> String {
+        color_print::cformat!(
+            r#"
+<magenta,em>Resources</magenta,em>
+
+Resources are files, documents, database schemas and other data exposed by the mcp servers you have configured.
+
+To attach the contents of a resource to your next message, mention it anywhere in your prompt:
+  <em>@<<server name>>:<<uri>></em>                                   <black!>Attach the resource with the given uri</black!>
+Resource templates are filled in by replacing their {{variables}} in the uri, e.g. for the template
+<em>repo://{{owner}}/{{name}}</em> of the server <em>git</em>:
+  <em>summarize @git:repo://aws/amazon-q-developer-cli</em>
+
+{}
+
+{}"#,
+            Self::BASE_COMMAND,
+            Self::AVAILABLE_COMMANDS
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HistorySubcommand {
+    List,
+    Search { query: String },
+    Help,
+}
+
+impl HistorySubcommand {
+    const AVAILABLE_COMMANDS: &str = color_print::cstr! {"<cyan!>Available subcommands</cyan!>
+  <em>help</em>                           <black!>Show an explanation for the history command</black!>
+  <em>list</em>                           <black!>List the most recent conversations</black!>
+  <em>search <<query>></em>                 <black!>Search the titles and transcripts of saved conversations</black!>"};
+    const BASE_COMMAND: &str = color_print::cstr! {"<cyan!>Usage: /history [SUBCOMMAND]</cyan!>
+
+<cyan!>Description</cyan!>
+  Show the conversations saved in the conversation history."};
+    const SEARCH_USAGE: &str = "/history search <query>";
+
+    fn usage_msg(header: impl AsRef<str>) -> String {
+        format!(
+            "{}\n\n{}\n\n{}",
+            header.as_ref(),
+            Self::BASE_COMMAND,
+            Self::AVAILABLE_COMMANDS
+        )
+    }
+
+    pub fn help_text() -> String {
+        color_print::cformat!(
+            r#"
+<magenta,em>Conversation history</magenta,em>
+
+Every conversation is saved once Amazon Q responds, along with its working directory, profile and
+approximate token usage. Saved conversations can be resumed from the command line:
+  <em>q chat --resume=<<id>></em>                           <black!>Resume the conversation with the given id</black!>
+  <em>q chat --list</em>                                  <black!>List the most recent conversations</black!>
+  <em>q chat --search <<query>></em>                        <black!>Search the saved conversations</black!>
+
+{}
+
+{}"#,
+            Self::BASE_COMMAND,
+            Self::AVAILABLE_COMMANDS
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckpointSubcommand {
+    List,
+    Restore { id: usize },
+    Help,
+}
+
+impl CheckpointSubcommand {
+    const AVAILABLE_COMMANDS: &str = color_print::cstr! {"<cyan!>Available subcommands</cyan!>
+  <em>help</em>                           <black!>Show an explanation for the checkpoint command</black!>
+  <em>list</em>                           <black!>List the checkpoints recorded for this session</black!>
+  <em>restore <<id>></em>                   <black!>Restore files to the state they were in before checkpoint <<id>></black!>"};
+    const BASE_COMMAND: &str = color_print::cstr! {"<cyan!>Usage: /checkpoint [SUBCOMMAND]</cyan!>
+
+<cyan!>Description</cyan!>
+  A checkpoint is recorded before every file write made by Amazon Q.
+  Restoring a checkpoint reverts it along with every checkpoint recorded after it."};
+    const RESTORE_USAGE: &str = "/checkpoint restore <id>";
+
+    fn usage_msg(header: impl AsRef<str>) -> String {
+        format!(
+            "{}\n\n{}\n\n{}",
+            header.as_ref(),
+            Self::BASE_COMMAND,
+            Self::AVAILABLE_COMMANDS
+        )
+    }
+
+    pub fn help_text() -> String {
+        color_print::cformat!(
+            r#"
+<magenta,em>Checkpoints</magenta,em>
+
+Files written by Amazon Q are snapshotted right before they are modified, so that changes can be
+reverted even outside of a git repository. Checkpoints only last for the current session.
+
+Use <em>/undo [n]</em> to revert every change made during the last n turns (defaults to 1).
+
+{}
+
+{}"#,
+            Self::BASE_COMMAND,
+            Self::AVAILABLE_COMMANDS
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobsSubcommand {
+    List,
+    Output { id: usize },
+    Kill { id: usize },
+    Help,
+}
+
+impl JobsSubcommand {
+    const AVAILABLE_COMMANDS: &str = color_print::cstr! {"<cyan!>Available subcommands</cyan!>
+  <em>help</em>                           <black!>Show an explanation for the jobs command</black!>
+  <em>list</em>                           <black!>List the background processes started in this session</black!>
+  <em>output <<id>></em>                    <black!>Show the last lines of output of a background process</black!>
+  <em>kill <<id>></em>                      <black!>Kill a background process</black!>"};
+    const BASE_COMMAND: &str = color_print::cstr! {"<cyan!>Usage: /jobs [SUBCOMMAND]</cyan!>
+
+<cyan!>Description</cyan!>
+  Manage the commands that Amazon Q started in the background."};
+    const KILL_USAGE: &str = "/jobs kill <id>";
+    const OUTPUT_USAGE: &str = "/jobs output <id>";
+
+    fn usage_msg(header: impl AsRef<str>) -> String {
+        format!(
+            "{}\n\n{}\n\n{}",
+            header.as_ref(),
+            Self::BASE_COMMAND,
+            Self::AVAILABLE_COMMANDS
+        )
+    }
+
+    pub fn help_text() -> String {
+        color_print::cformat!(
+            r#"
+<magenta,em>Background jobs</magenta,em>
+
+Amazon Q can run long-running commands such as dev servers, watch modes or test runners in the
+background, then read their output, send them input or kill them while the conversation goes on.
+Background processes are killed when the chat ends.
+
+{}
+
+{}"#,
+            Self::BASE_COMMAND,
+            Self::AVAILABLE_COMMANDS
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModelSubcommand {
+    List,
+    Set { model: String, default: bool },
+    Help,
+}
+
+impl ModelSubcommand {
+    const AVAILABLE_COMMANDS: &str = color_print::cstr! {"<cyan!>Available subcommands</cyan!>
+  <em>help</em>                           <black!>Show an explanation for the model command</black!>
+  <em>list</em>                           <black!>List the available models</black!>
+  <em>set <<model>> [--default]</em>        <black!>Switch to a model, optionally making it the profile's default</black!>"};
+    const BASE_COMMAND: &str = color_print::cstr! {"<cyan!>Usage: /model [SUBCOMMAND]</cyan!>
+
+<cyan!>Description</cyan!>
+  List the available models and choose the one answering in this conversation."};
+    const SET_USAGE: &str = "/model set <model> [--default]";
+
+    fn usage_msg(header: impl AsRef<str>) -> String {
+        format!(
+            "{}\n\n{}\n\n{}",
+            header.as_ref(),
+            Self::BASE_COMMAND,
+            Self::AVAILABLE_COMMANDS
+        )
+    }
+
+    pub fn help_text() -> String {
+        color_print::cformat!(
+            r#"
+<magenta,em>Model selection</magenta,em>
+
+Switching models takes effect from the next message, and the selected model is saved along with
+the conversation. With <em>--default</em>, new conversations using the current profile start with
+the model too. Models can only be chosen when chatting with a model endpoint (<em>--model-endpoint</em>).
+
+{}
+
+{}"#,
+            Self::BASE_COMMAND,
+            Self::AVAILABLE_COMMANDS
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KnowledgeSubcommand {
+    Show,
+    Add {
+        path: String,
+        name: Option<String>,
+    },
+    Remove {
+        target: String,
+    },
+    Update {
+        target: Option<String>,
+    },
+    Search {
+        query: String,
+        knowledge_base: Option<String>,
+    },
+    Help,
+}
+
+impl KnowledgeSubcommand {
+    const ADD_USAGE: &str = "/knowledge add <path> [--name <name>]";
+    const AVAILABLE_COMMANDS: &str = color_print::cstr! {"<cyan!>Available subcommands</cyan!>
+  <em>help</em>                                  <black!>Show an explanation for the knowledge command</black!>
+  <em>show</em>                                  <black!>Display the indexed knowledge bases</black!>
+  <em>add <<path>> [--name <<name>>]</em>            <black!>Index a file or directory as a new knowledge base</black!>
+  <em>rm <<name|path|id>></em>                     <black!>Remove a knowledge base</black!>
+  <em>update [name|path|id]</em>                 <black!>Re-index the files that changed, in one or all knowledge bases</black!>
+  <em>search [--kb <<name>>] <<query>></em>          <black!>Search the indexed knowledge bases</black!>"};
+    const BASE_COMMAND: &str = color_print::cstr! {"<cyan!>Usage: /knowledge [SUBCOMMAND]</cyan!>
+
+<cyan!>Description</cyan!>
+  Manage knowledge bases that Amazon Q can search by relevance with the <em>knowledge_search</em> tool."};
+    const REMOVE_USAGE: &str = "/knowledge rm <name|path|id>";
+    const SEARCH_USAGE: &str = "/knowledge search [--kb <name>] <query>";
+    const UPDATE_USAGE: &str = "/knowledge update [name|path|id]";
+
+    fn usage_msg(header: impl AsRef<str>) -> String {
+        format!(
+            "{}\n\n{}\n\n{}",
+            header.as_ref(),
+            Self::BASE_COMMAND,
+            Self::AVAILABLE_COMMANDS
+        )
+    }
+
+    pub fn help_text() -> String {
+        color_print::cformat!(
+            r#"
+<magenta,em>Knowledge Bases</magenta,em>
+
+Unlike context files, which are sent in full with every request, knowledge bases are indexed once
+and only the most relevant chunks are retrieved when needed. Use them for large repositories or
+documentation trees that would not fit in the context window.
+
+Knowledge bases persist across chat sessions. Indexing large directories can take a while, but
+/knowledge update only re-indexes the files that changed since.
+
+{}
+
+{}"#,
+            Self::BASE_COMMAND,
+            Self::AVAILABLE_COMMANDS
+        )
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PromptsGetCommand {
     pub orig_input: Option<String>,
@@ -446,6 +817,23 @@ impl Command {
             return Ok(match parts[0].to_lowercase().as_str() {
                 "clear" => Self::Clear,
                 "help" => Self::Help,
+                "compact" if matches!(parts.get(1).map(|s| s.to_lowercase()).as_deref(), Some("pin" | "unpin")) => {
+                    const USAGE: &str = "/compact pin [n]\n  /compact unpin [n]";
+                    let turn = match &parts[2..] {
+                        [] => None,
+                        [turn] => match turn.parse::<usize>() {
+                            Ok(turn) if turn > 0 => Some(turn),
+                            _ => return Err(format!("Invalid /compact arguments.\n\nUsage:\n  {}", USAGE)),
+                        },
+                        _ => return Err(format!("Invalid /compact arguments.\n\nUsage:\n  {}", USAGE)),
+                    };
+                    match parts[1].to_lowercase().as_str() {
+                        "pin" => Self::Pin {
+                            turn: turn.unwrap_or(1),
+                        },
+                        _ => Self::Unpin { turn },
+                    }
+                },
                 "compact" => {
                     let mut prompt = None;
                     let show_summary = true;
@@ -777,6 +1165,72 @@ impl Command {
                                 },
                             }
                         },
+                        "rules" => match parts.get(2..) {
+                            Some([]) | None => Self::Tools {
+                                subcommand: Some(ToolsSubcommand::Rules),
+                            },
+                            Some(["rm", index]) => match index.parse::<usize>() {
+                                Ok(index) if index > 0 => Self::Tools {
+                                    subcommand: Some(ToolsSubcommand::RemoveRule { index }),
+                                },
+                                _ => return Err(format!("Invalid rule number '{}'", index)),
+                            },
+                            Some(_) => {
+                                return Err(ToolsSubcommand::usage_msg("Usage: /tools rules [rm <number>]"));
+                            },
+                        },
+                        action @ ("allow" | "deny" | "ask") => {
+                            let action = match action {
+                                "allow" => PermissionAction::Allow,
+                                "deny" => PermissionAction::Deny,
+                                _ => PermissionAction::Ask,
+                            };
+                            let usage_err = || {
+                                Err(format!(
+                                    "Invalid /tools {} arguments.\n\nUsage:\n  {}",
+                                    action,
+                                    ToolsSubcommand::ADD_RULE_USAGE
+                                ))
+                            };
+                            let Some(args) = shlex::split(&parts[2..].join(" ")) else {
+                                return Err("Failed to parse quoted arguments".to_string());
+                            };
+                            let mut args = args.into_iter();
+                            let Some(tool) = args.next().filter(|t| !t.starts_with("--")) else {
+                                return usage_err();
+                            };
+                            let mut rule = PermissionRule::new(tool, action);
+                            let mut scope = RuleScope::Session;
+                            while let Some(arg) = args.next() {
+                                let field = match arg.as_str() {
+                                    "--workspace" => {
+                                        scope = RuleScope::Workspace;
+                                        continue;
+                                    },
+                                    "--profile" => {
+                                        scope = RuleScope::Profile;
+                                        continue;
+                                    },
+                                    "--path" => &mut rule.path,
+                                    "--command" => &mut rule.command,
+                                    "--regex" => &mut rule.command_regex,
+                                    "--service" => &mut rule.service,
+                                    "--operation" => &mut rule.operation,
+                                    "--server" => &mut rule.server,
+                                    _ => return usage_err(),
+                                };
+                                match args.next() {
+                                    Some(value) => *field = Some(value),
+                                    None => return usage_err(),
+                                }
+                            }
+                            if let Err(err) = rule.validate() {
+                                return Err(err.to_string());
+                            }
+                            Self::Tools {
+                                subcommand: Some(ToolsSubcommand::AddRule { rule, scope }),
+                            }
+                        },
                         "help" => Self::Tools {
                             subcommand: Some(ToolsSubcommand::Help),
                         },
@@ -817,6 +1271,49 @@ impl Command {
                         },
                     }
                 },
+                "resources" => match parts.get(1) {
+                    Some(c) if c.to_lowercase() == "list" => Self::Resources {
+                        subcommand: Some(ResourcesSubcommand::List {
+                            search_word: parts.get(2).map(|v| (*v).to_string()),
+                        }),
+                    },
+                    Some(c) if c.to_lowercase() == "help" => Self::Resources {
+                        subcommand: Some(ResourcesSubcommand::Help),
+                    },
+                    Some(other) => {
+                        return Err(ResourcesSubcommand::usage_msg(format!(
+                            "Unknown subcommand '{}'\n",
+                            other
+                        )));
+                    },
+                    None => Self::Resources { subcommand: None },
+                },
+                "history" => match parts.get(1).map(|s| s.to_lowercase()).as_deref() {
+                    Some("list") => Self::History {
+                        subcommand: Some(HistorySubcommand::List),
+                    },
+                    Some("search") => match parts.get(2..).filter(|terms| !terms.is_empty()) {
+                        Some(terms) => Self::History {
+                            subcommand: Some(HistorySubcommand::Search { query: terms.join(" ") }),
+                        },
+                        None => {
+                            return Err(format!(
+                                "Invalid /history arguments.\n\nUsage:\n  {}",
+                                HistorySubcommand::SEARCH_USAGE
+                            ));
+                        },
+                    },
+                    Some("help") => Self::History {
+                        subcommand: Some(HistorySubcommand::Help),
+                    },
+                    Some(other) => {
+                        return Err(HistorySubcommand::usage_msg(format!(
+                            "Unknown subcommand '{}'\n",
+                            other
+                        )));
+                    },
+                    None => Self::History { subcommand: None },
+                },
                 "usage" => Self::Usage,
                 "load" => {
                     let Some(path) = parts.get(1) else {
@@ -837,6 +1334,206 @@ impl Command {
                     }
                     Self::Save { path, force }
                 },
+                "undo" => {
+                    let count = match parts.get(1) {
+                        Some(count) => match count.parse::<usize>() {
+                            Ok(count) if count > 0 => count,
+                            _ => return Err("Invalid /undo arguments.\n\nUsage:\n  /undo [n]".to_string()),
+                        },
+                        None => 1,
+                    };
+                    Self::Undo { count }
+                },
+                "checkpoint" => match parts.get(1).map(|s| s.to_lowercase()).as_deref() {
+                    Some("list") => Self::Checkpoint {
+                        subcommand: CheckpointSubcommand::List,
+                    },
+                    Some("restore") => match parts.get(2).and_then(|id| id.parse::<usize>().ok()) {
+                        Some(id) => Self::Checkpoint {
+                            subcommand: CheckpointSubcommand::Restore { id },
+                        },
+                        None => {
+                            return Err(format!(
+                                "Invalid /checkpoint arguments.\n\nUsage:\n  {}",
+                                CheckpointSubcommand::RESTORE_USAGE
+                            ));
+                        },
+                    },
+                    Some("help") | None => Self::Checkpoint {
+                        subcommand: CheckpointSubcommand::Help,
+                    },
+                    Some(other) => {
+                        return Err(CheckpointSubcommand::usage_msg(format!(
+                            "Unknown subcommand '{}'.",
+                            other
+                        )));
+                    },
+                },
+                "jobs" => {
+                    let id = || parts.get(2).and_then(|id| id.parse::<usize>().ok());
+                    match parts.get(1).map(|s| s.to_lowercase()).as_deref() {
+                        Some("list") | None => Self::Jobs {
+                            subcommand: JobsSubcommand::List,
+                        },
+                        Some("output") => match id() {
+                            Some(id) => Self::Jobs {
+                                subcommand: JobsSubcommand::Output { id },
+                            },
+                            None => {
+                                return Err(format!(
+                                    "Invalid /jobs arguments.\n\nUsage:\n  {}",
+                                    JobsSubcommand::OUTPUT_USAGE
+                                ));
+                            },
+                        },
+                        Some("kill") => match id() {
+                            Some(id) => Self::Jobs {
+                                subcommand: JobsSubcommand::Kill { id },
+                            },
+                            None => {
+                                return Err(format!(
+                                    "Invalid /jobs arguments.\n\nUsage:\n  {}",
+                                    JobsSubcommand::KILL_USAGE
+                                ));
+                            },
+                        },
+                        Some("help") => Self::Jobs {
+                            subcommand: JobsSubcommand::Help,
+                        },
+                        Some(other) => {
+                            return Err(JobsSubcommand::usage_msg(format!("Unknown subcommand '{}'.", other)));
+                        },
+                    }
+                },
+                "model" => match parts.get(1).map(|s| s.to_lowercase()).as_deref() {
+                    Some("list") | None => Self::Model {
+                        subcommand: ModelSubcommand::List,
+                    },
+                    Some("set") => {
+                        let default = parts[2..].contains(&"--default");
+                        let mut models = parts[2..].iter().filter(|part| **part != "--default");
+                        match (models.next(), models.next()) {
+                            (Some(model), None) => Self::Model {
+                                subcommand: ModelSubcommand::Set {
+                                    model: (*model).to_string(),
+                                    default,
+                                },
+                            },
+                            _ => {
+                                return Err(format!(
+                                    "Invalid /model arguments.\n\nUsage:\n  {}",
+                                    ModelSubcommand::SET_USAGE
+                                ));
+                            },
+                        }
+                    },
+                    Some("help") => Self::Model {
+                        subcommand: ModelSubcommand::Help,
+                    },
+                    Some(other) => {
+                        return Err(ModelSubcommand::usage_msg(format!("Unknown subcommand '{}'.", other)));
+                    },
+                },
+                "knowledge" => {
+                    if parts.len() < 2 {
+                        return Ok(Self::Knowledge {
+                            subcommand: KnowledgeSubcommand::Help,
+                        });
+                    }
+
+                    macro_rules! usage_err {
+                        ($usage_str:expr) => {
+                            return Err(format!(
+                                "Invalid /knowledge arguments.\n\nUsage:\n  {}",
+                                $usage_str
+                            ))
+                        };
+                    }
+
+                    let args = match shlex::split(&parts[2..].join(" ")) {
+                        Some(args) => args,
+                        None => return Err("Failed to parse quoted arguments".to_string()),
+                    };
+
+                    match parts[1].to_lowercase().as_str() {
+                        "show" => Self::Knowledge {
+                            subcommand: KnowledgeSubcommand::Show,
+                        },
+                        "add" => {
+                            let mut path = None;
+                            let mut name = None;
+                            let mut args = args.into_iter();
+                            while let Some(arg) = args.next() {
+                                if arg == "--name" {
+                                    match args.next() {
+                                        Some(n) => name = Some(n),
+                                        None => usage_err!(KnowledgeSubcommand::ADD_USAGE),
+                                    }
+                                } else if path.is_none() {
+                                    path = Some(arg);
+                                } else {
+                                    usage_err!(KnowledgeSubcommand::ADD_USAGE);
+                                }
+                            }
+                            match path {
+                                Some(path) => Self::Knowledge {
+                                    subcommand: KnowledgeSubcommand::Add { path, name },
+                                },
+                                None => usage_err!(KnowledgeSubcommand::ADD_USAGE),
+                            }
+                        },
+                        "rm" => match args.as_slice() {
+                            [target] => Self::Knowledge {
+                                subcommand: KnowledgeSubcommand::Remove { target: target.clone() },
+                            },
+                            _ => usage_err!(KnowledgeSubcommand::REMOVE_USAGE),
+                        },
+                        "update" => match args.as_slice() {
+                            [] => Self::Knowledge {
+                                subcommand: KnowledgeSubcommand::Update { target: None },
+                            },
+                            [target] => Self::Knowledge {
+                                subcommand: KnowledgeSubcommand::Update {
+                                    target: Some(target.clone()),
+                                },
+                            },
+                            _ => usage_err!(KnowledgeSubcommand::UPDATE_USAGE),
+                        },
+                        "search" => {
+                            let mut knowledge_base = None;
+                            let mut query = Vec::new();
+                            let mut args = args.into_iter();
+                            while let Some(arg) = args.next() {
+                                if arg == "--kb" {
+                                    match args.next() {
+                                        Some(kb) => knowledge_base = Some(kb),
+                                        None => usage_err!(KnowledgeSubcommand::SEARCH_USAGE),
+                                    }
+                                } else {
+                                    query.push(arg);
+                                }
+                            }
+                            if query.is_empty() {
+                                usage_err!(KnowledgeSubcommand::SEARCH_USAGE);
+                            }
+                            Self::Knowledge {
+                                subcommand: KnowledgeSubcommand::Search {
+                                    query: query.join(" "),
+                                    knowledge_base,
+                                },
+                            }
+                        },
+                        "help" => Self::Knowledge {
+                            subcommand: KnowledgeSubcommand::Help,
+                        },
+                        other => {
+                            return Err(KnowledgeSubcommand::usage_msg(format!(
+                                "Unknown subcommand '{}'.",
+                                other
+                            )));
+                        },
+                    }
+                },
                 unknown_command => {
                     let looks_like_path = {
                         let after_slash_command_str = parts[1..].join(" ");
@@ -861,7 +1558,13 @@ impl Command {
             });
         }
 
-        if let Some(command) = input.strip_prefix('@') {
+        // Prompts are retrieved with `@name`, while `@server:uri` mentions a resource which is
+        // attached to the prompt.
+        let mentions_resource = input
+            .split_whitespace()
+            .next()
+            .is_some_and(|token| ResourceMention::parse(token).is_some());
+        if let Some(command) = input.strip_prefix('@').filter(|_| !mentions_resource) {
             let get_command = parse_input_to_prompts_get_command(command)?;
             let subcommand = Some(PromptsSubcommand::Get { get_command });
             return Ok(Self::Prompts { subcommand });
@@ -946,6 +1649,10 @@ mod tests {
         }
         let tests = &[
             ("/compact", compact!(None, true)),
+            ("/compact pin", Command::Pin { turn: 1 }),
+            ("/compact pin 3", Command::Pin { turn: 3 }),
+            ("/compact unpin", Command::Unpin { turn: None }),
+            ("/compact unpin 2", Command::Unpin { turn: Some(2) }),
             (
                 "/compact custom prompt",
                 compact!(Some("custom prompt".to_string()), true),
@@ -1034,7 +1741,20 @@ mod tests {
                         name: "test".to_string(),
                         global: true,
                         trigger: "per_prompt".to_string(),
-                        command: "echo 1".to_string()
+                        command: "echo 1".to_string(),
+                        matcher: None,
+                    })
+                }),
+            ),
+            (
+                "/context hooks add fmt --trigger post_tool_use --matcher fs_write --command 'cargo fmt'",
+                context!(ContextSubcommand::Hooks {
+                    subcommand: Some(HooksSubcommand::Add {
+                        name: "fmt".to_string(),
+                        global: false,
+                        trigger: "post_tool_use".to_string(),
+                        command: "cargo fmt".to_string(),
+                        matcher: Some("fs_write".to_string()),
                     })
                 }),
             ),
@@ -1083,6 +1803,129 @@ mod tests {
                     subcommand: Some(HooksSubcommand::Help)
                 }),
             ),
+            ("/undo", Command::Undo { count: 1 }),
+            ("/undo 3", Command::Undo { count: 3 }),
+            ("/checkpoint", Command::Checkpoint {
+                subcommand: CheckpointSubcommand::Help,
+            }),
+            ("/checkpoint list", Command::Checkpoint {
+                subcommand: CheckpointSubcommand::List,
+            }),
+            ("/checkpoint restore 2", Command::Checkpoint {
+                subcommand: CheckpointSubcommand::Restore { id: 2 },
+            }),
+            ("/knowledge show", Command::Knowledge {
+                subcommand: KnowledgeSubcommand::Show,
+            }),
+            ("/knowledge add ./docs --name \"my docs\"", Command::Knowledge {
+                subcommand: KnowledgeSubcommand::Add {
+                    path: "./docs".to_string(),
+                    name: Some("my docs".to_string()),
+                },
+            }),
+            ("/knowledge rm docs", Command::Knowledge {
+                subcommand: KnowledgeSubcommand::Remove {
+                    target: "docs".to_string(),
+                },
+            }),
+            ("/knowledge update", Command::Knowledge {
+                subcommand: KnowledgeSubcommand::Update { target: None },
+            }),
+            ("/knowledge update docs", Command::Knowledge {
+                subcommand: KnowledgeSubcommand::Update {
+                    target: Some("docs".to_string()),
+                },
+            }),
+            ("/knowledge search --kb docs how to deploy", Command::Knowledge {
+                subcommand: KnowledgeSubcommand::Search {
+                    query: "how to deploy".to_string(),
+                    knowledge_base: Some("docs".to_string()),
+                },
+            }),
+            ("/tools rules", Command::Tools {
+                subcommand: Some(ToolsSubcommand::Rules),
+            }),
+            ("/tools rules rm 3", Command::Tools {
+                subcommand: Some(ToolsSubcommand::RemoveRule { index: 3 }),
+            }),
+            (
+                "/tools allow execute_bash --command \"git status\" --workspace",
+                Command::Tools {
+                    subcommand: Some(ToolsSubcommand::AddRule {
+                        rule: PermissionRule {
+                            command: Some("git status".to_string()),
+                            ..PermissionRule::new("execute_bash", PermissionAction::Allow)
+                        },
+                        scope: RuleScope::Workspace,
+                    }),
+                },
+            ),
+            (
+                "/tools deny use_aws --service s3 --operation delete-*",
+                Command::Tools {
+                    subcommand: Some(ToolsSubcommand::AddRule {
+                        rule: PermissionRule {
+                            service: Some("s3".to_string()),
+                            operation: Some("delete-*".to_string()),
+                            ..PermissionRule::new("use_aws", PermissionAction::Deny)
+                        },
+                        scope: RuleScope::Session,
+                    }),
+                },
+            ),
+            ("/resources", Command::Resources { subcommand: None }),
+            ("/resources list schema", Command::Resources {
+                subcommand: Some(ResourcesSubcommand::List {
+                    search_word: Some("schema".to_string()),
+                }),
+            }),
+            ("/history", Command::History { subcommand: None }),
+            ("/history list", Command::History {
+                subcommand: Some(HistorySubcommand::List),
+            }),
+            ("/history search deploy lambda", Command::History {
+                subcommand: Some(HistorySubcommand::Search {
+                    query: "deploy lambda".to_string(),
+                }),
+            }),
+            ("/jobs", Command::Jobs {
+                subcommand: JobsSubcommand::List,
+            }),
+            ("/jobs output 2", Command::Jobs {
+                subcommand: JobsSubcommand::Output { id: 2 },
+            }),
+            ("/jobs kill 1", Command::Jobs {
+                subcommand: JobsSubcommand::Kill { id: 1 },
+            }),
+            ("/model", Command::Model {
+                subcommand: ModelSubcommand::List,
+            }),
+            ("/model set qwen3:8b", Command::Model {
+                subcommand: ModelSubcommand::Set {
+                    model: "qwen3:8b".to_string(),
+                    default: false,
+                },
+            }),
+            ("/model set --default llama3.1", Command::Model {
+                subcommand: ModelSubcommand::Set {
+                    model: "llama3.1".to_string(),
+                    default: true,
+                },
+            }),
+            ("@git:repo://aws/q summarize this", Command::Ask {
+                prompt: "@git:repo://aws/q summarize this".to_string(),
+            }),
+            ("@review main.rs", Command::Prompts {
+                subcommand: Some(PromptsSubcommand::Get {
+                    get_command: PromptsGetCommand {
+                        orig_input: Some("review main.rs".to_string()),
+                        params: PromptsGetParam {
+                            name: "review".to_string(),
+                            arguments: Some(vec!["main.rs".to_string()]),
+                        },
+                    },
+                }),
+            }),
         ];
 
         for (input, parsed) in tests {
@@ -1090,6 +1933,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_command_parse_errors() {
+        let mut stdout = std::io::stdout();
+        for input in [
+            "/undo 0",
+            "/undo abc",
+            "/checkpoint restore",
+            "/checkpoint restore x",
+            "/checkpoint foo",
+            "/tools rules rm 0",
+            "/tools allow",
+            "/tools allow fs_write --path",
+            "/tools ask execute_bash --regex (",
+            "/tools deny fs_write --unknown x",
+            "/resources foo",
+            "/history search",
+            "/jobs kill",
+            "/jobs output x",
+            "/jobs foo",
+            "/compact pin 0",
+            "/compact unpin x",
+            "/compact pin 1 2",
+            "/model set",
+            "/model set a b",
+            "/model foo",
+            "/history foo",
+        ] {
+            assert!(Command::parse(input, &mut stdout).is_err(), "{}", input);
+        }
+    }
+
     #[test]
     fn test_common_command_suggestions() {
         let mut stdout = std::io::stdout();
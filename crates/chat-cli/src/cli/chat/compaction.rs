@@ -0,0 +1,248 @@
+use std::fmt;
+use std::ops::Range;
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use tracing::warn;
+
+use super::consts::{
+    AUTO_COMPACTION_THRESHOLD,
+    CONTEXT_WINDOW_SIZE,
+};
+use super::message::UserMessage;
+use crate::database::settings::{
+    Setting,
+    Settings,
+};
+
+/// Number of turns used by the strategies that keep or summarize a number of turns, unless set
+/// with `chat.compaction.turns`.
+const DEFAULT_COMPACTION_TURNS: usize = 10;
+
+/// How the conversation history is made to fit in the context window, set with the
+/// `chat.compaction.strategy` setting.
+///
+/// A turn is a prompt along with the responses and tool uses that followed it. Pinned turns and
+/// the last turn are always kept verbatim.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum CompactionStrategy {
+    /// Replace the history with a summary generated by the model.
+    #[default]
+    Summarize,
+    /// Replace the oldest turns with a summary generated by the model, keeping the rest.
+    SummarizeOldest { turns: usize },
+    /// Drop all but the most recent turns.
+    SlidingWindow { turns: usize },
+    /// Replace the largest tool results with a placeholder.
+    DropToolResults,
+}
+
+impl CompactionStrategy {
+    /// Parses a strategy from its name in the `chat.compaction.strategy` setting.
+    pub fn from_name(name: &str, turns: usize) -> Option<Self> {
+        match name {
+            "summarize" => Some(Self::Summarize),
+            "summarize-oldest" => Some(Self::SummarizeOldest { turns }),
+            "sliding-window" => Some(Self::SlidingWindow { turns }),
+            "drop-tool-results" => Some(Self::DropToolResults),
+            _ => None,
+        }
+    }
+
+    /// Whether the strategy asks the model for a summary of the history.
+    pub fn summarizes(&self) -> bool {
+        matches!(self, Self::Summarize | Self::SummarizeOldest { .. })
+    }
+
+    /// The number of turns to summarize, or [None] if all but the last turn are summarized.
+    pub fn summarized_turns(&self) -> Option<usize> {
+        match self {
+            Self::SummarizeOldest { turns } => Some(*turns),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CompactionStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Summarize => write!(f, "summarize the history"),
+            Self::SummarizeOldest { turns } => write!(f, "summarize the oldest {turns} turns"),
+            Self::SlidingWindow { turns } => write!(f, "keep the last {turns} turns"),
+            Self::DropToolResults => write!(f, "drop the largest tool results"),
+        }
+    }
+}
+
+/// Compaction settings of a chat session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionConfig {
+    pub strategy: CompactionStrategy,
+    /// In tokens, the request size at which the history is compacted before sending the request,
+    /// set as a percentage of the context window with `chat.compaction.threshold`.
+    pub threshold: usize,
+}
+
+impl Default for CompactionConfig {
+    fn default() -> Self {
+        Self {
+            strategy: CompactionStrategy::default(),
+            threshold: AUTO_COMPACTION_THRESHOLD,
+        }
+    }
+}
+
+impl CompactionConfig {
+    /// Returns the compaction configured in `settings`. Invalid values are ignored in favor of
+    /// the defaults.
+    pub fn from_settings(settings: &Settings) -> Self {
+        let turns = settings
+            .get_int(Setting::ChatCompactionTurns)
+            .map_or(DEFAULT_COMPACTION_TURNS, |v| v.max(1) as usize);
+        let strategy = match settings.get_string(Setting::ChatCompactionStrategy) {
+            Some(name) => CompactionStrategy::from_name(&name, turns).unwrap_or_else(|| {
+                warn!(?name, "unknown compaction strategy, summarizing the history instead");
+                CompactionStrategy::default()
+            }),
+            None => CompactionStrategy::default(),
+        };
+        let threshold = settings
+            .get_int(Setting::ChatCompactionThreshold)
+            .map_or(AUTO_COMPACTION_THRESHOLD, |percent| {
+                CONTEXT_WINDOW_SIZE * percent.clamp(1, 100) as usize / 100
+            });
+
+        Self { strategy, threshold }
+    }
+}
+
+/// What caused the history to be compacted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CompactionTrigger {
+    /// The user ran `/compact`.
+    Manual,
+    /// The next request crossed [CompactionConfig::threshold].
+    Threshold,
+    /// The service rejected the request for overflowing the context window.
+    Overflow,
+}
+
+impl fmt::Display for CompactionTrigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Manual => write!(f, "/compact"),
+            Self::Threshold => write!(f, "the compaction threshold"),
+            Self::Overflow => write!(f, "a context window overflow"),
+        }
+    }
+}
+
+/// Describes the last compaction of a conversation, shown by `/usage`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompactionRecord {
+    /// The strategies that ran, in order. Summarizing is the fallback for strategies that could
+    /// not free enough of the context window.
+    pub strategies: Vec<CompactionStrategy>,
+    pub trigger: CompactionTrigger,
+    pub tokens_before: usize,
+    pub tokens_after: usize,
+}
+
+impl fmt::Display for CompactionRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, strategy) in self.strategies.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", then ")?;
+            }
+            write!(f, "{strategy}")?;
+        }
+        write!(
+            f,
+            " (triggered by {}, {} → {} tokens)",
+            self.trigger, self.tokens_before, self.tokens_after
+        )
+    }
+}
+
+/// Returns the range of history entries of each turn, given the user message of every entry. A
+/// turn starts at each prompt, since the user messages that follow it only hold tool results.
+pub fn turn_ranges<'a>(user_messages: impl Iterator<Item = &'a UserMessage>) -> Vec<Range<usize>> {
+    let mut turns: Vec<Range<usize>> = Vec::new();
+    for (i, message) in user_messages.enumerate() {
+        match turns.last_mut() {
+            Some(turn) if message.has_tool_use_results() => turn.end = i + 1,
+            _ => turns.push(i..i + 1),
+        }
+    }
+    turns
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::chat::message::ToolUseResult;
+
+    #[test]
+    fn test_strategy_from_name() {
+        assert_eq!(
+            CompactionStrategy::from_name("summarize", 5),
+            Some(CompactionStrategy::Summarize)
+        );
+        assert_eq!(
+            CompactionStrategy::from_name("summarize-oldest", 5),
+            Some(CompactionStrategy::SummarizeOldest { turns: 5 })
+        );
+        assert_eq!(
+            CompactionStrategy::from_name("sliding-window", 5),
+            Some(CompactionStrategy::SlidingWindow { turns: 5 })
+        );
+        assert_eq!(
+            CompactionStrategy::from_name("drop-tool-results", 5),
+            Some(CompactionStrategy::DropToolResults)
+        );
+        assert_eq!(CompactionStrategy::from_name("truncate", 5), None);
+    }
+
+    #[tokio::test]
+    async fn test_config_from_settings() {
+        let mut settings = Settings::new().await.unwrap();
+        assert_eq!(CompactionConfig::from_settings(&settings), CompactionConfig::default());
+
+        settings
+            .set(Setting::ChatCompactionStrategy, "sliding-window")
+            .await
+            .unwrap();
+        settings.set(Setting::ChatCompactionTurns, 4).await.unwrap();
+        settings.set(Setting::ChatCompactionThreshold, 50).await.unwrap();
+        assert_eq!(CompactionConfig::from_settings(&settings), CompactionConfig {
+            strategy: CompactionStrategy::SlidingWindow { turns: 4 },
+            threshold: CONTEXT_WINDOW_SIZE / 2,
+        });
+    }
+
+    #[test]
+    fn test_turn_ranges() {
+        let tool_results = || {
+            UserMessage::new_tool_use_results(vec![ToolUseResult {
+                tool_use_id: "1".to_string(),
+                content: vec![],
+                status: crate::api_client::model::ToolResultStatus::Success,
+            }])
+        };
+        let messages = [
+            UserMessage::new_prompt("first".to_string()),
+            tool_results(),
+            tool_results(),
+            UserMessage::new_prompt("second".to_string()),
+            UserMessage::new_prompt("third".to_string()),
+            tool_results(),
+        ];
+        assert_eq!(turn_ranges(messages.iter()), vec![0..3, 3..4, 4..6]);
+        assert!(turn_ranges([].iter()).is_empty());
+    }
+}
@@ -1,5 +1,3 @@
-use super::token_counter::TokenCounter;
-
 // These limits are the internal undocumented values from the service for each item
 
 pub const MAX_CURRENT_WORKING_DIRECTORY_LEN: usize = 256;
@@ -18,7 +16,17 @@ pub const CONTEXT_WINDOW_SIZE: usize = 200_000;
 
 pub const CONTEXT_FILES_MAX_SIZE: usize = 150_000;
 
-pub const MAX_CHARS: usize = TokenCounter::token_to_chars(CONTEXT_WINDOW_SIZE); // Character-based warning threshold
+/// In tokens, the conversation size at which users are warned that the conversation is getting
+/// lengthy.
+pub const TOKEN_WARNING_THRESHOLD: usize = CONTEXT_WINDOW_SIZE * 4 / 5;
+
+/// In tokens, the request size at which the conversation history is compacted before sending the
+/// request, rather than waiting for the service to reject it.
+pub const AUTO_COMPACTION_THRESHOLD: usize = CONTEXT_WINDOW_SIZE * 9 / 10;
+
+/// Number of tool uses from a single response that may run at the same time, unless overridden
+/// with the `chat.maxConcurrentTools` setting.
+pub const DEFAULT_MAX_CONCURRENT_TOOLS: usize = 4;
 
 pub const DUMMY_TOOL_NAME: &str = "dummy";
 
@@ -21,7 +21,9 @@ use tracing::debug;
 use super::consts::CONTEXT_FILES_MAX_SIZE;
 use super::hooks::{
     Hook,
+    HookDecision,
     HookExecutor,
+    HookInput,
 };
 use super::util::drop_matched_context_files;
 use crate::platform::Context;
@@ -38,6 +40,10 @@ pub struct ContextConfig {
 
     /// Map of Hook Name to [`Hook`]. The hook name serves as the hook's ID.
     pub hooks: HashMap<String, Hook>,
+
+    /// Model new conversations start with. A profile's model takes precedence over the global one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
 }
 
 #[allow(dead_code)]
@@ -490,6 +496,46 @@ impl ContextManager {
         Ok(context_files)
     }
 
+    /// Get the directories referenced by the global and profile context paths.
+    ///
+    /// Glob patterns are cut at their first wildcard component, and paths to files resolve to
+    /// their parent directory. Directories that do not exist are skipped.
+    pub fn context_dirs(&self) -> Result<Vec<PathBuf>> {
+        let cwd = self.ctx.env().current_dir()?;
+        let mut dirs = Vec::new();
+
+        for path in self.global_config.paths.iter().chain(self.profile_config.paths.iter()) {
+            let expanded_path = match path.strip_prefix('~') {
+                Some(rest) => match self.ctx.env().home() {
+                    Some(home_dir) => home_dir.join(rest.trim_start_matches('/')),
+                    None => continue,
+                },
+                None => PathBuf::from(path),
+            };
+
+            let mut dir = PathBuf::new();
+            for component in cwd.join(expanded_path).components() {
+                if component.as_os_str().to_string_lossy().contains(['*', '?', '[', '{']) {
+                    break;
+                }
+                dir.push(component);
+            }
+
+            let chrooted = self.ctx.fs().chroot_path(&dir);
+            if chrooted.is_file() {
+                dir.pop();
+            } else if !chrooted.is_dir() {
+                continue;
+            }
+
+            if !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+
+        Ok(dirs)
+    }
+
     /// Collects context files and optionally drops files if the total size exceeds the limit.
     /// Returns (files_to_use, dropped_files)
     pub async fn collect_context_files_with_limit(&self) -> Result<(Vec<(String, String)>, Vec<(String, String)>)> {
@@ -529,6 +575,7 @@ impl ContextManager {
     /// * `conversation_start` - If true, add the hook to conversation_start. Otherwise, it will be
     ///   added to per_prompt.
     pub async fn add_hook(&mut self, name: String, hook: Hook, global: bool) -> Result<()> {
+        hook.validate()?;
         let config = self.get_config_mut(global);
 
         if config.hooks.contains_key(&name) {
@@ -584,31 +631,58 @@ impl ContextManager {
         self.save_config(global).await
     }
 
-    /// Run all the currently enabled hooks from both the global and profile contexts.
+    /// Run all the currently enabled context hooks from both the global and profile contexts.
     /// Skipped hooks (disabled) will not appear in the output.
     /// # Arguments
     /// * `updates` - output stream to write hook run status to if Some, else do nothing if None
     /// # Returns
     /// A vector containing pairs of a [`Hook`] definition and its execution output
     pub async fn run_hooks(&mut self, updates: Option<&mut impl Write>) -> Vec<(Hook, String)> {
-        let mut hooks: Vec<&Hook> = Vec::new();
+        let hooks = all_hooks(&mut self.global_config, &mut self.profile_config)
+            .filter(|h| h.trigger.is_context())
+            .collect();
+        self.hook_executor.run_hooks(hooks, updates).await
+    }
+
+    /// Run the currently enabled lifecycle hooks for `input` from both the global and profile
+    /// contexts, global hooks first. Tool use hooks only run if they match the tool name.
+    pub async fn run_lifecycle_hooks(&mut self, input: HookInput, updates: &mut impl Write) -> HookDecision {
+        let hooks = all_hooks(&mut self.global_config, &mut self.profile_config)
+            .filter(|h| h.trigger == input.hook_event_name && h.matches_tool(input.tool_name.as_deref()))
+            .collect();
+        self.hook_executor.run_lifecycle_hooks(hooks, input, updates).await
+    }
 
-        // Set internal hook states
-        let configs = [
-            (&mut self.global_config.hooks, true),
-            (&mut self.profile_config.hooks, false),
-        ];
+    /// Returns the default model of the current profile, falling back to the global default.
+    pub fn default_model(&self) -> Option<&str> {
+        self.profile_config
+            .model
+            .as_deref()
+            .or(self.global_config.model.as_deref())
+    }
 
-        for (hook_list, is_global) in configs {
-            hooks.extend(hook_list.iter_mut().map(|(name, h)| {
+    /// Sets the default model of the current profile, or clears it if `model` is [None].
+    pub async fn set_default_model(&mut self, model: Option<String>) -> Result<()> {
+        self.profile_config.model = model;
+        self.save_config(false).await
+    }
+}
+
+/// Returns the hooks from both the global and profile contexts, after setting their internal
+/// state.
+fn all_hooks<'a>(
+    global_config: &'a mut ContextConfig,
+    profile_config: &'a mut ContextConfig,
+) -> impl Iterator<Item = &'a Hook> {
+    [(global_config, true), (profile_config, false)]
+        .into_iter()
+        .flat_map(|(config, is_global)| {
+            config.hooks.iter_mut().map(move |(name, h)| {
                 h.name = name.to_string();
                 h.is_global = is_global;
                 &*h
-            }));
-        }
-
-        self.hook_executor.run_hooks(hooks, updates).await
-    }
+            })
+        })
 }
 
 fn profile_dir_path(ctx: &Context, profile_name: &str) -> Result<PathBuf> {
@@ -642,6 +716,7 @@ async fn load_global_config(ctx: &Context) -> Result<ContextConfig> {
                 AMAZONQ_FILENAME.to_string(),
             ],
             hooks: HashMap::new(),
+            model: None,
         })
     }
 }
@@ -905,6 +980,35 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_context_dirs() -> Result<()> {
+        let mut manager = create_test_context_manager(None).await?;
+        let ctx: Arc<Context> = Arc::clone(&manager.ctx);
+        let cwd = ctx.env().current_dir()?;
+
+        ctx.fs().create_dir_all("docs/nested").await?;
+        ctx.fs().write("notes.md", "notes").await?;
+        manager
+            .add_paths(
+                vec![
+                    "docs/**/*.md".to_string(),
+                    "docs/nested".to_string(),
+                    "notes.md".to_string(),
+                    "missing/*.md".to_string(),
+                ],
+                false,
+                true,
+            )
+            .await?;
+
+        let dirs = manager.context_dirs()?;
+        assert!(dirs.contains(&cwd.join("docs")));
+        assert!(dirs.contains(&cwd.join("docs/nested")));
+        assert!(dirs.contains(&cwd));
+        assert!(!dirs.contains(&cwd.join("missing")));
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_path_ops() -> Result<()> {
         let mut manager = create_test_context_manager(None).await?;
@@ -954,6 +1058,12 @@ mod tests {
         // Test adding duplicate hook name
         assert!(manager.add_hook("test_hook".to_string(), hook, false).await.is_err());
 
+        // Test adding hook with an invalid matcher
+        let mut hook = Hook::new_inline_hook(HookTrigger::PreToolUse, "echo test".to_string());
+        hook.matcher = Some("fs_[".to_string());
+        assert!(manager.add_hook("bad_matcher".to_string(), hook, false).await.is_err());
+        assert!(!manager.profile_config.hooks.contains_key("bad_matcher"));
+
         Ok(())
     }
 
@@ -1053,4 +1163,25 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_default_model() -> Result<()> {
+        let mut manager = create_test_context_manager(None).await?;
+        assert_eq!(manager.default_model(), None);
+
+        manager.global_config.model = Some("global-model".to_string());
+        manager.save_config(true).await?;
+        manager.create_profile("test_profile").await?;
+        manager.switch_profile("test_profile").await?;
+        assert_eq!(manager.default_model(), Some("global-model"));
+
+        manager.set_default_model(Some("profile-model".to_string())).await?;
+        manager.reload_config().await?;
+        assert_eq!(manager.default_model(), Some("profile-model"));
+
+        manager.switch_profile("default").await?;
+        assert_eq!(manager.default_model(), Some("global-model"));
+
+        Ok(())
+    }
 }
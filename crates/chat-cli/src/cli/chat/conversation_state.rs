@@ -21,11 +21,15 @@ use tracing::{
     warn,
 };
 
+use super::compaction::{
+    CompactionRecord,
+    turn_ranges,
+};
 use super::consts::{
     DUMMY_TOOL_NAME,
-    MAX_CHARS,
     MAX_CONVERSATION_STATE_HISTORY_LEN,
     MAX_USER_MESSAGE_SIZE,
+    TOKEN_WARNING_THRESHOLD,
 };
 use super::context::ContextManager;
 use super::hooks::{
@@ -41,8 +45,8 @@ use super::message::{
     build_env_state,
 };
 use super::token_counter::{
-    CharCount,
-    CharCounter,
+    TokenCount,
+    TokenCountable,
 };
 use super::tool_manager::ToolManager;
 use super::tools::{
@@ -70,10 +74,22 @@ use crate::api_client::model::{
     UserInputMessageContext,
 };
 use crate::cli::chat::util::shared_writer::SharedWriter;
-use crate::database::Database;
+use crate::database::{
+    ConversationMetadata,
+    Database,
+};
 use crate::mcp_client::Prompt;
 use crate::platform::Context;
 
+/// Replaces the tool results dropped by [ConversationState::drop_tool_results].
+const DROPPED_TOOL_RESULT: &str = "<tool result dropped to free up space in the context window>";
+
+/// Sent with a response that was cut off to have the model continue it.
+const RESUME_RESPONSE_PROMPT: &str = "Your previous response was cut off. Continue it exactly where it ended, without repeating any of it or mentioning the interruption.";
+
+/// Maximum number of characters of the first prompt used as the title of a conversation.
+const MAX_TITLE_LEN: usize = 80;
+
 const CONTEXT_ENTRY_START_HEADER: &str = "--- CONTEXT ENTRY BEGIN ---\n";
 const CONTEXT_ENTRY_END_HEADER: &str = "--- CONTEXT ENTRY END ---\n\n";
 
@@ -103,6 +119,13 @@ pub struct ConversationState {
     context_message_length: Option<usize>,
     /// Stores the latest conversation summary created by /compact
     latest_summary: Option<String>,
+    /// Model selected with `--model`, `/model set`, or the profile's default. [None] lets the
+    /// backend choose.
+    #[serde(default)]
+    model: Option<String>,
+    /// The last time the history was compacted, shown by `/usage`.
+    #[serde(default)]
+    last_compaction: Option<CompactionRecord>,
     #[serde(skip)]
     pub updates: Option<SharedWriter>,
 }
@@ -132,6 +155,10 @@ impl ConversationState {
                 None
             },
         };
+        let model = context_manager
+            .as_ref()
+            .and_then(|cm| cm.default_model())
+            .map(str::to_string);
 
         Self {
             conversation_id: conversation_id.to_string(),
@@ -156,6 +183,8 @@ impl ConversationState {
             tool_manager,
             context_message_length: None,
             latest_summary: None,
+            model,
+            last_compaction: None,
             updates,
         }
     }
@@ -191,6 +220,58 @@ impl ConversationState {
         }
     }
 
+    /// The model requests are sent to, if one was selected.
+    pub fn model(&self) -> Option<&str> {
+        self.model.as_deref()
+    }
+
+    pub fn set_model(&mut self, model: Option<String>) {
+        self.model = model;
+    }
+
+    pub fn last_compaction(&self) -> Option<&CompactionRecord> {
+        self.last_compaction.as_ref()
+    }
+
+    pub fn set_last_compaction(&mut self, record: CompactionRecord) {
+        self.last_compaction = Some(record);
+    }
+
+    /// Pins or unpins the `n`th most recent prompt in the history, 1 being the last. Returns the
+    /// prompt.
+    pub fn set_pinned(&mut self, n: usize, pinned: bool) -> eyre::Result<String> {
+        let turns = turn_ranges(self.history.iter().map(|(user, _)| user));
+        let Some(turn) = n.checked_sub(1).and_then(|i| turns.iter().rev().nth(i)) else {
+            eyre::bail!("there are {} prompts in the history", turns.len());
+        };
+        let user = &mut self.history[turn.start].0;
+        user.pinned = pinned;
+        Ok(user.prompt().unwrap_or_default().to_string())
+    }
+
+    /// Unpins every prompt in the history, returning how many were pinned.
+    pub fn unpin_all(&mut self) -> usize {
+        self.history
+            .iter_mut()
+            .filter(|(user, _)| user.pinned)
+            .map(|(user, _)| user.pinned = false)
+            .count()
+    }
+
+    /// Returns the pinned prompts along with their position from the end of the history, as
+    /// given to [Self::set_pinned].
+    pub fn pinned_prompts(&self) -> Vec<(usize, &str)> {
+        let turns = turn_ranges(self.history.iter().map(|(user, _)| user));
+        let count = turns.len();
+        turns
+            .into_iter()
+            .enumerate()
+            .map(|(i, turn)| (count - i, &self.history[turn.start].0))
+            .filter(|(_, user)| user.pinned)
+            .map(|(n, user)| (n, user.prompt().unwrap_or_default()))
+            .collect()
+    }
+
     pub fn latest_summary(&self) -> Option<&str> {
         self.latest_summary.as_deref()
     }
@@ -205,6 +286,7 @@ impl ConversationState {
         self.history.clear();
         if !preserve_summary {
             self.latest_summary = None;
+            self.last_compaction = None;
         }
     }
 
@@ -264,15 +346,57 @@ impl ConversationState {
 
     /// Sets the response message according to the currently set [Self::next_message].
     pub fn push_assistant_message(&mut self, message: AssistantMessage, database: &mut Database) {
+        self.record_assistant_message(message);
+
+        if let Ok(cwd) = std::env::current_dir() {
+            let metadata = self.history_metadata(&cwd.to_string_lossy());
+            let transcript = self.transcript.iter().cloned().collect::<Vec<_>>().join("\n");
+            if let Err(err) = database.save_conversation(&metadata, &transcript, self) {
+                warn!(?err, "Failed to save the conversation to the history");
+            }
+            database.set_conversation_by_path(cwd, self).ok();
+        }
+    }
+
+    /// Returns the metadata describing this conversation in the conversation history.
+    pub fn history_metadata(&self, cwd: &str) -> ConversationMetadata {
+        let title = self
+            .history
+            .iter()
+            .find_map(|(user, _)| user.prompt())
+            .and_then(|prompt| prompt.lines().find(|line| !line.trim().is_empty()))
+            .unwrap_or_default()
+            .trim();
+        let title = match title.char_indices().nth(MAX_TITLE_LEN) {
+            Some((i, _)) => format!("{}...", &title[..i]),
+            None => title.to_string(),
+        };
+        let token_count = self
+            .history
+            .iter()
+            .map(|(user, assistant)| user.token_count() + assistant.token_count())
+            .sum::<TokenCount>();
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+
+        ConversationMetadata {
+            conversation_id: self.conversation_id.clone(),
+            title,
+            cwd: cwd.to_string(),
+            profile: self.current_profile().map(str::to_string),
+            created_at: now,
+            updated_at: now,
+            token_count: token_count.value() as u64,
+        }
+    }
+
+    /// Same as [Self::push_assistant_message], except that the conversation is not persisted. Used
+    /// by conversations that are not tied to the current directory, e.g. those of sub-agents.
+    pub fn record_assistant_message(&mut self, message: AssistantMessage) {
         debug_assert!(self.next_message.is_some(), "next_message should exist");
         let next_user_message = self.next_message.take().expect("next user message should exist");
 
         self.append_assistant_transcript(&message);
         self.history.push_back((next_user_message, message));
-
-        if let Ok(cwd) = std::env::current_dir() {
-            database.set_conversation_by_path(cwd, self).ok();
-        }
     }
 
     /// Returns the conversation id.
@@ -439,9 +563,7 @@ impl ConversationState {
     /// - `run_hooks` - whether hooks should be executed and included as context
     pub async fn as_sendable_conversation_state(&mut self, run_hooks: bool) -> FigConversationState {
         debug_assert!(self.next_message.is_some());
-        self.enforce_conversation_invariants();
-        self.history.drain(self.valid_history_range.1..);
-        self.history.drain(..self.valid_history_range.0);
+        self.trim_history_to_valid_range();
 
         let context = self.backend_conversation_state(run_hooks, false).await;
         if !context.dropped_context_files.is_empty() {
@@ -464,6 +586,20 @@ impl ConversationState {
             .expect("unable to construct conversation state")
     }
 
+    /// Returns a [FigConversationState] that asks the model to continue a response to the next
+    /// user message which was cut off after `partial_response`. Hooks are not run again.
+    pub async fn as_resumed_conversation_state(&mut self, partial_response: &str) -> FigConversationState {
+        debug_assert!(self.next_message.is_some());
+        self.trim_history_to_valid_range();
+
+        let state = self
+            .backend_conversation_state(false, true)
+            .await
+            .into_fig_conversation_state()
+            .expect("unable to construct conversation state");
+        resume_conversation_state(state, partial_response)
+    }
+
     pub async fn update_state(&mut self, force_update: bool) {
         let needs_update = self.tool_manager.has_new_stuff.load(Ordering::Acquire) || force_update;
         if !needs_update {
@@ -517,7 +653,8 @@ impl ConversationState {
             }
         }
 
-        let (context_messages, dropped_context_files) = self.context_messages(conversation_start_context).await;
+        let (context_messages, dropped_context_files, context_size) =
+            self.context_messages(conversation_start_context).await;
 
         BackendConversationState {
             conversation_id: self.conversation_id.as_str(),
@@ -527,7 +664,9 @@ impl ConversationState {
                 .range(self.valid_history_range.0..self.valid_history_range.1),
             context_messages,
             dropped_context_files,
+            context_size,
             tools: &self.tools,
+            model: self.model.as_deref(),
         }
     }
 
@@ -538,9 +677,129 @@ impl ConversationState {
         self.backend_conversation_state(false, true).await.history.len() >= 2
     }
 
+    /// Removes the history that is not sent to the backend.
+    fn trim_history_to_valid_range(&mut self) {
+        self.enforce_conversation_invariants();
+        self.history.drain(self.valid_history_range.1..);
+        self.history.drain(..self.valid_history_range.0);
+    }
+
+    /// Returns the indices of the history entries that are replaced by a summary: those of the
+    /// oldest `turns` turns if given, otherwise all but the last entry. Pinned turns and the last
+    /// turn are never summarized.
+    pub fn summarized_entries(&self, turns: Option<usize>) -> Vec<usize> {
+        let mut ranges = turn_ranges(self.history.iter().map(|(user, _)| user));
+        let unpinned = |turn: &std::ops::Range<usize>| !self.history[turn.start].0.pinned;
+        match turns {
+            Some(turns) => {
+                ranges.pop();
+                ranges.into_iter().filter(unpinned).take(turns).flatten().collect()
+            },
+            None => ranges
+                .into_iter()
+                .filter(unpinned)
+                .flatten()
+                .filter(|i| *i + 1 < self.history.len())
+                .collect(),
+        }
+    }
+
+    /// Drops all but the last `turns` turns and the pinned ones from the history. Returns the
+    /// number of turns dropped.
+    pub fn apply_sliding_window(&mut self, turns: usize) -> usize {
+        self.trim_history_to_valid_range();
+        let ranges = turn_ranges(self.history.iter().map(|(user, _)| user));
+        let dropped = ranges
+            .iter()
+            .take(ranges.len().saturating_sub(turns.max(1)))
+            .filter(|turn| !self.history[turn.start].0.pinned)
+            .collect::<Vec<_>>();
+        let count = dropped.len();
+        let removed = dropped.into_iter().flat_map(|turn| turn.clone()).collect::<Vec<_>>();
+        self.remove_history_entries(&removed);
+        count
+    }
+
+    /// Replaces the largest tool results in the history with a placeholder until the conversation
+    /// is estimated to fit in `target` tokens. The tool results of pinned turns and the last turn
+    /// are kept. Returns the number of tool results dropped.
+    pub async fn drop_tool_results(&mut self, target: usize) -> usize {
+        self.trim_history_to_valid_range();
+        let mut tokens = self.calculate_token_count().await.value();
+        let placeholder = ToolUseResultBlock::Text(DROPPED_TOOL_RESULT.to_string());
+        let placeholder_tokens = [ToolUseResult {
+            tool_use_id: String::new(),
+            content: vec![placeholder.clone()],
+            status: crate::api_client::model::ToolResultStatus::Success,
+        }]
+        .as_slice()
+        .token_count()
+        .value();
+
+        let mut ranges = turn_ranges(self.history.iter().map(|(user, _)| user));
+        ranges.pop();
+        let mut candidates = ranges
+            .into_iter()
+            .filter(|turn| !self.history[turn.start].0.pinned)
+            .flatten()
+            .flat_map(|entry| {
+                let results = self.history[entry].0.tool_use_results().unwrap_or_default();
+                results
+                    .iter()
+                    .enumerate()
+                    .map(move |(i, result)| (entry, i, std::slice::from_ref(result).token_count().value()))
+            })
+            .filter(|(_, _, count)| *count > placeholder_tokens)
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|(_, _, count)| std::cmp::Reverse(*count));
+
+        let mut dropped = 0;
+        for (entry, i, count) in candidates {
+            if tokens <= target {
+                break;
+            }
+            if let Some(result) = self.history[entry]
+                .0
+                .tool_use_results_mut()
+                .and_then(|results| results.get_mut(i))
+            {
+                result.content = vec![placeholder.clone()];
+                tokens = tokens.saturating_sub(count - placeholder_tokens);
+                dropped += 1;
+            }
+        }
+        dropped
+    }
+
+    /// Removes the history entries at the sorted `indices`. Tool results left without the tool
+    /// uses they answer are converted into prompts.
+    fn remove_history_entries(&mut self, indices: &[usize]) {
+        let mut removed = indices.iter().peekable();
+        let mut previous_removed = false;
+        let mut history = VecDeque::with_capacity(self.history.len() - indices.len());
+        for (i, (mut user, assistant)) in self.history.drain(..).enumerate() {
+            if removed.next_if_eq(&&i).is_some() {
+                previous_removed = true;
+                continue;
+            }
+            if previous_removed && user.has_tool_use_results() {
+                tool_results_into_prompt(&mut user);
+            }
+            previous_removed = false;
+            history.push_back((user, assistant));
+        }
+        self.history = history;
+    }
+
     /// Returns a [FigConversationState] capable of replacing the history of the current
     /// conversation with a summary generated by the model.
-    pub async fn create_summary_request(&mut self, custom_prompt: Option<impl AsRef<str>>) -> FigConversationState {
+    ///
+    /// Only the entries returned by [Self::summarized_entries] are summarized.
+    pub async fn create_summary_request(
+        &mut self,
+        custom_prompt: Option<impl AsRef<str>>,
+        turns: Option<usize>,
+    ) -> FigConversationState {
         let summary_content = match custom_prompt {
             Some(custom_prompt) => {
                 // Make the custom instructions much more prominent and directive
@@ -584,16 +843,21 @@ impl ConversationState {
             },
         };
 
-        let conv_state = self.backend_conversation_state(false, true).await;
-
-        // Include everything but the last message in the history.
-        let history_len = conv_state.history.len();
-        let history = if history_len < 2 {
-            vec![]
-        } else {
-            flatten_history(conv_state.history.take(history_len.saturating_sub(1)))
+        let summary_content = match &self.latest_summary {
+            // Summarizing again would otherwise lose what was summarized before.
+            Some(summary) => format!(
+                "{summary_content}\n\nThe conversation was summarized before, the new summary MUST also include the details of the previous summary:\n{summary}"
+            ),
+            None => summary_content,
         };
 
+        self.trim_history_to_valid_range();
+        let history = flatten_history(
+            self.summarized_entries(turns)
+                .into_iter()
+                .map(|entry| &self.history[entry]),
+        );
+
         let mut summary_message = UserInputMessage {
             content: summary_content,
             user_input_message_context: None,
@@ -615,39 +879,16 @@ impl ConversationState {
             conversation_id: Some(self.conversation_id.clone()),
             user_input_message: summary_message,
             history: Some(history),
+            model_id: self.model.clone(),
         }
     }
 
-    pub fn replace_history_with_summary(&mut self, summary: String) {
-        self.history.drain(..(self.history.len().saturating_sub(1)));
+    /// Replaces the history entries summarized by [Self::create_summary_request] with `summary`.
+    pub fn replace_history_with_summary(&mut self, summary: String, turns: Option<usize>) {
+        self.trim_history_to_valid_range();
+        let summarized = self.summarized_entries(turns);
+        self.remove_history_entries(&summarized);
         self.latest_summary = Some(summary);
-        // If the last message contains tool results, then we add the results to the content field
-        // instead. This is required to avoid validation errors.
-        // TODO: this can break since the max user content size is less than the max tool response
-        // size! Alternative could be to set the last tool use as part of the context messages.
-        if let Some((user, _)) = self.history.back_mut() {
-            if let Some(tool_results) = user.tool_use_results() {
-                let tool_content: Vec<String> = tool_results
-                    .iter()
-                    .flat_map(|tr| {
-                        tr.content.iter().map(|c| match c {
-                            ToolUseResultBlock::Json(document) => serde_json::to_string(&document)
-                                .map_err(|err| error!(?err, "failed to serialize tool result"))
-                                .unwrap_or_default(),
-                            ToolUseResultBlock::Text(s) => s.clone(),
-                        })
-                    })
-                    .collect::<_>();
-                let mut tool_content = tool_content.join(" ");
-                if tool_content.is_empty() {
-                    // To avoid validation errors with empty content, we need to make sure
-                    // something is set.
-                    tool_content.push_str("<tool result redacted>");
-                }
-                let prompt = truncate_safe(&tool_content, MAX_USER_MESSAGE_SIZE).to_string();
-                user.content = UserMessageContent::Prompt { prompt };
-            }
-        }
     }
 
     pub fn current_profile(&self) -> Option<&str> {
@@ -659,7 +900,8 @@ impl ConversationState {
     }
 
     /// Returns pairs of user and assistant messages to include as context in the message history
-    /// including both summaries and context files if available, and the dropped context files.
+    /// including both summaries and context files if available, the dropped context files, and
+    /// the number of tokens used by each part of the context.
     ///
     /// TODO:
     /// - Either add support for multiple context messages if the context is too large to fit inside
@@ -669,9 +911,14 @@ impl ConversationState {
     async fn context_messages(
         &mut self,
         conversation_start_context: Option<String>,
-    ) -> (Option<Vec<(UserMessage, AssistantMessage)>>, Vec<(String, String)>) {
+    ) -> (
+        Option<Vec<(UserMessage, AssistantMessage)>>,
+        Vec<(String, String)>,
+        ContextMessageSize,
+    ) {
         let mut context_content = String::new();
         let mut dropped_context_files = Vec::new();
+        let mut context_size = ContextMessageSize::default();
         if let Some(summary) = &self.latest_summary {
             context_content.push_str(CONTEXT_ENTRY_START_HEADER);
             context_content.push_str("This summary contains ALL relevant information from our previous conversation including tool uses, results, code analysis, and file operations. YOU MUST reference this information when answering questions and explicitly acknowledge specific details from the summary when they're relevant to the current question.\n\n");
@@ -679,6 +926,7 @@ impl ConversationState {
             context_content.push_str(summary);
             context_content.push('\n');
             context_content.push_str(CONTEXT_ENTRY_END_HEADER);
+            context_size.summary = TokenCount::from(context_content.as_str());
         }
 
         // Add context files if available
@@ -690,11 +938,14 @@ impl ConversationState {
                     }
 
                     if !files_to_use.is_empty() {
-                        context_content.push_str(CONTEXT_ENTRY_START_HEADER);
+                        let mut files_content = String::new();
+                        files_content.push_str(CONTEXT_ENTRY_START_HEADER);
                         for (filename, content) in files_to_use {
-                            context_content.push_str(&format!("[{}]\n{}\n", filename, content));
+                            files_content.push_str(&format!("[{}]\n{}\n", filename, content));
                         }
-                        context_content.push_str(CONTEXT_ENTRY_END_HEADER);
+                        files_content.push_str(CONTEXT_ENTRY_END_HEADER);
+                        context_size.context_files = TokenCount::from(files_content.as_str());
+                        context_content.push_str(&files_content);
                     }
                 },
                 Err(e) => {
@@ -704,6 +955,7 @@ impl ConversationState {
         }
 
         if let Some(context) = conversation_start_context {
+            context_size.hooks = TokenCount::from(context.as_str());
             context_content.push_str(&context);
         }
 
@@ -711,9 +963,15 @@ impl ConversationState {
             self.context_message_length = Some(context_content.len());
             let user_msg = UserMessage::new_prompt(context_content);
             let assistant_msg = AssistantMessage::new_response(None, "I will fully incorporate this information when generating my responses, and explicitly acknowledge relevant parts of the summary when answering questions.".into());
-            (Some(vec![(user_msg, assistant_msg)]), dropped_context_files)
+            // The acknowledgement is accounted for with the context files.
+            context_size.context_files += assistant_msg.token_count();
+            (
+                Some(vec![(user_msg, assistant_msg)]),
+                dropped_context_files,
+                context_size,
+            )
         } else {
-            (None, dropped_context_files)
+            (None, dropped_context_files, context_size)
         }
     }
 
@@ -722,16 +980,16 @@ impl ConversationState {
         self.context_message_length
     }
 
-    /// Calculate the total character count in the conversation
-    pub async fn calculate_char_count(&mut self) -> CharCount {
-        self.backend_conversation_state(false, true).await.char_count()
+    /// Calculate the total number of tokens used by the conversation
+    pub async fn calculate_token_count(&mut self) -> TokenCount {
+        self.backend_conversation_state(false, true).await.token_count()
     }
 
     /// Get the current token warning level
     pub async fn get_token_warning_level(&mut self) -> TokenWarningLevel {
-        let total_chars = self.calculate_char_count().await;
+        let total_tokens = self.calculate_token_count().await;
 
-        if *total_chars >= MAX_CHARS {
+        if *total_tokens >= TOKEN_WARNING_THRESHOLD {
             TokenWarningLevel::Critical
         } else {
             TokenWarningLevel::None
@@ -829,7 +1087,9 @@ pub struct BackendConversationStateImpl<'a, T, U> {
     pub history: T,
     pub context_messages: U,
     pub dropped_context_files: Vec<(String, String)>,
+    pub context_size: ContextMessageSize,
     pub tools: &'a HashMap<ToolOrigin, Vec<Tool>>,
+    pub model: Option<&'a str>,
 }
 
 impl
@@ -854,47 +1114,127 @@ impl
             conversation_id: Some(self.conversation_id.to_string()),
             user_input_message,
             history: Some(history),
+            model_id: self.model.map(str::to_string),
         })
     }
 
     pub fn calculate_conversation_size(&self) -> ConversationSize {
-        let mut user_chars = 0;
-        let mut assistant_chars = 0;
-        let mut context_chars = 0;
+        let mut user_messages = TokenCount::default();
+        let mut assistant_messages = TokenCount::default();
 
-        // Count the chars used by the messages in the history.
+        // Count the tokens used by the messages in the history.
         // this clone is cheap
         let history = self.history.clone();
         for (user, assistant) in history {
-            user_chars += *user.char_count();
-            assistant_chars += *assistant.char_count();
+            user_messages += user.token_count();
+            assistant_messages += assistant.token_count();
         }
 
-        // Add any chars from context messages, if available.
-        context_chars += self
-            .context_messages
-            .as_ref()
-            .map(|v| {
-                v.iter().fold(0, |acc, (user, assistant)| {
-                    acc + *user.char_count() + *assistant.char_count()
-                })
-            })
-            .unwrap_or_default();
+        // The next user message is sent along with the output of per prompt hooks.
+        let mut hooks = self.context_size.hooks;
+        if let Some(next_user_message) = self.next_user_message {
+            user_messages += next_user_message.token_count();
+            hooks += TokenCount::from(next_user_message.additional_context());
+        }
 
         ConversationSize {
-            context_messages: context_chars.into(),
-            user_messages: user_chars.into(),
-            assistant_messages: assistant_chars.into(),
+            context_files: self.context_size.context_files,
+            summary: self.context_size.summary,
+            hooks,
+            tools: self.tools.values().flatten().map(TokenCountable::token_count).sum(),
+            user_messages,
+            assistant_messages,
         }
     }
 }
 
+/// The number of tokens used by each part of the context message included at the start of the
+/// conversation history.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ContextMessageSize {
+    pub summary: TokenCount,
+    pub context_files: TokenCount,
+    /// Output of the hooks run at the start of the conversation.
+    pub hooks: TokenCount,
+}
+
 /// Reflects a detailed accounting of the context window utilization for a given conversation.
 #[derive(Debug, Clone, Copy)]
 pub struct ConversationSize {
-    pub context_messages: CharCount,
-    pub user_messages: CharCount,
-    pub assistant_messages: CharCount,
+    pub context_files: TokenCount,
+    pub summary: TokenCount,
+    pub hooks: TokenCount,
+    /// Specifications of the tools available to the model.
+    pub tools: TokenCount,
+    pub user_messages: TokenCount,
+    pub assistant_messages: TokenCount,
+}
+
+impl ConversationSize {
+    pub fn total(&self) -> TokenCount {
+        self.context_files + self.summary + self.hooks + self.tools + self.user_messages + self.assistant_messages
+    }
+}
+
+/// Replaces the tool results of `user` with a prompt containing their content, joined and
+/// truncated to [MAX_USER_MESSAGE_SIZE]. This is used for tool results whose tool uses were removed
+/// from the history, since the backend rejects tool results without a matching tool use.
+///
+/// TODO: this can break since the max user content size is less than the max tool response size!
+/// Alternative could be to set the last tool use as part of the context messages.
+fn tool_results_into_prompt(user: &mut UserMessage) {
+    let Some(tool_results) = user.tool_use_results() else {
+        return;
+    };
+    let tool_content: Vec<String> = tool_results
+        .iter()
+        .flat_map(|tr| {
+            tr.content.iter().map(|c| match c {
+                ToolUseResultBlock::Json(document) => serde_json::to_string(&document)
+                    .map_err(|err| error!(?err, "failed to serialize tool result"))
+                    .unwrap_or_default(),
+                ToolUseResultBlock::Text(s) => s.clone(),
+            })
+        })
+        .collect::<_>();
+    let mut tool_content = tool_content.join(" ");
+    if tool_content.is_empty() {
+        // To avoid validation errors with empty content, we need to make sure
+        // something is set.
+        tool_content.push_str("<tool result redacted>");
+    }
+    let prompt = truncate_safe(&tool_content, MAX_USER_MESSAGE_SIZE).to_string();
+    user.content = UserMessageContent::Prompt { prompt };
+}
+
+/// Moves the request in `state` to its history along with the `partial_response` it got, and asks
+/// for the rest of the response instead. The request is sent again as is if nothing was received.
+fn resume_conversation_state(mut state: FigConversationState, partial_response: &str) -> FigConversationState {
+    if partial_response.is_empty() {
+        return state;
+    }
+
+    let context = state.user_input_message.user_input_message_context.clone();
+    let mut request = std::mem::replace(&mut state.user_input_message, UserInputMessage {
+        images: None,
+        content: RESUME_RESPONSE_PROMPT.to_string(),
+        user_input_message_context: context.map(|context| UserInputMessageContext {
+            tool_results: None,
+            ..context
+        }),
+        user_intent: None,
+    });
+    if let Some(context) = request.user_input_message_context.as_mut() {
+        context.tools = None;
+    }
+    let history = state.history.get_or_insert_default();
+    history.push(ChatMessage::UserInputMessage(request));
+    history.push(ChatMessage::AssistantResponseMessage(AssistantResponseMessage {
+        message_id: None,
+        content: partial_response.to_string(),
+        tool_uses: None,
+    }));
+    state
 }
 
 /// Converts a list of user/assistant message pairs into a flattened list of ChatMessage.
@@ -909,12 +1249,12 @@ where
     })
 }
 
-/// Character count warning levels for conversation size
+/// Token count warning levels for conversation size
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TokenWarningLevel {
     /// No warning, conversation is within normal limits
     None,
-    /// Critical level - at single warning threshold (600K characters)
+    /// Critical level - at single warning threshold ([TOKEN_WARNING_THRESHOLD] tokens)
     Critical,
 }
 
@@ -953,6 +1293,8 @@ mod tests {
     use super::*;
     use crate::api_client::model::{
         AssistantResponseMessage,
+        ImageFormat,
+        ImageSource,
         ToolResultStatus,
     };
     use crate::cli::chat::tool_manager::ToolManager;
@@ -1073,6 +1415,41 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_conversation_state_saved_to_history() {
+        let mut database = Database::new().await.unwrap();
+
+        let mut tool_manager = ToolManager::default();
+        let mut conversation_state = ConversationState::new(
+            Context::new(),
+            "history_conv_id",
+            tool_manager.load_tools(&database).await.unwrap(),
+            None,
+            None,
+            tool_manager,
+        )
+        .await;
+
+        conversation_state
+            .set_next_user_message("\nHow do I deploy a lambda function?\nUse the CLI".to_string())
+            .await;
+        conversation_state.push_assistant_message(
+            AssistantMessage::new_response(None, "Run sam deploy, then check the Übersicht page".to_string()),
+            &mut database,
+        );
+
+        let conversations = database.search_conversations("LAMBDA sam", 10).unwrap();
+        assert_eq!(conversations.len(), 1);
+        assert_eq!(conversations[0].conversation_id, "history_conv_id");
+        assert_eq!(conversations[0].title, "How do I deploy a lambda function?");
+        assert!(database.search_conversations("kubernetes", 10).unwrap().is_empty());
+        assert_eq!(database.search_conversations("ÜBERSICHT", 10).unwrap().len(), 1);
+
+        let restored = database.get_conversation_by_id("history_conv_id").unwrap().unwrap();
+        assert_eq!(restored.history().len(), 1);
+        assert!(database.get_conversation_by_id("missing").unwrap().is_none());
+    }
+
     #[tokio::test]
     async fn test_conversation_state_history_handling_with_tool_results() {
         let mut database = Database::new().await.unwrap();
@@ -1194,6 +1571,50 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_conversation_size() {
+        let mut database = Database::new().await.unwrap();
+
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        ctx.fs().write(AMAZONQ_FILENAME, "test context").await.unwrap();
+
+        let mut tool_manager = ToolManager::default();
+        let mut conversation_state = ConversationState::new(
+            ctx,
+            "fake_conv_id",
+            tool_manager.load_tools(&database).await.unwrap(),
+            None,
+            None,
+            tool_manager,
+        )
+        .await;
+
+        conversation_state
+            .set_next_user_message("hello world".to_string())
+            .await;
+        let _ = conversation_state.as_sendable_conversation_state(true).await;
+        conversation_state.push_assistant_message(
+            AssistantMessage::new_response(None, "This is a test sentence.".to_string()),
+            &mut database,
+        );
+        conversation_state.set_next_user_message("hello".to_string()).await;
+
+        let size = conversation_state
+            .backend_conversation_state(false, true)
+            .await
+            .calculate_conversation_size();
+        assert_eq!(size.user_messages, TokenCount::from(3));
+        assert_eq!(size.assistant_messages, TokenCount::from(6));
+        assert_eq!(size.summary, TokenCount::default());
+        assert!(*size.context_files > 0);
+        assert!(*size.tools > 0);
+        assert_eq!(
+            size.total(),
+            size.context_files + size.hooks + size.tools + size.user_messages + size.assistant_messages
+        );
+        assert_eq!(conversation_state.calculate_token_count().await, size.total());
+    }
+
     #[tokio::test]
     async fn test_conversation_state_additional_context() {
         // tracing_subscriber::fmt::try_init().ok();
@@ -1261,4 +1682,184 @@ mod tests {
             conversation_state.set_next_user_message(i.to_string()).await;
         }
     }
+
+    /// Builds a conversation of one turn per prompt. Prompts with a tool result are answered with
+    /// a tool use first.
+    async fn conversation_with_turns(database: &mut Database, turns: &[(&str, Option<&str>)]) -> ConversationState {
+        let mut tool_manager = ToolManager::default();
+        let mut conversation_state = ConversationState::new(
+            Context::new(),
+            "fake_conv_id",
+            tool_manager.load_tools(database).await.unwrap(),
+            None,
+            None,
+            tool_manager,
+        )
+        .await;
+        for &(prompt, tool_result) in turns {
+            conversation_state.set_next_user_message(prompt.to_string()).await;
+            if let Some(tool_result) = tool_result {
+                conversation_state.push_assistant_message(
+                    AssistantMessage::new_tool_use(None, String::new(), vec![AssistantToolUse {
+                        id: format!("{prompt}_tool"),
+                        name: "fs_read".to_string(),
+                        ..Default::default()
+                    }]),
+                    database,
+                );
+                conversation_state.add_tool_results(vec![ToolUseResult {
+                    tool_use_id: format!("{prompt}_tool"),
+                    content: vec![ToolUseResultBlock::Text(tool_result.to_string())],
+                    status: ToolResultStatus::Success,
+                }]);
+            }
+            conversation_state
+                .push_assistant_message(AssistantMessage::new_response(None, prompt.to_string()), database);
+        }
+        conversation_state
+    }
+
+    fn prompts(conversation_state: &ConversationState) -> Vec<&str> {
+        conversation_state
+            .history()
+            .iter()
+            .filter_map(|(user, _)| user.prompt())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_compaction_sliding_window() {
+        let mut database = Database::new().await.unwrap();
+        let turns = [
+            ("p0", None),
+            ("p1", Some("result")),
+            ("p2", None),
+            ("p3", None),
+            ("p4", None),
+        ];
+        let mut conversation_state = conversation_with_turns(&mut database, &turns).await;
+
+        assert_eq!(conversation_state.set_pinned(4, true).unwrap(), "p1");
+        assert!(conversation_state.set_pinned(6, true).is_err());
+        assert_eq!(conversation_state.pinned_prompts(), vec![(4, "p1")]);
+
+        assert_eq!(conversation_state.apply_sliding_window(2), 2);
+        assert_eq!(prompts(&conversation_state), vec!["p1", "p3", "p4"]);
+        assert_eq!(conversation_state.history().len(), 4);
+        assert_eq!(conversation_state.pinned_prompts(), vec![(3, "p1")]);
+        assert_eq!(conversation_state.apply_sliding_window(2), 0);
+
+        assert_eq!(conversation_state.unpin_all(), 1);
+        assert_eq!(conversation_state.apply_sliding_window(1), 2);
+        assert_eq!(prompts(&conversation_state), vec!["p4"]);
+    }
+
+    #[tokio::test]
+    async fn test_compaction_summarize_oldest() {
+        let mut database = Database::new().await.unwrap();
+        let turns = [("p0", None), ("p1", None), ("p2", Some("result")), ("p3", None)];
+        let mut conversation_state = conversation_with_turns(&mut database, &turns).await;
+        conversation_state.set_pinned(3, true).unwrap();
+
+        // The pinned turn is skipped, and the tool use of p2 is summarized along with its result.
+        let request = conversation_state.create_summary_request(None::<&str>, Some(2)).await;
+        let history = request.history.unwrap();
+        assert_eq!(history.len(), 6);
+        assert!(matches!(&history[0], ChatMessage::UserInputMessage(user) if user.content.contains("p0")));
+        assert!(matches!(&history[2], ChatMessage::UserInputMessage(user) if user.content.contains("p2")));
+
+        conversation_state.replace_history_with_summary("summary".to_string(), Some(2));
+        assert_eq!(prompts(&conversation_state), vec!["p1", "p3"]);
+        assert_eq!(conversation_state.latest_summary(), Some("summary"));
+
+        // Summarizing everything keeps the pinned turn and the last entry.
+        conversation_state.replace_history_with_summary("summary".to_string(), None);
+        assert_eq!(prompts(&conversation_state), vec!["p1", "p3"]);
+        conversation_state.unpin_all();
+        conversation_state.replace_history_with_summary("summary".to_string(), None);
+        assert_eq!(prompts(&conversation_state), vec!["p3"]);
+    }
+
+    #[tokio::test]
+    async fn test_compaction_drop_tool_results() {
+        let mut database = Database::new().await.unwrap();
+        let large = "lorem ipsum ".repeat(2000);
+        let medium = "lorem ipsum ".repeat(500);
+        let turns = [
+            ("p0", Some(medium.as_str())),
+            ("p1", Some(large.as_str())),
+            ("p2", Some(large.as_str())),
+            ("p3", Some(large.as_str())),
+        ];
+        let mut conversation_state = conversation_with_turns(&mut database, &turns).await;
+        conversation_state.set_pinned(3, true).unwrap();
+        let tool_result = |conversation_state: &ConversationState, entry: usize| {
+            let results = conversation_state.history()[entry].0.tool_use_results().unwrap();
+            match results[0].content.first() {
+                Some(ToolUseResultBlock::Text(text)) => text.clone(),
+                _ => panic!("expected a text tool result"),
+            }
+        };
+
+        // The largest tool results go first, and those of the pinned and last turn are kept.
+        let tokens = conversation_state.calculate_token_count().await.value();
+        assert_eq!(conversation_state.drop_tool_results(tokens - 1).await, 1);
+        assert_eq!(tool_result(&conversation_state, 1), medium);
+        assert_eq!(tool_result(&conversation_state, 3), large);
+        assert_eq!(tool_result(&conversation_state, 5), DROPPED_TOOL_RESULT);
+        assert_eq!(tool_result(&conversation_state, 7), large);
+
+        assert_eq!(conversation_state.drop_tool_results(0).await, 1);
+        assert_eq!(tool_result(&conversation_state, 1), DROPPED_TOOL_RESULT);
+        assert_eq!(conversation_state.drop_tool_results(0).await, 0);
+    }
+
+    #[test]
+    fn test_resume_conversation_state() {
+        let request = UserInputMessage {
+            images: Some(vec![ImageBlock {
+                format: ImageFormat::Png,
+                source: ImageSource::Bytes(vec![1, 2, 3]),
+            }]),
+            content: "explain the code".to_string(),
+            user_input_message_context: Some(UserInputMessageContext {
+                tools: Some(vec![]),
+                ..Default::default()
+            }),
+            user_intent: None,
+        };
+        let state = FigConversationState {
+            conversation_id: Some("conversation".to_string()),
+            user_input_message: request.clone(),
+            history: None,
+            model_id: None,
+        };
+        let resent = resume_conversation_state(state.clone(), "");
+        assert_eq!(resent.user_input_message.content, request.content);
+        assert!(resent.history.is_none());
+
+        let resumed = resume_conversation_state(state, "The code");
+        assert_eq!(resumed.user_input_message.content, RESUME_RESPONSE_PROMPT);
+        assert!(resumed.user_input_message.images.is_none());
+        assert!(
+            resumed
+                .user_input_message
+                .user_input_message_context
+                .unwrap()
+                .tools
+                .is_some()
+        );
+        let history = resumed.history.unwrap();
+        assert_eq!(history.len(), 2);
+        assert!(matches!(
+            &history[0],
+            ChatMessage::UserInputMessage(message) if message.content == request.content
+                && message.images.as_ref().is_some_and(|images| images.len() == 1)
+                && message.user_input_message_context.as_ref().unwrap().tools.is_none()
+        ));
+        assert!(matches!(
+            &history[1],
+            ChatMessage::AssistantResponseMessage(message) if message.content == "The code"
+        ));
+    }
 }
@@ -0,0 +1,147 @@
+use std::io::Write;
+
+use crossterm::style::{
+    Attribute,
+    Color,
+};
+use crossterm::{
+    queue,
+    style,
+};
+use time::OffsetDateTime;
+
+use crate::database::ConversationMetadata;
+use crate::platform::Context;
+use crate::util::CLI_BINARY_NAME;
+use crate::util::directories::home_dir;
+
+/// Maximum number of conversations shown when listing or searching the conversation history.
+pub const LIST_LIMIT: usize = 20;
+
+/// Prints a list of conversations from the conversation history, most recent first.
+pub fn print_conversations(
+    ctx: &Context,
+    output: &mut impl Write,
+    conversations: &[ConversationMetadata],
+) -> std::io::Result<()> {
+    if conversations.is_empty() {
+        queue!(
+            output,
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print("No saved conversations found.\n"),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+        return output.flush();
+    }
+
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let home = home_dir(ctx).ok().map(|home| home.to_string_lossy().to_string());
+    for conversation in conversations {
+        let title = match conversation.title.as_str() {
+            "" => "(untitled)",
+            title => title,
+        };
+        let cwd = match &home {
+            Some(home) if conversation.cwd.starts_with(home.as_str()) => {
+                format!("~{}", &conversation.cwd[home.len()..])
+            },
+            _ => conversation.cwd.clone(),
+        };
+        let mut details = vec![format_age(now - conversation.updated_at, conversation.updated_at), cwd];
+        if let Some(profile) = &conversation.profile {
+            details.push(format!("profile {profile}"));
+        }
+        details.push(format!("~{} tokens", conversation.token_count));
+
+        queue!(
+            output,
+            style::SetForegroundColor(Color::Green),
+            style::SetAttribute(Attribute::Bold),
+            style::Print(&conversation.conversation_id),
+            style::SetAttribute(Attribute::Reset),
+            style::SetForegroundColor(Color::Reset),
+            style::Print(format!("  {title}\n")),
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print(format!(
+                "{}{}\n",
+                " ".repeat(conversation.conversation_id.len() + 2),
+                details.join(" · ")
+            )),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+    }
+
+    queue!(
+        output,
+        style::SetForegroundColor(Color::DarkGrey),
+        style::Print(format!(
+            "\nResume a conversation with {CLI_BINARY_NAME} chat --resume=<ID>\n"
+        )),
+        style::SetForegroundColor(Color::Reset),
+    )?;
+    output.flush()
+}
+
+/// Formats the time elapsed since `timestamp`, falling back to the date for older timestamps.
+fn format_age(elapsed_secs: i64, timestamp: i64) -> String {
+    const MINUTE: i64 = 60;
+    const HOUR: i64 = 60 * MINUTE;
+    const DAY: i64 = 24 * HOUR;
+
+    let plural = |count: i64, unit: &str| match count {
+        1 => format!("1 {unit} ago"),
+        count => format!("{count} {unit}s ago"),
+    };
+    if elapsed_secs < MINUTE {
+        "just now".to_string()
+    } else if elapsed_secs < HOUR {
+        plural(elapsed_secs / MINUTE, "minute")
+    } else if elapsed_secs < DAY {
+        plural(elapsed_secs / HOUR, "hour")
+    } else if elapsed_secs < 7 * DAY {
+        plural(elapsed_secs / DAY, "day")
+    } else {
+        OffsetDateTime::from_unix_timestamp(timestamp)
+            .map(|datetime| datetime.date().to_string())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(5, 0), "just now");
+        assert_eq!(format_age(60, 0), "1 minute ago");
+        assert_eq!(format_age(2 * 3600 + 5, 0), "2 hours ago");
+        assert_eq!(format_age(3 * 86400, 0), "3 days ago");
+        assert_eq!(format_age(30 * 86400, 1_700_000_000), "2023-11-14");
+    }
+
+    #[test]
+    fn test_print_conversations() {
+        let ctx = Context::new();
+        let mut output = Vec::new();
+        print_conversations(&ctx, &mut output, &[]).unwrap();
+        assert!(String::from_utf8(output).unwrap().contains("No saved conversations"));
+
+        let mut output = Vec::new();
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        print_conversations(&ctx, &mut output, &[ConversationMetadata {
+            conversation_id: "abc123".to_string(),
+            title: "How do I deploy this?".to_string(),
+            cwd: "/workspace/project".to_string(),
+            profile: Some("dev".to_string()),
+            created_at: now,
+            updated_at: now,
+            token_count: 1200,
+        }])
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("abc123"));
+        assert!(output.contains("How do I deploy this?"));
+        assert!(output.contains("/workspace/project · profile dev · ~1200 tokens"));
+    }
+}
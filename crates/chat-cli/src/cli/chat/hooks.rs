@@ -26,6 +26,7 @@ use futures::stream::{
     FuturesUnordered,
     StreamExt,
 };
+use globset::Glob;
 use serde::{
     Deserialize,
     Serialize,
@@ -34,13 +35,27 @@ use spinners::{
     Spinner,
     Spinners,
 };
+use tokio::io::AsyncWriteExt;
+use tracing::warn;
 
+use super::message::{
+    ToolUseResult,
+    ToolUseResultBlock,
+};
 use super::util::truncate_safe;
+use crate::api_client::model::ToolResultStatus;
 
 const DEFAULT_TIMEOUT_MS: u64 = 30_000;
 const DEFAULT_MAX_OUTPUT_SIZE: usize = 1024 * 10;
 const DEFAULT_CACHE_TTL_SECONDS: u64 = 0;
 
+/// Exit code with which a lifecycle hook blocks, using its STDERR as the reason.
+const BLOCKING_EXIT_CODE: i32 = 2;
+
+/// Number of times in a row that stop hooks may keep an assistant turn going before control is
+/// handed back to the user.
+pub const MAX_STOP_HOOK_CONTINUATIONS: usize = 5;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Hook {
     pub trigger: HookTrigger,
@@ -62,6 +77,11 @@ pub struct Hook {
     #[serde(default = "Hook::default_cache_ttl_seconds")]
     pub cache_ttl_seconds: u64,
 
+    /// For tool use hooks, a glob matched against the tool name. Hooks without a matcher run for
+    /// every tool.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matcher: Option<String>,
+
     // Type-specific fields
     /// The bash command to execute
     pub command: Option<String>, // For inline hooks
@@ -82,12 +102,37 @@ impl Hook {
             timeout_ms: Self::default_timeout_ms(),
             max_output_size: Self::default_max_output_size(),
             cache_ttl_seconds: Self::default_cache_ttl_seconds(),
+            matcher: None,
             command: Some(command),
             is_global: false,
             name: "new hook".to_string(),
         }
     }
 
+    /// Whether the hook applies to a tool use of `tool_name`, or to events that are not about a
+    /// tool use if `tool_name` is `None`.
+    pub fn matches_tool(&self, tool_name: Option<&str>) -> bool {
+        match (&self.matcher, tool_name) {
+            (None, _) => true,
+            (Some(matcher), Some(tool_name)) => match Glob::new(matcher) {
+                Ok(glob) => glob.compile_matcher().is_match(tool_name),
+                Err(err) => {
+                    warn!("Hook '{}' has an invalid matcher '{}': {}", self.name, matcher, err);
+                    false
+                },
+            },
+            (Some(_), None) => false,
+        }
+    }
+
+    /// Checks that the matcher of the hook, if any, is a valid glob.
+    pub fn validate(&self) -> Result<()> {
+        if let Some(matcher) = &self.matcher {
+            Glob::new(matcher).map_err(|err| eyre!("invalid matcher '{}': {}", matcher, err))?;
+        }
+        Ok(())
+    }
+
     fn default_disabled() -> bool {
         false
     }
@@ -117,6 +162,105 @@ pub enum HookType {
 pub enum HookTrigger {
     ConversationStart,
     PerPrompt,
+    /// Runs before a tool use is invoked, and may block it or rewrite its input.
+    PreToolUse,
+    /// Runs after a tool use completes, and may report feedback to the model.
+    PostToolUse,
+    /// Runs when the assistant ends its turn, and may ask it to keep going.
+    Stop,
+}
+
+impl HookTrigger {
+    pub const ALL: [HookTrigger; 5] = [
+        HookTrigger::ConversationStart,
+        HookTrigger::PerPrompt,
+        HookTrigger::PreToolUse,
+        HookTrigger::PostToolUse,
+        HookTrigger::Stop,
+    ];
+
+    /// Whether the output of hooks with this trigger is added to the context sent to the model.
+    /// Other hooks are lifecycle hooks, see [HookExecutor::run_lifecycle_hooks].
+    pub fn is_context(&self) -> bool {
+        matches!(self, HookTrigger::ConversationStart | HookTrigger::PerPrompt)
+    }
+}
+
+/// JSON written to the STDIN of lifecycle hooks.
+#[derive(Debug, Clone, Serialize)]
+pub struct HookInput {
+    pub hook_event_name: HookTrigger,
+    pub conversation_id: String,
+    pub cwd: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_input: Option<serde_json::Value>,
+    /// For post_tool_use hooks, the result of the tool use.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_response: Option<serde_json::Value>,
+    /// For stop hooks, whether the turn was already kept going by a stop hook.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_hook_active: Option<bool>,
+}
+
+impl HookInput {
+    pub fn new(hook_event_name: HookTrigger, conversation_id: impl Into<String>, cwd: impl Into<String>) -> Self {
+        Self {
+            hook_event_name,
+            conversation_id: conversation_id.into(),
+            cwd: cwd.into(),
+            tool_name: None,
+            tool_input: None,
+            tool_response: None,
+            stop_hook_active: None,
+        }
+    }
+
+    /// Sets the result of a tool use for post_tool_use hooks.
+    pub fn set_tool_response(&mut self, tool_result: &ToolUseResult) {
+        self.tool_response = Some(serde_json::json!({
+            "is_error": matches!(tool_result.status, ToolResultStatus::Error),
+            "content": tool_result
+                .content
+                .iter()
+                .map(|block| match block {
+                    ToolUseResultBlock::Json(json) => json.clone(),
+                    ToolUseResultBlock::Text(text) => serde_json::Value::String(text.clone()),
+                })
+                .collect::<Vec<_>>(),
+        }));
+    }
+}
+
+/// JSON that a lifecycle hook may print to STDOUT when it exits successfully.
+#[derive(Debug, Default, Deserialize)]
+struct HookResponse {
+    #[serde(default)]
+    decision: Option<HookResponseDecision>,
+    #[serde(default)]
+    reason: Option<String>,
+    /// For pre_tool_use hooks, replaces the input of the tool use.
+    #[serde(default)]
+    tool_input: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum HookResponseDecision {
+    Allow,
+    Block,
+}
+
+/// The outcome of running lifecycle hooks.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HookDecision {
+    /// Carry on. `tool_input` is set if a pre_tool_use hook rewrote the input of the tool use.
+    Allow { tool_input: Option<serde_json::Value> },
+    /// For pre_tool_use hooks, the tool use is not invoked. For post_tool_use hooks, the reason is
+    /// sent to the model along with the tool result. For stop hooks, the reason is sent to the
+    /// model so that it keeps going.
+    Block { hook: String, reason: String },
 }
 
 #[derive(Debug, Clone)]
@@ -270,7 +414,7 @@ impl HookExecutor {
         results.iter().skip(start_cache_index).for_each(|(_, (hook, output))| {
             let expiry = match hook.trigger {
                 HookTrigger::ConversationStart => None,
-                HookTrigger::PerPrompt => Some(Instant::now() + Duration::from_secs(hook.cache_ttl_seconds)),
+                _ => Some(Instant::now() + Duration::from_secs(hook.cache_ttl_seconds)),
             };
             self.insert_cache(hook, CachedHook {
                 output: output.clone(),
@@ -328,6 +472,139 @@ impl HookExecutor {
         }
     }
 
+    /// Runs lifecycle [`Hook`]s one after another with `input` written to their STDIN as JSON.
+    /// Lifecycle hooks are never cached.
+    ///
+    /// Stops at the first hook that blocks. If a pre_tool_use hook rewrites the tool input, the
+    /// following hooks receive the rewritten input. Hooks that fail to execute are reported to
+    /// `updates` and otherwise ignored.
+    pub async fn run_lifecycle_hooks(
+        &self,
+        hooks: Vec<&Hook>,
+        mut input: HookInput,
+        updates: &mut impl Write,
+    ) -> HookDecision {
+        let mut rewritten_input = None;
+        for hook in hooks {
+            if hook.disabled {
+                continue;
+            }
+
+            let start_time = Instant::now();
+            match self.execute_lifecycle_hook(hook, &input).await {
+                Ok(HookDecision::Allow {
+                    tool_input: Some(tool_input),
+                }) => {
+                    let _ = queue!(
+                        updates,
+                        style::SetForegroundColor(style::Color::Green),
+                        style::Print("✓ "),
+                        style::SetForegroundColor(style::Color::Blue),
+                        style::Print(&hook.name),
+                        style::ResetColor,
+                        style::Print(" rewrote the tool input\n"),
+                    );
+                    input.tool_input = Some(tool_input.clone());
+                    rewritten_input = Some(tool_input);
+                },
+                Ok(HookDecision::Allow { tool_input: None }) => (),
+                Ok(HookDecision::Block { hook: name, reason }) => {
+                    let _ = queue!(
+                        updates,
+                        style::SetForegroundColor(style::Color::Red),
+                        style::Print("✗ "),
+                        style::SetForegroundColor(style::Color::Blue),
+                        style::Print(&name),
+                        style::ResetColor,
+                        style::Print(format!(" blocked: {}\n", reason)),
+                    );
+                    let _ = updates.flush();
+                    return HookDecision::Block { hook: name, reason };
+                },
+                Err(e) => {
+                    warn!("Lifecycle hook '{}' failed, continuing without it: {}", hook.name, e);
+                    let _ = queue!(
+                        updates,
+                        style::SetForegroundColor(style::Color::Red),
+                        style::Print("✗ "),
+                        style::SetForegroundColor(style::Color::Blue),
+                        style::Print(&hook.name),
+                        style::ResetColor,
+                        style::Print(" failed after "),
+                        style::SetForegroundColor(style::Color::Yellow),
+                        style::Print(format!("{:.2} s", start_time.elapsed().as_secs_f32())),
+                        style::ResetColor,
+                        style::Print(format!(": {}\n", e)),
+                    );
+                },
+            }
+        }
+        let _ = updates.flush();
+
+        HookDecision::Allow {
+            tool_input: rewritten_input,
+        }
+    }
+
+    /// Runs a lifecycle hook. The hook blocks by exiting with [BLOCKING_EXIT_CODE], or by printing
+    /// a [HookResponse] with a `block` decision.
+    async fn execute_lifecycle_hook(&self, hook: &Hook, input: &HookInput) -> Result<HookDecision> {
+        let command = hook.command.as_ref().ok_or_else(|| eyre!("no command specified"))?;
+        let input = serde_json::to_vec(input)?;
+
+        let mut child = tokio::process::Command::new("bash")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        let command_future = async {
+            if let Some(mut stdin) = child.stdin.take() {
+                // Hooks are free to exit without reading their input.
+                let _ = stdin.write_all(&input).await;
+            }
+            child.wait_with_output().await
+        };
+        let timeout = Duration::from_millis(hook.timeout_ms);
+
+        let result = match tokio::time::timeout(timeout, command_future).await {
+            Ok(result) => result?,
+            Err(_) => return Err(eyre!("command timed out after {} ms", timeout.as_millis())),
+        };
+        let stdout = result.stdout.to_str_lossy();
+        let stderr = result.stderr.to_str_lossy();
+        let reason = |output: &str| {
+            let output = output.trim();
+            match output.is_empty() {
+                true => format!("blocked by hook '{}'", hook.name),
+                false => truncate_safe(output, hook.max_output_size).to_string(),
+            }
+        };
+
+        match result.status.code() {
+            Some(0) => {
+                // Output that isn't a hook response is ignored.
+                let response = serde_json::from_str::<HookResponse>(stdout.trim()).unwrap_or_default();
+                match response.decision {
+                    Some(HookResponseDecision::Block) => Ok(HookDecision::Block {
+                        hook: hook.name.clone(),
+                        reason: reason(response.reason.as_deref().unwrap_or_default()),
+                    }),
+                    _ => Ok(HookDecision::Allow {
+                        tool_input: response.tool_input,
+                    }),
+                }
+            },
+            Some(BLOCKING_EXIT_CODE) => Ok(HookDecision::Block {
+                hook: hook.name.clone(),
+                reason: reason(if stderr.trim().is_empty() { &stdout } else { &stderr }),
+            }),
+            _ => Err(eyre!("command returned non-zero exit code: {}", result.status)),
+        }
+    }
+
     /// Will return a cached hook's output if it exists and isn't expired.
     fn get_cache(&self, hook: &Hook) -> Option<String> {
         let cache = if hook.is_global {
@@ -554,4 +831,90 @@ mod tests {
 
         assert!(results[0].1.len() <= hook.max_output_size + " ... truncated".len());
     }
+
+    fn tool_use_input(trigger: HookTrigger, tool_name: &str) -> HookInput {
+        let mut input = HookInput::new(trigger, "conv", "/");
+        input.tool_name = Some(tool_name.to_string());
+        input.tool_input = Some(serde_json::json!({ "path": "/file.txt" }));
+        input
+    }
+
+    #[tokio::test]
+    async fn test_lifecycle_hook_block() {
+        let executor = HookExecutor::new();
+        let mut output = Vec::new();
+
+        // Blocking with the exit code, depending on the input read from STDIN.
+        let mut hook = Hook::new_inline_hook(
+            HookTrigger::PreToolUse,
+            "grep -q '\"tool_name\":\"fs_write\"' && echo 'no writes' >&2 && exit 2; exit 0".to_string(),
+        );
+        hook.name = "policy".to_string();
+        let decision = executor
+            .run_lifecycle_hooks(
+                vec![&hook],
+                tool_use_input(HookTrigger::PreToolUse, "fs_write"),
+                &mut output,
+            )
+            .await;
+        assert_eq!(decision, HookDecision::Block {
+            hook: "policy".to_string(),
+            reason: "no writes".to_string()
+        });
+        let decision = executor
+            .run_lifecycle_hooks(
+                vec![&hook],
+                tool_use_input(HookTrigger::PreToolUse, "fs_read"),
+                &mut output,
+            )
+            .await;
+        assert_eq!(decision, HookDecision::Allow { tool_input: None });
+
+        // Blocking with a JSON response.
+        let hook = Hook::new_inline_hook(
+            HookTrigger::Stop,
+            r#"echo '{"decision": "block", "reason": "run the tests"}'"#.to_string(),
+        );
+        let decision = executor
+            .run_lifecycle_hooks(vec![&hook], HookInput::new(HookTrigger::Stop, "conv", "/"), &mut output)
+            .await;
+        assert!(matches!(decision, HookDecision::Block { reason, .. } if reason == "run the tests"));
+    }
+
+    #[tokio::test]
+    async fn test_lifecycle_hook_rewrite() {
+        let executor = HookExecutor::new();
+        let mut output = Vec::new();
+        let rewrite = Hook::new_inline_hook(
+            HookTrigger::PreToolUse,
+            r#"echo '{"tool_input": {"path": "/other.txt"}}'"#.to_string(),
+        );
+        // Hooks that run after a rewrite receive the rewritten input.
+        let check = Hook::new_inline_hook(HookTrigger::PreToolUse, "grep -q other.txt || exit 2".to_string());
+        let failing = Hook::new_inline_hook(HookTrigger::PreToolUse, "exit 1".to_string());
+
+        let decision = executor
+            .run_lifecycle_hooks(
+                vec![&rewrite, &failing, &check],
+                tool_use_input(HookTrigger::PreToolUse, "fs_read"),
+                &mut output,
+            )
+            .await;
+        assert_eq!(decision, HookDecision::Allow {
+            tool_input: Some(serde_json::json!({ "path": "/other.txt" }))
+        });
+        assert!(String::from_utf8(output).unwrap().contains("non-zero exit code"));
+    }
+
+    #[test]
+    fn test_hook_matches_tool() {
+        let mut hook = Hook::new_inline_hook(HookTrigger::PostToolUse, "true".to_string());
+        assert!(hook.matches_tool(Some("fs_write")));
+        assert!(hook.matches_tool(None));
+
+        hook.matcher = Some("fs_*".to_string());
+        assert!(hook.matches_tool(Some("fs_write")));
+        assert!(!hook.matches_tool(Some("execute_bash")));
+        assert!(!hook.matches_tool(None));
+    }
 }
@@ -65,11 +65,20 @@ pub async fn add_mcp_server(ctx: &Context, output: &mut SharedWriter, args: McpA
     }
 
     let merged_env = args.env.into_iter().flatten().collect::<HashMap<_, _>>();
-    let tool: CustomToolConfig = serde_json::from_value(serde_json::json!({
-        "command": args.command,
-        "env": merged_env,
-        "timeout": args.timeout.unwrap_or(default_timeout()),
-    }))?;
+    let merged_headers = args.header.into_iter().collect::<HashMap<_, _>>();
+    let tool: CustomToolConfig = match (args.command, args.url) {
+        (Some(command), None) => serde_json::from_value(serde_json::json!({
+            "command": command,
+            "env": merged_env,
+            "timeout": args.timeout.unwrap_or(default_timeout()),
+        }))?,
+        (None, Some(url)) => serde_json::from_value(serde_json::json!({
+            "url": url,
+            "headers": merged_headers,
+            "timeout": args.timeout.unwrap_or(default_timeout()),
+        }))?,
+        _ => bail!("\nExactly one of --command or --url must be provided"),
+    };
 
     writeln!(
         output,
@@ -132,7 +141,7 @@ pub async fn list_mcp_server(ctx: &Context, output: &mut SharedWriter, args: Mcp
         match cfg_opt {
             Some(cfg) if !cfg.mcp_servers.is_empty() => {
                 for (name, tool_cfg) in &cfg.mcp_servers {
-                    writeln!(output, "    • {name:<12} {}", tool_cfg.command)?;
+                    writeln!(output, "    • {name:<12} {}", tool_cfg.target())?;
                 }
             },
             _ => {
@@ -192,7 +201,10 @@ pub async fn get_mcp_server_status(ctx: &Context, output: &mut SharedWriter, nam
                 style::Print("\n─────────────\n"),
                 style::Print(format!("Scope   : {}\n", scope_display(&sc))),
                 style::Print(format!("File    : {}\n", path.display())),
-                style::Print(format!("Command : {}\n", cfg.command)),
+                style::Print(match &cfg.url {
+                    Some(url) => format!("Url     : {}\n", url),
+                    None => format!("Command : {}\n", cfg.command),
+                }),
                 style::Print(format!("Timeout : {} ms\n", cfg.timeout)),
                 style::Print(format!(
                     "Env Vars: {}\n",
@@ -329,13 +341,15 @@ mod tests {
             McpRemove,
         };
 
-        let ctx = Context::new();
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
         let mut out = SharedWriter::null();
 
         // 1. add
         let add_args = McpAdd {
             name: "local".into(),
-            command: "echo hi".into(),
+            command: Some("echo hi".into()),
+            url: None,
+            header: vec![],
             env: vec![],
             timeout: None,
             scope: None,
@@ -37,6 +37,9 @@ pub struct UserMessage {
     pub env_context: UserEnvContext,
     pub content: UserMessageContent,
     pub images: Option<Vec<ImageBlock>>,
+    /// Pinned prompts, and the responses to them, are kept verbatim when the history is compacted.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -64,6 +67,7 @@ impl UserMessage {
             additional_context: String::new(),
             env_context: UserEnvContext::generate_new(),
             content: UserMessageContent::Prompt { prompt },
+            pinned: false,
         }
     }
 
@@ -84,6 +88,7 @@ impl UserMessage {
                     })
                     .collect(),
             },
+            pinned: false,
         }
     }
 
@@ -95,6 +100,7 @@ impl UserMessage {
                 tool_use_results: results,
             },
             images: None,
+            pinned: false,
         }
     }
 
@@ -106,6 +112,7 @@ impl UserMessage {
                 tool_use_results: results,
             },
             images: Some(images),
+            pinned: false,
         }
     }
 
@@ -176,6 +183,14 @@ impl UserMessage {
         }
     }
 
+    pub fn tool_use_results_mut(&mut self) -> Option<&mut Vec<ToolUseResult>> {
+        match &mut self.content {
+            UserMessageContent::Prompt { .. } => None,
+            UserMessageContent::CancelledToolUses { tool_use_results, .. } => Some(tool_use_results),
+            UserMessageContent::ToolUseResults { tool_use_results } => Some(tool_use_results),
+        }
+    }
+
     pub fn additional_context(&self) -> &str {
         &self.additional_context
     }
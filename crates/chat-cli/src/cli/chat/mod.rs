@@ -1,8 +1,11 @@
+mod checkpoint;
 pub mod cli;
 mod command;
+mod compaction;
 mod consts;
 mod context;
 mod conversation_state;
+mod history;
 mod hooks;
 mod input_source;
 pub mod mcp;
@@ -10,9 +13,11 @@ mod message;
 mod parse;
 mod parser;
 mod prompt;
+mod sampling;
 mod server_messenger;
 #[cfg(unix)]
 mod skim_integration;
+mod structured;
 mod token_counter;
 mod tool_manager;
 mod tools;
@@ -25,10 +30,13 @@ use std::collections::{
     VecDeque,
 };
 use std::io::{
+    BufRead,
+    BufReader,
     IsTerminal,
     Read,
     Write,
 };
+use std::path::PathBuf;
 use std::process::{
     Command as ProcessCommand,
     ExitCode,
@@ -40,14 +48,28 @@ use std::{
     fs,
 };
 
+use checkpoint::CheckpointManager;
 use command::{
+    CheckpointSubcommand,
     Command,
+    HistorySubcommand,
+    JobsSubcommand,
+    KnowledgeSubcommand,
+    ModelSubcommand,
     PromptsSubcommand,
+    ResourcesSubcommand,
     ToolsSubcommand,
 };
+use compaction::{
+    CompactionConfig,
+    CompactionRecord,
+    CompactionStrategy,
+    CompactionTrigger,
+};
 use consts::{
     CONTEXT_FILES_MAX_SIZE,
     CONTEXT_WINDOW_SIZE,
+    DEFAULT_MAX_CONCURRENT_TOOLS,
     DUMMY_TOOL_NAME,
 };
 use context::ContextManager;
@@ -70,9 +92,13 @@ use eyre::{
     Result,
     bail,
 };
+use futures::StreamExt;
 use hooks::{
     Hook,
+    HookDecision,
+    HookInput,
     HookTrigger,
+    MAX_STOP_HOOK_CONTINUATIONS,
 };
 use input_source::InputSource;
 use message::{
@@ -95,10 +121,15 @@ use rand::distr::{
 };
 use regex::Regex;
 use serde_json::Map;
+use server_messenger::SamplingRequest;
 use spinners::{
     Spinner,
     Spinners,
 };
+use structured::{
+    OutputEvent,
+    StructuredOutput,
+};
 use thiserror::Error;
 use token_counter::{
     TokenCount,
@@ -112,9 +143,20 @@ use tool_manager::{
     ToolManager,
     ToolManagerBuilder,
 };
+use tools::bash_process::BackgroundProcesses;
+use tools::delegate::DelegateContext;
 use tools::gh_issue::GhIssueContext;
+use tools::knowledge_search::KnowledgeSearch;
+use tools::mcp_resource::{
+    attach_resource_mentions,
+    list_resources,
+};
+use tools::permissions::PermissionAction;
+use tools::sandbox::SandboxPolicy;
 use tools::{
+    InvokeOutput,
     OutputKind,
+    PermissionDecision,
     QueuedTool,
     Tool,
     ToolPermissions,
@@ -129,6 +171,7 @@ use tracing::{
 };
 use unicode_width::UnicodeWidthStr;
 use util::images::RichImageBlock;
+use util::knowledge_store::KnowledgeStore;
 use util::shared_writer::{
     NullWriter,
     SharedWriter,
@@ -139,23 +182,28 @@ use util::{
     drop_matched_context_files,
     play_notification_bell,
     region_check,
+    truncate_safe,
 };
 use uuid::Uuid;
 use winnow::Partial;
 use winnow::stream::Offset;
 
-use crate::api_client::StreamingClient;
 use crate::api_client::clients::SendMessageOutput;
 use crate::api_client::model::{
     ChatResponseStream,
     Tool as FigTool,
     ToolResultStatus,
 };
+use crate::api_client::{
+    RetryPolicy,
+    StreamingClient,
+};
 use crate::database::Database;
 use crate::database::settings::Setting;
 use crate::mcp_client::{
     Prompt,
     PromptGetResult,
+    Root,
 };
 use crate::platform::Context;
 use crate::telemetry::TelemetryThread;
@@ -173,8 +221,10 @@ while preserving essential information. This is useful for long-running conversa
 that may eventually reach memory constraints.
 
 <cyan!>Usage</cyan!>
-  <em>/compact</em>                   <black!>Summarize the conversation and clear history</black!>
+  <em>/compact</em>                   <black!>Compact the conversation history</black!>
   <em>/compact [prompt]</em>          <black!>Provide custom guidance for summarization</black!>
+  <em>/compact pin [n]</em>           <black!>Keep the nth most recent prompt verbatim, 1 being the last</black!>
+  <em>/compact unpin [n]</em>         <black!>Unpin the nth most recent prompt, or all prompts</black!>
 
 <cyan!>When to use</cyan!>
 • When you see the memory constraint warning message
@@ -187,6 +237,16 @@ that may eventually reach memory constraints.
 • Retains key information, code, and tool executions in the summary
 • Clears the conversation history to free up space
 • The assistant will reference the summary context in future responses
+
+<cyan!>Strategies</cyan!>
+The history is compacted automatically once it crosses <em>chat.compaction.threshold</em> percent of
+the context window (90 by default). Choose how with the <em>chat.compaction.strategy</em> setting:
+  <em>summarize</em>                  <black!>Summarize the whole history (default)</black!>
+  <em>summarize-oldest</em>           <black!>Summarize only the oldest chat.compaction.turns turns</black!>
+  <em>sliding-window</em>             <black!>Drop all but the last chat.compaction.turns turns</black!>
+  <em>drop-tool-results</em>          <black!>Drop the largest tool results first</black!>
+Pinned turns and the last turn are always kept. Strategies that cannot free enough space fall
+back to summarizing. Run <em>/usage</em> to see which strategy fired last.
 "#
     )
 }
@@ -239,12 +299,18 @@ const HELP_TEXT: &str = color_print::cstr! {"
 <em>/compact</em>      <black!>Summarize the conversation to free up context space</black!>
   <em>help</em>        <black!>Show help for the compact command</black!>
   <em>[prompt]</em>    <black!>Optional custom prompt to guide summarization</black!>
+  <em>pin</em>         <black!>Keep a prompt verbatim when compacting [n]</black!>
+  <em>unpin</em>       <black!>Unpin a prompt, or all prompts [n]</black!>
 <em>/tools</em>        <black!>View and manage tools and permissions</black!>
   <em>help</em>        <black!>Show an explanation for the trust command</black!>
   <em>trust</em>       <black!>Trust a specific tool or tools for the session</black!>
   <em>untrust</em>     <black!>Revert a tool or tools to per-request confirmation</black!>
   <em>trustall</em>    <black!>Trust all tools (equivalent to deprecated /acceptall)</black!>
   <em>reset</em>       <black!>Reset all tools to default permission levels</black!>
+  <em>rules</em>       <black!>Show or remove permission rules [rm]</black!>
+  <em>allow</em>       <black!>Allow a tool use matching a rule [--path|--command|--regex|...]</black!>
+  <em>deny</em>        <black!>Deny a tool use matching a rule</black!>
+  <em>ask</em>         <black!>Ask before a tool use matching a rule</black!>
 <em>/profile</em>      <black!>Manage profiles</black!>
   <em>help</em>        <black!>Show profile help</black!>
   <em>list</em>        <black!>List profiles</black!>
@@ -256,6 +322,13 @@ const HELP_TEXT: &str = color_print::cstr! {"
   <em>help</em>        <black!>Show prompts help</black!>
   <em>list</em>        <black!>List or search available prompts</black!>
   <em>get</em>         <black!>Retrieve and send a prompt</black!>
+<em>/resources</em>    <black!>View resources of mcp servers, attach them with @server:uri</black!>
+  <em>help</em>        <black!>Show resources help</black!>
+  <em>list</em>        <black!>List or search available resources</black!>
+<em>/history</em>      <black!>Browse conversations saved in the conversation history</black!>
+  <em>help</em>        <black!>Show history help</black!>
+  <em>list</em>        <black!>List the most recent conversations</black!>
+  <em>search</em>      <black!>Search saved conversations</black!>
 <em>/context</em>      <black!>Manage context files and hooks for the chat session</black!>
   <em>help</em>        <black!>Show context help</black!>
   <em>show</em>        <black!>Display current context rules configuration [--expand]</black!>
@@ -266,6 +339,26 @@ const HELP_TEXT: &str = color_print::cstr! {"
 <em>/usage</em>        <black!>Show current session's context window usage</black!>
 <em>/load</em>         <black!>Load conversation state from a JSON file</black!>
 <em>/save</em>         <black!>Save conversation state to a JSON file</black!>
+<em>/undo</em>         <black!>Revert file changes made during the last [n] turns</black!>
+<em>/checkpoint</em>   <black!>Manage file checkpoints for the session</black!>
+  <em>help</em>        <black!>Show checkpoint help</black!>
+  <em>list</em>        <black!>List checkpoints recorded for this session</black!>
+  <em>restore</em>     <black!>Restore files to the state before a checkpoint</black!>
+<em>/jobs</em>         <black!>Manage commands running in the background</black!>
+  <em>help</em>        <black!>Show jobs help</black!>
+  <em>list</em>        <black!>List background processes</black!>
+  <em>output</em>      <black!>Show the recent output of a background process</black!>
+  <em>kill</em>        <black!>Kill a background process</black!>
+<em>/model</em>        <black!>List the available models and switch between them</black!>
+  <em>help</em>        <black!>Show model help</black!>
+  <em>list</em>        <black!>List the available models</black!>
+  <em>set</em>         <black!>Switch to a model [--default]</black!>
+<em>/knowledge</em>    <black!>(Beta) Manage knowledge bases searchable by Amazon Q</black!>
+  <em>help</em>        <black!>Show knowledge help</black!>
+  <em>show</em>        <black!>Display the indexed knowledge bases</black!>
+  <em>add</em>         <black!>Index a file or directory [--name]</black!>
+  <em>rm</em>          <black!>Remove a knowledge base</black!>
+  <em>search</em>      <black!>Search the knowledge bases [--kb]</black!>
 
 <cyan,em>MCP:</cyan,em>
 <black!>You can now configure the Amazon Q CLI to use MCP servers. \nLearn how: https://docs.aws.amazon.com/en_us/amazonq/latest/qdeveloper-ug/command-line-mcp.html</black!>
@@ -288,6 +381,15 @@ const CONTINUATION_LINE: &str = " ⋮ ";
 const PURPOSE_ARROW: &str = " ↳ ";
 
 pub async fn launch_chat(database: &mut Database, telemetry: &TelemetryThread, args: cli::Chat) -> Result<ExitCode> {
+    if args.list || args.search.is_some() {
+        let conversations = match &args.search {
+            Some(query) => database.search_conversations(query, history::LIST_LIMIT)?,
+            None => database.list_conversations(history::LIST_LIMIT)?,
+        };
+        history::print_conversations(&Context::new(), &mut std::io::stdout(), &conversations)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
     let trust_tools = args.trust_tools.map(|mut tools| {
         if tools.len() == 1 && tools[0].is_empty() {
             tools.pop();
@@ -300,42 +402,78 @@ pub async fn launch_chat(database: &mut Database, telemetry: &TelemetryThread, a
         telemetry,
         args.input,
         args.no_interactive,
+        args.output_format,
+        args.input_format,
         args.resume,
         args.accept_all,
         args.profile,
         args.trust_all_tools,
         trust_tools,
+        args.model_endpoint,
+        args.model,
     )
     .await
 }
 
-#[allow(clippy::too_many_arguments, clippy::fn_params_excessive_bools)]
+#[allow(clippy::too_many_arguments, clippy::fn_params_excessive_bools, clippy::option_option)]
 pub async fn chat(
     database: &mut Database,
     telemetry: &TelemetryThread,
     input: Option<String>,
     no_interactive: bool,
-    resume_conversation: bool,
+    output_format: cli::ChatOutputFormat,
+    input_format: cli::ChatInputFormat,
+    resume: Option<Option<String>>,
     accept_all: bool,
     profile: Option<String>,
     trust_all_tools: bool,
     trust_tools: Option<Vec<String>>,
+    model_endpoint: Option<String>,
+    model: Option<String>,
 ) -> Result<ExitCode> {
-    if !crate::util::system_info::in_cloudshell() && !crate::auth::is_logged_in(database).await {
-        bail!(
-            "You are not logged in, please log in with {}",
-            format!("{CLI_BINARY_NAME} login").bold()
-        );
+    // A model endpoint is used instead of Amazon Q, so it needs neither a login nor a region.
+    let model_endpoint = model_endpoint.or_else(|| database.settings.get_string(Setting::ApiModelEndpointUrl));
+    if model_endpoint.is_none() {
+        if !crate::util::system_info::in_cloudshell() && !crate::auth::is_logged_in(database).await {
+            bail!(
+                "You are not logged in, please log in with {}",
+                format!("{CLI_BINARY_NAME} login").bold()
+            );
+        }
+
+        region_check("chat")?;
+    }
+
+    let structured = output_format != cli::ChatOutputFormat::Text;
+    if input_format == cli::ChatInputFormat::StreamJson && !structured {
+        bail!("--input-format stream-json requires --output-format json or stream-json");
     }
 
-    region_check("chat")?;
+    let prior_conversation = match resume {
+        None => None,
+        Some(None) => std::env::current_dir()
+            .ok()
+            .and_then(|cwd| database.get_conversation_by_path(cwd).ok())
+            .flatten(),
+        Some(Some(conversation_id)) => match database.get_conversation_by_id(&conversation_id)? {
+            Some(conversation) => Some(conversation),
+            None => bail!(
+                "No conversation with the id '{conversation_id}' was found, list the saved conversations with {}",
+                format!("{CLI_BINARY_NAME} chat --list").bold()
+            ),
+        },
+    };
 
     let ctx = Context::new();
 
     let stdin = std::io::stdin();
-    // no_interactive flag or part of a pipe
-    let interactive = !no_interactive && stdin.is_terminal();
-    let input = if !interactive && !stdin.is_terminal() {
+    // no_interactive flag, structured output, or part of a pipe
+    let interactive = !no_interactive && !structured && stdin.is_terminal();
+    let structured_input: Option<Box<dyn BufRead + Send + Sync>> = match input_format {
+        cli::ChatInputFormat::StreamJson => Some(Box::new(BufReader::new(std::io::stdin()))),
+        cli::ChatInputFormat::Text => None,
+    };
+    let input = if !interactive && !stdin.is_terminal() && structured_input.is_none() {
         // append to input string any extra info that was provided, e.g. via pipe
         let mut input = input.unwrap_or_default();
         stdin.lock().read_to_string(&mut input)?;
@@ -344,15 +482,35 @@ pub async fn chat(
         input
     };
 
-    let mut output = match interactive {
-        true => SharedWriter::stderr(),
-        false => SharedWriter::stdout(),
+    // Structured output is written to STDOUT on its own, so the formatted output is discarded.
+    let mut output = match (interactive, structured) {
+        (true, _) => SharedWriter::stderr(),
+        (false, true) => SharedWriter::null(),
+        (false, false) => SharedWriter::stdout(),
     };
 
     let client = match ctx.env().get("Q_MOCK_CHAT_RESPONSE") {
         Ok(json) => create_stream(serde_json::from_str(std::fs::read_to_string(json)?.as_str())?),
-        _ => StreamingClient::new(database).await?,
-    };
+        _ => match &model_endpoint {
+            Some(url) => {
+                let model = database.settings.get_string(Setting::ApiModelEndpointModel);
+                StreamingClient::new_openai_client(url, model).await?
+            },
+            None => StreamingClient::new(database).await?,
+        },
+    }
+    .with_retry_policy(RetryPolicy::from_settings(&database.settings));
+
+    if let Some(model) = &model {
+        let models = client.list_models().await?;
+        if !models.contains(model) {
+            bail!(
+                "Model '{}' is not available. Available models: {}",
+                model,
+                models.join(", ")
+            );
+        }
+    }
 
     let mcp_server_configs = match McpServerConfig::load_config(&mut output).await {
         Ok(config) => {
@@ -441,13 +599,12 @@ pub async fn chat(
 
     let mut chat = ChatContext::new(
         ctx,
-        database,
         &conversation_id,
         output,
         input,
         InputSource::new(database, prompt_request_sender, prompt_response_receiver)?,
         interactive,
-        resume_conversation,
+        prior_conversation,
         client,
         || terminal::window_size().map(|s| s.columns.into()).ok(),
         tool_manager,
@@ -457,7 +614,29 @@ pub async fn chat(
     )
     .await?;
 
-    let result = chat.try_chat(database, telemetry).await.map(|_| ExitCode::SUCCESS);
+    // --model takes precedence over the model of a resumed conversation and the profile's default.
+    if model.is_some() {
+        chat.conversation_state.set_model(model);
+    }
+    chat.structured_output =
+        structured.then(|| StructuredOutput::new(output_format, SharedWriter::stdout(), structured_input));
+    chat.sandbox = SandboxPolicy::from_settings(&database.settings);
+    chat.compaction = CompactionConfig::from_settings(&database.settings);
+
+    let mut result = chat.try_chat(database, telemetry).await.map(|_| ExitCode::SUCCESS);
+    if let Some(structured_output) = chat.structured_output.as_mut() {
+        // Errors are reported as events, and through the exit code.
+        if let Err(err) = &result {
+            structured_output.emit(OutputEvent::Error {
+                message: err.to_string(),
+            })?;
+        }
+        structured_output.finish(chat.conversation_state.conversation_id())?;
+        result = Ok(match structured_output.is_error() {
+            true => ExitCode::FAILURE,
+            false => ExitCode::SUCCESS,
+        });
+    }
     drop(chat); // Explicit drop for clarity
 
     result
@@ -522,41 +701,48 @@ pub struct ChatContext {
     failed_request_ids: Vec<String>,
     /// Pending prompts to be sent
     pending_prompts: VecDeque<Prompt>,
+    /// Snapshots of files taken before each write, used for `/undo` and `/checkpoint`.
+    checkpoints: CheckpointManager,
+    /// Sampling requests sent by MCP servers, answered while their tool uses are running.
+    sampling_requests: Option<tokio::sync::mpsc::Receiver<SamplingRequest>>,
+    /// Machine readable output, set with `--output-format json|stream-json`.
+    structured_output: Option<StructuredOutput>,
+    /// Number of times in a row that stop hooks kept the current turn going.
+    stop_hook_continuations: usize,
+    /// Restrictions applied to `execute_bash`, set with the `chat.sandbox.*` settings.
+    sandbox: Option<SandboxPolicy>,
+    /// How the history is compacted, set with the `chat.compaction.*` settings.
+    compaction: CompactionConfig,
+    /// Commands started by `execute_bash` in the background, listed with `/jobs`.
+    background_processes: BackgroundProcesses,
 }
 
 impl ChatContext {
     #[allow(clippy::too_many_arguments)]
     pub async fn new(
         ctx: Arc<Context>,
-        database: &mut Database,
         conversation_id: &str,
-        output: SharedWriter,
+        mut output: SharedWriter,
         mut input: Option<String>,
         input_source: InputSource,
         interactive: bool,
-        resume_conversation: bool,
+        prior_conversation: Option<ConversationState>,
         client: StreamingClient,
         terminal_width_provider: fn() -> Option<usize>,
-        tool_manager: ToolManager,
+        mut tool_manager: ToolManager,
         profile: Option<String>,
         tool_config: HashMap<String, ToolSpec>,
-        tool_permissions: ToolPermissions,
+        mut tool_permissions: ToolPermissions,
     ) -> Result<Self> {
         let ctx_clone = Arc::clone(&ctx);
         let output_clone = output.clone();
+        let sampling_requests = tool_manager.take_sampling_receiver();
 
         let mut existing_conversation = false;
-        let conversation_state = if resume_conversation {
-            let prior = std::env::current_dir()
-                .ok()
-                .and_then(|cwd| database.get_conversation_by_path(cwd).ok())
-                .flatten();
-
-            // Only restore conversations where there were actual messages.
-            // Prevents edge case where user clears conversation with --new, then exits without chatting.
-            if prior.as_ref().is_some_and(|cs| !cs.history().is_empty()) {
-                let mut cs = prior.unwrap();
-
+        // Only restore conversations where there were actual messages.
+        // Prevents edge case where user clears conversation with --new, then exits without chatting.
+        let conversation_state = match prior_conversation.filter(|cs| !cs.history().is_empty()) {
+            Some(mut cs) => {
                 existing_conversation = true;
                 cs.reload_serialized_state(Arc::clone(&ctx), Some(output.clone())).await;
                 input = Some(input.unwrap_or("In a few words, summarize our conversation so far.".to_owned()));
@@ -564,7 +750,8 @@ impl ChatContext {
                 cs.update_state(true).await;
                 cs.enforce_tool_use_history_invariants();
                 cs
-            } else {
+            },
+            None => {
                 ConversationState::new(
                     ctx_clone,
                     conversation_id,
@@ -574,20 +761,21 @@ impl ChatContext {
                     tool_manager,
                 )
                 .await
-            }
-        } else {
-            ConversationState::new(
-                ctx_clone,
-                conversation_id,
-                tool_config,
-                profile,
-                Some(output_clone),
-                tool_manager,
-            )
-            .await
+            },
         };
 
-        Ok(Self {
+        let current_profile = conversation_state.current_profile().unwrap_or("default");
+        if let Err(err) = tool_permissions.policy.load(&ctx, current_profile).await {
+            warn!(?err, "Failed to load permission rules");
+            execute!(
+                output,
+                style::SetForegroundColor(Color::Yellow),
+                style::Print(format!("\nWARNING: {}\n", err)),
+                style::SetForegroundColor(Color::Reset)
+            )?;
+        }
+
+        let chat_context = Self {
             ctx,
             output,
             initial_input: input,
@@ -603,16 +791,60 @@ impl ChatContext {
             tool_use_status: ToolUseStatus::Idle,
             failed_request_ids: Vec::new(),
             pending_prompts: VecDeque::new(),
-        })
+            checkpoints: CheckpointManager::new(),
+            sampling_requests,
+            structured_output: None,
+            stop_hook_continuations: 0,
+            sandbox: None,
+            compaction: CompactionConfig::default(),
+            background_processes: BackgroundProcesses::new(),
+        };
+        chat_context.update_mcp_roots().await;
+
+        Ok(chat_context)
+    }
+
+    /// Exposes the current working directory and the directories of the active context paths
+    /// as roots to MCP servers.
+    async fn update_mcp_roots(&self) {
+        let mut dirs = Vec::new();
+        if let Ok(cwd) = self.ctx.env().current_dir() {
+            dirs.push(cwd);
+        }
+        if let Some(context_manager) = &self.conversation_state.context_manager {
+            match context_manager.context_dirs() {
+                Ok(context_dirs) => dirs.extend(context_dirs),
+                Err(err) => warn!(?err, "Failed to collect context directories"),
+            }
+        }
+        self.conversation_state.tool_manager.set_roots(mcp_roots(dirs)).await;
     }
 }
 
+/// Converts directories to MCP roots, skipping duplicates while keeping the order of the
+/// directories.
+fn mcp_roots(dirs: Vec<PathBuf>) -> Vec<Root> {
+    let mut seen = HashSet::new();
+    dirs.into_iter()
+        .filter(|dir| seen.insert(dir.clone()))
+        .filter_map(|dir| {
+            let uri = url::Url::from_directory_path(&dir).ok()?;
+            Some(Root {
+                uri: uri.to_string(),
+                name: dir.file_name().map(|name| name.to_string_lossy().to_string()),
+            })
+        })
+        .collect()
+}
+
 impl Drop for ChatContext {
     fn drop(&mut self) {
         if let Some(spinner) = &mut self.spinner {
             spinner.stop();
         }
 
+        self.background_processes.kill_all();
+
         if self.interactive {
             queue!(
                 self.output,
@@ -666,6 +898,7 @@ enum ChatState {
         show_summary: bool,
         /// Whether or not to show the /compact help text.
         help: bool,
+        trigger: CompactionTrigger,
     },
     /// Exit the chat.
     Exit,
@@ -801,7 +1034,27 @@ impl ChatContext {
             skip_printing_tools: true,
         });
 
+        if let Some(structured_output) = self.structured_output.as_mut() {
+            let mut tools = self
+                .conversation_state
+                .tools
+                .values()
+                .flatten()
+                .map(|FigTool::ToolSpecification(spec)| spec.name.clone())
+                .collect::<Vec<_>>();
+            tools.sort();
+            structured_output.emit(OutputEvent::Init {
+                conversation_id: self.conversation_state.conversation_id().to_string(),
+                tools,
+            })?;
+        }
+
         if let Some(user_input) = self.initial_input.take() {
+            if let Some(structured_output) = self.structured_output.as_mut() {
+                structured_output.emit(OutputEvent::UserMessage {
+                    content: user_input.clone(),
+                })?;
+            }
             next_state = Some(ChatState::HandleInput {
                 input: user_input,
                 tool_uses: None,
@@ -824,9 +1077,23 @@ impl ChatContext {
                     pending_tool_index,
                     skip_printing_tools,
                 } => {
-                    // Cannot prompt in non-interactive mode no matter what.
+                    // Cannot prompt in non-interactive mode no matter what, though structured input
+                    // may provide the next prompt.
                     if !self.interactive {
-                        return Ok(());
+                        let input = match self.structured_output.as_mut() {
+                            Some(structured_output) => structured_output.next_input()?,
+                            None => None,
+                        };
+                        let (Some(input), Some(structured_output)) = (input, self.structured_output.as_mut()) else {
+                            return Ok(());
+                        };
+                        structured_output.emit(OutputEvent::UserMessage { content: input.clone() })?;
+                        next_state = Some(ChatState::HandleInput {
+                            input,
+                            tool_uses: None,
+                            pending_tool_index: None,
+                        });
+                        continue;
                     }
                     self.prompt_user(database, tool_uses, pending_tool_index, skip_printing_tools)
                         .await
@@ -838,7 +1105,7 @@ impl ChatContext {
                 } => {
                     let tool_uses_clone = tool_uses.clone();
                     tokio::select! {
-                        res = self.handle_input(telemetry, input, tool_uses, pending_tool_index) => res,
+                        res = self.handle_input(database, telemetry, input, tool_uses, pending_tool_index) => res,
                         Ok(_) = ctrl_c_stream => Err(ChatError::Interrupted { tool_uses: tool_uses_clone })
                     }
                 },
@@ -848,10 +1115,11 @@ impl ChatContext {
                     prompt,
                     show_summary,
                     help,
+                    trigger,
                 } => {
                     let tool_uses_clone = tool_uses.clone();
                     tokio::select! {
-                        res = self.compact_history(telemetry, tool_uses, pending_tool_index, prompt, show_summary, help) => res,
+                        res = self.compact_history(telemetry, tool_uses, pending_tool_index, prompt, show_summary, help, trigger) => res,
                         Ok(_) = ctrl_c_stream => Err(ChatError::Interrupted { tool_uses: tool_uses_clone })
                     }
                 },
@@ -900,6 +1168,12 @@ impl ChatContext {
                         )?;
 
                         let report = eyre::Report::from($err);
+                        // The error is only reported here, it does not propagate to [chat].
+                        if let Some(structured_output) = self.structured_output.as_mut() {
+                            structured_output.emit(OutputEvent::Error {
+                                message: report.to_string(),
+                            })?;
+                        }
 
                         let text = re
                             .replace_all(&format!("{}: {:?}\n", $prepend_msg, report), "")
@@ -959,6 +1233,11 @@ impl ChatContext {
                         // this case, attempt to automatically compact the history for the user.
                         crate::api_client::ApiClientError::ContextWindowOverflow => {
                             if !self.conversation_state.can_create_summary_request().await {
+                                if let Some(structured_output) = self.structured_output.as_mut() {
+                                    structured_output.emit(OutputEvent::Error {
+                                        message: err.to_string(),
+                                    })?;
+                                }
                                 execute!(
                                     self.output,
                                     style::SetForegroundColor(Color::Red),
@@ -994,10 +1273,11 @@ impl ChatContext {
                                 prompt: None,
                                 show_summary: false,
                                 help: false,
+                                trigger: CompactionTrigger::Overflow,
                             });
                         },
-                        crate::api_client::ApiClientError::QuotaBreach(msg) => {
-                            print_err!(msg, err);
+                        crate::api_client::ApiClientError::QuotaBreach { message, .. } => {
+                            print_err!(message, err);
                         },
                         _ => {
                             print_default_error!(err);
@@ -1018,10 +1298,11 @@ impl ChatContext {
         }
     }
 
-    /// Compacts the conversation history, replacing the history with a summary generated by the
-    /// model.
+    /// Compacts the conversation history with the configured [CompactionStrategy], falling back to
+    /// replacing the history with a summary generated by the model.
     ///
-    /// The last two user messages in the history are not included in the compaction process.
+    /// Pinned turns and the last turn are not included in the compaction process.
+    #[allow(clippy::too_many_arguments)]
     async fn compact_history(
         &mut self,
         telemetry: &TelemetryThread,
@@ -1030,6 +1311,7 @@ impl ChatContext {
         custom_prompt: Option<String>,
         show_summary: bool,
         help: bool,
+        trigger: CompactionTrigger,
     ) -> Result<ChatState, ChatError> {
         let hist = self.conversation_state.history();
         debug!(?hist, "compacting history");
@@ -1065,89 +1347,177 @@ impl ChatContext {
             });
         }
 
-        // Send a request for summarizing the history.
-        let summary_state = self
-            .conversation_state
-            .create_summary_request(custom_prompt.as_ref())
-            .await;
-        if self.interactive {
-            execute!(self.output, cursor::Hide, style::Print("\n"))?;
-            self.spinner = Some(Spinner::new(Spinners::Dots, "Creating summary...".to_string()));
-        }
-        let response = self.client.send_message(summary_state).await;
-
-        // TODO(brandonskiser): This is a temporary hotfix for failing compaction. We should instead
-        // retry except with less context included.
-        let response = match response {
-            Ok(res) => res,
-            Err(e) => match e {
-                crate::api_client::ApiClientError::ContextWindowOverflow => {
-                    self.conversation_state.clear(true);
-                    if self.interactive {
-                        self.spinner.take();
-                        execute!(
-                            self.output,
-                            terminal::Clear(terminal::ClearType::CurrentLine),
-                            cursor::MoveToColumn(0),
-                            style::SetForegroundColor(Color::Yellow),
-                            style::Print(
-                                "The context window usage has overflowed. Clearing the conversation history.\n\n"
-                            ),
-                            style::SetAttribute(Attribute::Reset)
-                        )?;
+        // A custom prompt is guidance for the summary, so it is only used when summarizing.
+        let strategy = match self.compaction.strategy {
+            strategy if custom_prompt.is_some() && !strategy.summarizes() => CompactionStrategy::Summarize,
+            strategy => strategy,
+        };
+        let tokens_before = self.conversation_state.calculate_token_count().await;
+        let mut strategies = vec![strategy];
+        let mut details = Vec::new();
+        if !strategy.summarizes() {
+            match strategy {
+                CompactionStrategy::SlidingWindow { turns } => {
+                    let dropped = self.conversation_state.apply_sliding_window(turns);
+                    if dropped > 0 {
+                        details.push(format!("Dropped the {} oldest turns", dropped));
                     }
-                    return Ok(ChatState::PromptUser {
-                        tool_uses,
-                        pending_tool_index,
-                        skip_printing_tools: true,
-                    });
                 },
-                e => return Err(e.into()),
-            },
-        };
+                CompactionStrategy::DropToolResults => {
+                    // Compacting manually drops every large tool result, otherwise only enough to
+                    // stay clear of the threshold for a while.
+                    let target = match trigger {
+                        CompactionTrigger::Manual => 0,
+                        _ => self.compaction.threshold / 2,
+                    };
+                    let dropped = self.conversation_state.drop_tool_results(target).await;
+                    if dropped > 0 {
+                        details.push(format!("Dropped {} tool results", dropped));
+                    }
+                },
+                CompactionStrategy::Summarize | CompactionStrategy::SummarizeOldest { .. } => (),
+            }
 
-        let summary = {
-            let mut parser = ResponseParser::new(response);
-            loop {
-                match parser.recv().await {
-                    Ok(parser::ResponseEvent::EndStream { message }) => {
-                        break message.content().to_string();
-                    },
-                    Ok(_) => (),
-                    Err(err) => {
-                        if let Some(request_id) = &err.request_id {
-                            self.failed_request_ids.push(request_id.clone());
-                        };
-                        return Err(err.into());
+            // Fall back to summarizing if the strategy could not free enough of the context window.
+            let tokens = self.conversation_state.calculate_token_count().await;
+            if details.is_empty()
+                || (trigger != CompactionTrigger::Manual && tokens.value() >= self.compaction.threshold)
+            {
+                strategies.push(CompactionStrategy::Summarize);
+            }
+        }
+
+        let mut summary = None;
+        let summarized_turns = strategies
+            .last()
+            .filter(|s| s.summarizes())
+            .map(|s| s.summarized_turns());
+        if let Some(turns) =
+            summarized_turns.filter(|turns| !self.conversation_state.summarized_entries(*turns).is_empty())
+        {
+            // Send a request for summarizing the history.
+            let summary_state = self
+                .conversation_state
+                .create_summary_request(custom_prompt.as_ref(), turns)
+                .await;
+            if self.interactive {
+                execute!(self.output, cursor::Hide, style::Print("\n"))?;
+                self.spinner = Some(Spinner::new(Spinners::Dots, "Creating summary...".to_string()));
+            }
+            let response = self.client.send_message(summary_state).await;
+
+            // TODO(brandonskiser): This is a temporary hotfix for failing compaction. We should instead
+            // retry except with less context included.
+            let response = match response {
+                Ok(res) => res,
+                Err(e) => match e {
+                    crate::api_client::ApiClientError::ContextWindowOverflow => {
+                        self.conversation_state.clear(true);
+                        if self.interactive {
+                            self.spinner.take();
+                            execute!(
+                                self.output,
+                                terminal::Clear(terminal::ClearType::CurrentLine),
+                                cursor::MoveToColumn(0),
+                                style::SetForegroundColor(Color::Yellow),
+                                style::Print(
+                                    "The context window usage has overflowed. Clearing the conversation history.\n\n"
+                                ),
+                                style::SetAttribute(Attribute::Reset)
+                            )?;
+                        }
+                        return Ok(ChatState::PromptUser {
+                            tool_uses,
+                            pending_tool_index,
+                            skip_printing_tools: true,
+                        });
                     },
+                    e => return Err(e.into()),
+                },
+            };
+
+            let text = {
+                let mut parser = ResponseParser::new(response);
+                loop {
+                    match parser.recv().await {
+                        Ok(parser::ResponseEvent::EndStream { message }) => {
+                            break message.content().to_string();
+                        },
+                        Ok(_) => (),
+                        Err(err) => {
+                            if let Some(request_id) = &err.request_id {
+                                self.failed_request_ids.push(request_id.clone());
+                            };
+                            return Err(err.into());
+                        },
+                    }
                 }
+            };
+
+            if self.interactive && self.spinner.is_some() {
+                drop(self.spinner.take());
+                queue!(
+                    self.output,
+                    terminal::Clear(terminal::ClearType::CurrentLine),
+                    cursor::MoveToColumn(0),
+                    cursor::Show
+                )?;
             }
-        };
 
-        if self.interactive && self.spinner.is_some() {
-            drop(self.spinner.take());
-            queue!(
-                self.output,
-                terminal::Clear(terminal::ClearType::CurrentLine),
-                cursor::MoveToColumn(0),
-                cursor::Show
-            )?;
-        }
+            if let Some(message_id) = self.conversation_state.message_id() {
+                telemetry
+                    .send_chat_added_message(
+                        self.conversation_state.conversation_id().to_owned(),
+                        message_id.to_owned(),
+                        self.conversation_state.context_message_length(),
+                    )
+                    .ok();
+            }
 
-        if let Some(message_id) = self.conversation_state.message_id() {
-            telemetry
-                .send_chat_added_message(
-                    self.conversation_state.conversation_id().to_owned(),
-                    message_id.to_owned(),
-                    self.conversation_state.context_message_length(),
-                )
-                .ok();
+            self.conversation_state
+                .replace_history_with_summary(text.clone(), turns);
+            if let Some(turns) = turns {
+                details.push(format!("Summarized the oldest {} turns", turns));
+            }
+            summary = Some(text);
+        } else if summarized_turns.is_some() {
+            strategies.pop();
         }
 
-        self.conversation_state.replace_history_with_summary(summary.clone());
-
         // Print output to the user.
-        {
+        if details.is_empty() && summary.is_none() {
+            execute!(
+                self.output,
+                style::SetForegroundColor(Color::Yellow),
+                style::Print("Nothing to compact, pinned turns and the last turn are always kept.\n\n"),
+                style::SetForegroundColor(Color::Reset)
+            )?;
+            // Retrying would overflow the context window again.
+            if trigger == CompactionTrigger::Overflow {
+                execute!(
+                    self.output,
+                    style::Print(format!("• Run {} to unpin turns\n", "/compact unpin".green())),
+                    style::Print(format!(
+                        "• Run {} to reset your conversation state\n\n",
+                        "/clear".green()
+                    )),
+                )?;
+                self.conversation_state.reset_next_user_message();
+                return Ok(ChatState::PromptUser {
+                    tool_uses: None,
+                    pending_tool_index: None,
+                    skip_printing_tools: false,
+                });
+            }
+        } else {
+            let tokens_after = self.conversation_state.calculate_token_count().await;
+            self.conversation_state.set_last_compaction(CompactionRecord {
+                strategies,
+                trigger,
+                tokens_before: tokens_before.value(),
+                tokens_after: tokens_after.value(),
+            });
+
             execute!(
                 self.output,
                 style::SetForegroundColor(Color::Green),
@@ -1162,10 +1532,13 @@ impl ChatContext {
                     style::Print(format!("• Custom prompt applied: {}\n", custom_prompt))
                 )?;
             }
+            for detail in &details {
+                execute!(output, style::Print(format!("• {}\n", detail)))?;
+            }
             animate_output(&mut self.output, &output)?;
 
             // Display the summary if the show_summary flag is set
-            if show_summary {
+            if let Some(summary) = summary.filter(|_| show_summary) {
                 // Add a border around the summary for better visual separation
                 let terminal_width = self.terminal_width();
                 let border = "═".repeat(terminal_width.min(80));
@@ -1230,6 +1603,9 @@ impl ChatContext {
         execute!(self.output, cursor::Show)?;
         let tool_uses = tool_uses.take().unwrap_or_default();
 
+        // Context paths or the active profile may have changed since the last prompt.
+        self.update_mcp_roots().await;
+
         // Check token usage and display warnings if needed
         if pending_tool_index.is_none() {
             // Only display warnings when not waiting for tool approval
@@ -1302,6 +1678,7 @@ impl ChatContext {
 
     async fn handle_input(
         &mut self,
+        database: &mut Database,
         telemetry: &TelemetryThread,
         mut user_input: String,
         tool_uses: Option<Vec<QueuedTool>>,
@@ -1351,8 +1728,38 @@ impl ChatContext {
                         .ok_or(ChatError::Custom("Prompt append failed".into()))?;
                 }
 
+                match attach_resource_mentions(&self.conversation_state.tool_manager.clients, &user_input).await {
+                    Ok(Some((mentions, resources))) => {
+                        for mention in mentions {
+                            queue!(
+                                self.output,
+                                style::SetForegroundColor(Color::DarkGrey),
+                                style::Print(format!("Attached {}\n", mention)),
+                                style::SetForegroundColor(Color::Reset),
+                            )?;
+                        }
+                        user_input = format!("{}\n\n{}", user_input, resources);
+                    },
+                    Ok(None) => (),
+                    Err(err) => {
+                        execute!(
+                            self.output,
+                            style::SetForegroundColor(Color::Red),
+                            style::Print(format!("\nError: {}\n\n", err)),
+                            style::SetForegroundColor(Color::Reset)
+                        )?;
+                        return Ok(ChatState::PromptUser {
+                            tool_uses: Some(tool_uses),
+                            pending_tool_index,
+                            skip_printing_tools: true,
+                        });
+                    },
+                }
+
                 // Otherwise continue with normal chat on 'n' or other responses
                 self.tool_use_status = ToolUseStatus::Idle;
+                self.stop_hook_continuations = 0;
+                self.checkpoints.start_turn();
 
                 if pending_tool_index.is_some() {
                     self.conversation_state.abandon_tool_use(tool_uses, user_input);
@@ -1362,6 +1769,9 @@ impl ChatContext {
 
                 let conv_state = self.conversation_state.as_sendable_conversation_state(true).await;
                 self.send_tool_use_telemetry(telemetry).await;
+                if let Some(state) = self.compact_before_sending().await? {
+                    return Ok(state);
+                }
 
                 if self.interactive {
                     queue!(self.output, style::SetForegroundColor(Color::Magenta))?;
@@ -1441,9 +1851,68 @@ impl ChatContext {
                     prompt,
                     show_summary,
                     help,
+                    CompactionTrigger::Manual,
                 )
                 .await?
             },
+            Command::Pin { turn } => {
+                match self.conversation_state.set_pinned(turn, true) {
+                    Ok(prompt) => queue!(
+                        self.output,
+                        style::SetForegroundColor(Color::Green),
+                        style::Print(format!(
+                            "\nPinned: {}\n\n",
+                            truncate_safe(prompt.lines().next().unwrap_or_default(), 80)
+                        )),
+                        style::SetForegroundColor(Color::Reset),
+                    )?,
+                    Err(err) => queue!(
+                        self.output,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(format!("\nError: {err}\n\n")),
+                        style::SetForegroundColor(Color::Reset),
+                    )?,
+                }
+                self.output.flush()?;
+
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Unpin { turn } => {
+                let result = match turn {
+                    Some(turn) => self.conversation_state.set_pinned(turn, false).map(|prompt| {
+                        format!(
+                            "Unpinned: {}",
+                            truncate_safe(prompt.lines().next().unwrap_or_default(), 80)
+                        )
+                    }),
+                    None => Ok(format!("Unpinned {} prompts", self.conversation_state.unpin_all())),
+                };
+                match result {
+                    Ok(message) => queue!(
+                        self.output,
+                        style::SetForegroundColor(Color::Green),
+                        style::Print(format!("\n{message}\n\n")),
+                        style::SetForegroundColor(Color::Reset),
+                    )?,
+                    Err(err) => queue!(
+                        self.output,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(format!("\nError: {err}\n\n")),
+                        style::SetForegroundColor(Color::Reset),
+                    )?,
+                }
+                self.output.flush()?;
+
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
             Command::Help => {
                 execute!(self.output, style::Print(HELP_TEXT))?;
                 ChatState::PromptUser {
@@ -1588,6 +2057,12 @@ impl ChatContext {
                                         .await
                                         .map_err(|e| warn!(?e, "failed to switch to newly created profile"))
                                         .ok();
+                                    self.tool_permissions
+                                        .policy
+                                        .load_profile(&self.ctx, &context_manager.current_profile)
+                                        .await
+                                        .map_err(|e| warn!(?e, "failed to load the profile's permission rules"))
+                                        .ok();
                                 },
                                 Err(e) => print_err!(e),
                             }
@@ -1607,12 +2082,19 @@ impl ChatContext {
                         },
                         command::ProfileSubcommand::Set { name } => match context_manager.switch_profile(&name).await {
                             Ok(_) => {
+                                if let Err(err) = self.tool_permissions.policy.load_profile(&self.ctx, &name).await {
+                                    warn!(?err, "failed to load the profile's permission rules");
+                                }
+                                let default_model = context_manager.default_model().map(str::to_string);
                                 execute!(
                                     self.output,
                                     style::SetForegroundColor(Color::Green),
                                     style::Print(format!("\nSwitched to profile: {}\n\n", name)),
                                     style::SetForegroundColor(Color::Reset)
                                 )?;
+                                if default_model.is_some() {
+                                    self.conversation_state.set_model(default_model);
+                                }
                             },
                             Err(e) => print_err!(e),
                         },
@@ -1755,18 +2237,14 @@ impl ChatContext {
                                     style::SetForegroundColor(Color::DarkYellow),
                                     style::Print("    🔧 Hooks:\n")
                                 )?;
-                                print_hook_section(
-                                    &mut self.output,
-                                    &context_manager.profile_config.hooks,
-                                    HookTrigger::ConversationStart,
-                                )
-                                .map_err(map_chat_error)?;
-                                print_hook_section(
-                                    &mut self.output,
-                                    &context_manager.profile_config.hooks,
-                                    HookTrigger::PerPrompt,
-                                )
-                                .map_err(map_chat_error)?;
+                                for trigger in HookTrigger::ALL {
+                                    print_hook_section(
+                                        &mut self.output,
+                                        &context_manager.profile_config.hooks,
+                                        trigger,
+                                    )
+                                    .map_err(map_chat_error)?;
+                                }
                                 execute!(self.output, style::Print("\n"))?;
                             }
 
@@ -2009,17 +2487,20 @@ impl ChatContext {
                                         name,
                                         trigger,
                                         command,
+                                        matcher,
                                         global,
                                     } => {
-                                        let trigger = if trigger == "conversation_start" {
-                                            HookTrigger::ConversationStart
-                                        } else {
-                                            HookTrigger::PerPrompt
+                                        let trigger = match trigger.as_str() {
+                                            "conversation_start" => HookTrigger::ConversationStart,
+                                            "pre_tool_use" => HookTrigger::PreToolUse,
+                                            "post_tool_use" => HookTrigger::PostToolUse,
+                                            "stop" => HookTrigger::Stop,
+                                            _ => HookTrigger::PerPrompt,
                                         };
+                                        let mut hook = Hook::new_inline_hook(trigger, command);
+                                        hook.matcher = matcher;
 
-                                        let result = context_manager
-                                            .add_hook(name.clone(), Hook::new_inline_hook(trigger, command), global)
-                                            .await;
+                                        let result = context_manager.add_hook(name.clone(), hook, global).await;
                                         match result {
                                             Ok(_) => {
                                                 execute!(
@@ -2172,18 +2653,10 @@ impl ChatContext {
                                     style::SetAttribute(Attribute::Reset),
                                 )?;
 
-                                print_hook_section(
-                                    &mut self.output,
-                                    &context_manager.global_config.hooks,
-                                    HookTrigger::ConversationStart,
-                                )
-                                .map_err(map_chat_error)?;
-                                print_hook_section(
-                                    &mut self.output,
-                                    &context_manager.global_config.hooks,
-                                    HookTrigger::PerPrompt,
-                                )
-                                .map_err(map_chat_error)?;
+                                for trigger in HookTrigger::ALL {
+                                    print_hook_section(&mut self.output, &context_manager.global_config.hooks, trigger)
+                                        .map_err(map_chat_error)?;
+                                }
 
                                 queue!(
                                     self.output,
@@ -2193,18 +2666,14 @@ impl ChatContext {
                                     style::SetAttribute(Attribute::Reset),
                                 )?;
 
-                                print_hook_section(
-                                    &mut self.output,
-                                    &context_manager.profile_config.hooks,
-                                    HookTrigger::ConversationStart,
-                                )
-                                .map_err(map_chat_error)?;
-                                print_hook_section(
-                                    &mut self.output,
-                                    &context_manager.profile_config.hooks,
-                                    HookTrigger::PerPrompt,
-                                )
-                                .map_err(map_chat_error)?;
+                                for trigger in HookTrigger::ALL {
+                                    print_hook_section(
+                                        &mut self.output,
+                                        &context_manager.profile_config.hooks,
+                                        trigger,
+                                    )
+                                    .map_err(map_chat_error)?;
+                                }
 
                                 execute!(
                                     self.output,
@@ -2365,6 +2834,70 @@ impl ChatContext {
                             )?;
                         }
                     },
+                    Some(ToolsSubcommand::Rules) => {
+                        let rules = self.tool_permissions.policy.rules().collect::<Vec<_>>();
+                        if rules.is_empty() {
+                            queue!(
+                                self.output,
+                                style::SetForegroundColor(Color::DarkGrey),
+                                style::Print("\nNo permission rules are set. Use "),
+                                style::SetForegroundColor(Color::Green),
+                                style::Print("/tools allow|deny|ask <tool>"),
+                                style::SetForegroundColor(Color::DarkGrey),
+                                style::Print(" to add one."),
+                                style::SetForegroundColor(Color::Reset),
+                            )?;
+                        }
+                        for (index, (scope, rule)) in rules.into_iter().enumerate() {
+                            let color = match rule.action {
+                                PermissionAction::Allow => Color::Green,
+                                PermissionAction::Deny => Color::Red,
+                                PermissionAction::Ask => Color::Yellow,
+                            };
+                            queue!(
+                                self.output,
+                                style::Print(format!("\n{:>3}  ", index + 1)),
+                                style::SetForegroundColor(Color::DarkGrey),
+                                style::Print(format!("{:<10} ", scope)),
+                                style::SetForegroundColor(color),
+                                style::Print(rule.to_string()),
+                                style::SetForegroundColor(Color::Reset),
+                            )?;
+                        }
+                    },
+                    Some(ToolsSubcommand::AddRule { rule, scope }) => {
+                        let description = rule.to_string();
+                        match self.tool_permissions.policy.add(&self.ctx, scope, rule).await {
+                            Ok(()) => queue!(
+                                self.output,
+                                style::SetForegroundColor(Color::Green),
+                                style::Print(format!("\nAdded {} rule: {}", scope, description)),
+                                style::SetForegroundColor(Color::Reset),
+                            )?,
+                            Err(err) => queue!(
+                                self.output,
+                                style::SetForegroundColor(Color::Red),
+                                style::Print(format!("\nFailed to add rule: {}", err)),
+                                style::SetForegroundColor(Color::Reset),
+                            )?,
+                        }
+                    },
+                    Some(ToolsSubcommand::RemoveRule { index }) => {
+                        match self.tool_permissions.policy.remove(&self.ctx, index).await {
+                            Ok((scope, rule)) => queue!(
+                                self.output,
+                                style::SetForegroundColor(Color::Green),
+                                style::Print(format!("\nRemoved {} rule: {}", scope, rule)),
+                                style::SetForegroundColor(Color::Reset),
+                            )?,
+                            Err(err) => queue!(
+                                self.output,
+                                style::SetForegroundColor(Color::Red),
+                                style::Print(format!("\nFailed to remove rule: {}", err)),
+                                style::SetForegroundColor(Color::Reset),
+                            )?,
+                        }
+                    },
                     Some(ToolsSubcommand::Help) => {
                         queue!(
                             self.output,
@@ -2452,7 +2985,11 @@ impl ChatContext {
                             style::Print("/tools help"),
                             style::SetForegroundColor(Color::Reset),
                             style::SetForegroundColor(Color::DarkGrey),
-                            style::Print(" to edit permissions."),
+                            style::Print(" to edit permissions and "),
+                            style::SetForegroundColor(Color::Green),
+                            style::Print("/tools rules"),
+                            style::SetForegroundColor(Color::DarkGrey),
+                            style::Print(" to see rules matching on tool arguments."),
                             style::SetForegroundColor(Color::Reset),
                         )?;
                     },
@@ -2668,6 +3205,122 @@ impl ChatContext {
                     skip_printing_tools: true,
                 }
             },
+            Command::History { subcommand } => {
+                let conversations = match subcommand {
+                    Some(HistorySubcommand::Help) => {
+                        execute!(
+                            self.output,
+                            style::Print(HistorySubcommand::help_text()),
+                            style::Print("\n")
+                        )?;
+                        return Ok(ChatState::PromptUser {
+                            tool_uses: Some(tool_uses),
+                            pending_tool_index,
+                            skip_printing_tools: true,
+                        });
+                    },
+                    Some(HistorySubcommand::Search { query }) => {
+                        database.search_conversations(&query, history::LIST_LIMIT)
+                    },
+                    Some(HistorySubcommand::List) | None => database.list_conversations(history::LIST_LIMIT),
+                };
+                match conversations {
+                    Ok(conversations) => {
+                        execute!(self.output, style::Print("\n"))?;
+                        history::print_conversations(&self.ctx, &mut self.output, &conversations)?;
+                    },
+                    Err(err) => {
+                        queue!(
+                            self.output,
+                            style::SetForegroundColor(Color::Red),
+                            style::Print(format!("\nFailed to read the conversation history: {}\n", err)),
+                            style::SetForegroundColor(Color::Reset),
+                        )?;
+                    },
+                }
+                execute!(self.output, style::Print("\n"))?;
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Resources { subcommand } => {
+                if let Some(ResourcesSubcommand::Help) = subcommand {
+                    queue!(self.output, style::Print(ResourcesSubcommand::help_text()))?;
+                } else {
+                    let search_word = match subcommand {
+                        Some(ResourcesSubcommand::List { search_word }) => search_word,
+                        _ => None,
+                    };
+                    match list_resources(&self.conversation_state.tool_manager.clients, None).await {
+                        Ok(servers) if servers.is_empty() => {
+                            queue!(
+                                self.output,
+                                style::SetForegroundColor(Color::DarkGrey),
+                                style::Print("\nNo mcp servers with resources are loaded.\n"),
+                                style::SetForegroundColor(Color::Reset),
+                            )?;
+                        },
+                        Ok(servers) => {
+                            let search_word = search_word.unwrap_or_default();
+                            for server in servers {
+                                queue!(
+                                    self.output,
+                                    style::Print("\n"),
+                                    style::SetAttribute(Attribute::Bold),
+                                    style::Print(&server.server_name),
+                                    style::Print(" (MCP):"),
+                                    style::SetAttribute(Attribute::Reset),
+                                    style::Print("\n"),
+                                )?;
+                                let entries = server
+                                    .resources
+                                    .iter()
+                                    .map(|r| (&r.uri, &r.name, &r.description))
+                                    .chain(
+                                        server
+                                            .resource_templates
+                                            .iter()
+                                            .map(|t| (&t.uri_template, &t.name, &t.description)),
+                                    )
+                                    .filter(|(uri, name, _)| uri.contains(&search_word) || name.contains(&search_word));
+                                for (uri, name, description) in entries {
+                                    queue!(
+                                        self.output,
+                                        style::Print("- @"),
+                                        style::Print(&server.server_name),
+                                        style::Print(":"),
+                                        style::SetForegroundColor(Color::Green),
+                                        style::Print(uri),
+                                        style::SetForegroundColor(Color::DarkGrey),
+                                        style::Print(format!("  {}", name)),
+                                        style::Print(
+                                            description.as_ref().map(|d| format!(" - {}", d)).unwrap_or_default()
+                                        ),
+                                        style::SetForegroundColor(Color::Reset),
+                                        style::Print("\n"),
+                                    )?;
+                                }
+                            }
+                        },
+                        Err(err) => {
+                            queue!(
+                                self.output,
+                                style::SetForegroundColor(Color::Red),
+                                style::Print(format!("\nError: {}\n", err)),
+                                style::SetForegroundColor(Color::Reset),
+                            )?;
+                        },
+                    }
+                }
+                execute!(self.output, style::Print("\n"))?;
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
             Command::Usage => {
                 let state = self.conversation_state.backend_conversation_state(true, true).await;
 
@@ -2685,114 +3338,117 @@ impl ChatContext {
                 }
 
                 let data = state.calculate_conversation_size();
-
-                let context_token_count: TokenCount = data.context_messages.into();
-                let assistant_token_count: TokenCount = data.assistant_messages.into();
-                let user_token_count: TokenCount = data.user_messages.into();
-                let total_token_used: TokenCount =
-                    (data.context_messages + data.user_messages + data.assistant_messages).into();
+                let total_token_used = data.total();
+                let percentage = |count: TokenCount| (count.value() as f32 / CONTEXT_WINDOW_SIZE as f32) * 100.0;
+
+                let components = [
+                    ("Context files", Color::DarkCyan, data.context_files),
+                    ("Summary", Color::Cyan, data.summary),
+                    ("Hooks", Color::DarkYellow, data.hooks),
+                    ("Tools", Color::DarkGreen, data.tools),
+                    ("Q responses", Color::Blue, data.assistant_messages),
+                    ("Your prompts", Color::Magenta, data.user_messages),
+                ];
 
                 let window_width = self.terminal_width();
                 // set a max width for the progress bar for better aesthetic
                 let progress_bar_width = std::cmp::min(window_width, 80);
+                let widths = components.map(|(_, _, count)| {
+                    ((count.value() as f64 / CONTEXT_WINDOW_SIZE as f64) * progress_bar_width as f64) as usize
+                });
+                let used_width = widths.iter().sum::<usize>();
 
-                let context_width = ((context_token_count.value() as f64 / CONTEXT_WINDOW_SIZE as f64)
-                    * progress_bar_width as f64) as usize;
-                let assistant_width = ((assistant_token_count.value() as f64 / CONTEXT_WINDOW_SIZE as f64)
-                    * progress_bar_width as f64) as usize;
-                let user_width = ((user_token_count.value() as f64 / CONTEXT_WINDOW_SIZE as f64)
-                    * progress_bar_width as f64) as usize;
-
-                let left_over_width = progress_bar_width
-                    - std::cmp::min(context_width + assistant_width + user_width, progress_bar_width);
-
-                let is_overflow = (context_width + assistant_width + user_width) > progress_bar_width;
-
-                if is_overflow {
+                queue!(
+                    self.output,
+                    style::Print(format!(
+                        "\nCurrent context window ({} of {}k tokens used)\n",
+                        total_token_used,
+                        CONTEXT_WINDOW_SIZE / 1000
+                    )),
+                )?;
+                if used_width > progress_bar_width {
                     queue!(
                         self.output,
-                        style::Print(format!(
-                            "\nCurrent context window ({} of {}k tokens used)\n",
-                            total_token_used,
-                            CONTEXT_WINDOW_SIZE / 1000
-                        )),
                         style::SetForegroundColor(Color::DarkRed),
                         style::Print("█".repeat(progress_bar_width)),
-                        style::SetForegroundColor(Color::Reset),
-                        style::Print(" "),
-                        style::Print(format!(
-                            "{:.2}%",
-                            (total_token_used.value() as f32 / CONTEXT_WINDOW_SIZE as f32) * 100.0
-                        )),
                     )?;
                 } else {
+                    for ((_, color, count), width) in components.iter().zip(widths) {
+                        queue!(
+                            self.output,
+                            style::SetForegroundColor(*color),
+                            // add a nice visual to mimic "tiny" progress, so the overral progress bar doesn't look
+                            // too empty
+                            style::Print("|".repeat(if width == 0 && **count > 0 { 1 } else { 0 })),
+                            style::Print("█".repeat(width)),
+                        )?;
+                    }
                     queue!(
                         self.output,
-                        style::Print(format!(
-                            "\nCurrent context window ({} of {}k tokens used)\n",
-                            total_token_used,
-                            CONTEXT_WINDOW_SIZE / 1000
-                        )),
-                        style::SetForegroundColor(Color::DarkCyan),
-                        // add a nice visual to mimic "tiny" progress, so the overral progress bar doesn't look too
-                        // empty
-                        style::Print("|".repeat(if context_width == 0 && *context_token_count > 0 {
-                            1
-                        } else {
-                            0
-                        })),
-                        style::Print("█".repeat(context_width)),
-                        style::SetForegroundColor(Color::Blue),
-                        style::Print("|".repeat(if assistant_width == 0 && *assistant_token_count > 0 {
-                            1
-                        } else {
-                            0
-                        })),
-                        style::Print("█".repeat(assistant_width)),
-                        style::SetForegroundColor(Color::Magenta),
-                        style::Print("|".repeat(if user_width == 0 && *user_token_count > 0 { 1 } else { 0 })),
-                        style::Print("█".repeat(user_width)),
                         style::SetForegroundColor(Color::DarkGrey),
-                        style::Print("█".repeat(left_over_width)),
-                        style::Print(" "),
-                        style::SetForegroundColor(Color::Reset),
-                        style::Print(format!(
-                            "{:.2}%",
-                            (total_token_used.value() as f32 / CONTEXT_WINDOW_SIZE as f32) * 100.0
-                        )),
+                        style::Print("█".repeat(progress_bar_width - used_width)),
                     )?;
                 }
-
-                queue!(self.output, style::Print("\n\n"))?;
+                queue!(
+                    self.output,
+                    style::SetForegroundColor(Color::Reset),
+                    style::Print(format!(" {:.2}%\n\n", percentage(total_token_used))),
+                )?;
                 self.output.flush()?;
 
+                let label_width = components
+                    .iter()
+                    .map(|(label, _, _)| label.len())
+                    .max()
+                    .unwrap_or_default()
+                    + 1;
+                for (label, color, count) in components {
+                    // Only show the summary once the conversation was compacted.
+                    if label == "Summary" && *count == 0 {
+                        continue;
+                    }
+                    queue!(
+                        self.output,
+                        style::SetForegroundColor(color),
+                        style::Print(format!("█ {:<label_width$}", format!("{label}:"))),
+                        style::SetForegroundColor(Color::Reset),
+                        style::Print(format!(" {} tokens ({:.2}%)\n", count, percentage(count))),
+                    )?;
+                }
+                queue!(self.output, style::Print("\n"))?;
+
                 queue!(
                     self.output,
-                    style::SetForegroundColor(Color::DarkCyan),
-                    style::Print("█ Context files: "),
-                    style::SetForegroundColor(Color::Reset),
-                    style::Print(format!(
-                        "~{} tokens ({:.2}%)\n",
-                        context_token_count,
-                        (context_token_count.value() as f32 / CONTEXT_WINDOW_SIZE as f32) * 100.0
-                    )),
-                    style::SetForegroundColor(Color::Blue),
-                    style::Print("█ Q responses: "),
-                    style::SetForegroundColor(Color::Reset),
+                    style::SetAttribute(Attribute::Bold),
+                    style::Print("Compaction\n"),
+                    style::SetAttribute(Attribute::Reset),
                     style::Print(format!(
-                        "  ~{} tokens ({:.2}%)\n",
-                        assistant_token_count,
-                        (assistant_token_count.value() as f32 / CONTEXT_WINDOW_SIZE as f32) * 100.0
+                        "Strategy: {} at {} tokens ({:.0}%)\n",
+                        self.compaction.strategy,
+                        self.compaction.threshold,
+                        percentage(TokenCount::from(self.compaction.threshold))
                     )),
-                    style::SetForegroundColor(Color::Magenta),
-                    style::Print("█ Your prompts: "),
-                    style::SetForegroundColor(Color::Reset),
                     style::Print(format!(
-                        " ~{} tokens ({:.2}%)\n\n",
-                        user_token_count,
-                        (user_token_count.value() as f32 / CONTEXT_WINDOW_SIZE as f32) * 100.0
+                        "Last compaction: {}\n",
+                        self.conversation_state
+                            .last_compaction()
+                            .map_or("none".to_string(), |record| record.to_string())
                     )),
                 )?;
+                let pinned = self.conversation_state.pinned_prompts();
+                if !pinned.is_empty() {
+                    queue!(self.output, style::Print("Pinned prompts:\n"))?;
+                    for (n, prompt) in pinned {
+                        queue!(
+                            self.output,
+                            style::Print(format!("  {n}: ")),
+                            style::SetForegroundColor(Color::DarkGrey),
+                            style::Print(truncate_safe(prompt.lines().next().unwrap_or_default(), 80)),
+                            style::SetForegroundColor(Color::Reset),
+                            style::Print("\n"),
+                        )?;
+                    }
+                }
 
                 queue!(
                     self.output,
@@ -2920,178 +3576,499 @@ impl ChatContext {
                     skip_printing_tools: true,
                 }
             },
-        })
-    }
-
-    async fn tool_use_execute(
-        &mut self,
-        database: &Database,
-        telemetry: &TelemetryThread,
-        mut tool_uses: Vec<QueuedTool>,
-    ) -> Result<ChatState, ChatError> {
-        // Verify tools have permissions.
-        for (index, tool) in tool_uses.iter_mut().enumerate() {
-            // Manually accepted by the user or otherwise verified already.
-            if tool.accepted {
-                continue;
-            }
-
-            // If there is an override, we will use it. Otherwise fall back to Tool's default.
-            let allowed = self.tool_permissions.trust_all
-                || (self.tool_permissions.has(&tool.name) && self.tool_permissions.is_trusted(&tool.name))
-                || !tool.tool.requires_acceptance(&self.ctx);
-
-            if database
-                .settings
-                .get_bool(Setting::ChatEnableNotifications)
-                .unwrap_or(false)
-            {
-                play_notification_bell(!allowed);
-            }
-
-            self.print_tool_descriptions(tool, allowed).await?;
-
-            if allowed {
-                tool.accepted = true;
-                continue;
-            }
-
-            let pending_tool_index = Some(index);
-            if !self.interactive {
-                // Cannot request in non-interactive, so fail.
-                return Err(ChatError::NonInteractiveToolApproval);
-            }
-
-            return Ok(ChatState::PromptUser {
-                tool_uses: Some(tool_uses),
-                pending_tool_index,
-                skip_printing_tools: false,
-            });
-        }
-
-        // Execute the requested tools.
-        let mut tool_results = vec![];
-        let mut image_blocks: Vec<RichImageBlock> = Vec::new();
-
-        for tool in tool_uses {
-            let mut tool_telemetry = self.tool_use_telemetry_events.entry(tool.id.clone());
-            tool_telemetry = tool_telemetry.and_modify(|ev| ev.is_accepted = true);
-
-            let tool_start = std::time::Instant::now();
-            let invoke_result = tool.tool.invoke(&self.ctx, &mut self.output).await;
-
-            if self.interactive && self.spinner.is_some() {
-                queue!(
-                    self.output,
-                    terminal::Clear(terminal::ClearType::CurrentLine),
-                    cursor::MoveToColumn(0),
-                    cursor::Show
-                )?;
-            }
-            execute!(self.output, style::Print("\n"))?;
-
-            let tool_time = std::time::Instant::now().duration_since(tool_start);
-            if let Tool::Custom(ct) = &tool.tool {
-                tool_telemetry = tool_telemetry.and_modify(|ev| {
-                    ev.custom_tool_call_latency = Some(tool_time.as_secs() as usize);
-                    ev.input_token_size = Some(ct.get_input_token_size());
-                    ev.is_custom_tool = true;
-                });
-            }
-            let tool_time = format!("{}.{}", tool_time.as_secs(), tool_time.subsec_millis());
-            match invoke_result {
-                Ok(result) => {
-                    match result.output {
-                        OutputKind::Text(ref text) => {
-                            debug!("Output is Text: {}", text);
-                        },
-                        OutputKind::Json(ref json) => {
-                            debug!("Output is JSON: {}", json);
-                        },
-                        OutputKind::Images(ref image) => {
-                            image_blocks.extend(image.clone());
-                        },
-                    }
-
-                    debug!("tool result output: {:#?}", result);
+            Command::Knowledge { subcommand } => {
+                if !KnowledgeSearch::is_enabled(database) {
                     execute!(
                         self.output,
-                        style::Print(CONTINUATION_LINE),
-                        style::Print("\n"),
+                        style::SetForegroundColor(Color::Yellow),
+                        style::Print("\nKnowledge bases are a beta feature. Enable them with: "),
                         style::SetForegroundColor(Color::Green),
-                        style::SetAttribute(Attribute::Bold),
-                        style::Print(format!(" ● Completed in {}s", tool_time)),
-                        style::SetForegroundColor(Color::Reset),
-                        style::Print("\n"),
-                    )?;
-
-                    tool_telemetry = tool_telemetry.and_modify(|ev| ev.is_success = Some(true));
-                    if let Tool::Custom(_) = &tool.tool {
-                        tool_telemetry
-                            .and_modify(|ev| ev.output_token_size = Some(TokenCounter::count_tokens(result.as_str())));
-                    }
-                    tool_results.push(ToolUseResult {
-                        tool_use_id: tool.id,
-                        content: vec![result.into()],
-                        status: ToolResultStatus::Success,
-                    });
-                },
-                Err(err) => {
-                    error!(?err, "An error occurred processing the tool");
-                    execute!(
-                        self.output,
-                        style::Print(CONTINUATION_LINE),
-                        style::Print("\n"),
-                        style::SetAttribute(Attribute::Bold),
-                        style::SetForegroundColor(Color::Red),
-                        style::Print(format!(" ● Execution failed after {}s:\n", tool_time)),
-                        style::SetAttribute(Attribute::Reset),
-                        style::SetForegroundColor(Color::Red),
-                        style::Print(&err),
-                        style::SetAttribute(Attribute::Reset),
-                        style::Print("\n\n"),
+                        style::Print(format!("{CLI_BINARY_NAME} settings chat.enableKnowledge true\n\n")),
+                        style::SetForegroundColor(Color::Reset)
                     )?;
+                } else {
+                    self.handle_knowledge_command(subcommand).await?;
+                }
 
-                    tool_telemetry.and_modify(|ev| ev.is_success = Some(false));
-                    tool_results.push(ToolUseResult {
-                        tool_use_id: tool.id,
-                        content: vec![ToolUseResultBlock::Text(format!(
-                            "An error occurred processing the tool: \n{}",
-                            &err
-                        ))],
-                        status: ToolResultStatus::Error,
-                    });
-                    if let ToolUseStatus::Idle = self.tool_use_status {
-                        self.tool_use_status = ToolUseStatus::RetryInProgress(
-                            self.conversation_state
-                                .message_id()
-                                .map_or("No utterance id found".to_string(), |v| v.to_string()),
-                        );
-                    }
-                },
-            }
-        }
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Undo { count } => {
+                let result = self.checkpoints.undo(&self.ctx, count).await;
+                self.print_restored_files(result)?;
 
-        if !image_blocks.is_empty() {
-            let images = image_blocks.into_iter().map(|(block, _)| block).collect();
-            self.conversation_state
-                .add_tool_results_with_images(tool_results, images);
-            execute!(
-                self.output,
-                style::SetAttribute(Attribute::Reset),
-                style::SetForegroundColor(Color::Reset),
-                style::Print("\n")
-            )?;
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Checkpoint { subcommand } => {
+                match subcommand {
+                    CheckpointSubcommand::List => {
+                        let cwd = self.ctx.env().current_dir()?;
+                        self.checkpoints.print_list(&mut self.output, &cwd)?;
+                    },
+                    CheckpointSubcommand::Restore { id } => {
+                        let result = self.checkpoints.restore(&self.ctx, id).await;
+                        self.print_restored_files(result)?;
+                    },
+                    CheckpointSubcommand::Help => {
+                        queue!(
+                            self.output,
+                            style::Print("\n"),
+                            style::Print(CheckpointSubcommand::help_text()),
+                            style::Print("\n")
+                        )?;
+                    },
+                }
+                self.output.flush()?;
+
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Model { subcommand } => {
+                if let Err(err) = self.handle_model_command(subcommand).await {
+                    queue!(
+                        self.output,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(format!("\nError: {err}\n\n")),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                }
+                self.output.flush()?;
+
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Jobs { subcommand } => {
+                let result = match subcommand {
+                    JobsSubcommand::List => Ok(self.background_processes.print_list(&mut self.output).await?),
+                    JobsSubcommand::Output { id } => self.background_processes.print_output(id, &mut self.output).await,
+                    JobsSubcommand::Kill { id } => match self.background_processes.kill(id).await {
+                        Ok(status) => {
+                            queue!(
+                                self.output,
+                                style::SetForegroundColor(Color::Green),
+                                style::Print(format!("\nBackground process {id} {status}\n\n")),
+                                style::SetForegroundColor(Color::Reset),
+                            )?;
+                            Ok(())
+                        },
+                        Err(err) => Err(err),
+                    },
+                    JobsSubcommand::Help => {
+                        queue!(
+                            self.output,
+                            style::Print("\n"),
+                            style::Print(JobsSubcommand::help_text()),
+                            style::Print("\n")
+                        )?;
+                        Ok(())
+                    },
+                };
+                if let Err(err) = result {
+                    queue!(
+                        self.output,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(format!("\nError: {err}\n\n")),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                }
+                self.output.flush()?;
+
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+        })
+    }
+
+    async fn handle_model_command(&mut self, subcommand: ModelSubcommand) -> Result<(), ChatError> {
+        match subcommand {
+            ModelSubcommand::List => {
+                let models = self.client.list_models().await?;
+                let current = self.conversation_state.model();
+                queue!(self.output, style::Print("\n"))?;
+                for model in &models {
+                    if Some(model.as_str()) == current {
+                        queue!(
+                            self.output,
+                            style::SetForegroundColor(Color::Green),
+                            style::Print(format!("* {model}\n")),
+                            style::SetForegroundColor(Color::Reset),
+                        )?;
+                    } else {
+                        queue!(self.output, style::Print(format!("  {model}\n")))?;
+                    }
+                }
+                if current.is_none() {
+                    queue!(
+                        self.output,
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print("\nNo model selected, the endpoint's default model is used.\n"),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                }
+                queue!(self.output, style::Print("\n"))?;
+            },
+            ModelSubcommand::Set { model, default } => {
+                let models = self.client.list_models().await?;
+                if !models.contains(&model) {
+                    return Err(ChatError::Custom(
+                        format!(
+                            "Model '{}' is not available. Available models: {}",
+                            model,
+                            models.join(", ")
+                        )
+                        .into(),
+                    ));
+                }
+                if default {
+                    match self.conversation_state.context_manager.as_mut() {
+                        Some(context_manager) => {
+                            context_manager
+                                .set_default_model(Some(model.clone()))
+                                .await
+                                .map_err(|err| {
+                                    ChatError::Custom(format!("Failed to save the default model: {err}").into())
+                                })?;
+                        },
+                        None => return Err(ChatError::Custom("Profiles are not available".into())),
+                    }
+                }
+                self.conversation_state.set_model(Some(model.clone()));
+                queue!(
+                    self.output,
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(format!("\nSwitched to model: {model}\n\n")),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+            },
+            ModelSubcommand::Help => {
+                queue!(
+                    self.output,
+                    style::Print("\n"),
+                    style::Print(ModelSubcommand::help_text()),
+                    style::Print("\n")
+                )?;
+            },
+        }
+        Ok(())
+    }
+
+    async fn handle_knowledge_command(&mut self, subcommand: KnowledgeSubcommand) -> Result<(), ChatError> {
+        let store = match KnowledgeStore::get_instance(&self.ctx).await {
+            Ok(store) => store,
+            Err(err) => {
+                execute!(
+                    self.output,
+                    style::SetForegroundColor(Color::Red),
+                    style::Print(format!("\nError: Failed to load knowledge bases: {}\n\n", err)),
+                    style::SetForegroundColor(Color::Reset)
+                )?;
+                return Ok(());
+            },
+        };
+
+        let result = match subcommand {
+            KnowledgeSubcommand::Show => store.contexts().await.map(|contexts| {
+                if contexts.is_empty() {
+                    return "No knowledge bases have been added. Use /knowledge add <path> to index one.".to_string();
+                }
+                contexts.iter().fold(String::new(), |mut acc, context| {
+                    acc.push_str(&format!(
+                        "{} ({} items)\n  id: {}\n  path: {}\n",
+                        context.name,
+                        context.item_count,
+                        context.id,
+                        context.source_path.as_deref().unwrap_or("-"),
+                    ));
+                    acc
+                })
+            }),
+            KnowledgeSubcommand::Add { path, name } => {
+                let path = tools::sanitize_path_tool_arg(&self.ctx, &path);
+                if self.interactive {
+                    queue!(self.output, cursor::Hide)?;
+                    self.spinner = Some(Spinner::new(Spinners::Dots, format!("Indexing {}...", path.display())));
+                }
+                let result = store.add(&path, name).await;
+                if let Some(mut spinner) = self.spinner.take() {
+                    spinner.stop();
+                    queue!(
+                        self.output,
+                        terminal::Clear(terminal::ClearType::CurrentLine),
+                        cursor::MoveToColumn(0),
+                        cursor::Show
+                    )?;
+                }
+                result.map(|id| format!("Added {} to knowledge bases (id: {})", path.display(), id))
+            },
+            KnowledgeSubcommand::Remove { target } => store
+                .remove(target.clone())
+                .await
+                .map(|_| format!("Removed {} from knowledge bases", target)),
+            KnowledgeSubcommand::Update { target } => store.update(target).await.map(|updated| {
+                if updated.is_empty() {
+                    return "No knowledge bases have been added. Use /knowledge add <path> to index one.".to_string();
+                }
+                updated.iter().fold(String::new(), |mut acc, (context, summary)| {
+                    acc.push_str(&if summary.has_changes() {
+                        format!(
+                            "{}: {} added, {} modified, {} removed ({} items)\n",
+                            context.name, summary.added, summary.modified, summary.removed, context.item_count
+                        )
+                    } else {
+                        format!("{}: up to date\n", context.name)
+                    });
+                    acc
+                })
+            }),
+            KnowledgeSubcommand::Search { query, knowledge_base } => store
+                .search(query, knowledge_base, Default::default())
+                .await
+                .map(|results| {
+                    let mut text = String::new();
+                    for (context, results) in results {
+                        for result in results {
+                            text.push_str(&format!(
+                                "{} {} (score: {:.3})\n",
+                                context.name,
+                                result.location().unwrap_or_default(),
+                                result.score()
+                            ));
+                            if let Some(snippet) = result.text() {
+                                let snippet = snippet.lines().take(5).collect::<Vec<_>>().join("\n    ");
+                                text.push_str(&format!("    {}\n", snippet));
+                            }
+                        }
+                    }
+                    if text.is_empty() {
+                        text.push_str("No results found");
+                    }
+                    text
+                }),
+            KnowledgeSubcommand::Help => Ok(KnowledgeSubcommand::help_text()),
+        };
+
+        match result {
+            Ok(text) => {
+                execute!(self.output, style::Print(format!("\n{}\n\n", text.trim_end())))?;
+            },
+            Err(err) => {
+                execute!(
+                    self.output,
+                    style::SetForegroundColor(Color::Red),
+                    style::Print(format!("\nError: {}\n\n", err)),
+                    style::SetForegroundColor(Color::Reset)
+                )?;
+            },
+        }
+        Ok(())
+    }
+
+    fn print_restored_files(&mut self, result: Result<Vec<std::path::PathBuf>>) -> Result<(), ChatError> {
+        match result {
+            Ok(paths) => {
+                let cwd = self.ctx.env().current_dir()?;
+                queue!(
+                    self.output,
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(format!("\n✔ Restored {} file(s):\n", paths.len())),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+                for path in paths {
+                    let path = path.strip_prefix(&cwd).unwrap_or(&path);
+                    queue!(self.output, style::Print(format!("  {}\n", path.display())))?;
+                }
+                execute!(self.output, style::Print("\n"))?;
+            },
+            Err(err) => {
+                execute!(
+                    self.output,
+                    style::SetForegroundColor(Color::Red),
+                    style::Print(format!("\nError: {}\n\n", err)),
+                    style::SetForegroundColor(Color::Reset)
+                )?;
+            },
+        }
+        Ok(())
+    }
+
+    async fn tool_use_execute(
+        &mut self,
+        database: &Database,
+        telemetry: &TelemetryThread,
+        mut tool_uses: Vec<QueuedTool>,
+    ) -> Result<ChatState, ChatError> {
+        // Run pre_tool_use hooks, which may block a tool use or rewrite its input. They run before
+        // permissions are checked so that a rewritten tool use is what gets checked and accepted.
+        for tool in tool_uses.iter_mut().filter(|tool| !tool.pre_tool_use_ran) {
+            tool.pre_tool_use_ran = true;
+            let mut input = self.hook_input(HookTrigger::PreToolUse);
+            input.tool_name = Some(tool.name.clone());
+            input.tool_input = Some(tool.args.clone());
+            match self.run_lifecycle_hooks(input).await {
+                HookDecision::Allow { tool_input: None } => (),
+                HookDecision::Allow {
+                    tool_input: Some(tool_input),
+                } => {
+                    if let Err(err) = self.rewrite_tool_use(tool, tool_input).await {
+                        tool.blocked = Some(format!(
+                            "The tool input rewritten by the user's pre_tool_use hook is invalid: {err}"
+                        ));
+                    }
+                },
+                HookDecision::Block { hook, reason } => {
+                    tool.blocked = Some(format!(
+                        "The tool use was blocked by the user's pre_tool_use hook '{hook}': {reason}"
+                    ));
+                },
+            }
+        }
+
+        // Verify tools have permissions.
+        let mut denied = HashMap::new();
+        for (index, tool) in tool_uses.iter_mut().enumerate() {
+            // Manually accepted by the user or otherwise verified already.
+            if tool.accepted || tool.blocked.is_some() {
+                continue;
+            }
+
+            // If a permission rule matches, we will use it. Otherwise fall back to Tool's default.
+            let allowed = match self.tool_permissions.evaluate(&self.ctx, &tool.name, &tool.tool) {
+                PermissionDecision::Allow => true,
+                PermissionDecision::Ask => false,
+                PermissionDecision::Deny(rule) => {
+                    self.print_tool_descriptions(tool, false).await?;
+                    execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(format!("\nDenied by permission rule: {}\n\n", rule)),
+                        style::SetForegroundColor(Color::Reset)
+                    )?;
+                    denied.insert(tool.id.clone(), rule);
+                    continue;
+                },
+            };
+
+            if database
+                .settings
+                .get_bool(Setting::ChatEnableNotifications)
+                .unwrap_or(false)
+            {
+                play_notification_bell(!allowed);
+            }
+
+            self.print_tool_descriptions(tool, allowed).await?;
+
+            if allowed {
+                tool.accepted = true;
+                continue;
+            }
+
+            let pending_tool_index = Some(index);
+            if !self.interactive {
+                // Cannot request in non-interactive, so fail.
+                return Err(ChatError::NonInteractiveToolApproval);
+            }
+
+            return Ok(ChatState::PromptUser {
+                tool_uses: Some(tool_uses),
+                pending_tool_index,
+                skip_printing_tools: false,
+            });
+        }
+
+        // Execute the requested tools. Consecutive tool uses that can run concurrently are invoked
+        // together, everything else is invoked one at a time in the order requested.
+        let max_concurrent_tools = database
+            .settings
+            .get_int(Setting::ChatMaxConcurrentTools)
+            .map_or(DEFAULT_MAX_CONCURRENT_TOOLS, |v| v.max(1) as usize);
+        let mut tool_results = vec![];
+        let mut image_blocks: Vec<RichImageBlock> = Vec::new();
+
+        let mut tool_uses = tool_uses.into_iter().peekable();
+        while let Some(tool) = tool_uses.next() {
+            if let Some(rule) = denied.remove(&tool.id) {
+                tool_results.push(ToolUseResult {
+                    tool_use_id: tool.id,
+                    content: vec![ToolUseResultBlock::Text(format!(
+                        "The tool use was denied by the user's permission rule `{rule}`. Do not retry it with the same arguments."
+                    ))],
+                    status: ToolResultStatus::Error,
+                });
+                continue;
+            }
+            if let Some(reason) = tool.blocked {
+                tool_results.push(ToolUseResult {
+                    tool_use_id: tool.id,
+                    content: vec![ToolUseResultBlock::Text(reason)],
+                    status: ToolResultStatus::Error,
+                });
+                continue;
+            }
+
+            let mut batch = vec![tool];
+            if max_concurrent_tools > 1 && batch[0].tool.is_concurrency_safe() {
+                while let Some(next) = tool_uses
+                    .next_if(|t| t.tool.is_concurrency_safe() && !denied.contains_key(&t.id) && t.blocked.is_none())
+                {
+                    batch.push(next);
+                }
+            }
+
+            let results = if batch.len() > 1 {
+                self.invoke_tools_concurrently(&batch, max_concurrent_tools).await?
+            } else {
+                vec![self.invoke_tool(&batch[0]).await?]
+            };
+            for (tool, (invoke_result, tool_time)) in batch.into_iter().zip(results) {
+                let (name, args) = (tool.name.clone(), tool.args.clone());
+                let mut tool_result = self.record_tool_result(tool, invoke_result, tool_time, &mut image_blocks);
+                self.run_post_tool_use_hooks(name, args, &mut tool_result).await;
+                tool_results.push(tool_result);
+            }
+        }
+
+        self.emit_tool_results(&tool_results)?;
+        if !image_blocks.is_empty() {
+            let images = image_blocks.into_iter().map(|(block, _)| block).collect();
+            self.conversation_state
+                .add_tool_results_with_images(tool_results, images);
+            execute!(
+                self.output,
+                style::SetAttribute(Attribute::Reset),
+                style::SetForegroundColor(Color::Reset),
+                style::Print("\n")
+            )?;
         } else {
             self.conversation_state.add_tool_results(tool_results);
         }
+        self.send_tool_use_telemetry(telemetry).await;
+        if let Some(state) = self.compact_before_sending().await? {
+            return Ok(state);
+        }
+
         if self.interactive {
             execute!(self.output, cursor::Hide)?;
             execute!(self.output, style::Print("\n"), style::SetAttribute(Attribute::Reset))?;
             self.spinner = Some(Spinner::new(Spinners::Dots, "Thinking...".to_string()));
         }
 
-        self.send_tool_use_telemetry(telemetry).await;
         return Ok(ChatState::HandleResponseStream(
             self.client
                 .send_message(self.conversation_state.as_sendable_conversation_state(false).await)
@@ -3099,6 +4076,201 @@ impl ChatContext {
         ));
     }
 
+    /// Invokes a single tool use, printing its updates and outcome as it runs.
+    async fn invoke_tool(&mut self, tool: &QueuedTool) -> Result<(Result<InvokeOutput>, Duration), ChatError> {
+        let checkpoint_id = match &tool.tool {
+            Tool::FsWrite(fs_write) => {
+                let paths = fs_write.affected_paths(&self.ctx);
+                match self.checkpoints.record(&self.ctx, paths).await {
+                    Ok(id) => Some(id),
+                    Err(err) => {
+                        error!(?err, "Failed to record a checkpoint for tool use {}", tool.id);
+                        None
+                    },
+                }
+            },
+            _ => None,
+        };
+
+        let tool_start = std::time::Instant::now();
+        // MCP servers may send sampling requests while handling a tool call, which have to be
+        // answered before the call itself can complete.
+        let invoke_result = {
+            let mut sampling_output = self.output.clone();
+            let invoke = tool.tool.invoke(&self.ctx, &mut self.output);
+            tokio::pin!(invoke);
+            loop {
+                let request = match &mut self.sampling_requests {
+                    Some(requests) => tokio::select! {
+                        result = &mut invoke => break result,
+                        Some(request) = requests.recv() => request,
+                    },
+                    None => break (&mut invoke).await,
+                };
+                if let Some(mut spinner) = self.spinner.take() {
+                    spinner.stop();
+                }
+                sampling::handle_sampling_request(
+                    &mut sampling_output,
+                    &mut self.input_source,
+                    &self.client,
+                    self.tool_permissions.trust_all,
+                    self.interactive,
+                    request,
+                )
+                .await;
+            }
+        };
+        if let (Some(id), Err(_)) = (checkpoint_id, &invoke_result) {
+            self.checkpoints.discard(id);
+        }
+
+        if self.interactive && self.spinner.is_some() {
+            queue!(
+                self.output,
+                terminal::Clear(terminal::ClearType::CurrentLine),
+                cursor::MoveToColumn(0),
+                cursor::Show
+            )?;
+        }
+        execute!(self.output, style::Print("\n"))?;
+
+        let tool_time = tool_start.elapsed();
+        print_tool_outcome(&mut self.output, None, &invoke_result, tool_time)?;
+        Ok((invoke_result, tool_time))
+    }
+
+    /// Invokes tool uses concurrently, at most `limit` at a time.
+    ///
+    /// The updates of each tool are buffered and printed along with its own progress line once it
+    /// completes. Results are returned in the same order as `tools`.
+    async fn invoke_tools_concurrently(
+        &mut self,
+        tools: &[QueuedTool],
+        limit: usize,
+    ) -> Result<Vec<(Result<InvokeOutput>, Duration)>, ChatError> {
+        execute!(
+            self.output,
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print(format!("Running {} tools concurrently...\n\n", tools.len())),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+
+        let ctx = &self.ctx;
+        let mut invocations = futures::stream::iter(tools.iter().enumerate())
+            .map(|(index, tool)| async move {
+                let tool_start = std::time::Instant::now();
+                let mut updates = Vec::new();
+                let result = tool.tool.invoke(ctx, &mut updates).await;
+                (index, result, updates, tool_start.elapsed())
+            })
+            .buffer_unordered(limit);
+
+        let mut sampling_output = self.output.clone();
+        let mut results = Vec::with_capacity(tools.len());
+        loop {
+            let next = match &mut self.sampling_requests {
+                Some(requests) => tokio::select! {
+                    next = invocations.next() => next,
+                    Some(request) = requests.recv() => {
+                        sampling::handle_sampling_request(
+                            &mut sampling_output,
+                            &mut self.input_source,
+                            &self.client,
+                            self.tool_permissions.trust_all,
+                            self.interactive,
+                            request,
+                        )
+                        .await;
+                        continue;
+                    },
+                },
+                None => invocations.next().await,
+            };
+            let Some((index, result, updates, tool_time)) = next else {
+                break;
+            };
+
+            self.output.write_all(&updates)?;
+            let label = format!("[{}/{}] {}", index + 1, tools.len(), tools[index].name);
+            print_tool_outcome(&mut self.output, Some(&label), &result, tool_time)?;
+            results.push((index, result, tool_time));
+        }
+
+        results.sort_by_key(|(index, ..)| *index);
+        Ok(results
+            .into_iter()
+            .map(|(_, result, tool_time)| (result, tool_time))
+            .collect())
+    }
+
+    /// Records the telemetry of an invoked tool use and converts its result into the
+    /// [ToolUseResult] sent back to the model.
+    fn record_tool_result(
+        &mut self,
+        tool: QueuedTool,
+        invoke_result: Result<InvokeOutput>,
+        tool_time: Duration,
+        image_blocks: &mut Vec<RichImageBlock>,
+    ) -> ToolUseResult {
+        let mut tool_telemetry = self.tool_use_telemetry_events.entry(tool.id.clone());
+        tool_telemetry = tool_telemetry.and_modify(|ev| ev.is_accepted = true);
+        if let Tool::Custom(ct) = &tool.tool {
+            tool_telemetry = tool_telemetry.and_modify(|ev| {
+                ev.custom_tool_call_latency = Some(tool_time.as_secs() as usize);
+                ev.input_token_size = Some(ct.get_input_token_size());
+                ev.is_custom_tool = true;
+            });
+        }
+
+        match invoke_result {
+            Ok(result) => {
+                match result.output {
+                    OutputKind::Text(ref text) => {
+                        debug!("Output is Text: {}", text);
+                    },
+                    OutputKind::Json(ref json) => {
+                        debug!("Output is JSON: {}", json);
+                    },
+                    OutputKind::Images(ref image) => {
+                        image_blocks.extend(image.clone());
+                    },
+                }
+
+                debug!("tool result output: {:#?}", result);
+                tool_telemetry = tool_telemetry.and_modify(|ev| ev.is_success = Some(true));
+                if let Tool::Custom(_) = &tool.tool {
+                    tool_telemetry
+                        .and_modify(|ev| ev.output_token_size = Some(TokenCounter::count_tokens(result.as_str())));
+                }
+                ToolUseResult {
+                    tool_use_id: tool.id,
+                    content: vec![result.into()],
+                    status: ToolResultStatus::Success,
+                }
+            },
+            Err(err) => {
+                error!(?err, "An error occurred processing the tool");
+                tool_telemetry.and_modify(|ev| ev.is_success = Some(false));
+                if let ToolUseStatus::Idle = self.tool_use_status {
+                    self.tool_use_status = ToolUseStatus::RetryInProgress(
+                        self.conversation_state
+                            .message_id()
+                            .map_or("No utterance id found".to_string(), |v| v.to_string()),
+                    );
+                }
+                ToolUseResult {
+                    tool_use_id: tool.id,
+                    content: vec![ToolUseResultBlock::Text(format!(
+                        "An error occurred processing the tool: \n{}",
+                        &err
+                    ))],
+                    status: ToolResultStatus::Error,
+                }
+            },
+        }
+    }
+
     async fn handle_response(
         &mut self,
         database: &mut Database,
@@ -3114,6 +4286,8 @@ impl ChatContext {
 
         let mut tool_uses = Vec::new();
         let mut tool_name_being_recvd: Option<String> = None;
+        // Number of times the response was resumed after being cut off.
+        let mut resumes = 0;
 
         if self.interactive && self.spinner.is_some() {
             drop(self.spinner.take());
@@ -3140,6 +4314,9 @@ impl ChatContext {
                             tool_name_being_recvd = Some(name);
                         },
                         parser::ResponseEvent::AssistantText(text) => {
+                            if let Some(structured_output) = self.structured_output.as_mut() {
+                                structured_output.emit(OutputEvent::TextDelta { text: text.clone() })?;
+                            }
                             buf.push_str(&text);
                         },
                         parser::ResponseEvent::ToolUse(tool_use) => {
@@ -3152,6 +4329,13 @@ impl ChatContext {
                                     cursor::Show
                                 )?;
                             }
+                            if let Some(structured_output) = self.structured_output.as_mut() {
+                                structured_output.emit(OutputEvent::ToolUse {
+                                    id: tool_use.id.clone(),
+                                    name: tool_use.name.clone(),
+                                    input: tool_use.args.clone(),
+                                })?;
+                            }
                             tool_uses.push(tool_use);
                             tool_name_being_recvd = None;
                         },
@@ -3255,6 +4439,30 @@ impl ChatContext {
                                     .await?,
                             ));
                         },
+                        RecvErrorKind::Client(err)
+                            if err.is_retryable()
+                                && parser.can_resume()
+                                && resumes + 1 < self.client.retry_policy().max_attempts
+                                && self.client.retry_policy().can_wait(err.retry_after()) =>
+                        {
+                            // Ask for the rest of the response, which is appended to the text
+                            // already printed.
+                            resumes += 1;
+                            let delay = self.client.retry_policy().delay(resumes, err.retry_after());
+                            warn!(
+                                recv_error.request_id,
+                                ?err,
+                                ?delay,
+                                "The response stream was cut off, resuming it"
+                            );
+                            tokio::time::sleep(delay).await;
+                            let conv_state = self
+                                .conversation_state
+                                .as_resumed_conversation_state(parser.assistant_text())
+                                .await;
+                            parser.resume(self.client.send_message(conv_state).await?);
+                            continue;
+                        },
                         _ => return Err(recv_error.into()),
                     }
                 },
@@ -3277,8 +4485,9 @@ impl ChatContext {
                 )?;
             }
 
-            // Print the response for normal cases
-            loop {
+            // Print the response for normal cases. Structured output has already been emitted as
+            // the text was received.
+            while self.structured_output.is_none() {
                 let input = Partial::new(&buf[offset..]);
                 match interpret_markdown(input, &mut self.output, &mut state) {
                     Ok(parsed) => {
@@ -3305,6 +4514,7 @@ impl ChatContext {
             }
 
             if ended {
+                self.emit_usage().await?;
                 if let Some(message_id) = self.conversation_state.message_id() {
                     telemetry
                         .send_chat_added_message(
@@ -3348,6 +4558,8 @@ impl ChatContext {
 
         if !tool_uses.is_empty() {
             Ok(ChatState::ValidateTools(tool_uses))
+        } else if let Some(state) = self.run_stop_hooks().await? {
+            Ok(state)
         } else {
             Ok(ChatState::PromptUser {
                 tool_uses: None,
@@ -3357,6 +4569,185 @@ impl ChatContext {
         }
     }
 
+    /// Returns the input of a lifecycle hook, without any tool use set.
+    fn hook_input(&self, trigger: HookTrigger) -> HookInput {
+        let cwd = self.ctx.env().current_dir().unwrap_or_default();
+        HookInput::new(
+            trigger,
+            self.conversation_state.conversation_id(),
+            cwd.to_string_lossy(),
+        )
+    }
+
+    async fn run_lifecycle_hooks(&mut self, input: HookInput) -> HookDecision {
+        match self.conversation_state.context_manager.as_mut() {
+            Some(context_manager) => context_manager.run_lifecycle_hooks(input, &mut self.output).await,
+            None => HookDecision::Allow { tool_input: None },
+        }
+    }
+
+    /// Replaces the input of a queued tool use with the input rewritten by a pre_tool_use hook.
+    async fn rewrite_tool_use(&mut self, tool: &mut QueuedTool, args: serde_json::Value) -> Result<(), String> {
+        let tool_use = AssistantToolUse {
+            id: tool.id.clone(),
+            name: tool.name.clone(),
+            orig_name: tool.name.clone(),
+            args: args.clone(),
+            orig_args: args.clone(),
+        };
+        let mut rewritten = self
+            .conversation_state
+            .tool_manager
+            .get_tool_from_tool_use(tool_use)
+            .map_err(|err| {
+                ToolUseResult::from(err)
+                    .content
+                    .into_iter()
+                    .filter_map(|block| match block {
+                        ToolUseResultBlock::Text(text) => Some(text),
+                        ToolUseResultBlock::Json(_) => None,
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })?;
+        self.contextualize_tool(&mut rewritten);
+        rewritten.validate(&self.ctx).await.map_err(|err| err.to_string())?;
+
+        tool.tool = rewritten;
+        tool.args = args;
+        Ok(())
+    }
+
+    /// Runs the post_tool_use hooks of a completed tool use. Feedback from a hook that blocks is
+    /// sent to the model along with the tool result.
+    async fn run_post_tool_use_hooks(
+        &mut self,
+        tool_name: String,
+        tool_input: serde_json::Value,
+        tool_result: &mut ToolUseResult,
+    ) {
+        let mut input = self.hook_input(HookTrigger::PostToolUse);
+        input.tool_name = Some(tool_name);
+        input.tool_input = Some(tool_input);
+        input.set_tool_response(tool_result);
+
+        if let HookDecision::Block { hook, reason } = self.run_lifecycle_hooks(input).await {
+            tool_result.content.push(ToolUseResultBlock::Text(format!(
+                "Feedback from the user's post_tool_use hook '{hook}': {reason}"
+            )));
+        }
+    }
+
+    /// Runs the stop hooks at the end of an assistant turn. If a hook blocks, its reason is sent
+    /// to the model as a new message so that the turn keeps going.
+    async fn run_stop_hooks(&mut self) -> Result<Option<ChatState>, ChatError> {
+        let mut input = self.hook_input(HookTrigger::Stop);
+        input.stop_hook_active = Some(self.stop_hook_continuations > 0);
+        let HookDecision::Block { hook, reason } = self.run_lifecycle_hooks(input).await else {
+            self.stop_hook_continuations = 0;
+            return Ok(None);
+        };
+
+        if self.stop_hook_continuations >= MAX_STOP_HOOK_CONTINUATIONS {
+            execute!(
+                self.output,
+                style::SetForegroundColor(Color::Yellow),
+                style::Print(format!(
+                    "The stop hook '{hook}' kept the turn going {} times in a row, waiting for your input instead.\n\n",
+                    self.stop_hook_continuations
+                )),
+                style::SetForegroundColor(Color::Reset),
+            )?;
+            self.stop_hook_continuations = 0;
+            return Ok(None);
+        }
+        self.stop_hook_continuations += 1;
+
+        self.conversation_state
+            .set_next_user_message(format!(
+                "The user's stop hook '{hook}' prevented you from ending your turn: {reason}"
+            ))
+            .await;
+        if self.interactive {
+            execute!(self.output, cursor::Hide)?;
+            self.spinner = Some(Spinner::new(Spinners::Dots, "Thinking...".to_string()));
+        }
+        Ok(Some(ChatState::HandleResponseStream(
+            self.client
+                .send_message(self.conversation_state.as_sendable_conversation_state(false).await)
+                .await?,
+        )))
+    }
+
+    /// Returns [ChatState::CompactHistory] if the next request would come close to overflowing the
+    /// context window, so that the history is summarized before the request is sent rather than
+    /// after the service rejects it.
+    async fn compact_before_sending(&mut self) -> Result<Option<ChatState>, ChatError> {
+        if !self.conversation_state.can_create_summary_request().await {
+            return Ok(None);
+        }
+
+        let token_count = self.conversation_state.calculate_token_count().await;
+        if token_count.value() < self.compaction.threshold {
+            return Ok(None);
+        }
+
+        execute!(
+            self.output,
+            style::SetForegroundColor(Color::Yellow),
+            style::Print(format!(
+                "The context window is almost full ({} of {} tokens), compacting the history ({})...",
+                token_count, CONTEXT_WINDOW_SIZE, self.compaction.strategy
+            )),
+            style::SetAttribute(Attribute::Reset),
+            style::Print("\n\n"),
+        )?;
+
+        Ok(Some(ChatState::CompactHistory {
+            tool_uses: None,
+            pending_tool_index: None,
+            prompt: None,
+            show_summary: false,
+            help: false,
+            trigger: CompactionTrigger::Threshold,
+        }))
+    }
+
+    /// Emits the estimated context window usage as structured output, if enabled.
+    async fn emit_usage(&mut self) -> Result<(), ChatError> {
+        if self.structured_output.is_none() {
+            return Ok(());
+        }
+
+        let data = self
+            .conversation_state
+            .backend_conversation_state(false, true)
+            .await
+            .calculate_conversation_size();
+        if let Some(structured_output) = self.structured_output.as_mut() {
+            structured_output.emit(OutputEvent::Usage {
+                context_files_tokens: data.context_files.value(),
+                summary_tokens: data.summary.value(),
+                hooks_tokens: data.hooks.value(),
+                tools_tokens: data.tools.value(),
+                user_tokens: data.user_messages.value(),
+                assistant_tokens: data.assistant_messages.value(),
+                total_tokens: data.total().value(),
+                context_window_tokens: CONTEXT_WINDOW_SIZE,
+            })?;
+        }
+        Ok(())
+    }
+
+    fn emit_tool_results(&mut self, tool_results: &[ToolUseResult]) -> Result<(), ChatError> {
+        if let Some(structured_output) = self.structured_output.as_mut() {
+            for tool_result in tool_results {
+                structured_output.emit(tool_result.into())?;
+            }
+        }
+        Ok(())
+    }
+
     async fn validate_tools(
         &mut self,
         telemetry: &TelemetryThread,
@@ -3370,6 +4761,7 @@ impl ChatContext {
         for tool_use in tool_uses {
             let tool_use_id = tool_use.id.clone();
             let tool_use_name = tool_use.name.clone();
+            let tool_use_args = tool_use.args.clone();
             let mut tool_telemetry = ToolUseEventBuilder::new(conv_id.clone(), tool_use.id.clone())
                 .set_tool_use_id(tool_use_id.clone())
                 .set_tool_name(tool_use.name.clone())
@@ -3385,8 +4777,11 @@ impl ChatContext {
                             queued_tools.push(QueuedTool {
                                 id: tool_use_id.clone(),
                                 name: tool_use_name,
+                                args: tool_use_args,
                                 tool,
                                 accepted: false,
+                                pre_tool_use_ran: false,
+                                blocked: None,
                             });
                         },
                         Err(err) => {
@@ -3412,6 +4807,7 @@ impl ChatContext {
         // If we have any validation errors, then return them immediately to the model.
         if !tool_results.is_empty() {
             debug!(?tool_results, "Error found in the model tools");
+            self.emit_tool_results(&tool_results)?;
             queue!(
                 self.output,
                 style::SetAttribute(Attribute::Bold),
@@ -3440,6 +4836,9 @@ impl ChatContext {
             }
             self.conversation_state.add_tool_results(tool_results);
             self.send_tool_use_telemetry(telemetry).await;
+            if let Some(state) = self.compact_before_sending().await? {
+                return Ok(state);
+            }
             if let ToolUseStatus::Idle = self.tool_use_status {
                 self.tool_use_status = ToolUseStatus::RetryInProgress(
                     self.conversation_state
@@ -3463,7 +4862,6 @@ impl ChatContext {
     // output from Amazon Q.
     // TODO: Is there a better way?
     fn contextualize_tool(&self, tool: &mut Tool) {
-        #[allow(clippy::single_match)]
         match tool {
             Tool::GhIssue(gh_issue) => {
                 gh_issue.set_context(GhIssueContext {
@@ -3473,10 +4871,32 @@ impl ChatContext {
                     context_manager: self.conversation_state.context_manager.clone(),
                     transcript: self.conversation_state.transcript.clone(),
                     failed_request_ids: self.failed_request_ids.clone(),
-                    tool_permissions: self.tool_permissions.permissions.clone(),
+                    tool_permissions: self
+                        .tool_permissions
+                        .policy
+                        .rules()
+                        .map(|(_, rule)| rule.clone())
+                        .collect(),
                     interactive: self.interactive,
                 });
             },
+            Tool::Delegate(delegate) => {
+                delegate.context = Some(DelegateContext {
+                    ctx: Arc::clone(&self.ctx),
+                    client: self.client.clone(),
+                    tool_config: self.conversation_state.tool_manager.schema.clone(),
+                    tool_permissions: self.tool_permissions.clone(),
+                    profile: self.conversation_state.current_profile().map(str::to_string),
+                    model: self.conversation_state.model().map(str::to_string),
+                    sandbox: self.sandbox.clone(),
+                    context_manager: self.conversation_state.context_manager.clone(),
+                });
+            },
+            Tool::ExecuteBash(execute_bash) => {
+                execute_bash.sandbox = self.sandbox.clone();
+                execute_bash.processes = Some(self.background_processes.clone());
+            },
+            Tool::BashProcess(bash_process) => bash_process.processes = Some(self.background_processes.clone()),
             _ => (),
         };
     }
@@ -3603,11 +5023,53 @@ impl ChatContext {
     }
 }
 
+/// Prints whether an invoked tool use succeeded and how long it took, prefixed by `label` if the
+/// tool ran alongside others.
+fn print_tool_outcome(
+    output: &mut impl Write,
+    label: Option<&str>,
+    result: &Result<InvokeOutput>,
+    tool_time: Duration,
+) -> Result<(), ChatError> {
+    let tool_time = format!("{}.{}", tool_time.as_secs(), tool_time.subsec_millis());
+    let label = label.map(|label| format!("{label}: ")).unwrap_or_default();
+    match result {
+        Ok(_) => execute!(
+            output,
+            style::Print(CONTINUATION_LINE),
+            style::Print("\n"),
+            style::SetForegroundColor(Color::Green),
+            style::SetAttribute(Attribute::Bold),
+            style::Print(format!(" ● {label}Completed in {}s", tool_time)),
+            style::SetForegroundColor(Color::Reset),
+            style::SetAttribute(Attribute::Reset),
+            style::Print("\n"),
+        )?,
+        Err(err) => execute!(
+            output,
+            style::Print(CONTINUATION_LINE),
+            style::Print("\n"),
+            style::SetAttribute(Attribute::Bold),
+            style::SetForegroundColor(Color::Red),
+            style::Print(format!(" ● {label}Execution failed after {}s:\n", tool_time)),
+            style::SetAttribute(Attribute::Reset),
+            style::SetForegroundColor(Color::Red),
+            style::Print(err),
+            style::SetAttribute(Attribute::Reset),
+            style::Print("\n\n"),
+        )?,
+    }
+    Ok(())
+}
+
 /// Prints hook configuration grouped by trigger: conversation session start or per user message
 fn print_hook_section(output: &mut impl Write, hooks: &HashMap<String, Hook>, trigger: HookTrigger) -> Result<()> {
     let section = match trigger {
         HookTrigger::ConversationStart => "On Session Start",
         HookTrigger::PerPrompt => "Per User Message",
+        HookTrigger::PreToolUse => "Before Tool Use",
+        HookTrigger::PostToolUse => "After Tool Use",
+        HookTrigger::Stop => "On Turn End",
     };
     let hooks: Vec<(&String, &Hook)> = hooks.iter().filter(|(_, h)| h.trigger == trigger).collect();
 
@@ -3627,6 +5089,10 @@ fn print_hook_section(output: &mut impl Write, hooks: &HashMap<String, Hook>, tr
         )?;
     } else {
         for (name, hook) in hooks {
+            let name = match &hook.matcher {
+                Some(matcher) => format!("{name} ({matcher})"),
+                None => name.clone(),
+            };
             if hook.disabled {
                 queue!(
                     output,
@@ -3694,35 +5160,376 @@ fn create_stream(model_responses: serde_json::Value) -> StreamingClient {
                 other => panic!("Unexpected value: {:?}", other),
             }
         }
-        mock.push(stream);
+        mock.push(stream);
+    }
+    StreamingClient::mock(mock)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::Env;
+
+    #[test]
+    fn test_mcp_roots() {
+        let roots = mcp_roots(
+            ["/repo", "/docs", "/repo", "relative", "/docs"]
+                .into_iter()
+                .map(PathBuf::from)
+                .collect(),
+        );
+        assert_eq!(roots.iter().map(|root| root.uri.as_str()).collect::<Vec<_>>(), vec![
+            "file:///repo/",
+            "file:///docs/"
+        ]);
+        assert_eq!(roots[0].name.as_deref(), Some("repo"));
+    }
+
+    #[tokio::test]
+    async fn test_flow() {
+        // let _ = tracing_subscriber::fmt::try_init();
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let test_client = create_stream(serde_json::json!([
+            [
+                "Sure, I'll create a file for you",
+                {
+                    "tool_use_id": "1",
+                    "name": "fs_write",
+                    "args": {
+                        "command": "create",
+                        "file_text": "Hello, world!",
+                        "path": "/file.txt",
+                    }
+                }
+            ],
+            [
+                "Hope that looks good to you!",
+            ],
+        ]));
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        ChatContext::new(
+            Arc::clone(&ctx),
+            "fake_conv_id",
+            SharedWriter::stdout(),
+            None,
+            InputSource::new_mock(vec![
+                "create a new file".to_string(),
+                "y".to_string(),
+                "exit".to_string(),
+            ]),
+            true,
+            None,
+            test_client,
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            ToolPermissions::new(0),
+        )
+        .await
+        .unwrap()
+        .try_chat(&mut database, &telemetry)
+        .await
+        .unwrap();
+
+        assert_eq!(ctx.fs().read_to_string("/file.txt").await.unwrap(), "Hello, world!\n");
+    }
+
+    #[tokio::test]
+    async fn test_flow_structured_output() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let test_client = create_stream(serde_json::json!([
+            [
+                "Creating it",
+                {
+                    "tool_use_id": "1",
+                    "name": "fs_write",
+                    "args": {
+                        "command": "create",
+                        "file_text": "Hello, world!",
+                        "path": "/file.txt",
+                    }
+                }
+            ],
+            [
+                "Done",
+            ],
+            [
+                "Second answer",
+            ],
+        ]));
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let mut tool_permissions = ToolPermissions::new(0);
+        tool_permissions.trust_all = true;
+        let mut chat = ChatContext::new(
+            Arc::clone(&ctx),
+            "fake_conv_id",
+            SharedWriter::null(),
+            Some("create a new file".to_string()),
+            InputSource::new_mock(vec![]),
+            false,
+            None,
+            test_client,
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            tool_permissions,
+        )
+        .await
+        .unwrap();
+        let sink = util::shared_writer::TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(Vec::new())),
+        };
+        chat.structured_output = Some(StructuredOutput::new(
+            cli::ChatOutputFormat::StreamJson,
+            SharedWriter::new(sink.clone()),
+            Some(Box::new(std::io::Cursor::new(
+                "{\"type\":\"user_message\",\"content\":\"and now?\"}\n",
+            ))),
+        ));
+        chat.try_chat(&mut database, &telemetry).await.unwrap();
+        chat.structured_output
+            .as_mut()
+            .unwrap()
+            .finish(chat.conversation_state.conversation_id())
+            .unwrap();
+
+        assert_eq!(ctx.fs().read_to_string("/file.txt").await.unwrap(), "Hello, world!\n");
+        let events = String::from_utf8(sink.get_content())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        let types = events
+            .iter()
+            .map(|event| event["type"].as_str().unwrap().to_string())
+            .filter(|ty| ty != "text_delta" && ty != "usage")
+            .collect::<Vec<_>>();
+        assert_eq!(types, vec![
+            "init",
+            "user_message",
+            "tool_use",
+            "tool_result",
+            "user_message",
+            "result"
+        ]);
+        let result = events.last().unwrap();
+        assert_eq!(result["message"], "Second answer");
+        assert_eq!(result["num_turns"], 2);
+        assert_eq!(result["is_error"], false);
+    }
+
+    #[tokio::test]
+    async fn test_structured_errors() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let mut database = Database::new().await.unwrap();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let mut chat = ChatContext::new(
+            Arc::clone(&ctx),
+            "fake_conv_id",
+            SharedWriter::null(),
+            None,
+            InputSource::new_mock(vec![]),
+            false,
+            None,
+            StreamingClient::mock(vec![]),
+            || Some(80),
+            ToolManager::default(),
+            None,
+            tool_config,
+            ToolPermissions::new(0),
+        )
+        .await
+        .unwrap();
+        let sink = util::shared_writer::TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(Vec::new())),
+        };
+        chat.structured_output = Some(StructuredOutput::new(
+            cli::ChatOutputFormat::StreamJson,
+            SharedWriter::new(sink.clone()),
+            None,
+        ));
+        for prompt in ["first", "second"] {
+            chat.conversation_state.set_next_user_message(prompt.to_string()).await;
+            chat.conversation_state.push_assistant_message(
+                AssistantMessage::new_response(None, "answer".to_string()),
+                &mut database,
+            );
+        }
+
+        // An overflowing context window that is compacted is not an error.
+        let state = chat
+            .handle_state_execution_result(
+                &mut database,
+                Err(ChatError::Client(
+                    crate::api_client::ApiClientError::ContextWindowOverflow,
+                )),
+            )
+            .await
+            .unwrap();
+        assert!(matches!(state, ChatState::CompactHistory { .. }));
+        assert!(!chat.structured_output.as_ref().unwrap().is_error());
+
+        chat.handle_state_execution_result(&mut database, Err(ChatError::Custom("failed".into())))
+            .await
+            .unwrap();
+        assert!(chat.structured_output.as_ref().unwrap().is_error());
+        let errors = String::from_utf8(sink.get_content())
+            .unwrap()
+            .lines()
+            .filter(|line| line.contains("\"type\":\"error\""))
+            .count();
+        assert_eq!(errors, 1);
     }
-    StreamingClient::mock(mock)
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::platform::Env;
+    #[tokio::test]
+    async fn test_flow_concurrent_tools() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        for i in 1..=3 {
+            ctx.fs()
+                .write(format!("/file{i}.txt"), format!("contents {i}"))
+                .await
+                .unwrap();
+        }
+        let read = |id: &str, path: &str| {
+            serde_json::json!({
+                "tool_use_id": id,
+                "name": "fs_read",
+                "args": { "mode": "Line", "path": path }
+            })
+        };
+        let test_client = create_stream(serde_json::json!([
+            [
+                "Reading the files",
+                read("1", "/file1.txt"),
+                read("2", "/file2.txt"),
+                read("3", "/file3.txt"),
+            ],
+            ["Done",],
+        ]));
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let output = util::shared_writer::TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(Vec::new())),
+        };
+        let mut chat = ChatContext::new(
+            Arc::clone(&ctx),
+            "fake_conv_id",
+            SharedWriter::new(output.clone()),
+            Some("read the files".to_string()),
+            InputSource::new_mock(vec![]),
+            false,
+            None,
+            test_client,
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            ToolPermissions::new(0),
+        )
+        .await
+        .unwrap();
+        let sink = util::shared_writer::TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(Vec::new())),
+        };
+        chat.structured_output = Some(StructuredOutput::new(
+            cli::ChatOutputFormat::StreamJson,
+            SharedWriter::new(sink.clone()),
+            None,
+        ));
+        chat.try_chat(&mut database, &telemetry).await.unwrap();
+
+        let output = String::from_utf8(output.get_content()).unwrap();
+        assert!(output.contains("Running 3 tools concurrently"));
+        assert!(output.contains("[1/3] fs_read: Completed"));
+        assert!(output.contains("[2/3] fs_read: Completed"));
+        assert!(output.contains("[3/3] fs_read: Completed"));
+
+        // Results are sent back in the order the tools were requested.
+        let results = String::from_utf8(sink.get_content())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .filter(|event| event["type"] == "tool_result")
+            .collect::<Vec<_>>();
+        let ids = results
+            .iter()
+            .map(|r| r["tool_use_id"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["1", "2", "3"]);
+        assert_eq!(results[0]["content"][0], "contents 1");
+        assert_eq!(results[1]["content"][0], "contents 2");
+        assert_eq!(results[2]["content"][0], "contents 3");
+    }
 
     #[tokio::test]
-    async fn test_flow() {
-        // let _ = tracing_subscriber::fmt::try_init();
+    async fn test_flow_lifecycle_hooks() {
         let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let config = serde_json::json!({
+            "hooks": {
+                "no_writes": {
+                    "trigger": "pre_tool_use",
+                    "type": "inline",
+                    "matcher": "fs_write",
+                    "command": "echo 'writes are not allowed' >&2; exit 2"
+                },
+                "lint": {
+                    "trigger": "post_tool_use",
+                    "type": "inline",
+                    "command": "echo 'lint failed' >&2; exit 2"
+                }
+            }
+        });
+        let config_path = context::profile_context_path(&ctx, "default").unwrap();
+        ctx.fs().create_dir_all(config_path.parent().unwrap()).await.unwrap();
+        ctx.fs()
+            .write(&config_path, serde_json::to_string(&config).unwrap())
+            .await
+            .unwrap();
+        ctx.fs().write("/file.txt", "contents").await.unwrap();
+
         let test_client = create_stream(serde_json::json!([
             [
-                "Sure, I'll create a file for you",
+                "Writing and reading",
                 {
                     "tool_use_id": "1",
                     "name": "fs_write",
                     "args": {
                         "command": "create",
                         "file_text": "Hello, world!",
-                        "path": "/file.txt",
+                        "path": "/new.txt",
                     }
+                },
+                {
+                    "tool_use_id": "2",
+                    "name": "fs_read",
+                    "args": { "mode": "Line", "path": "/file.txt" }
                 }
             ],
             [
-                "Hope that looks good to you!",
+                "Done",
             ],
         ]));
 
@@ -3733,25 +5540,130 @@ mod tests {
         let tool_manager = ToolManager::default();
         let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
             .expect("Tools failed to load");
-        ChatContext::new(
+        let mut tool_permissions = ToolPermissions::new(0);
+        tool_permissions.trust_all = true;
+        let mut chat = ChatContext::new(
             Arc::clone(&ctx),
-            &mut database,
             "fake_conv_id",
-            SharedWriter::stdout(),
+            SharedWriter::null(),
+            Some("write a file".to_string()),
+            InputSource::new_mock(vec![]),
+            false,
             None,
-            InputSource::new_mock(vec![
-                "create a new file".to_string(),
-                "y".to_string(),
-                "exit".to_string(),
-            ]),
-            true,
+            test_client,
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            tool_permissions,
+        )
+        .await
+        .unwrap();
+        let sink = util::shared_writer::TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(Vec::new())),
+        };
+        chat.structured_output = Some(StructuredOutput::new(
+            cli::ChatOutputFormat::StreamJson,
+            SharedWriter::new(sink.clone()),
+            None,
+        ));
+        chat.try_chat(&mut database, &telemetry).await.unwrap();
+
+        assert!(!ctx.fs().exists("/new.txt"));
+        let results = String::from_utf8(sink.get_content())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .filter(|event| event["type"] == "tool_result")
+            .collect::<Vec<_>>();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0]["is_error"], true);
+        assert!(
+            results[0]["content"][0]
+                .as_str()
+                .unwrap()
+                .contains("blocked by the user's pre_tool_use hook 'no_writes': writes are not allowed")
+        );
+        assert_eq!(results[1]["is_error"], false);
+        assert_eq!(results[1]["content"][0], "contents");
+        assert_eq!(
+            results[1]["content"][1],
+            "Feedback from the user's post_tool_use hook 'lint': lint failed"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_flow_delegate_runs_lifecycle_hooks() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let marker = std::env::temp_dir().join(format!("q_hook_delegate_{}", std::process::id()));
+        let _ = std::fs::remove_file(&marker);
+        let config = serde_json::json!({
+            "hooks": {
+                "no_reads": {
+                    "trigger": "pre_tool_use",
+                    "type": "inline",
+                    "matcher": "fs_read",
+                    "command": format!("cat > {}; echo 'reads are not allowed' >&2; exit 2", marker.display())
+                }
+            }
+        });
+        let config_path = context::profile_context_path(&ctx, "default").unwrap();
+        ctx.fs().create_dir_all(config_path.parent().unwrap()).await.unwrap();
+        ctx.fs()
+            .write(&config_path, serde_json::to_string(&config).unwrap())
+            .await
+            .unwrap();
+        ctx.fs().write("/file.txt", "contents").await.unwrap();
+
+        // The parent and the sub-agent share the mocked responses.
+        let test_client = create_stream(serde_json::json!([
+            [
+                "Delegating",
+                {
+                    "tool_use_id": "1",
+                    "name": "delegate",
+                    "args": { "tasks": [{ "description": "read", "prompt": "Read /file.txt" }] }
+                }
+            ],
+            [
+                "Reading",
+                {
+                    "tool_use_id": "2",
+                    "name": "fs_read",
+                    "args": { "mode": "Line", "path": "/file.txt" }
+                }
+            ],
+            [
+                "The read was blocked",
+            ],
+            [
+                "Done",
+            ],
+        ]));
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let mut tool_permissions = ToolPermissions::new(0);
+        tool_permissions.trust_all = true;
+        ChatContext::new(
+            Arc::clone(&ctx),
+            "fake_conv_id",
+            SharedWriter::null(),
+            Some("read the file".to_string()),
+            InputSource::new_mock(vec![]),
             false,
+            None,
             test_client,
             || Some(80),
             tool_manager,
             None,
             tool_config,
-            ToolPermissions::new(0),
+            tool_permissions,
         )
         .await
         .unwrap()
@@ -3759,7 +5671,87 @@ mod tests {
         .await
         .unwrap();
 
-        assert_eq!(ctx.fs().read_to_string("/file.txt").await.unwrap(), "Hello, world!\n");
+        let input = std::fs::read_to_string(&marker).unwrap();
+        let _ = std::fs::remove_file(&marker);
+        let input = serde_json::from_str::<serde_json::Value>(&input).unwrap();
+        assert_eq!(input["hook_event_name"], "pre_tool_use");
+        assert_eq!(input["tool_name"], "fs_read");
+    }
+
+    #[tokio::test]
+    async fn test_flow_rewritten_tool_use_checks_permissions() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let marker = std::env::temp_dir().join(format!("q_hook_rewrite_{}", std::process::id()));
+        let _ = std::fs::remove_file(&marker);
+        let rewritten = serde_json::json!({
+            "tool_input": { "command": format!("touch {}", marker.display()) }
+        });
+        let config = serde_json::json!({
+            "hooks": {
+                "rewrite": {
+                    "trigger": "pre_tool_use",
+                    "type": "inline",
+                    "matcher": "execute_bash",
+                    "command": format!("echo '{}'", rewritten)
+                }
+            }
+        });
+        let config_path = context::profile_context_path(&ctx, "default").unwrap();
+        ctx.fs().create_dir_all(config_path.parent().unwrap()).await.unwrap();
+        ctx.fs()
+            .write(&config_path, serde_json::to_string(&config).unwrap())
+            .await
+            .unwrap();
+
+        let test_client = create_stream(serde_json::json!([
+            [
+                "Listing files",
+                {
+                    "tool_use_id": "1",
+                    "name": "execute_bash",
+                    "args": { "command": "ls" }
+                }
+            ],
+            [
+                "Done",
+            ],
+        ]));
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let out = util::shared_writer::TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(Vec::new())),
+        };
+        let mut chat = ChatContext::new(
+            Arc::clone(&ctx),
+            "fake_conv_id",
+            SharedWriter::new(out.clone()),
+            Some("list the files".to_string()),
+            InputSource::new_mock(vec![]),
+            false,
+            None,
+            test_client,
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            ToolPermissions::new(0),
+        )
+        .await
+        .unwrap();
+
+        // `ls` would run without asking, but the command it is rewritten to needs approval, which
+        // cannot be given in non-interactive mode.
+        chat.try_chat(&mut database, &telemetry).await.unwrap();
+        let output = String::from_utf8(out.get_content()).unwrap();
+        assert!(output.contains("rewrote the tool input"));
+        assert!(output.contains("Tool approval required"));
+        assert!(!marker.exists());
     }
 
     #[tokio::test]
@@ -3868,7 +5860,6 @@ mod tests {
             .expect("Tools failed to load");
         ChatContext::new(
             Arc::clone(&ctx),
-            &mut database,
             "fake_conv_id",
             SharedWriter::stdout(),
             None,
@@ -3891,7 +5882,7 @@ mod tests {
                 "exit".to_string(),
             ]),
             true,
-            false,
+            None,
             test_client,
             || Some(80),
             tool_manager,
@@ -3976,7 +5967,6 @@ mod tests {
             .expect("Tools failed to load");
         ChatContext::new(
             Arc::clone(&ctx),
-            &mut database,
             "fake_conv_id",
             SharedWriter::stdout(),
             None,
@@ -3990,7 +5980,7 @@ mod tests {
                 "exit".to_string(),
             ]),
             true,
-            false,
+            None,
             test_client,
             || Some(80),
             tool_manager,
@@ -4056,7 +6046,6 @@ mod tests {
             .expect("Tools failed to load");
         ChatContext::new(
             Arc::clone(&ctx),
-            &mut database,
             "fake_conv_id",
             SharedWriter::stdout(),
             None,
@@ -4068,7 +6057,7 @@ mod tests {
                 "exit".to_string(),
             ]),
             true,
-            false,
+            None,
             test_client,
             || Some(80),
             tool_manager,
@@ -4086,6 +6075,67 @@ mod tests {
         assert!(!ctx.fs().exists("/file2.txt"));
     }
 
+    #[tokio::test]
+    async fn test_flow_trust_all_non_interactive() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let test_client = create_stream(serde_json::json!([
+            [
+                "Checking",
+                {
+                    "tool_use_id": "1",
+                    "name": "execute_bash",
+                    "args": {
+                        "command": "echo $(echo hello)",
+                    }
+                }
+            ],
+            [
+                "Done",
+            ],
+        ]));
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let mut tool_permissions = ToolPermissions::new(0);
+        tool_permissions.trust_all = true;
+        let mut chat = ChatContext::new(
+            Arc::clone(&ctx),
+            "fake_conv_id",
+            SharedWriter::null(),
+            Some("say hello".to_string()),
+            InputSource::new_mock(vec![]),
+            false,
+            None,
+            test_client,
+            || Some(80),
+            tool_manager,
+            None,
+            tool_config,
+            tool_permissions,
+        )
+        .await
+        .unwrap();
+        let sink = util::shared_writer::TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(Vec::new())),
+        };
+        chat.structured_output = Some(StructuredOutput::new(
+            cli::ChatOutputFormat::StreamJson,
+            SharedWriter::new(sink.clone()),
+            None,
+        ));
+        chat.try_chat(&mut database, &telemetry).await.unwrap();
+
+        // Commands hidden from the permission rules still run without approval.
+        assert!(!chat.structured_output.as_ref().unwrap().is_error());
+        let output = String::from_utf8(sink.get_content()).unwrap();
+        assert!(output.contains("\"type\":\"tool_result\""), "{output}");
+    }
+
     #[test]
     fn test_editor_content_processing() {
         // Since we no longer have template replacement, this test is simplified
@@ -94,6 +94,8 @@ pub struct ResponseParser {
     /// Whether or not we are currently receiving tool use delta events. Tuple of
     /// `Some((tool_use_id, name))` if true, [None] otherwise.
     parsing_tool_use: Option<(String, String)>,
+    /// Whether a tool use event was received, after which the response can no longer be resumed.
+    received_tool_use: bool,
 }
 
 impl ResponseParser {
@@ -107,9 +109,31 @@ impl ResponseParser {
             assistant_text: String::new(),
             tool_uses: Vec::new(),
             parsing_tool_use: None,
+            received_tool_use: false,
         }
     }
 
+    /// Whether the response can be resumed with [Self::resume] after failing to receive it. Only
+    /// responses cut off while receiving text can be resumed.
+    pub fn can_resume(&self) -> bool {
+        !self.received_tool_use
+    }
+
+    /// The assistant text received so far.
+    pub fn assistant_text(&self) -> &str {
+        &self.assistant_text
+    }
+
+    /// Continues parsing from `response`, the rest of a response that was cut off. The text
+    /// received from it is appended to [Self::assistant_text], so that the final message holds
+    /// the whole response.
+    pub fn resume(&mut self, response: SendMessageOutput) {
+        debug_assert!(self.can_resume());
+        info!(message_id = ?self.message_id, "Resuming the response");
+        self.response = response;
+        self.peek = None;
+    }
+
     /// Consumes the associated [ConverseStreamResponse] until a valid [ResponseEvent] is parsed.
     pub async fn recv(&mut self) -> Result<ResponseEvent, RecvError> {
         if let Some((id, name)) = self.parsing_tool_use.take() {
@@ -156,6 +180,7 @@ impl ResponseParser {
                             "Unexpected immediate stop in first tool use event"
                         );
                         self.parsing_tool_use = Some((tool_use_id.clone(), name.clone()));
+                        self.received_tool_use = true;
                         return Ok(ResponseEvent::ToolUseStart { name });
                     },
                     _ => {},
@@ -382,4 +407,34 @@ mod tests {
             println!("{:?}", parser.recv().await.unwrap());
         }
     }
+
+    #[tokio::test]
+    async fn test_resume() {
+        let text = |content: &str| ChatResponseStream::AssistantResponseEvent {
+            content: content.to_string(),
+        };
+        let mut parser = ResponseParser::new(SendMessageOutput::Mock(vec![text(" there"), text("hi")]));
+        for _ in 0..2 {
+            parser.recv().await.unwrap();
+        }
+        assert_eq!(parser.assistant_text(), "hi there");
+        assert!(parser.can_resume());
+
+        // The stream was cut off, the rest of the response is received from a new one.
+        parser.resume(SendMessageOutput::Mock(vec![text(", how are you?")]));
+        assert!(matches!(parser.recv().await.unwrap(), ResponseEvent::AssistantText(_)));
+        match parser.recv().await.unwrap() {
+            ResponseEvent::EndStream { message } => assert_eq!(message.content(), "hi there, how are you?"),
+            other => panic!("unexpected event: {other:?}"),
+        }
+
+        let mut parser = ResponseParser::new(SendMessageOutput::Mock(vec![ChatResponseStream::ToolUseEvent {
+            tool_use_id: "1".to_string(),
+            name: "fs_read".to_string(),
+            input: None,
+            stop: None,
+        }]));
+        parser.recv().await.unwrap();
+        assert!(!parser.can_resume());
+    }
 }
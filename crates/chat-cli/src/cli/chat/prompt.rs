@@ -75,9 +75,39 @@ pub const COMMANDS: &[&str] = &[
     "/context hooks disable-all",
     "/compact",
     "/compact help",
+    "/compact pin",
+    "/compact unpin",
     "/usage",
+    "/model",
+    "/model list",
+    "/model set",
+    "/model help",
     "/save",
     "/load",
+    "/undo",
+    "/checkpoint",
+    "/checkpoint list",
+    "/checkpoint restore",
+    "/checkpoint help",
+    "/knowledge",
+    "/knowledge show",
+    "/knowledge add",
+    "/knowledge rm",
+    "/knowledge update",
+    "/knowledge search",
+    "/knowledge help",
+    "/resources",
+    "/resources list",
+    "/resources help",
+    "/history",
+    "/history list",
+    "/history search",
+    "/history help",
+    "/jobs",
+    "/jobs list",
+    "/jobs output",
+    "/jobs kill",
+    "/jobs help",
 ];
 
 pub fn generate_prompt(current_profile: Option<&str>, warning: bool) -> String {
@@ -327,6 +357,16 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_complete_command() {
+        for command in ["/undo", "/checkpoint", "/knowledge", "/resources", "/history", "/jobs"] {
+            let (start, completions) = complete_command(&command[..3], 0);
+            assert_eq!(start, 0);
+            assert!(completions.contains(&command.to_string()), "{command} should complete");
+        }
+        assert_eq!(complete_command("/jobs k", 0).1, vec!["/jobs kill".to_string()]);
+    }
+
     #[test]
     fn test_chat_completer_command_completion() {
         let (prompt_request_sender, _) = std::sync::mpsc::channel::<Option<String>>();
@@ -0,0 +1,270 @@
+use std::io::Write;
+
+use crossterm::style::{
+    Attribute,
+    Color,
+};
+use crossterm::{
+    execute,
+    style,
+};
+use tracing::warn;
+
+use super::input_source::InputSource;
+use super::server_messenger::SamplingRequest;
+use crate::api_client::StreamingClient;
+use crate::api_client::model::{
+    AssistantResponseMessage,
+    ChatMessage,
+    ChatResponseStream,
+    ConversationState,
+    UserInputMessage,
+};
+use crate::mcp_client::{
+    MessageContent,
+    Role,
+    SamplingCreateMessageParams,
+    SamplingCreateMessageResult,
+};
+
+/// Model name reported back to servers in sampling results.
+const SAMPLING_MODEL_NAME: &str = "amazon-q";
+
+/// Maximum number of characters of the last sampling message shown to the user for approval.
+const PREVIEW_MAX_LEN: usize = 500;
+
+/// Handles a `sampling/createMessage` request from a server: asks the user for approval, sends
+/// the messages to the model, and responds to the server with the model's reply.
+pub async fn handle_sampling_request(
+    output: &mut impl Write,
+    input_source: &mut InputSource,
+    client: &StreamingClient,
+    trust_all: bool,
+    interactive: bool,
+    request: SamplingRequest,
+) {
+    let SamplingRequest {
+        server_name,
+        params,
+        responder,
+    } = request;
+
+    let result = match approve(output, input_source, &server_name, &params, trust_all, interactive) {
+        Ok(true) => sample(client, params).await,
+        Ok(false) => Err(format!("The user declined the sampling request from {server_name}")),
+        Err(e) => Err(e.to_string()),
+    };
+
+    if let Err(err) = &result {
+        let _ = execute!(
+            output,
+            style::SetForegroundColor(Color::Red),
+            style::Print(format!("\nSampling request from {server_name} failed: {err}\n\n")),
+            style::SetForegroundColor(Color::Reset)
+        );
+    }
+
+    if responder.send(result).is_err() {
+        warn!("Server {} stopped waiting for its sampling result", server_name);
+    }
+}
+
+fn approve(
+    output: &mut impl Write,
+    input_source: &mut InputSource,
+    server_name: &str,
+    params: &SamplingCreateMessageParams,
+    trust_all: bool,
+    interactive: bool,
+) -> std::io::Result<bool> {
+    let preview = params
+        .messages
+        .last()
+        .map(|m| message_text(&m.content))
+        .unwrap_or_default();
+    let preview = match preview.char_indices().nth(PREVIEW_MAX_LEN) {
+        Some((i, _)) => format!("{}...", &preview[..i]),
+        None => preview,
+    };
+
+    execute!(
+        output,
+        style::SetForegroundColor(Color::Magenta),
+        style::Print(format!("\nServer {server_name} is requesting a model response:\n")),
+        style::SetForegroundColor(Color::Reset),
+        style::SetAttribute(Attribute::Italic),
+        style::Print(format!("{preview}\n")),
+        style::SetAttribute(Attribute::Reset),
+    )?;
+
+    if trust_all {
+        return Ok(true);
+    }
+    if !interactive {
+        execute!(
+            output,
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print("Declined, sampling requests require approval in interactive mode\n"),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+        return Ok(false);
+    }
+
+    loop {
+        match input_source.read_line(Some("Allow this request? [y/n]: ")) {
+            Ok(Some(line)) => match line.trim().to_lowercase().as_str() {
+                "y" | "yes" => return Ok(true),
+                "n" | "no" => return Ok(false),
+                _ => continue,
+            },
+            Ok(None) | Err(_) => return Ok(false),
+        }
+    }
+}
+
+async fn sample(
+    client: &StreamingClient,
+    params: SamplingCreateMessageParams,
+) -> Result<SamplingCreateMessageResult, String> {
+    let conversation_state = build_conversation_state(params)?;
+    let mut response = client
+        .send_message(conversation_state)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut text = String::new();
+    while let Some(event) = response.recv().await.map_err(|e| e.to_string())? {
+        if let ChatResponseStream::AssistantResponseEvent { content } = event {
+            text.push_str(&content);
+        }
+    }
+
+    Ok(SamplingCreateMessageResult {
+        role: Role::Assistant,
+        content: MessageContent::Text { text },
+        model: SAMPLING_MODEL_NAME.to_string(),
+        stop_reason: Some("endTurn".to_string()),
+    })
+}
+
+fn message_text(content: &MessageContent) -> String {
+    match content {
+        MessageContent::Image { .. } => "[image omitted]".to_string(),
+        content => String::from(content.clone()),
+    }
+}
+
+/// Converts sampling messages into a conversation that alternates between user and assistant
+/// messages, as required by the backend. Consecutive messages of the same role are merged, and
+/// the system prompt is prepended to the first user message.
+///
+/// Sampling requests are sent outside of the current chat conversation.
+fn build_conversation_state(params: SamplingCreateMessageParams) -> Result<ConversationState, String> {
+    if params.messages.is_empty() {
+        return Err("No sampling messages were provided".to_string());
+    }
+
+    let mut turns: Vec<(Role, String)> = Vec::new();
+    if let Some(system_prompt) = params.system_prompt.filter(|s| !s.is_empty()) {
+        turns.push((Role::User, system_prompt));
+    }
+    for message in &params.messages {
+        let text = message_text(&message.content);
+        match turns.last_mut() {
+            Some((role, content)) if *role == message.role => {
+                content.push_str("\n\n");
+                content.push_str(&text);
+            },
+            _ => turns.push((message.role.clone(), text)),
+        }
+    }
+
+    match turns.last() {
+        Some((Role::User, _)) => (),
+        _ => return Err("The last sampling message must be from the user".to_string()),
+    }
+    if let Some((Role::Assistant, _)) = turns.first() {
+        turns.insert(0, (Role::User, "Continue the conversation.".to_string()));
+    }
+
+    let user_message = |content: String| UserInputMessage {
+        content,
+        user_input_message_context: None,
+        user_intent: None,
+        images: None,
+    };
+    let (_, last) = turns.pop().ok_or("No sampling messages were provided")?;
+    let history = turns
+        .into_iter()
+        .map(|(role, content)| match role {
+            Role::User => ChatMessage::UserInputMessage(user_message(content)),
+            Role::Assistant => ChatMessage::AssistantResponseMessage(AssistantResponseMessage {
+                message_id: None,
+                content,
+                tool_uses: None,
+            }),
+        })
+        .collect::<Vec<_>>();
+
+    Ok(ConversationState {
+        conversation_id: None,
+        user_input_message: user_message(last),
+        history: (!history.is_empty()).then_some(history),
+        model_id: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp_client::SamplingMessage;
+
+    fn text_message(role: Role, text: &str) -> SamplingMessage {
+        SamplingMessage {
+            role,
+            content: MessageContent::Text { text: text.to_string() },
+        }
+    }
+
+    fn params(messages: Vec<SamplingMessage>, system_prompt: Option<&str>) -> SamplingCreateMessageParams {
+        SamplingCreateMessageParams {
+            messages,
+            system_prompt: system_prompt.map(str::to_string),
+            max_tokens: None,
+            model_preferences: None,
+            include_context: None,
+            temperature: None,
+            stop_sequences: None,
+        }
+    }
+
+    #[test]
+    fn test_build_conversation_state() {
+        let state = build_conversation_state(params(
+            vec![
+                text_message(Role::User, "a"),
+                text_message(Role::User, "b"),
+                text_message(Role::Assistant, "c"),
+                text_message(Role::User, "d"),
+            ],
+            Some("system"),
+        ))
+        .unwrap();
+
+        assert_eq!(state.user_input_message.content, "d");
+        let history = state.history.unwrap();
+        assert_eq!(history.len(), 2);
+        assert!(matches!(&history[0], ChatMessage::UserInputMessage(m) if m.content == "system\n\na\n\nb"));
+        assert!(matches!(&history[1], ChatMessage::AssistantResponseMessage(m) if m.content == "c"));
+
+        let state = build_conversation_state(params(vec![text_message(Role::User, "hi")], None)).unwrap();
+        assert_eq!(state.user_input_message.content, "hi");
+        assert!(state.history.is_none());
+    }
+
+    #[test]
+    fn test_build_conversation_state_errors() {
+        assert!(build_conversation_state(params(vec![], Some("system"))).is_err());
+        assert!(build_conversation_state(params(vec![text_message(Role::Assistant, "a")], None)).is_err());
+    }
+}
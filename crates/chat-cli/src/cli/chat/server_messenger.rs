@@ -1,8 +1,14 @@
+use std::sync::{
+    Arc,
+    RwLock as SyncRwLock,
+};
+
 use tokio::sync::mpsc::{
     Receiver,
     Sender,
     channel,
 };
+use tokio::sync::oneshot;
 
 use crate::mcp_client::{
     Messenger,
@@ -10,6 +16,10 @@ use crate::mcp_client::{
     PromptsListResult,
     ResourceTemplatesListResult,
     ResourcesListResult,
+    Root,
+    RootsListResult,
+    SamplingCreateMessageParams,
+    SamplingCreateMessageResult,
     ToolsListResult,
 };
 
@@ -37,24 +47,41 @@ pub enum UpdateEventMessage {
     },
 }
 
+/// A `sampling/createMessage` request from a server, which has to be approved by the user before
+/// it is sent to the model.
+#[derive(Debug)]
+pub struct SamplingRequest {
+    pub server_name: String,
+    pub params: SamplingCreateMessageParams,
+    pub responder: oneshot::Sender<Result<SamplingCreateMessageResult, String>>,
+}
+
 #[derive(Clone, Debug)]
 pub struct ServerMessengerBuilder {
     pub update_event_sender: Sender<UpdateEventMessage>,
+    pub sampling_request_sender: Sender<SamplingRequest>,
+    /// Roots shared by every server, kept up to date by the chat session.
+    pub roots: Arc<SyncRwLock<Vec<Root>>>,
 }
 
 impl ServerMessengerBuilder {
-    pub fn new(capacity: usize) -> (Receiver<UpdateEventMessage>, Self) {
+    pub fn new(capacity: usize) -> (Receiver<UpdateEventMessage>, Receiver<SamplingRequest>, Self) {
         let (tx, rx) = channel::<UpdateEventMessage>(capacity);
+        let (sampling_tx, sampling_rx) = channel::<SamplingRequest>(capacity);
         let this = Self {
             update_event_sender: tx,
+            sampling_request_sender: sampling_tx,
+            roots: Arc::new(SyncRwLock::new(Vec::new())),
         };
-        (rx, this)
+        (rx, sampling_rx, this)
     }
 
     pub fn build_with_name(&self, server_name: String) -> ServerMessenger {
         ServerMessenger {
             server_name,
             update_event_sender: self.update_event_sender.clone(),
+            sampling_request_sender: self.sampling_request_sender.clone(),
+            roots: self.roots.clone(),
         }
     }
 }
@@ -63,6 +90,8 @@ impl ServerMessengerBuilder {
 pub struct ServerMessenger {
     pub server_name: String,
     pub update_event_sender: Sender<UpdateEventMessage>,
+    pub sampling_request_sender: Sender<SamplingRequest>,
+    pub roots: Arc<SyncRwLock<Vec<Root>>>,
 }
 
 #[async_trait::async_trait]
@@ -124,6 +153,33 @@ impl Messenger for ServerMessenger {
             .map_err(|e| MessengerError::Custom(e.to_string()))?)
     }
 
+    async fn list_roots(&self) -> Result<RootsListResult, MessengerError> {
+        let roots = self
+            .roots
+            .read()
+            .map_err(|e| MessengerError::Custom(e.to_string()))?
+            .clone();
+        Ok(RootsListResult { roots })
+    }
+
+    async fn create_message(
+        &self,
+        params: SamplingCreateMessageParams,
+    ) -> Result<SamplingCreateMessageResult, MessengerError> {
+        let (tx, rx) = oneshot::channel();
+        self.sampling_request_sender
+            .send(SamplingRequest {
+                server_name: self.server_name.clone(),
+                params,
+                responder: tx,
+            })
+            .await
+            .map_err(|e| MessengerError::Custom(e.to_string()))?;
+        rx.await
+            .map_err(|e| MessengerError::Custom(e.to_string()))?
+            .map_err(MessengerError::Custom)
+    }
+
     fn duplicate(&self) -> Box<dyn Messenger> {
         Box::new(self.clone())
     }
@@ -0,0 +1,332 @@
+use std::io::{
+    BufRead,
+    Write,
+};
+use std::time::Instant;
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use super::cli::ChatOutputFormat;
+use super::message::{
+    ToolUseResult,
+    ToolUseResultBlock,
+};
+use super::util::shared_writer::SharedWriter;
+use crate::api_client::model::ToolResultStatus;
+
+/// Machine readable events printed by `q chat --output-format json|stream-json`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutputEvent {
+    /// Printed once, before the first prompt is sent.
+    Init {
+        conversation_id: String,
+        tools: Vec<String>,
+    },
+    /// A prompt sent to the model.
+    UserMessage {
+        content: String,
+    },
+    /// A chunk of the text streamed by the model.
+    TextDelta {
+        text: String,
+    },
+    /// A tool use requested by the model.
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    /// The result of a tool use, as sent back to the model.
+    ToolResult {
+        tool_use_id: String,
+        is_error: bool,
+        content: Vec<serde_json::Value>,
+    },
+    Error {
+        message: String,
+    },
+    /// Estimated context window usage after a response, in tokens.
+    Usage {
+        context_files_tokens: usize,
+        summary_tokens: usize,
+        hooks_tokens: usize,
+        tools_tokens: usize,
+        user_tokens: usize,
+        assistant_tokens: usize,
+        total_tokens: usize,
+        context_window_tokens: usize,
+    },
+    /// Printed once the chat ends. `message` is the text of the last response.
+    Result {
+        conversation_id: String,
+        is_error: bool,
+        message: String,
+        num_turns: usize,
+        duration_ms: u64,
+    },
+}
+
+impl From<&ToolUseResult> for OutputEvent {
+    fn from(result: &ToolUseResult) -> Self {
+        Self::ToolResult {
+            tool_use_id: result.tool_use_id.clone(),
+            is_error: matches!(result.status, ToolResultStatus::Error),
+            content: result
+                .content
+                .iter()
+                .map(|block| match block {
+                    ToolUseResultBlock::Json(json) => json.clone(),
+                    ToolUseResultBlock::Text(text) => serde_json::Value::String(text.clone()),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Lines read from STDIN with `--input-format stream-json`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum InputEvent {
+    UserMessage { content: String },
+}
+
+/// The single object printed with `--output-format json`.
+#[derive(Serialize)]
+struct JsonOutput<'a> {
+    #[serde(flatten)]
+    result: &'a OutputEvent,
+    events: &'a [OutputEvent],
+}
+
+/// Prints [OutputEvent]s in the requested format, and reads the prompts of multi-turn
+/// conversations from STDIN.
+pub struct StructuredOutput {
+    format: ChatOutputFormat,
+    writer: SharedWriter,
+    input: Option<Box<dyn BufRead + Send + Sync>>,
+    /// Events buffered until the chat ends with [ChatOutputFormat::Json].
+    events: Vec<OutputEvent>,
+    /// Text of the response currently being received.
+    message: String,
+    num_turns: usize,
+    is_error: bool,
+    start: Instant,
+}
+
+impl StructuredOutput {
+    pub fn new(format: ChatOutputFormat, writer: SharedWriter, input: Option<Box<dyn BufRead + Send + Sync>>) -> Self {
+        Self {
+            format,
+            writer,
+            input,
+            events: Vec::new(),
+            message: String::new(),
+            num_turns: 0,
+            is_error: false,
+            start: Instant::now(),
+        }
+    }
+
+    /// Whether an error was emitted at any point of the chat.
+    pub fn is_error(&self) -> bool {
+        self.is_error
+    }
+
+    pub fn emit(&mut self, event: OutputEvent) -> std::io::Result<()> {
+        match &event {
+            OutputEvent::UserMessage { .. } => {
+                self.num_turns += 1;
+                self.message.clear();
+            },
+            OutputEvent::ToolResult { .. } => self.message.clear(),
+            OutputEvent::TextDelta { text } => self.message.push_str(text),
+            OutputEvent::Error { .. } => self.is_error = true,
+            _ => (),
+        }
+
+        match self.format {
+            ChatOutputFormat::StreamJson => self.write_line(&event),
+            _ => {
+                // Consecutive text deltas are merged since the whole output is printed at once.
+                match (self.events.last_mut(), event) {
+                    (Some(OutputEvent::TextDelta { text }), OutputEvent::TextDelta { text: delta }) => {
+                        text.push_str(&delta);
+                    },
+                    (_, event) => self.events.push(event),
+                }
+                Ok(())
+            },
+        }
+    }
+
+    /// Returns the next prompt read from the input, if any. Invalid lines are reported as an
+    /// error and end the input.
+    pub fn next_input(&mut self) -> std::io::Result<Option<String>> {
+        let Some(input) = self.input.as_mut() else {
+            return Ok(None);
+        };
+
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            if !line.trim().is_empty() {
+                break;
+            }
+        }
+
+        match serde_json::from_str::<InputEvent>(&line) {
+            Ok(InputEvent::UserMessage { content }) => Ok(Some(content)),
+            Err(err) => {
+                self.input = None;
+                self.emit(OutputEvent::Error {
+                    message: format!("Invalid input line: {err}"),
+                })?;
+                Ok(None)
+            },
+        }
+    }
+
+    /// Prints the final [OutputEvent::Result].
+    pub fn finish(&mut self, conversation_id: &str) -> std::io::Result<()> {
+        let result = OutputEvent::Result {
+            conversation_id: conversation_id.to_string(),
+            is_error: self.is_error,
+            message: self.message.clone(),
+            num_turns: self.num_turns,
+            duration_ms: self.start.elapsed().as_millis() as u64,
+        };
+
+        match self.format {
+            ChatOutputFormat::StreamJson => self.write_line(&result),
+            _ => {
+                let output = JsonOutput {
+                    result: &result,
+                    events: &self.events,
+                };
+                let json = serde_json::to_string(&output)?;
+                writeln!(self.writer, "{json}")?;
+                self.writer.flush()
+            },
+        }
+    }
+
+    fn write_line(&mut self, event: &OutputEvent) -> std::io::Result<()> {
+        writeln!(self.writer, "{}", serde_json::to_string(event)?)?;
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc,
+        Mutex,
+    };
+
+    use super::*;
+    use crate::cli::chat::util::shared_writer::TestWriterWithSink;
+
+    fn structured_output(format: ChatOutputFormat, input: &'static str) -> (StructuredOutput, TestWriterWithSink) {
+        let sink = TestWriterWithSink {
+            sink: Arc::new(Mutex::new(Vec::new())),
+        };
+        let output = StructuredOutput::new(
+            format,
+            SharedWriter::new(sink.clone()),
+            Some(Box::new(std::io::Cursor::new(input))),
+        );
+        (output, sink)
+    }
+
+    fn lines(sink: &TestWriterWithSink) -> Vec<serde_json::Value> {
+        String::from_utf8(sink.get_content())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_stream_json() {
+        let (mut output, sink) = structured_output(ChatOutputFormat::StreamJson, "");
+        output
+            .emit(OutputEvent::UserMessage {
+                content: "hi".to_string(),
+            })
+            .unwrap();
+        output
+            .emit(OutputEvent::TextDelta {
+                text: "Hel".to_string(),
+            })
+            .unwrap();
+        output.emit(OutputEvent::TextDelta { text: "lo".to_string() }).unwrap();
+        output.finish("conv").unwrap();
+
+        let lines = lines(&sink);
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], serde_json::json!({ "type": "user_message", "content": "hi" }));
+        assert_eq!(lines[2], serde_json::json!({ "type": "text_delta", "text": "lo" }));
+        assert_eq!(lines[3]["type"], "result");
+        assert_eq!(lines[3]["message"], "Hello");
+        assert_eq!(lines[3]["num_turns"], 1);
+        assert_eq!(lines[3]["is_error"], false);
+    }
+
+    #[test]
+    fn test_json() {
+        let (mut output, sink) = structured_output(ChatOutputFormat::Json, "");
+        output.emit(OutputEvent::TextDelta { text: "a".to_string() }).unwrap();
+        output.emit(OutputEvent::TextDelta { text: "b".to_string() }).unwrap();
+        output
+            .emit(OutputEvent::from(&ToolUseResult {
+                tool_use_id: "1".to_string(),
+                content: vec![ToolUseResultBlock::Text("done".to_string())],
+                status: ToolResultStatus::Success,
+            }))
+            .unwrap();
+        output.emit(OutputEvent::TextDelta { text: "c".to_string() }).unwrap();
+        output
+            .emit(OutputEvent::Error {
+                message: "oops".to_string(),
+            })
+            .unwrap();
+        assert!(sink.get_content().is_empty());
+        output.finish("conv").unwrap();
+
+        let lines = lines(&sink);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["type"], "result");
+        assert_eq!(lines[0]["message"], "c");
+        assert_eq!(lines[0]["is_error"], true);
+        assert_eq!(
+            lines[0]["events"][0],
+            serde_json::json!({ "type": "text_delta", "text": "ab" })
+        );
+        assert_eq!(
+            lines[0]["events"][1],
+            serde_json::json!({ "type": "tool_result", "tool_use_id": "1", "is_error": false, "content": ["done"] })
+        );
+        assert_eq!(lines[0]["events"].as_array().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_next_input() {
+        let (mut output, sink) = structured_output(
+            ChatOutputFormat::StreamJson,
+            "{\"type\":\"user_message\",\"content\":\"first\"}\n\n{\"type\":\"user_message\",\"content\":\"second\"}\nnot json\n{\"type\":\"user_message\",\"content\":\"ignored\"}\n",
+        );
+        assert_eq!(output.next_input().unwrap(), Some("first".to_string()));
+        assert_eq!(output.next_input().unwrap(), Some("second".to_string()));
+        assert_eq!(output.next_input().unwrap(), None);
+        assert_eq!(output.next_input().unwrap(), None);
+        assert!(output.is_error());
+        assert_eq!(lines(&sink)[0]["type"], "error");
+    }
+}
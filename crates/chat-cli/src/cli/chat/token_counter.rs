@@ -1,5 +1,16 @@
+use std::collections::HashMap;
+use std::hash::{
+    DefaultHasher,
+    Hash,
+    Hasher,
+};
 use std::ops::Deref;
+use std::sync::{
+    Mutex,
+    OnceLock,
+};
 
+use super::consts::CONTEXT_WINDOW_SIZE;
 use super::conversation_state::{
     BackendConversationState,
     ConversationSize,
@@ -11,8 +22,9 @@ use super::message::{
     UserMessage,
     UserMessageContent,
 };
+use crate::api_client::model::Tool;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
 pub struct CharCount(usize);
 
 impl CharCount {
@@ -43,7 +55,7 @@ impl std::ops::Add for CharCount {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
 pub struct TokenCount(usize);
 
 impl TokenCount {
@@ -60,12 +72,46 @@ impl Deref for TokenCount {
     }
 }
 
+impl From<usize> for TokenCount {
+    fn from(value: usize) -> Self {
+        Self(value)
+    }
+}
+
+/// Estimates the number of tokens from a number of characters, for content too large to be worth
+/// tokenizing.
 impl From<CharCount> for TokenCount {
     fn from(value: CharCount) -> Self {
         Self(TokenCounter::count_tokens_char_count(value.value()))
     }
 }
 
+impl From<&str> for TokenCount {
+    fn from(value: &str) -> Self {
+        Self(TokenCounter::count_tokens(value))
+    }
+}
+
+impl std::ops::Add for TokenCount {
+    type Output = TokenCount;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self(self.value() + rhs.value())
+    }
+}
+
+impl std::ops::AddAssign for TokenCount {
+    fn add_assign(&mut self, rhs: Self) {
+        self.0 += rhs.0;
+    }
+}
+
+impl std::iter::Sum for TokenCount {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |acc, v| acc + v)
+    }
+}
+
 impl std::fmt::Display for TokenCount {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.0)
@@ -75,16 +121,57 @@ impl std::fmt::Display for TokenCount {
 pub struct TokenCounter;
 
 impl TokenCounter {
+    /// Maximum number of cached token counts before the cache is cleared.
+    const CACHE_MAX_ENTRIES: usize = 4096;
+    /// Content shorter than this is tokenized every time instead of being cached.
+    const CACHE_MIN_LEN: usize = 256;
+    /// Content longer than this is far larger than the context window, so its tokens are
+    /// estimated from its length rather than counted.
+    const TOKENIZE_MAX_CHARS: usize = Self::token_to_chars(CONTEXT_WINDOW_SIZE) * 2;
     pub const TOKEN_TO_CHAR_RATIO: usize = 3;
 
-    /// Estimates the number of tokens in the input content.
-    /// Currently uses a simple heuristic: content length / TOKEN_TO_CHAR_RATIO
+    /// Counts the number of tokens in the input content using a BPE tokenizer bundled with the
+    /// binary (cl100k_base). The model's own tokenizer is not public, so counts are a close
+    /// estimate rather than exact.
     ///
-    /// Rounds up to the nearest multiple of 10 to avoid giving users a false sense of precision.
+    /// Token counts of large content are cached, since the same context files and messages are
+    /// counted again on every request.
     pub fn count_tokens(content: &str) -> usize {
-        Self::count_tokens_char_count(content.len())
+        let char_count = content.char_count();
+        if *char_count > Self::TOKENIZE_MAX_CHARS {
+            return TokenCount::from(char_count).value();
+        }
+        if *char_count < Self::CACHE_MIN_LEN {
+            return Self::encode_len(content);
+        }
+
+        static CACHE: OnceLock<Mutex<HashMap<u64, usize>>> = OnceLock::new();
+        let mut hasher = DefaultHasher::new();
+        content.hash(&mut hasher);
+        let key = hasher.finish();
+
+        let cache = CACHE.get_or_init(Default::default);
+        if let Some(count) = cache.lock().ok().and_then(|cache| cache.get(&key).copied()) {
+            return count;
+        }
+
+        let count = Self::encode_len(content);
+        if let Ok(mut cache) = cache.lock() {
+            if cache.len() >= Self::CACHE_MAX_ENTRIES {
+                cache.clear();
+            }
+            cache.insert(key, count);
+        }
+        count
     }
 
+    fn encode_len(content: &str) -> usize {
+        tiktoken_rs::cl100k_base_singleton().encode_ordinary(content).len()
+    }
+
+    /// Estimates the number of tokens from a number of characters.
+    ///
+    /// Rounds up to the nearest multiple of 10 to avoid giving users a false sense of precision.
     fn count_tokens_char_count(count: usize) -> usize {
         (count / Self::TOKEN_TO_CHAR_RATIO + 5) / 10 * 10
     }
@@ -92,10 +179,20 @@ impl TokenCounter {
     pub const fn token_to_chars(token: usize) -> usize {
         token * Self::TOKEN_TO_CHAR_RATIO
     }
+
+    /// Counts the tokens of a JSON value as it is serialized when sent to the model.
+    pub fn count_json_tokens(value: &serde_json::Value) -> usize {
+        match value {
+            serde_json::Value::String(s) => Self::count_tokens(s),
+            // Avoid serializing values that are too large to be tokenized anyway
+            value if *value.char_count() > Self::TOKENIZE_MAX_CHARS => TokenCount::from(value.char_count()).value(),
+            value => Self::count_tokens(&value.to_string()),
+        }
+    }
 }
 
-/// A trait for types that represent some number of characters (aka bytes). For use in calculating
-/// context window size utilization.
+/// A trait for types that represent some number of characters (aka bytes). Used to bound content
+/// before it is tokenized.
 pub trait CharCounter {
     /// Returns the number of characters contained within this type.
     ///
@@ -103,67 +200,84 @@ pub trait CharCounter {
     fn char_count(&self) -> CharCount;
 }
 
-impl CharCounter for BackendConversationState<'_> {
+impl CharCounter for str {
     fn char_count(&self) -> CharCount {
-        self.calculate_conversation_size().char_count()
+        self.len().into()
     }
 }
 
-impl CharCounter for ConversationSize {
+impl CharCounter for serde_json::Value {
+    /// Only counts the characters of the values, not of the keys and punctuation.
     fn char_count(&self) -> CharCount {
-        self.user_messages + self.assistant_messages + self.context_messages
+        calculate_value_char_count(self).into()
     }
 }
 
-impl CharCounter for UserMessage {
-    fn char_count(&self) -> CharCount {
-        let mut total_chars = 0;
-        total_chars += self.additional_context().len();
+/// A trait for types that occupy some number of tokens of the context window when sent to the
+/// model.
+pub trait TokenCountable {
+    /// Returns the number of tokens used by this type.
+    fn token_count(&self) -> TokenCount;
+}
+
+impl TokenCountable for BackendConversationState<'_> {
+    fn token_count(&self) -> TokenCount {
+        self.calculate_conversation_size().total()
+    }
+}
+
+impl TokenCountable for ConversationSize {
+    fn token_count(&self) -> TokenCount {
+        self.total()
+    }
+}
+
+impl TokenCountable for UserMessage {
+    /// Additional context (e.g. the output of per prompt hooks) is not included since it is only
+    /// sent along with the next user message, and not with messages in the history.
+    fn token_count(&self) -> TokenCount {
         match self.content() {
-            UserMessageContent::Prompt { prompt } => {
-                total_chars += prompt.len();
-            },
+            UserMessageContent::Prompt { prompt } => TokenCount::from(prompt.as_str()),
             UserMessageContent::CancelledToolUses {
                 prompt,
                 tool_use_results,
-            } => {
-                total_chars += prompt.as_ref().map_or(0, String::len);
-                total_chars += tool_use_results.as_slice().char_count().0;
-            },
-            UserMessageContent::ToolUseResults { tool_use_results } => {
-                total_chars += tool_use_results.as_slice().char_count().0;
-            },
+            } => TokenCount::from(prompt.as_deref().unwrap_or_default()) + tool_use_results.as_slice().token_count(),
+            UserMessageContent::ToolUseResults { tool_use_results } => tool_use_results.as_slice().token_count(),
         }
-        total_chars.into()
     }
 }
 
-impl CharCounter for AssistantMessage {
-    fn char_count(&self) -> CharCount {
-        let mut total_chars = 0;
-        total_chars += self.content().len();
+impl TokenCountable for AssistantMessage {
+    fn token_count(&self) -> TokenCount {
+        let mut count = TokenCount::from(self.content());
         if let Some(tool_uses) = self.tool_uses() {
-            total_chars += tool_uses
+            count += tool_uses
                 .iter()
-                .map(|v| calculate_value_char_count(&v.args))
-                .reduce(|acc, e| acc + e)
-                .unwrap_or_default();
+                .map(|v| TokenCount::from(TokenCounter::count_json_tokens(&v.args)))
+                .sum();
         }
-        total_chars.into()
+        count
     }
 }
 
-impl CharCounter for &[ToolUseResult] {
-    fn char_count(&self) -> CharCount {
+impl TokenCountable for &[ToolUseResult] {
+    fn token_count(&self) -> TokenCount {
         self.iter()
             .flat_map(|v| &v.content)
-            .fold(0, |acc, v| {
-                acc + match v {
-                    ToolUseResultBlock::Json(v) => calculate_value_char_count(v),
-                    ToolUseResultBlock::Text(s) => s.len(),
-                }
+            .map(|v| match v {
+                ToolUseResultBlock::Json(v) => TokenCounter::count_json_tokens(v).into(),
+                ToolUseResultBlock::Text(s) => TokenCount::from(s.as_str()),
             })
-            .into()
+            .sum()
+    }
+}
+
+impl TokenCountable for Tool {
+    /// The tool specification is sent as JSON along with every request.
+    fn token_count(&self) -> TokenCount {
+        serde_json::to_string(self)
+            .map(|json| TokenCount::from(json.as_str()))
+            .unwrap_or_default()
     }
 }
 
@@ -180,14 +294,23 @@ fn calculate_value_char_count(document: &serde_json::Value) -> usize {
 
 #[cfg(test)]
 mod tests {
-
     use super::*;
 
     #[test]
     fn test_token_count() {
-        let text = "This is a test sentence.";
-        let count = TokenCounter::count_tokens(text);
-        assert_eq!(count, (text.len() / 3 + 5) / 10 * 10);
+        assert_eq!(TokenCounter::count_tokens(""), 0);
+        assert_eq!(TokenCounter::count_tokens("This is a test sentence."), 6);
+
+        // Code and non-English text use more tokens per character than English prose.
+        let code = "fn main() { println!(\"{:?}\", vec![1, 2, 3]); }";
+        let japanese = "これはテストの文章です。";
+        assert!(TokenCounter::count_tokens(code) > code.len() / 4);
+        assert!(TokenCounter::count_tokens(japanese) > japanese.chars().count() / 2);
+
+        // Cached counts match uncached ones.
+        let long = "The quick brown fox jumps over the lazy dog. ".repeat(20);
+        assert_eq!(TokenCounter::count_tokens(&long), TokenCounter::encode_len(&long));
+        assert_eq!(TokenCounter::count_tokens(&long), TokenCounter::encode_len(&long));
     }
 
     #[test]
@@ -248,4 +371,42 @@ mod tests {
             0
         );
     }
+
+    #[test]
+    fn test_estimate_oversized_content() {
+        let huge = "a".repeat(TokenCounter::TOKENIZE_MAX_CHARS + 3);
+        assert_eq!(
+            TokenCounter::count_tokens(&huge),
+            TokenCount::from(CharCount::from(huge.len())).value()
+        );
+        let huge = serde_json::json!([huge]);
+        assert_eq!(
+            TokenCounter::count_json_tokens(&huge),
+            TokenCount::from(huge.char_count()).value()
+        );
+    }
+
+    #[test]
+    fn test_count_json_tokens() {
+        assert_eq!(
+            TokenCounter::count_json_tokens(&serde_json::Value::String("hello".to_string())),
+            1
+        );
+
+        let value = serde_json::json!({ "path": "/tmp/file.txt", "lines": [1, 2, 3] });
+        assert_eq!(
+            TokenCounter::count_json_tokens(&value),
+            TokenCounter::count_tokens(&value.to_string())
+        );
+    }
+
+    #[test]
+    fn test_token_count_sum() {
+        let counts = [TokenCount::from(1), TokenCount::from(2), TokenCount::from(3)];
+        assert_eq!(counts.into_iter().sum::<TokenCount>(), TokenCount::from(6));
+        assert_eq!(
+            TokenCount::from("hello world") + TokenCount::from(1),
+            TokenCount::from(3)
+        );
+    }
 }
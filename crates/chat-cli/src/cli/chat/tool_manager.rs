@@ -60,18 +60,23 @@ use crate::api_client::model::{
 use crate::cli::chat::command::PromptsGetCommand;
 use crate::cli::chat::message::AssistantToolUse;
 use crate::cli::chat::server_messenger::{
+    SamplingRequest,
     ServerMessengerBuilder,
     UpdateEventMessage,
 };
+use crate::cli::chat::tools::bash_process::BashProcess;
 use crate::cli::chat::tools::custom_tool::{
     CustomTool,
     CustomToolClient,
     CustomToolConfig,
 };
+use crate::cli::chat::tools::delegate::Delegate;
 use crate::cli::chat::tools::execute_bash::ExecuteBash;
 use crate::cli::chat::tools::fs_read::FsRead;
 use crate::cli::chat::tools::fs_write::FsWrite;
 use crate::cli::chat::tools::gh_issue::GhIssue;
+use crate::cli::chat::tools::knowledge_search::KnowledgeSearch;
+use crate::cli::chat::tools::mcp_resource::McpResource;
 use crate::cli::chat::tools::thinking::Thinking;
 use crate::cli::chat::tools::use_aws::UseAws;
 use crate::cli::chat::tools::{
@@ -84,6 +89,7 @@ use crate::database::settings::Setting;
 use crate::mcp_client::{
     JsonRpcResponse,
     PromptGet,
+    Root,
 };
 use crate::platform::Context;
 use crate::telemetry::TelemetryThread;
@@ -424,7 +430,8 @@ impl ToolManagerBuilder {
         let has_new_stuff_clone = has_new_stuff.clone();
         let pending = Arc::new(RwLock::new(HashSet::<String>::new()));
         let pending_clone = pending.clone();
-        let (mut msg_rx, messenger_builder) = ServerMessengerBuilder::new(20);
+        let (mut msg_rx, sampling_receiver, messenger_builder) = ServerMessengerBuilder::new(20);
+        let roots = messenger_builder.roots.clone();
         let telemetry_clone = telemetry.clone();
         tokio::spawn(async move {
             while let Some(msg) = msg_rx.recv().await {
@@ -621,6 +628,8 @@ impl ToolManagerBuilder {
             loading_status_sender,
             new_tool_specs,
             has_new_stuff,
+            roots,
+            sampling_receiver: Some(sampling_receiver),
             is_interactive,
             ..Default::default()
         })
@@ -703,6 +712,14 @@ pub struct ToolManager {
     /// model.
     pub schema: HashMap<String, ToolSpec>,
 
+    /// Roots exposed to servers via `roots/list`. This is shared with the messengers assigned to
+    /// each client.
+    pub roots: Arc<SyncRwLock<Vec<Root>>>,
+
+    /// Receiving end of the sampling requests sent by servers. This is meant to be taken by the
+    /// chat session, which is responsible for getting the user's approval.
+    sampling_receiver: Option<tokio::sync::mpsc::Receiver<SamplingRequest>>,
+
     is_interactive: bool,
 }
 
@@ -716,6 +733,7 @@ impl Clone for ToolManager {
             prompts: self.prompts.clone(),
             tn_map: self.tn_map.clone(),
             schema: self.schema.clone(),
+            roots: self.roots.clone(),
             is_interactive: self.is_interactive,
             ..Default::default()
         }
@@ -732,6 +750,12 @@ impl ToolManager {
             if !crate::cli::chat::tools::thinking::Thinking::is_enabled(database) {
                 tool_specs.remove("thinking");
             }
+            if !KnowledgeSearch::is_enabled(database) {
+                tool_specs.remove("knowledge_search");
+            }
+            if self.clients.is_empty() {
+                tool_specs.remove("mcp_resource");
+            }
             tool_specs
         };
         let load_tools = self
@@ -810,6 +834,16 @@ impl ToolManager {
             "use_aws" => Tool::UseAws(serde_json::from_value::<UseAws>(value.args).map_err(map_err)?),
             "report_issue" => Tool::GhIssue(serde_json::from_value::<GhIssue>(value.args).map_err(map_err)?),
             "thinking" => Tool::Thinking(serde_json::from_value::<Thinking>(value.args).map_err(map_err)?),
+            "knowledge_search" => {
+                Tool::KnowledgeSearch(serde_json::from_value::<KnowledgeSearch>(value.args).map_err(map_err)?)
+            },
+            "delegate" => Tool::Delegate(serde_json::from_value::<Delegate>(value.args).map_err(map_err)?),
+            "bash_process" => Tool::BashProcess(serde_json::from_value::<BashProcess>(value.args).map_err(map_err)?),
+            "mcp_resource" => {
+                let mut mcp_resource = serde_json::from_value::<McpResource>(value.args).map_err(map_err)?;
+                mcp_resource.clients = self.clients.clone();
+                Tool::McpResource(mcp_resource)
+            },
             // Note that this name is namespaced with server_name{DELIMITER}tool_name
             name => {
                 // Note: tn_map also has tools that underwent no transformation. In otherwords, if
@@ -853,6 +887,10 @@ impl ToolManager {
                         }
                     },
                 }?;
+                let read_only = self
+                    .schema
+                    .get(name)
+                    .is_some_and(|spec| spec.annotations.read_only_hint);
                 let name = self.tn_map.get(name).map_or(name, String::as_str);
                 let (server_name, tool_name) = name.split_once(NAMESPACE_DELIMITER).ok_or(ToolResult {
                     tool_use_id: value.id.clone(),
@@ -884,6 +922,7 @@ impl ToolManager {
                     client: client.clone(),
                     method: "tools/call".to_owned(),
                     params: Some(params),
+                    read_only,
                 };
                 Tool::Custom(custom_tool)
             },
@@ -1090,6 +1129,30 @@ impl ToolManager {
         Ok(())
     }
 
+    /// Takes the receiver for sampling requests sent by servers.
+    pub fn take_sampling_receiver(&mut self) -> Option<tokio::sync::mpsc::Receiver<SamplingRequest>> {
+        self.sampling_receiver.take()
+    }
+
+    /// Replaces the roots exposed to servers, notifying every server if they have changed.
+    pub async fn set_roots(&self, roots: Vec<Root>) {
+        {
+            let Ok(mut roots_wl) = self.roots.write() else {
+                error!("Error retrieving write lock on roots");
+                return;
+            };
+            if *roots_wl == roots {
+                return;
+            }
+            *roots_wl = roots;
+        }
+        for (server_name, client) in &self.clients {
+            if let Err(e) = client.notify("roots/list_changed", None).await {
+                warn!("Error notifying {} of roots change: {:?}", server_name, e);
+            }
+        }
+    }
+
     pub async fn pending_clients(&self) -> Vec<String> {
         self.pending_clients.read().await.iter().cloned().collect::<Vec<_>>()
     }
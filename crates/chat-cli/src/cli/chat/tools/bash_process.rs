@@ -0,0 +1,615 @@
+use std::collections::{
+    BTreeMap,
+    VecDeque,
+};
+use std::io::Write;
+use std::process::Stdio;
+use std::sync::{
+    Arc,
+    MutexGuard,
+    PoisonError,
+};
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use crossterm::style::Color;
+use crossterm::{
+    queue,
+    style,
+};
+use eyre::{
+    Context as EyreContext,
+    Result,
+    bail,
+};
+use serde::Deserialize;
+use tokio::io::{
+    AsyncBufReadExt,
+    AsyncRead,
+    AsyncWriteExt,
+};
+use tokio::process::{
+    Child,
+    ChildStdin,
+};
+use tokio::sync::Mutex;
+
+use super::execute_bash::bash_command;
+use super::sandbox::SandboxPolicy;
+use super::{
+    InvokeOutput,
+    MAX_TOOL_RESPONSE_SIZE,
+    OutputKind,
+};
+use crate::platform::Context;
+
+/// Maximum size of the output of each stream kept until the model reads it.
+const MAX_UNREAD_OUTPUT: usize = MAX_TOOL_RESPONSE_SIZE / 3;
+
+/// Number of lines of each stream kept for `/jobs output`.
+const RECENT_LINE_COUNT: usize = 20;
+
+/// How long to wait for a killed process to exit.
+const KILL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Reads the output of, writes to, or kills a process started by `execute_bash` with
+/// `background: true`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BashProcess {
+    pub process_id: usize,
+    #[serde(flatten)]
+    pub action: ProcessAction,
+    /// Set by the chat session before the tool is validated.
+    #[serde(skip)]
+    pub processes: Option<BackgroundProcesses>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ProcessAction {
+    /// Returns the output printed since the last read.
+    Read,
+    /// Writes to the standard input of the process.
+    Write {
+        input: String,
+    },
+    Kill,
+}
+
+impl BashProcess {
+    pub fn requires_acceptance(&self) -> bool {
+        // The input could be a command for a shell or an interpreter.
+        matches!(self.action, ProcessAction::Write { .. })
+    }
+
+    pub async fn invoke(&self, mut updates: impl Write) -> Result<InvokeOutput> {
+        let processes = self.processes()?;
+        match &self.action {
+            ProcessAction::Read => (),
+            ProcessAction::Write { input } => processes.write(self.process_id, input).await?,
+            ProcessAction::Kill => {
+                processes.kill(self.process_id).await?;
+                writeln!(updates, "Killed background process {}", self.process_id)?;
+            },
+        }
+
+        Ok(InvokeOutput {
+            output: OutputKind::Json(processes.read(self.process_id).await?),
+        })
+    }
+
+    pub fn queue_description(&self, updates: &mut impl Write) -> Result<()> {
+        let action = match &self.action {
+            ProcessAction::Read => "Reading the output of",
+            ProcessAction::Write { .. } => "Writing to",
+            ProcessAction::Kill => "Killing",
+        };
+        queue!(
+            updates,
+            style::Print(format!("{action} background process ")),
+            style::SetForegroundColor(Color::Green),
+            style::Print(self.process_id),
+            style::ResetColor,
+            style::Print("\n"),
+        )?;
+        if let ProcessAction::Write { input } = &self.action {
+            queue!(
+                updates,
+                style::SetForegroundColor(Color::Green),
+                style::Print(input),
+                style::ResetColor,
+                style::Print("\n"),
+            )?;
+        }
+        Ok(())
+    }
+
+    pub async fn validate(&mut self, _ctx: &Context) -> Result<()> {
+        if !self.processes()?.contains(self.process_id).await {
+            bail!("There is no background process with the id {}", self.process_id);
+        }
+        Ok(())
+    }
+
+    fn processes(&self) -> Result<&BackgroundProcesses> {
+        match &self.processes {
+            Some(processes) => Ok(processes),
+            None => bail!("Background processes are not available here"),
+        }
+    }
+}
+
+/// The state of a background process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessStatus {
+    Running,
+    /// Contains the exit code, which is [None] if the process was killed by a signal.
+    Exited(Option<i32>),
+}
+
+impl std::fmt::Display for ProcessStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProcessStatus::Running => write!(f, "running"),
+            ProcessStatus::Exited(Some(code)) => write!(f, "exited ({code})"),
+            ProcessStatus::Exited(None) => write!(f, "killed"),
+        }
+    }
+}
+
+/// Output of one of the streams of a background process.
+#[derive(Debug, Default)]
+struct StreamOutput {
+    /// Output that the model has not read yet.
+    unread: String,
+    /// Whether the start of [Self::unread] was dropped to stay within [MAX_UNREAD_OUTPUT].
+    truncated: bool,
+    /// The last lines of output, whether or not they were read.
+    recent: VecDeque<String>,
+}
+
+impl StreamOutput {
+    fn push_line(&mut self, line: String) {
+        self.unread.push_str(&line);
+        self.unread.push('\n');
+        if self.unread.len() > MAX_UNREAD_OUTPUT {
+            let mut start = self.unread.len() - MAX_UNREAD_OUTPUT;
+            while !self.unread.is_char_boundary(start) {
+                start += 1;
+            }
+            self.unread.drain(..start);
+            self.truncated = true;
+        }
+
+        if self.recent.len() >= RECENT_LINE_COUNT {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(line);
+    }
+
+    fn take_unread(&mut self) -> String {
+        let unread = std::mem::take(&mut self.unread);
+        match std::mem::take(&mut self.truncated) {
+            true => format!("... truncated\n{unread}"),
+            false => unread,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ProcessOutput {
+    stdout: StreamOutput,
+    stderr: StreamOutput,
+}
+
+#[derive(Debug)]
+struct BackgroundProcess {
+    command: String,
+    started_at: Instant,
+    /// Also the id of the process group, which includes the processes started by the command.
+    pid: Option<u32>,
+    child: Child,
+    /// Whether the exit status of the process was collected. Until then, the process id and thus
+    /// the process group id can't be reused, even if the process exited.
+    reaped: bool,
+    stdin: Option<Arc<Mutex<ChildStdin>>>,
+    output: Arc<std::sync::Mutex<ProcessOutput>>,
+}
+
+impl BackgroundProcess {
+    fn status(&mut self) -> ProcessStatus {
+        let status = match self.child.try_wait() {
+            Ok(Some(status)) => ProcessStatus::Exited(status.code()),
+            Ok(None) => ProcessStatus::Running,
+            // The status can't be retrieved, most likely since the process is gone.
+            Err(_) => ProcessStatus::Exited(None),
+        };
+        self.reaped = status != ProcessStatus::Running;
+        status
+    }
+
+    /// Kills the process along with any process it started, without waiting for it to exit.
+    ///
+    /// Nothing is signaled once the exit status of the process was collected, since the id of its
+    /// process group may then have been reused by unrelated processes. Until then, the whole group
+    /// is signaled even if the shell already exited, so that the commands it started in the
+    /// background, such as `server &`, are killed too.
+    fn start_kill(&mut self) {
+        if self.reaped {
+            return;
+        }
+        #[cfg(unix)]
+        if let Some(pid) = self.pid {
+            use nix::sys::signal::{
+                Signal,
+                killpg,
+            };
+            use nix::unistd::Pid;
+
+            let _ = killpg(Pid::from_raw(pid as i32), Signal::SIGKILL);
+        }
+        let _ = self.child.start_kill();
+    }
+}
+
+#[derive(Debug, Default)]
+struct ProcessTable {
+    next_id: usize,
+    processes: BTreeMap<usize, BackgroundProcess>,
+}
+
+impl Drop for ProcessTable {
+    fn drop(&mut self) {
+        for process in self.processes.values_mut() {
+            process.start_kill();
+        }
+    }
+}
+
+/// The processes started in the background by `execute_bash` during a chat session, managed by
+/// the model with the `bash_process` tool and by the user with `/jobs`.
+///
+/// Every process still running is killed once the last handle is dropped.
+///
+/// The table is never locked across an await, so that it can be locked while the chat session is
+/// dropped.
+#[derive(Debug, Clone, Default)]
+pub struct BackgroundProcesses(Arc<std::sync::Mutex<ProcessTable>>);
+
+impl BackgroundProcesses {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn table(&self) -> MutexGuard<'_, ProcessTable> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Starts `command` in its own process group, returning the id of the process.
+    pub async fn spawn(&self, command: &str, sandbox: Option<&SandboxPolicy>) -> Result<usize> {
+        let mut cmd = bash_command(command, sandbox)?;
+        cmd.stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        // Keeps the process out of the foreground process group, so that it isn't interrupted by
+        // Ctrl+C, and allows killing every process it started.
+        #[cfg(unix)]
+        cmd.process_group(0);
+        let mut child = cmd
+            .spawn()
+            .wrap_err_with(|| format!("Unable to spawn command '{command}'"))?;
+
+        let output = Arc::new(std::sync::Mutex::new(ProcessOutput::default()));
+        if let Some(stdout) = child.stdout.take() {
+            tokio::spawn(collect_output(stdout, Arc::clone(&output), |output| &mut output.stdout));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(collect_output(stderr, Arc::clone(&output), |output| &mut output.stderr));
+        }
+
+        let mut table = self.table();
+        table.next_id += 1;
+        let id = table.next_id;
+        table.processes.insert(id, BackgroundProcess {
+            command: command.to_string(),
+            started_at: Instant::now(),
+            pid: child.id(),
+            stdin: child.stdin.take().map(|stdin| Arc::new(Mutex::new(stdin))),
+            child,
+            reaped: false,
+            output,
+        });
+        Ok(id)
+    }
+
+    pub async fn contains(&self, id: usize) -> bool {
+        self.table().processes.contains_key(&id)
+    }
+
+    /// Returns the status of the process along with the output printed since the last read.
+    pub async fn read(&self, id: usize) -> Result<serde_json::Value> {
+        let mut table = self.table();
+        let process = get_process(&mut table, id)?;
+        let status = process.status();
+        let mut output = process.output.lock().unwrap();
+        Ok(serde_json::json!({
+            "process_id": id,
+            "status": status.to_string(),
+            "exit_status": match status {
+                ProcessStatus::Exited(code) => code,
+                ProcessStatus::Running => None,
+            },
+            "stdout": output.stdout.take_unread(),
+            "stderr": output.stderr.take_unread(),
+        }))
+    }
+
+    /// Writes `input` to the standard input of the process, adding a trailing newline if missing.
+    pub async fn write(&self, id: usize, input: &str) -> Result<()> {
+        let stdin = {
+            let mut table = self.table();
+            let process = get_process(&mut table, id)?;
+            if process.status() != ProcessStatus::Running {
+                bail!("Background process {id} is no longer running");
+            }
+            match &process.stdin {
+                Some(stdin) => Arc::clone(stdin),
+                None => bail!("The input of background process {id} is closed"),
+            }
+        };
+        let mut stdin = stdin.lock().await;
+
+        let mut input = input.to_string();
+        if !input.ends_with('\n') {
+            input.push('\n');
+        }
+        stdin.write_all(input.as_bytes()).await?;
+        stdin.flush().await?;
+        Ok(())
+    }
+
+    /// Kills the process along with any process it started.
+    pub async fn kill(&self, id: usize) -> Result<ProcessStatus> {
+        get_process(&mut self.table(), id)?.start_kill();
+        let wait_for_exit = async {
+            loop {
+                let status = get_process(&mut self.table(), id)?.status();
+                if status != ProcessStatus::Running {
+                    return Ok(status);
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        match tokio::time::timeout(KILL_TIMEOUT, wait_for_exit).await {
+            Ok(result) => result,
+            Err(_) => bail!(
+                "Process {id} did not exit within {}s of being killed",
+                KILL_TIMEOUT.as_secs()
+            ),
+        }
+    }
+
+    /// Kills every process that is still running without waiting for them to exit.
+    pub fn kill_all(&self) {
+        for process in self.table().processes.values_mut() {
+            process.start_kill();
+        }
+    }
+
+    pub async fn print_list(&self, output: &mut impl Write) -> std::io::Result<()> {
+        let mut table = self.table();
+        if table.processes.is_empty() {
+            queue!(
+                output,
+                style::SetForegroundColor(Color::DarkGrey),
+                style::Print("\nNo background processes were started in this session.\n\n"),
+                style::SetForegroundColor(Color::Reset),
+            )?;
+            return Ok(());
+        }
+
+        queue!(output, style::Print("\n"))?;
+        for (id, process) in &mut table.processes {
+            let status = process.status();
+            queue!(
+                output,
+                style::SetForegroundColor(Color::Green),
+                style::Print(format!("{:>4}", id)),
+                style::SetForegroundColor(match status {
+                    ProcessStatus::Running => Color::Yellow,
+                    ProcessStatus::Exited(_) => Color::DarkGrey,
+                }),
+                style::Print(format!("  {:<12}", status.to_string())),
+                style::SetForegroundColor(Color::DarkGrey),
+                style::Print(format!("{:>6}s  ", process.started_at.elapsed().as_secs())),
+                style::SetForegroundColor(Color::Reset),
+                style::Print(format!("{}\n", process.command)),
+            )?;
+        }
+        queue!(output, style::Print("\n"))?;
+        Ok(())
+    }
+
+    /// Prints the last lines of output of the process, without marking them as read.
+    pub async fn print_output(&self, id: usize, output: &mut impl Write) -> Result<()> {
+        let mut table = self.table();
+        let process = get_process(&mut table, id)?;
+        let status = process.status();
+        let process_output = process.output.lock().unwrap();
+        queue!(
+            output,
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print(format!("\n{} ({status})\n", process.command)),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+        for (name, stream) in [("stdout", &process_output.stdout), ("stderr", &process_output.stderr)] {
+            if stream.recent.is_empty() {
+                continue;
+            }
+            queue!(
+                output,
+                style::SetForegroundColor(Color::Cyan),
+                style::Print(format!("{name}:\n")),
+                style::SetForegroundColor(Color::Reset),
+            )?;
+            for line in &stream.recent {
+                queue!(output, style::Print(format!("  {line}\n")))?;
+            }
+        }
+        queue!(output, style::Print("\n"))?;
+        Ok(())
+    }
+}
+
+fn get_process(table: &mut ProcessTable, id: usize) -> Result<&mut BackgroundProcess> {
+    match table.processes.get_mut(&id) {
+        Some(process) => Ok(process),
+        None => bail!("There is no background process with the id {id}"),
+    }
+}
+
+async fn collect_output(
+    reader: impl AsyncRead + Unpin,
+    output: Arc<std::sync::Mutex<ProcessOutput>>,
+    stream: fn(&mut ProcessOutput) -> &mut StreamOutput,
+) {
+    let mut lines = tokio::io::BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        stream(&mut output.lock().unwrap()).push_line(line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    async fn read_until(processes: &BackgroundProcesses, id: usize, expected: &str) -> serde_json::Value {
+        let mut stdout = String::new();
+        for _ in 0..100 {
+            let output = processes.read(id).await.unwrap();
+            stdout.push_str(output["stdout"].as_str().unwrap());
+            if stdout.contains(expected) {
+                return serde_json::json!({ "status": output["status"], "stdout": stdout });
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("expected {expected:?} in the output, got {stdout:?}");
+    }
+
+    #[tokio::test]
+    async fn test_background_process() {
+        let processes = BackgroundProcesses::new();
+        let id = processes
+            .spawn("echo ready; while read line; do echo \"got $line\"; done", None)
+            .await
+            .unwrap();
+        assert!(processes.contains(id).await);
+        assert!(!processes.contains(id + 1).await);
+
+        let output = read_until(&processes, id, "ready\n").await;
+        assert_eq!(output["status"], "running");
+
+        processes.write(id, "hello").await.unwrap();
+        let output = read_until(&processes, id, "got hello\n").await;
+        // Output that was already read is not returned again.
+        assert_eq!(output["stdout"], "got hello\n");
+
+        assert_eq!(processes.kill(id).await.unwrap(), ProcessStatus::Exited(None));
+        assert!(processes.write(id, "again").await.is_err());
+        assert_eq!(processes.read(id).await.unwrap()["status"], "killed");
+
+        // The status of a process that already exited is kept.
+        let id = processes.spawn("exit 3", None).await.unwrap();
+        while processes.read(id).await.unwrap()["status"] == "running" {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(processes.kill(id).await.unwrap(), ProcessStatus::Exited(Some(3)));
+        processes.kill_all();
+        assert_eq!(processes.read(id).await.unwrap()["exit_status"], 3);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_kill_process_group() {
+        let processes = BackgroundProcesses::new();
+        let id = processes.spawn("sleep 30 & echo $!; wait", None).await.unwrap();
+        let output = read_until(&processes, id, "\n").await;
+        let pid = output["stdout"].as_str().unwrap().trim().to_string();
+
+        // The command started in the background by the shell is killed along with it.
+        assert_eq!(processes.kill(id).await.unwrap(), ProcessStatus::Exited(None));
+        let is_running = || {
+            std::fs::read_to_string(format!("/proc/{pid}/stat")).is_ok_and(|stat| {
+                stat.rsplit(')')
+                    .next()
+                    .is_some_and(|rest| !rest.trim_start().starts_with('Z'))
+            })
+        };
+        for _ in 0..100 {
+            if !is_running() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("process {pid} is still running");
+    }
+
+    #[tokio::test]
+    async fn test_bash_process_tool() {
+        let processes = BackgroundProcesses::new();
+        let id = processes.spawn("echo done; exit 3", None).await.unwrap();
+
+        let mut tool = serde_json::from_value::<BashProcess>(serde_json::json!({
+            "process_id": id,
+            "action": "read",
+        }))
+        .unwrap();
+        assert!(tool.processes().is_err());
+        tool.processes = Some(processes.clone());
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        tool.validate(&ctx).await.unwrap();
+        assert!(!tool.requires_acceptance());
+
+        let mut status = serde_json::Value::Null;
+        for _ in 0..100 {
+            let OutputKind::Json(output) = tool.invoke(std::io::sink()).await.unwrap().output else {
+                panic!("expected JSON output");
+            };
+            status = output["status"].clone();
+            if status != "running" {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert_eq!(status, "exited (3)");
+
+        let mut tool = serde_json::from_value::<BashProcess>(serde_json::json!({
+            "process_id": id + 1,
+            "action": "write",
+            "input": "y",
+        }))
+        .unwrap();
+        assert_eq!(tool.action, ProcessAction::Write { input: "y".to_string() });
+        assert!(tool.requires_acceptance());
+        tool.processes = Some(processes);
+        assert!(tool.validate(&ctx).await.is_err());
+    }
+
+    #[test]
+    fn test_stream_output_truncation() {
+        let mut stream = StreamOutput::default();
+        for _ in 0..MAX_UNREAD_OUTPUT / 10 + 1 {
+            stream.push_line("123456789".to_string());
+        }
+        assert_eq!(stream.recent.len(), RECENT_LINE_COUNT);
+        let unread = stream.take_unread();
+        assert!(unread.starts_with("... truncated\n"));
+        assert!(unread.len() <= MAX_UNREAD_OUTPUT + "... truncated\n".len());
+        assert_eq!(stream.take_unread(), "");
+    }
+}
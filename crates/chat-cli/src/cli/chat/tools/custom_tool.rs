@@ -7,7 +7,10 @@ use crossterm::{
     queue,
     style,
 };
-use eyre::Result;
+use eyre::{
+    Result,
+    bail,
+};
 use serde::{
     Deserialize,
     Serialize,
@@ -21,6 +24,8 @@ use crate::cli::chat::token_counter::TokenCounter;
 use crate::mcp_client::{
     Client as McpClient,
     ClientConfig as McpClientConfig,
+    HttpClientConfig as McpHttpClientConfig,
+    HttpTransport,
     JsonRpcResponse,
     JsonRpcStdioTransport,
     MessageContent,
@@ -32,18 +37,32 @@ use crate::mcp_client::{
 };
 use crate::platform::Context;
 
-// TODO: support http transport type
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct CustomToolConfig {
+    /// Command used to launch a local server. Mutually exclusive with `url`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub command: String,
     #[serde(default)]
     pub args: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub env: Option<HashMap<String, String>>,
+    /// Url of a remote server that speaks the streamable HTTP (or the older HTTP+SSE) transport.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Headers sent with every request to `url`, e.g. for authorization.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headers: Option<HashMap<String, String>>,
     #[serde(default = "default_timeout")]
     pub timeout: u64,
 }
 
+impl CustomToolConfig {
+    /// The url of the server if it is remote, otherwise the command used to launch it.
+    pub fn target(&self) -> &str {
+        self.url.as_deref().unwrap_or(&self.command)
+    }
+}
+
 pub fn default_timeout() -> u64 {
     120 * 1000
 }
@@ -55,34 +74,62 @@ pub enum CustomToolClient {
         client: McpClient<StdioTransport>,
         server_capabilities: RwLock<Option<ServerCapabilities>>,
     },
+    Http {
+        server_name: String,
+        client: McpClient<HttpTransport>,
+        server_capabilities: RwLock<Option<ServerCapabilities>>,
+    },
 }
 
 impl CustomToolClient {
-    // TODO: add support for http transport
     pub fn from_config(server_name: String, config: CustomToolConfig) -> Result<Self> {
         let CustomToolConfig {
             command,
             args,
             env,
+            url,
+            headers,
             timeout,
         } = config;
-        let mcp_client_config = McpClientConfig {
-            server_name: server_name.clone(),
-            bin_path: command.clone(),
-            args,
-            timeout,
-            client_info: serde_json::json!({
-               "name": "Q CLI Chat",
-               "version": "1.0.0"
-            }),
-            env,
-        };
-        let client = McpClient::<JsonRpcStdioTransport>::from_config(mcp_client_config)?;
-        Ok(CustomToolClient::Stdio {
-            server_name,
-            client,
-            server_capabilities: RwLock::new(None),
-        })
+        let client_info = serde_json::json!({
+           "name": "Q CLI Chat",
+           "version": "1.0.0"
+        });
+        match (url, command.is_empty()) {
+            (Some(_), false) => bail!("Server {server_name} cannot specify both a command and a url"),
+            (None, true) => bail!("Server {server_name} must specify either a command or a url"),
+            (Some(url), true) => {
+                let mcp_client_config = McpHttpClientConfig {
+                    server_name: server_name.clone(),
+                    url,
+                    headers: headers.unwrap_or_default(),
+                    timeout,
+                    client_info,
+                };
+                let client = McpClient::<HttpTransport>::from_http_config(mcp_client_config)?;
+                Ok(CustomToolClient::Http {
+                    server_name,
+                    client,
+                    server_capabilities: RwLock::new(None),
+                })
+            },
+            (None, false) => {
+                let mcp_client_config = McpClientConfig {
+                    server_name: server_name.clone(),
+                    bin_path: command.clone(),
+                    args,
+                    timeout,
+                    client_info,
+                    env,
+                };
+                let client = McpClient::<JsonRpcStdioTransport>::from_config(mcp_client_config)?;
+                Ok(CustomToolClient::Stdio {
+                    server_name,
+                    client,
+                    server_capabilities: RwLock::new(None),
+                })
+            },
+        }
     }
 
     pub async fn init(&self) -> Result<()> {
@@ -103,6 +150,18 @@ impl CustomToolClient {
                 server_capabilities.write().await.replace(cap);
                 Ok(())
             },
+            CustomToolClient::Http {
+                client,
+                server_capabilities,
+                ..
+            } => {
+                if let Some(messenger) = &client.messenger {
+                    let _ = messenger.send_init_msg().await;
+                }
+                let cap = client.init().await?;
+                server_capabilities.write().await.replace(cap);
+                Ok(())
+            },
         }
     }
 
@@ -111,24 +170,46 @@ impl CustomToolClient {
             CustomToolClient::Stdio { client, .. } => {
                 client.messenger = Some(messenger);
             },
+            CustomToolClient::Http { client, .. } => {
+                client.messenger = Some(messenger);
+            },
         }
     }
 
     pub fn get_server_name(&self) -> &str {
         match self {
-            CustomToolClient::Stdio { server_name, .. } => server_name.as_str(),
+            CustomToolClient::Stdio { server_name, .. } | CustomToolClient::Http { server_name, .. } => {
+                server_name.as_str()
+            },
+        }
+    }
+
+    /// Whether the server advertised the resources capability during initialization.
+    pub async fn supports_resources(&self) -> bool {
+        let (CustomToolClient::Stdio {
+            server_capabilities, ..
         }
+        | CustomToolClient::Http {
+            server_capabilities, ..
+        }) = self;
+        server_capabilities
+            .read()
+            .await
+            .as_ref()
+            .is_some_and(|cap| cap.resources.is_some())
     }
 
     pub async fn request(&self, method: &str, params: Option<serde_json::Value>) -> Result<JsonRpcResponse> {
         match self {
             CustomToolClient::Stdio { client, .. } => Ok(client.request(method, params).await?),
+            CustomToolClient::Http { client, .. } => Ok(client.request(method, params).await?),
         }
     }
 
     pub fn list_prompt_gets(&self) -> Arc<std::sync::RwLock<HashMap<String, PromptGet>>> {
         match self {
             CustomToolClient::Stdio { client, .. } => client.prompt_gets.clone(),
+            CustomToolClient::Http { client, .. } => client.prompt_gets.clone(),
         }
     }
 
@@ -136,18 +217,21 @@ impl CustomToolClient {
     pub async fn notify(&self, method: &str, params: Option<serde_json::Value>) -> Result<()> {
         match self {
             CustomToolClient::Stdio { client, .. } => Ok(client.notify(method, params).await?),
+            CustomToolClient::Http { client, .. } => Ok(client.notify(method, params).await?),
         }
     }
 
     pub fn is_prompts_out_of_date(&self) -> bool {
         match self {
             CustomToolClient::Stdio { client, .. } => client.is_prompts_out_of_date.load(Ordering::Relaxed),
+            CustomToolClient::Http { client, .. } => client.is_prompts_out_of_date.load(Ordering::Relaxed),
         }
     }
 
     pub fn prompts_updated(&self) {
         match self {
             CustomToolClient::Stdio { client, .. } => client.is_prompts_out_of_date.store(false, Ordering::Relaxed),
+            CustomToolClient::Http { client, .. } => client.is_prompts_out_of_date.store(false, Ordering::Relaxed),
         }
     }
 }
@@ -166,6 +250,8 @@ pub struct CustomTool {
     /// Optional parameters to pass to the tool when invoking the method.
     /// Structured as a JSON value to accommodate various parameter types and structures.
     pub params: Option<serde_json::Value>,
+    /// Whether the server marks the tool as read-only with the `readOnlyHint` annotation.
+    pub read_only: bool,
 }
 
 impl CustomTool {
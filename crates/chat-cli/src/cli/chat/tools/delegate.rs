@@ -0,0 +1,461 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::{
+    Arc,
+    Mutex,
+};
+
+use crossterm::queue;
+use crossterm::style::{
+    self,
+    Color,
+    Stylize,
+};
+use eyre::{
+    Result,
+    bail,
+    eyre,
+};
+use futures::future::join_all;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::sandbox::SandboxPolicy;
+use super::{
+    InvokeOutput,
+    OutputKind,
+    PermissionDecision,
+    Tool,
+    ToolPermissions,
+    ToolSpec,
+};
+use crate::api_client::StreamingClient;
+use crate::api_client::model::ToolResultStatus;
+use crate::cli::chat::context::ContextManager;
+use crate::cli::chat::conversation_state::ConversationState;
+use crate::cli::chat::hooks::{
+    HookDecision,
+    HookInput,
+    HookTrigger,
+};
+use crate::cli::chat::message::{
+    AssistantToolUse,
+    ToolUseResult,
+    ToolUseResultBlock,
+};
+use crate::cli::chat::parser::{
+    ResponseEvent,
+    ResponseParser,
+};
+use crate::cli::chat::tool_manager::ToolManager;
+use crate::platform::Context;
+
+/// Tools that sub-agents are allowed to use. Since there is no user to ask for approval, a tool
+/// use additionally has to be allowed by the [ToolPermissions] of the parent conversation, and
+/// goes through the same pre_tool_use and post_tool_use hooks.
+const SUB_AGENT_TOOLS: &[&str] = &["fs_read", "execute_bash", "use_aws", "knowledge_search", "thinking"];
+
+/// Maximum number of tasks that can be delegated in a single tool use.
+const MAX_TASKS: usize = 4;
+
+/// Maximum number of requests a sub-agent can send before it is stopped.
+const MAX_SUB_AGENT_TURNS: usize = 25;
+
+/// Maximum number of characters of a tool use's arguments shown in the progress of a sub-agent.
+const MAX_PROGRESS_ARGS_LEN: usize = 80;
+
+const SUB_AGENT_INSTRUCTIONS: &str = "You are a sub-agent working on a task delegated to you by another agent. \
+You cannot ask the user for clarification or for approval, so tool uses that need the user's approval are refused. \
+Prefer read-only tools and commands. \
+When you are done, reply with a concise summary of your findings, including relevant file paths and details. \
+Your final reply is the only thing returned to the other agent.\n\nTask:\n";
+
+/// Runs tasks in isolated conversations with a restricted set of tools, returning only the
+/// final summary of each sub-agent.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Delegate {
+    pub tasks: Vec<DelegateTask>,
+
+    #[serde(skip_deserializing)]
+    pub context: Option<DelegateContext>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DelegateTask {
+    /// Short label used to prefix the progress of the sub-agent
+    pub description: String,
+    /// Instructions sent to the sub-agent
+    pub prompt: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct DelegateContext {
+    pub ctx: Arc<Context>,
+    pub client: StreamingClient,
+    /// Tool specs of the parent conversation, narrowed down to [SUB_AGENT_TOOLS] for sub-agents.
+    pub tool_config: HashMap<String, ToolSpec>,
+    pub tool_permissions: ToolPermissions,
+    pub profile: Option<String>,
+    /// Model of the parent conversation, which sub-agents use as well.
+    pub model: Option<String>,
+    pub sandbox: Option<SandboxPolicy>,
+    /// Provides the lifecycle hooks of the parent conversation.
+    pub context_manager: Option<ContextManager>,
+}
+
+impl Delegate {
+    pub fn queue_description(&self, updates: &mut impl Write) -> Result<()> {
+        queue!(updates, style::Print("Delegating to sub-agents:\n"))?;
+        for task in &self.tasks {
+            queue!(
+                updates,
+                style::Print("- "),
+                style::SetForegroundColor(Color::Green),
+                style::Print(&task.description),
+                style::ResetColor,
+                style::Print("\n"),
+            )?;
+        }
+        Ok(())
+    }
+
+    pub async fn invoke(&self, updates: &mut impl Write) -> Result<InvokeOutput> {
+        let Some(context) = self.context.as_ref() else {
+            return Err(eyre!(
+                "delegate: Required tool context (DelegateContext) not set by the program."
+            ));
+        };
+
+        let updates = Mutex::new(updates);
+        let results = join_all(self.tasks.iter().map(|task| {
+            let mut progress = PrefixedWriter::new(format!("[{}] ", task.description), &updates);
+            async move {
+                let result = run_sub_agent(context, task, &mut progress).await;
+                match &result {
+                    Ok(_) => writeln!(progress, "{}", "Done".green())?,
+                    Err(err) => writeln!(progress, "{}", format!("Failed: {err}").red())?,
+                }
+                progress.flush()?;
+                Ok::<_, eyre::Report>(result)
+            }
+        }))
+        .await;
+
+        let mut sections = Vec::new();
+        let mut failures = 0;
+        for (task, result) in self.tasks.iter().zip(results) {
+            let summary = match result? {
+                Ok(summary) => summary,
+                Err(err) => {
+                    failures += 1;
+                    format!("The sub-agent failed: {err}")
+                },
+            };
+            sections.push(format!("## {}\n\n{}", task.description, summary));
+        }
+        if failures == self.tasks.len() {
+            bail!("{}", sections.join("\n\n"));
+        }
+
+        Ok(InvokeOutput {
+            output: OutputKind::Text(sections.join("\n\n")),
+        })
+    }
+
+    pub async fn validate(&mut self, _ctx: &Context) -> Result<()> {
+        if self.tasks.is_empty() {
+            bail!("At least one task must be provided");
+        }
+        if self.tasks.len() > MAX_TASKS {
+            bail!("At most {MAX_TASKS} tasks can be delegated at once");
+        }
+        if self
+            .tasks
+            .iter()
+            .any(|task| task.description.trim().is_empty() || task.prompt.trim().is_empty())
+        {
+            bail!("Every task must have a description and a prompt");
+        }
+        Ok(())
+    }
+}
+
+/// Runs a single task to completion in its own conversation, returning the final response.
+async fn run_sub_agent(context: &DelegateContext, task: &DelegateTask, progress: &mut impl Write) -> Result<String> {
+    let tool_config = context
+        .tool_config
+        .iter()
+        .filter(|(name, _)| SUB_AGENT_TOOLS.contains(&name.as_str()))
+        .map(|(name, spec)| (name.clone(), spec.clone()))
+        .collect();
+    let tool_manager = ToolManager::default();
+    let mut context_manager = context.context_manager.clone();
+    let mut conversation = ConversationState::new(
+        Arc::clone(&context.ctx),
+        &Uuid::new_v4().to_string(),
+        tool_config,
+        context.profile.clone(),
+        None,
+        tool_manager.clone(),
+    )
+    .await;
+    conversation.set_model(context.model.clone());
+    conversation
+        .set_next_user_message(format!("{SUB_AGENT_INSTRUCTIONS}{}", task.prompt))
+        .await;
+
+    for _ in 0..MAX_SUB_AGENT_TURNS {
+        let response = context
+            .client
+            .send_message(conversation.as_sendable_conversation_state(false).await)
+            .await?;
+        let mut parser = ResponseParser::new(response);
+        let message = loop {
+            if let ResponseEvent::EndStream { message } = parser.recv().await? {
+                break message;
+            }
+        };
+
+        let tool_uses = message.tool_uses().map(<[_]>::to_vec).unwrap_or_default();
+        let content = message.content().to_string();
+        conversation.record_assistant_message(message);
+        if tool_uses.is_empty() {
+            return Ok(content);
+        }
+
+        let mut tool_results = Vec::new();
+        for tool_use in tool_uses {
+            let conversation_id = conversation.conversation_id();
+            tool_results.push(
+                run_tool(
+                    context,
+                    &tool_manager,
+                    &mut context_manager,
+                    conversation_id,
+                    tool_use,
+                    progress,
+                )
+                .await?,
+            );
+        }
+        conversation.add_tool_results(tool_results);
+    }
+
+    bail!("The sub-agent did not finish within {MAX_SUB_AGENT_TURNS} requests")
+}
+
+/// Runs a tool use of a sub-agent the way [crate::cli::chat::ChatContext] does, except that tool
+/// uses needing approval are refused.
+async fn run_tool(
+    context: &DelegateContext,
+    tool_manager: &ToolManager,
+    context_manager: &mut Option<ContextManager>,
+    conversation_id: &str,
+    mut tool_use: AssistantToolUse,
+    progress: &mut impl Write,
+) -> Result<ToolUseResult> {
+    let ctx = context.ctx.as_ref();
+    let tool_use_id = tool_use.id.clone();
+    let name = tool_use.name.clone();
+    let error = |text: String| ToolUseResult {
+        tool_use_id: tool_use_id.clone(),
+        content: vec![ToolUseResultBlock::Text(text)],
+        status: ToolResultStatus::Error,
+    };
+
+    let args = tool_use.args.to_string();
+    let args = match args.char_indices().nth(MAX_PROGRESS_ARGS_LEN) {
+        Some((i, _)) => format!("{}...", &args[..i]),
+        None => args,
+    };
+    writeln!(progress, "● {} {}", name, args.dark_grey())?;
+
+    if !SUB_AGENT_TOOLS.contains(&name.as_str()) {
+        return Ok(error(format!("The tool {name} is not available to sub-agents")));
+    }
+
+    let cwd = ctx.env().current_dir().unwrap_or_default();
+    let hook_input = |trigger: HookTrigger, tool_input: serde_json::Value| {
+        let mut input = HookInput::new(trigger, conversation_id, cwd.to_string_lossy());
+        input.tool_name = Some(name.clone());
+        input.tool_input = Some(tool_input);
+        input
+    };
+    let input = hook_input(HookTrigger::PreToolUse, tool_use.args.clone());
+    match run_lifecycle_hooks(context_manager, input, progress).await {
+        HookDecision::Allow { tool_input: None } => (),
+        HookDecision::Allow {
+            tool_input: Some(tool_input),
+        } => {
+            tool_use.args = tool_input.clone();
+            tool_use.orig_args = tool_input;
+        },
+        HookDecision::Block { hook, reason } => {
+            return Ok(error(format!(
+                "The tool use was blocked by the user's pre_tool_use hook '{hook}': {reason}"
+            )));
+        },
+    }
+    let tool_input = tool_use.args.clone();
+
+    let mut tool = match tool_manager.get_tool_from_tool_use(tool_use) {
+        Ok(tool) => tool,
+        Err(err) => return Ok(err.into()),
+    };
+    if let Err(err) = tool.validate(ctx).await {
+        return Ok(error(format!("Failed to validate tool parameters: {err}")));
+    }
+    if let Tool::ExecuteBash(execute_bash) = &mut tool {
+        execute_bash.sandbox = context.sandbox.clone();
+    }
+    match context.tool_permissions.evaluate(ctx, &name, &tool) {
+        PermissionDecision::Allow => (),
+        PermissionDecision::Ask => {
+            return Ok(error(
+                "This tool use requires the user's approval, which is not available to sub-agents. Use a read-only alternative, or mention what is left to do in your summary.".to_string(),
+            ));
+        },
+        PermissionDecision::Deny(rule) => {
+            return Ok(error(format!(
+                "The tool use was denied by the user's permission rule `{rule}`. Do not retry it with the same arguments."
+            )));
+        },
+    }
+
+    // The output of the tool is returned to the sub-agent only.
+    let mut result = match tool.invoke(ctx, &mut std::io::sink()).await {
+        Ok(output) => ToolUseResult {
+            tool_use_id,
+            content: vec![output.into()],
+            status: ToolResultStatus::Success,
+        },
+        Err(err) => error(format!("An error occurred processing the tool: \n{err}")),
+    };
+
+    let mut input = hook_input(HookTrigger::PostToolUse, tool_input);
+    input.set_tool_response(&result);
+    if let HookDecision::Block { hook, reason } = run_lifecycle_hooks(context_manager, input, progress).await {
+        result.content.push(ToolUseResultBlock::Text(format!(
+            "Feedback from the user's post_tool_use hook '{hook}': {reason}"
+        )));
+    }
+    Ok(result)
+}
+
+async fn run_lifecycle_hooks(
+    context_manager: &mut Option<ContextManager>,
+    input: HookInput,
+    progress: &mut impl Write,
+) -> HookDecision {
+    match context_manager {
+        Some(context_manager) => context_manager.run_lifecycle_hooks(input, progress).await,
+        None => HookDecision::Allow { tool_input: None },
+    }
+}
+
+/// Writes complete lines to a shared writer with a prefix, so that the progress of sub-agents
+/// running in parallel does not interleave within a line.
+struct PrefixedWriter<'a, W: Write> {
+    prefix: String,
+    out: &'a Mutex<W>,
+    buf: Vec<u8>,
+}
+
+impl<'a, W: Write> PrefixedWriter<'a, W> {
+    fn new(prefix: String, out: &'a Mutex<W>) -> Self {
+        Self {
+            prefix,
+            out,
+            buf: Vec::new(),
+        }
+    }
+
+    fn write_line(&mut self, line: &[u8]) -> std::io::Result<()> {
+        let mut out = self.out.lock().map_err(|e| std::io::Error::other(e.to_string()))?;
+        out.write_all(self.prefix.clone().dark_grey().to_string().as_bytes())?;
+        out.write_all(line)?;
+        out.write_all(b"\n")?;
+        out.flush()
+    }
+}
+
+impl<W: Write> Write for PrefixedWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        while let Some(pos) = self.buf.iter().position(|b| *b == b'\n') {
+            let line = self.buf.drain(..=pos).collect::<Vec<_>>();
+            self.write_line(&line[..line.len() - 1])?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if !self.buf.is_empty() {
+            let line = std::mem::take(&mut self.buf);
+            self.write_line(&line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_delegate_deserialize_and_validate() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+
+        let mut tool = serde_json::from_value::<Delegate>(serde_json::json!({
+            "tasks": [
+                { "description": "Find handlers", "prompt": "Find the request handlers" },
+                { "description": "Find tests", "prompt": "Find the tests of the handlers" },
+            ]
+        }))
+        .unwrap();
+        assert_eq!(tool.tasks.len(), 2);
+        assert!(tool.context.is_none());
+        assert!(tool.validate(&ctx).await.is_ok());
+
+        let mut tool = serde_json::from_value::<Delegate>(serde_json::json!({ "tasks": [] })).unwrap();
+        assert!(tool.validate(&ctx).await.is_err());
+
+        let mut tool = serde_json::from_value::<Delegate>(serde_json::json!({
+            "tasks": [{ "description": " ", "prompt": "Find the request handlers" }]
+        }))
+        .unwrap();
+        assert!(tool.validate(&ctx).await.is_err());
+
+        let task = serde_json::json!({ "description": "a", "prompt": "b" });
+        let mut tool =
+            serde_json::from_value::<Delegate>(serde_json::json!({ "tasks": vec![task; MAX_TASKS + 1] })).unwrap();
+        assert!(tool.validate(&ctx).await.is_err());
+    }
+
+    #[test]
+    fn test_prefixed_writer() {
+        let out = Mutex::new(Vec::<u8>::new());
+        let mut first = PrefixedWriter::new("[a] ".to_string(), &out);
+        let mut second = PrefixedWriter::new("[b] ".to_string(), &out);
+
+        write!(first, "one ").unwrap();
+        writeln!(second, "two").unwrap();
+        writeln!(first, "three\nfour").unwrap();
+        write!(second, "five").unwrap();
+        assert_eq!(first.buf.len(), 0);
+        second.flush().unwrap();
+
+        let prefix = |p: &str| p.to_string().dark_grey().to_string();
+        let out = String::from_utf8(out.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            out,
+            format!(
+                "{}two\n{}one three\n{}four\n{}five\n",
+                prefix("[b] "),
+                prefix("[a] "),
+                prefix("[a] "),
+                prefix("[b] ")
+            )
+        );
+    }
+}
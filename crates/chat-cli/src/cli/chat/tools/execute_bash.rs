@@ -14,6 +14,7 @@ use crossterm::style::{
 use eyre::{
     Context as EyreContext,
     Result,
+    bail,
 };
 use serde::Deserialize;
 use tokio::io::AsyncBufReadExt;
@@ -21,6 +22,8 @@ use tokio::select;
 use tracing::error;
 
 use super::super::util::truncate_safe;
+use super::bash_process::BackgroundProcesses;
+use super::sandbox::SandboxPolicy;
 use super::{
     InvokeOutput,
     MAX_TOOL_RESPONSE_SIZE,
@@ -32,11 +35,27 @@ use crate::cli::chat::{
 };
 use crate::platform::Context;
 const READONLY_COMMANDS: &[&str] = &["ls", "cat", "echo", "pwd", "which", "head", "tail", "find", "grep"];
+const DANGEROUS_PATTERNS: &[&str] = &["<(", "$(", "`", ">", "&&", "||", "&", ";"];
+
+/// Whether `command` chains, pipes, redirects, or substitutes other commands.
+pub fn has_shell_operators(command: &str) -> bool {
+    command.contains(['|', '\n']) || DANGEROUS_PATTERNS.iter().any(|p| command.contains(p))
+}
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct ExecuteBash {
     pub command: String,
     pub summary: Option<String>,
+    /// Run the command in the background, returning a process id to use with the `bash_process`
+    /// tool instead of waiting for it to exit.
+    #[serde(default)]
+    pub background: bool,
+    /// Set by the chat session before background commands are run.
+    #[serde(skip)]
+    pub processes: Option<BackgroundProcesses>,
+    /// Set from the `chat.sandbox.*` settings before the command is run.
+    #[serde(skip)]
+    pub sandbox: Option<SandboxPolicy>,
 }
 
 impl ExecuteBash {
@@ -45,7 +64,6 @@ impl ExecuteBash {
             return true;
         };
 
-        const DANGEROUS_PATTERNS: &[&str] = &["<(", "$(", "`", ">", "&&", "||", "&", ";"];
         if args
             .iter()
             .any(|arg| DANGEROUS_PATTERNS.iter().any(|p| arg.contains(p)))
@@ -97,8 +115,28 @@ impl ExecuteBash {
         false
     }
 
-    pub async fn invoke(&self, updates: impl Write) -> Result<InvokeOutput> {
-        let output = run_command(&self.command, MAX_TOOL_RESPONSE_SIZE / 3, Some(updates)).await?;
+    pub async fn invoke(&self, mut updates: impl Write) -> Result<InvokeOutput> {
+        if self.background {
+            let Some(processes) = &self.processes else {
+                bail!("Background commands are not available here, run the command without `background` instead");
+            };
+            let process_id = processes.spawn(&self.command, self.sandbox.as_ref()).await?;
+            writeln!(updates, "Started background process {process_id}")?;
+            return Ok(InvokeOutput {
+                output: OutputKind::Json(serde_json::json!({
+                    "process_id": process_id,
+                    "status": "running",
+                })),
+            });
+        }
+
+        let output = run_command(
+            &self.command,
+            MAX_TOOL_RESPONSE_SIZE / 3,
+            Some(updates),
+            self.sandbox.as_ref(),
+        )
+        .await?;
         let result = serde_json::json!({
             "exit_status": output.exit_status.unwrap_or(0).to_string(),
             "stdout": output.stdout,
@@ -111,7 +149,11 @@ impl ExecuteBash {
     }
 
     pub fn queue_description(&self, updates: &mut impl Write) -> Result<()> {
-        queue!(updates, style::Print("I will run the following shell command: "),)?;
+        let label = match self.background {
+            true => "I will run the following shell command in the background: ",
+            false => "I will run the following shell command: ",
+        };
+        queue!(updates, style::Print(label))?;
 
         // TODO: Could use graphemes for a better heuristic
         if self.command.len() > 20 {
@@ -141,6 +183,27 @@ impl ExecuteBash {
             )?;
         }
 
+        if let Some(sandbox) = &self.sandbox {
+            queue!(
+                updates,
+                style::SetForegroundColor(Color::DarkGrey),
+                style::Print(format!(
+                    "Sandboxed: {}, {}\n",
+                    if sandbox.allow_writes {
+                        "writes allowed under the current directory"
+                    } else {
+                        "read only"
+                    },
+                    if sandbox.allow_network {
+                        "network allowed"
+                    } else {
+                        "no network"
+                    }
+                )),
+                style::ResetColor,
+            )?;
+        }
+
         queue!(updates, style::Print("\n"))?;
 
         Ok(())
@@ -160,21 +223,33 @@ pub struct CommandResult {
     pub stderr: String,
 }
 
+/// Builds the `bash -c` invocation of `command`, confined to `sandbox` if any.
+pub fn bash_command(command: &str, sandbox: Option<&SandboxPolicy>) -> Result<tokio::process::Command> {
+    let mut cmd = tokio::process::Command::new("bash");
+    cmd.arg("-c").arg(command);
+    if let Some(sandbox) = sandbox {
+        sandbox
+            .apply(&mut cmd, &std::env::current_dir()?)
+            .wrap_err("Unable to set up the execute_bash sandbox")?;
+    }
+    Ok(cmd)
+}
+
 /// Run a bash command.
 /// # Arguments
 /// * `max_result_size` - max size of output streams, truncating if required
 /// * `updates` - output stream to push informational messages about the progress
+/// * `sandbox` - restrictions to run the command with, if any
 /// # Returns
 /// A [`CommandResult`]
 pub async fn run_command<W: Write>(
     command: &str,
     max_result_size: usize,
     mut updates: Option<W>,
+    sandbox: Option<&SandboxPolicy>,
 ) -> Result<CommandResult> {
     // We need to maintain a handle on stderr and stdout, but pipe it to the terminal as well
-    let mut child = tokio::process::Command::new("bash")
-        .arg("-c")
-        .arg(command)
+    let mut child = bash_command(command, sandbox)?
         .stdin(Stdio::inherit())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
@@ -1,6 +1,10 @@
 use std::collections::VecDeque;
 use std::fs::Metadata;
 use std::io::Write;
+use std::path::{
+    Path,
+    PathBuf,
+};
 
 use crossterm::queue;
 use crossterm::style::{
@@ -11,6 +15,14 @@ use eyre::{
     Result,
     bail,
 };
+use globset::{
+    GlobBuilder,
+    GlobMatcher,
+};
+use regex::{
+    Regex,
+    RegexBuilder,
+};
 use serde::{
     Deserialize,
     Serialize,
@@ -33,6 +45,7 @@ use crate::cli::chat::util::images::{
     is_supported_image_type,
     pre_process,
 };
+use crate::cli::chat::util::truncate_safe;
 use crate::platform::Context;
 
 #[derive(Debug, Clone, Deserialize)]
@@ -41,6 +54,8 @@ pub enum FsRead {
     Line(FsLine),
     Directory(FsDirectory),
     Search(FsSearch),
+    Grep(FsGrep),
+    Glob(FsGlob),
     Image(FsImage),
 }
 
@@ -50,15 +65,35 @@ impl FsRead {
             FsRead::Line(fs_line) => fs_line.validate(ctx).await,
             FsRead::Directory(fs_directory) => fs_directory.validate(ctx).await,
             FsRead::Search(fs_search) => fs_search.validate(ctx).await,
+            FsRead::Grep(fs_grep) => fs_grep.validate(ctx).await,
+            FsRead::Glob(fs_glob) => fs_glob.validate(ctx).await,
             FsRead::Image(fs_image) => fs_image.validate(ctx).await,
         }
     }
 
+    /// Paths read by the tool use.
+    pub fn affected_paths(&self, ctx: &Context) -> Vec<PathBuf> {
+        match self {
+            FsRead::Line(fs_line) => vec![sanitize_path_tool_arg(ctx, &fs_line.path)],
+            FsRead::Directory(fs_directory) => vec![sanitize_path_tool_arg(ctx, &fs_directory.path)],
+            FsRead::Search(fs_search) => vec![sanitize_path_tool_arg(ctx, &fs_search.path)],
+            FsRead::Grep(fs_grep) => vec![sanitize_path_tool_arg(ctx, &fs_grep.path)],
+            FsRead::Glob(fs_glob) => vec![sanitize_path_tool_arg(ctx, &fs_glob.path)],
+            FsRead::Image(fs_image) => fs_image
+                .image_paths
+                .iter()
+                .map(|path| sanitize_path_tool_arg(ctx, path))
+                .collect(),
+        }
+    }
+
     pub async fn queue_description(&self, ctx: &Context, updates: &mut impl Write) -> Result<()> {
         match self {
             FsRead::Line(fs_line) => fs_line.queue_description(ctx, updates).await,
             FsRead::Directory(fs_directory) => fs_directory.queue_description(updates),
             FsRead::Search(fs_search) => fs_search.queue_description(updates),
+            FsRead::Grep(fs_grep) => fs_grep.queue_description(updates),
+            FsRead::Glob(fs_glob) => fs_glob.queue_description(updates),
             FsRead::Image(fs_image) => fs_image.queue_description(updates),
         }
     }
@@ -68,6 +103,8 @@ impl FsRead {
             FsRead::Line(fs_line) => fs_line.invoke(ctx, updates).await,
             FsRead::Directory(fs_directory) => fs_directory.invoke(ctx, updates).await,
             FsRead::Search(fs_search) => fs_search.invoke(ctx, updates).await,
+            FsRead::Grep(fs_grep) => fs_grep.invoke(ctx, updates).await,
+            FsRead::Glob(fs_glob) => fs_glob.invoke(ctx, updates).await,
             FsRead::Image(fs_image) => fs_image.invoke(ctx, updates).await,
         }
     }
@@ -334,6 +371,345 @@ impl FsSearch {
     }
 }
 
+/// Search for a regex in the files under a directory.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FsGrep {
+    pub path: String,
+    pub pattern: String,
+    /// Only search files matching this glob, e.g. `*.rs`.
+    pub include: Option<String>,
+    /// Defaults to case insensitive, unless the pattern contains an uppercase character outside
+    /// of an escape sequence such as `\S`.
+    pub case_sensitive: Option<bool>,
+    pub context_lines: Option<usize>,
+    pub max_results: Option<usize>,
+}
+
+impl FsGrep {
+    const DEFAULT_CONTEXT_LINES: usize = 0;
+    const DEFAULT_MAX_RESULTS: usize = 100;
+    /// Larger files, e.g. logs and generated data, are not searched.
+    const MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+    /// Longer lines, e.g. from minified files, are truncated.
+    const MAX_LINE_LENGTH: usize = 300;
+
+    pub async fn validate(&mut self, ctx: &Context) -> Result<()> {
+        let path = sanitize_path_tool_arg(ctx, &self.path);
+        if !ctx.fs().exists(&path) {
+            bail!("Path not found: {}", format_path(ctx.env().current_dir()?, &path));
+        }
+        if self.pattern.is_empty() {
+            bail!("Search pattern cannot be empty");
+        }
+        self.regex()?;
+        if let Some(include) = &self.include {
+            PathGlob::new(include)?;
+        }
+        Ok(())
+    }
+
+    pub fn queue_description(&self, updates: &mut impl Write) -> Result<()> {
+        queue!(
+            updates,
+            style::Print("Searching: "),
+            style::SetForegroundColor(Color::Green),
+            style::Print(&self.path),
+            style::ResetColor,
+            style::Print(" for regex: "),
+            style::SetForegroundColor(Color::Green),
+            style::Print(&self.pattern),
+            style::ResetColor,
+        )?;
+        if let Some(include) = &self.include {
+            queue!(
+                updates,
+                style::Print(" in files matching "),
+                style::SetForegroundColor(Color::Green),
+                style::Print(include),
+                style::ResetColor,
+            )?;
+        }
+        Ok(())
+    }
+
+    pub async fn invoke(&self, ctx: &Context, updates: &mut impl Write) -> Result<InvokeOutput> {
+        let root = sanitize_path_tool_arg(ctx, &self.path);
+        let regex = self.regex()?;
+        let include = self.include.as_deref().map(PathGlob::new).transpose()?;
+        let files =
+            tokio::task::spawn_blocking(move || walk_files(&root, include.as_ref()).collect::<Vec<_>>()).await?;
+        let (output, match_count, file_count) = self.search(ctx, files, &regex).await;
+
+        queue!(
+            updates,
+            style::Print(format!(
+                "Found {match_count} matches in {file_count} files for regex '{}'\n",
+                self.pattern
+            )),
+        )?;
+
+        Ok(InvokeOutput {
+            output: OutputKind::Text(output),
+        })
+    }
+
+    /// Returns the output for the model along with the number of matches and matching files.
+    async fn search(&self, ctx: &Context, files: Vec<(PathBuf, PathBuf)>, regex: &Regex) -> (String, usize, usize) {
+        let context_lines = self.context_lines.unwrap_or(Self::DEFAULT_CONTEXT_LINES);
+        let max_results = self
+            .max_results
+            .unwrap_or(Self::DEFAULT_MAX_RESULTS)
+            .clamp(1, MAX_RESULTS);
+        let mut output = String::new();
+        let (mut match_count, mut file_count) = (0, 0);
+
+        for (path, relative_path) in files {
+            if ctx
+                .fs()
+                .symlink_metadata(&path)
+                .await
+                .map_or(true, |metadata| metadata.len() > Self::MAX_FILE_SIZE)
+            {
+                continue;
+            }
+            let Ok(content) = ctx.fs().read(&path).await else {
+                continue;
+            };
+            // Skip binary files.
+            if content.iter().take(8192).any(|b| *b == 0) {
+                continue;
+            }
+            let content = String::from_utf8_lossy(&content);
+            let lines = content.lines().collect::<Vec<_>>();
+            let matches = lines
+                .iter()
+                .enumerate()
+                .filter(|(_, line)| regex.is_match(line))
+                .map(|(i, _)| i)
+                .take(max_results - match_count)
+                .collect::<Vec<_>>();
+            if matches.is_empty() {
+                continue;
+            }
+
+            let display_path = display_path(&self.path, &relative_path);
+            let mut printed_until = 0;
+            for &i in &matches {
+                let start = i.saturating_sub(context_lines).max(printed_until);
+                let end = lines.len().min(i + context_lines + 1);
+                if context_lines > 0 && start > printed_until && printed_until > 0 {
+                    output.push_str("--\n");
+                }
+                for (j, line) in lines.iter().enumerate().take(end).skip(start) {
+                    let separator = if matches.binary_search(&j).is_ok() { ':' } else { '-' };
+                    let line = truncate_safe(line, Self::MAX_LINE_LENGTH);
+                    output.push_str(&format!("{display_path}{separator}{}{separator} {line}\n", j + 1));
+                }
+                printed_until = end;
+            }
+            if context_lines > 0 {
+                output.push_str("--\n");
+            }
+            match_count += matches.len();
+            file_count += 1;
+
+            if output.len() > MAX_TOOL_RESPONSE_SIZE {
+                output.truncate(truncate_safe(&output, MAX_TOOL_RESPONSE_SIZE).len());
+                output.push_str(&format!(
+                    "\n(Output truncated at {MAX_TOOL_RESPONSE_SIZE} bytes, narrow the search with a more specific path, pattern or include glob)"
+                ));
+                break;
+            }
+            if match_count >= max_results {
+                output.push_str(&format!(
+                    "(Results limited to {max_results} matches, narrow the search with a more specific path, pattern or include glob)"
+                ));
+                break;
+            }
+        }
+
+        if match_count == 0 {
+            output = format!("No matches found for regex '{}'", self.pattern);
+        }
+        (output, match_count, file_count)
+    }
+
+    fn regex(&self) -> Result<Regex> {
+        let case_sensitive = self.case_sensitive.unwrap_or_else(|| has_uppercase(&self.pattern));
+        Ok(RegexBuilder::new(&self.pattern)
+            .case_insensitive(!case_sensitive)
+            .build()?)
+    }
+}
+
+/// Returns whether a regex pattern contains an uppercase character, ignoring escape sequences such
+/// as `\W` and `\p{Lu}`.
+fn has_uppercase(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                // Unicode classes are named either with a single letter as in `\pL`, or in braces.
+                if let Some('p' | 'P') = chars.next() {
+                    if chars.next() == Some('{') {
+                        chars.find(|c| *c == '}');
+                    }
+                }
+            },
+            c if c.is_uppercase() => return true,
+            _ => (),
+        }
+    }
+    false
+}
+
+/// Find files under a directory with a glob.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FsGlob {
+    pub path: String,
+    pub pattern: String,
+    pub max_results: Option<usize>,
+}
+
+impl FsGlob {
+    const DEFAULT_MAX_RESULTS: usize = 200;
+
+    pub async fn validate(&mut self, ctx: &Context) -> Result<()> {
+        let path = sanitize_path_tool_arg(ctx, &self.path);
+        let relative_path = format_path(ctx.env().current_dir()?, &path);
+        if !ctx.fs().exists(&path) {
+            bail!("Directory not found: {}", relative_path);
+        }
+        if !ctx.fs().symlink_metadata(path).await?.is_dir() {
+            bail!("Path is not a directory: {}", relative_path);
+        }
+        PathGlob::new(&self.pattern)?;
+        Ok(())
+    }
+
+    pub fn queue_description(&self, updates: &mut impl Write) -> Result<()> {
+        queue!(
+            updates,
+            style::Print("Finding files: "),
+            style::SetForegroundColor(Color::Green),
+            style::Print(&self.pattern),
+            style::ResetColor,
+            style::Print(" in "),
+            style::SetForegroundColor(Color::Green),
+            style::Print(&self.path),
+            style::ResetColor,
+        )?;
+        Ok(())
+    }
+
+    pub async fn invoke(&self, ctx: &Context, updates: &mut impl Write) -> Result<InvokeOutput> {
+        let root = sanitize_path_tool_arg(ctx, &self.path);
+        let glob = PathGlob::new(&self.pattern)?;
+        let max_results = self
+            .max_results
+            .unwrap_or(Self::DEFAULT_MAX_RESULTS)
+            .clamp(1, MAX_RESULTS);
+        let path = self.path.clone();
+        let mut files = tokio::task::spawn_blocking(move || {
+            walk_files(&root, Some(&glob))
+                .map(|(_, relative_path)| display_path(&path, &relative_path))
+                .take(max_results + 1)
+                .collect::<Vec<_>>()
+        })
+        .await?;
+
+        queue!(
+            updates,
+            style::Print(format!("Found {} files\n", files.len().min(max_results)))
+        )?;
+
+        let truncated = files.len() > max_results;
+        files.truncate(max_results);
+        let mut output = files.join("\n");
+        if files.is_empty() {
+            output = format!("No files found matching '{}'", self.pattern);
+        } else if truncated {
+            output.push_str(&format!(
+                "\n(Results limited to {max_results} files, narrow the search with a more specific path or pattern)"
+            ));
+        }
+        if output.len() > MAX_TOOL_RESPONSE_SIZE {
+            bail!(
+                "This tool only supports reading up to {MAX_TOOL_RESPONSE_SIZE} bytes at a time. Try executing with a lower max_results."
+            );
+        }
+
+        Ok(InvokeOutput {
+            output: OutputKind::Text(output),
+        })
+    }
+}
+
+/// Upper bound for the `max_results` of [FsGrep] and [FsGlob].
+const MAX_RESULTS: usize = 1000;
+
+/// A glob matched against paths relative to the search root, or against file names if it
+/// doesn't contain a `/`, like in `.gitignore`.
+struct PathGlob {
+    matcher: GlobMatcher,
+    match_file_name: bool,
+}
+
+impl PathGlob {
+    fn new(glob: &str) -> Result<Self> {
+        Ok(Self {
+            matcher: GlobBuilder::new(glob)
+                .literal_separator(true)
+                .build()?
+                .compile_matcher(),
+            match_file_name: !glob.contains('/'),
+        })
+    }
+
+    fn is_match(&self, relative_path: &Path) -> bool {
+        match relative_path.file_name() {
+            Some(file_name) if self.match_file_name => self.matcher.is_match(file_name),
+            _ => self.matcher.is_match(relative_path),
+        }
+    }
+}
+
+/// Returns the files under `root` that match `glob`, with their paths relative to `root`, in a
+/// stable order. Hidden files and files ignored by `.gitignore`, `.ignore` or the git excludes are
+/// skipped.
+fn walk_files<'a>(root: &Path, glob: Option<&'a PathGlob>) -> impl Iterator<Item = (PathBuf, PathBuf)> + 'a {
+    let root = root.to_path_buf();
+    ignore::WalkBuilder::new(&root)
+        .require_git(false)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build()
+        .filter_map(|entry| {
+            entry
+                .inspect_err(|err| debug!(?err, "Skipping entry while walking files"))
+                .ok()
+        })
+        .filter(|entry| entry.file_type().is_some_and(|file_type| file_type.is_file()))
+        .filter_map(move |entry| {
+            let relative_path = entry.path().strip_prefix(&root).ok()?.to_path_buf();
+            let is_match = match glob {
+                // The root itself is a file, so its relative path is empty.
+                Some(glob) if relative_path.as_os_str().is_empty() => glob.is_match(Path::new(entry.file_name())),
+                Some(glob) => glob.is_match(&relative_path),
+                None => true,
+            };
+            is_match.then(|| (entry.into_path(), relative_path))
+        })
+}
+
+/// Formats a path found under the root `path` given in the tool use, so that the model can pass
+/// it back to the tool.
+fn display_path(path: &str, relative_path: &Path) -> String {
+    match relative_path.as_os_str().is_empty() {
+        true => path.to_string(),
+        false => Path::new(path).join(relative_path).to_string_lossy().to_string(),
+    }
+}
+
 /// List directory contents.
 #[derive(Debug, Clone, Deserialize)]
 pub struct FsDirectory {
@@ -753,4 +1129,171 @@ mod tests {
             )
         );
     }
+
+    /// Sets up a project with ignored and hidden files under `/project`.
+    async fn setup_test_project() -> Arc<Context> {
+        let ctx = setup_test_directory().await;
+        let fs = ctx.fs();
+        fs.create_dir_all("/project/src/cli").await.unwrap();
+        fs.create_dir_all("/project/target").await.unwrap();
+        fs.create_dir_all("/project/.hidden").await.unwrap();
+        fs.write("/project/.gitignore", "target/\n*.log\n").await.unwrap();
+        fs.write("/project/src/main.rs", "fn main() {\n    run();\n}\n\nfn run() {}\n")
+            .await
+            .unwrap();
+        fs.write("/project/src/cli/mod.rs", "pub fn Run() {}\n").await.unwrap();
+        fs.write("/project/README.md", "Call run() to run\n").await.unwrap();
+        fs.write("/project/target/main.rs", "fn run() {}\n").await.unwrap();
+        fs.write("/project/debug.log", "fn run() {}\n").await.unwrap();
+        fs.write("/project/.hidden/main.rs", "fn run() {}\n").await.unwrap();
+        ctx
+    }
+
+    async fn invoke_text(ctx: &Context, value: serde_json::Value) -> String {
+        let mut fs_read = serde_json::from_value::<FsRead>(value).unwrap();
+        fs_read.validate(ctx).await.unwrap();
+        match fs_read.invoke(ctx, &mut std::io::stdout()).await.unwrap().output {
+            OutputKind::Text(text) => text,
+            _ => panic!("expected text output"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fs_read_grep_invoke() {
+        let ctx = setup_test_project().await;
+
+        let text = invoke_text(
+            &ctx,
+            serde_json::json!({ "mode": "Grep", "path": "/project", "pattern": "fn run" }),
+        )
+        .await;
+        assert_eq!(
+            text,
+            "/project/src/cli/mod.rs:1: pub fn Run() {}\n/project/src/main.rs:5: fn run() {}\n"
+        );
+
+        let text = invoke_text(
+            &ctx,
+            serde_json::json!({ "mode": "Grep", "path": "/project", "pattern": "run\\(", "include": "*.md" }),
+        )
+        .await;
+        assert_eq!(text, "/project/README.md:1: Call run() to run\n");
+
+        // Uppercase patterns are case sensitive.
+        let text = invoke_text(
+            &ctx,
+            serde_json::json!({ "mode": "Grep", "path": "/project", "pattern": "R" }),
+        )
+        .await;
+        assert_eq!(text, "/project/src/cli/mod.rs:1: pub fn Run() {}\n");
+
+        // Escape sequences are not uppercase characters.
+        let text = invoke_text(
+            &ctx,
+            serde_json::json!({ "mode": "Grep", "path": "/project", "pattern": "call\\W" }),
+        )
+        .await;
+        assert_eq!(text, "/project/README.md:1: Call run() to run\n");
+
+        let text = invoke_text(
+            &ctx,
+            serde_json::json!({ "mode": "Grep", "path": "/project/src/main.rs", "pattern": "run", "context_lines": 1 }),
+        )
+        .await;
+        assert_eq!(
+            text,
+            "/project/src/main.rs-1- fn main() {\n/project/src/main.rs:2:     run();\n/project/src/main.rs-3- }\n\
+             /project/src/main.rs-4- \n/project/src/main.rs:5: fn run() {}\n--\n"
+        );
+
+        let text = invoke_text(
+            &ctx,
+            serde_json::json!({ "mode": "Grep", "path": "/project", "pattern": "missing" }),
+        )
+        .await;
+        assert_eq!(text, "No matches found for regex 'missing'");
+    }
+
+    #[test]
+    fn test_has_uppercase() {
+        assert!(has_uppercase("Run"));
+        assert!(has_uppercase("\\sRun"));
+        assert!(has_uppercase("\\p{Lu}R"));
+        assert!(!has_uppercase("run\\S+\\W\\D\\B"));
+        assert!(!has_uppercase("\\p{Lu}\\PL\\P{Greek}x"));
+        assert!(!has_uppercase("run\\"));
+    }
+
+    #[tokio::test]
+    async fn test_fs_read_grep_large_files() {
+        let ctx = setup_test_project().await;
+        let content = "fn run() {}\n".repeat(FsGrep::MAX_FILE_SIZE as usize / 12 + 1);
+        ctx.fs().write("/project/src/generated.rs", content).await.unwrap();
+        let text = invoke_text(
+            &ctx,
+            serde_json::json!({ "mode": "Grep", "path": "/project/src", "pattern": "fn run" }),
+        )
+        .await;
+        assert_eq!(
+            text,
+            "/project/src/cli/mod.rs:1: pub fn Run() {}\n/project/src/main.rs:5: fn run() {}\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fs_read_grep_max_results() {
+        let ctx = setup_test_project().await;
+        let text = invoke_text(
+            &ctx,
+            serde_json::json!({ "mode": "Grep", "path": "/project", "pattern": "run", "max_results": 2 }),
+        )
+        .await;
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[2].contains("Results limited to 2 matches"));
+    }
+
+    #[tokio::test]
+    async fn test_fs_read_grep_validate() {
+        let ctx = setup_test_project().await;
+        for value in [
+            serde_json::json!({ "mode": "Grep", "path": "/missing", "pattern": "run" }),
+            serde_json::json!({ "mode": "Grep", "path": "/project", "pattern": "" }),
+            serde_json::json!({ "mode": "Grep", "path": "/project", "pattern": "run(" }),
+            serde_json::json!({ "mode": "Grep", "path": "/project", "pattern": "run", "include": "[" }),
+            serde_json::json!({ "mode": "Glob", "path": "/project/README.md", "pattern": "*.md" }),
+        ] {
+            let mut fs_read = serde_json::from_value::<FsRead>(value.clone()).unwrap();
+            assert!(fs_read.validate(&ctx).await.is_err(), "{value} should be invalid");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fs_read_glob_invoke() {
+        let ctx = setup_test_project().await;
+
+        let text = invoke_text(
+            &ctx,
+            serde_json::json!({ "mode": "Glob", "path": "/project", "pattern": "*.rs" }),
+        )
+        .await;
+        assert_eq!(text, "/project/src/cli/mod.rs\n/project/src/main.rs");
+
+        let text = invoke_text(
+            &ctx,
+            serde_json::json!({ "mode": "Glob", "path": "/project", "pattern": "src/*.rs" }),
+        )
+        .await;
+        assert_eq!(text, "/project/src/main.rs");
+
+        let text = invoke_text(
+            &ctx,
+            serde_json::json!({ "mode": "Glob", "path": "/project", "pattern": "**/*", "max_results": 2 }),
+        )
+        .await;
+        assert_eq!(
+            text,
+            "/project/README.md\n/project/src/cli/mod.rs\n(Results limited to 2 files, narrow the search with a more specific path or pattern)"
+        );
+    }
 }
@@ -1,5 +1,9 @@
+use std::collections::HashSet;
 use std::io::Write;
-use std::path::Path;
+use std::path::{
+    Path,
+    PathBuf,
+};
 use std::sync::LazyLock;
 
 use crossterm::queue;
@@ -10,6 +14,7 @@ use crossterm::style::{
 use eyre::{
     ContextCompat as _,
     Result,
+    WrapErr as _,
     bail,
     eyre,
 };
@@ -33,6 +38,10 @@ use super::{
     sanitize_path_tool_arg,
     supports_truecolor,
 };
+use crate::cli::chat::util::patch::{
+    apply_hunks,
+    parse_patch,
+};
 use crate::platform::Context;
 
 static SYNTAX_SET: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
@@ -63,6 +72,23 @@ pub enum FsWrite {
     },
     #[serde(rename = "append")]
     Append { path: String, new_str: String },
+    /// Several replacements in a single file, written only if all of them can be applied.
+    #[serde(rename = "multi_edit")]
+    MultiEdit { path: String, edits: Vec<Edit> },
+    /// A unified diff that may change, create, delete and rename several files.
+    #[serde(rename = "apply_patch")]
+    ApplyPatch { patch: String },
+    #[serde(rename = "delete")]
+    Delete { path: String },
+    #[serde(rename = "rename")]
+    Rename { path: String, new_path: String },
+}
+
+/// A replacement of [FsWrite::MultiEdit], which follows the same rules as [FsWrite::StrReplace].
+#[derive(Debug, Clone, Deserialize)]
+pub struct Edit {
+    pub old_str: String,
+    pub new_str: String,
 }
 
 impl FsWrite {
@@ -160,6 +186,94 @@ impl FsWrite {
                 write_to_file(ctx, path, file).await?;
                 Ok(Default::default())
             },
+            FsWrite::MultiEdit { path, edits } => {
+                let path = sanitize_path_tool_arg(ctx, path);
+                let file = fs.read_to_string(&path).await?;
+                queue!(
+                    updates,
+                    style::Print("Updating: "),
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(format_path(cwd, &path)),
+                    style::ResetColor,
+                    style::Print("\n"),
+                )?;
+                let file = apply_edits(&file, edits)?;
+                write_to_file(ctx, path, file).await?;
+                Ok(Default::default())
+            },
+            FsWrite::ApplyPatch { patch } => {
+                // Every file is patched in memory first, so that nothing is written if any hunk
+                // can't be applied.
+                let files = patch_files(ctx, patch)?;
+                for file in &files {
+                    let (description, path) = match (&file.old_path, &file.new_path) {
+                        (Some(old_path), None) => ("Deleting: ", format_path(&cwd, old_path)),
+                        (None, Some(new_path)) => ("Creating: ", format_path(&cwd, new_path)),
+                        (Some(old_path), Some(new_path)) if old_path != new_path => (
+                            "Renaming: ",
+                            format!("{} to {}", format_path(&cwd, old_path), format_path(&cwd, new_path)),
+                        ),
+                        _ => ("Updating: ", format_path(&cwd, file.path())),
+                    };
+                    queue!(
+                        updates,
+                        style::Print(description),
+                        style::SetForegroundColor(Color::Green),
+                        style::Print(path),
+                        style::ResetColor,
+                        style::Print("\n"),
+                    )?;
+                }
+
+                for (written, file) in files.iter().enumerate() {
+                    if let Err(err) = write_patched_file(ctx, file).await {
+                        // Restore the files that were already written along with the one that
+                        // failed, which may be partially written, so that the patch is not left
+                        // half applied.
+                        for file in files[..=written].iter().rev() {
+                            if let Err(err) = restore_patched_file(ctx, file).await {
+                                warn!("Failed to restore {}: {}", file.path().display(), err);
+                            }
+                        }
+                        return Err(err.wrap_err(format!("Failed to write {}", file.path().display())));
+                    }
+                }
+                Ok(Default::default())
+            },
+            FsWrite::Delete { path } => {
+                let path = sanitize_path_tool_arg(ctx, path);
+                queue!(
+                    updates,
+                    style::Print("Deleting: "),
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(format_path(cwd, &path)),
+                    style::ResetColor,
+                    style::Print("\n"),
+                )?;
+                fs.remove_file(path).await?;
+                Ok(Default::default())
+            },
+            FsWrite::Rename { path, new_path } => {
+                let path = sanitize_path_tool_arg(ctx, path);
+                let new_path = sanitize_path_tool_arg(ctx, new_path);
+                queue!(
+                    updates,
+                    style::Print("Renaming: "),
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(format!(
+                        "{} to {}",
+                        format_path(&cwd, &path),
+                        format_path(&cwd, &new_path)
+                    )),
+                    style::ResetColor,
+                    style::Print("\n"),
+                )?;
+                if let Some(parent) = new_path.parent() {
+                    fs.create_dir_all(parent).await?;
+                }
+                fs.rename(path, new_path).await?;
+                Ok(Default::default())
+            },
         }
     }
 
@@ -223,6 +337,53 @@ impl FsWrite {
                 print_diff(updates, &Default::default(), &file, start_line)?;
                 Ok(())
             },
+            FsWrite::MultiEdit { path, edits } => {
+                let relative_path = format_path(cwd, path);
+                let file = ctx.fs().read_to_string_sync(&relative_path)?;
+                let new_file = apply_edits(&file, edits)?;
+                print_file_diff(ctx, updates, &relative_path, &file, &new_file)
+            },
+            FsWrite::ApplyPatch { patch } => {
+                for file in patch_files(ctx, patch)? {
+                    let relative_path = format_path(&cwd, file.path());
+                    let note = match (&file.old_path, &file.new_path) {
+                        (Some(_), None) => " (deleted)".to_string(),
+                        (None, Some(_)) => " (created)".to_string(),
+                        (Some(old_path), Some(new_path)) if old_path != new_path => {
+                            format!(" (renamed from {})", format_path(&cwd, old_path))
+                        },
+                        _ => String::new(),
+                    };
+                    queue!(
+                        updates,
+                        style::Print("Path: "),
+                        style::SetForegroundColor(Color::Green),
+                        style::Print(&relative_path),
+                        style::ResetColor,
+                        style::Print(format!("{note}\n\n")),
+                    )?;
+                    print_file_diff(ctx, updates, &relative_path, &file.old_content, &file.new_content)?;
+                }
+                Ok(())
+            },
+            FsWrite::Delete { path } => {
+                let relative_path = format_path(cwd, path);
+                let file = ctx.fs().read_to_string_sync(&relative_path).unwrap_or_default();
+                let file = stylize_output_if_able(ctx, &relative_path, &file);
+                print_diff(updates, &file, &Default::default(), 1)?;
+                Ok(())
+            },
+            FsWrite::Rename { new_path, .. } => {
+                queue!(
+                    updates,
+                    style::Print("New path: "),
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(format_path(cwd, new_path)),
+                    style::ResetColor,
+                    style::Print("\n\n"),
+                )?;
+                Ok(())
+            },
         }
     }
 
@@ -247,19 +408,98 @@ impl FsWrite {
                     bail!("Content to append must not be empty")
                 };
             },
+            FsWrite::MultiEdit { path, edits } => {
+                if edits.is_empty() {
+                    bail!("At least one edit must be provided")
+                }
+                let path = sanitize_path_tool_arg(ctx, path);
+                if !path.exists() {
+                    bail!("The provided path must exist in order to edit it")
+                }
+                // Checked up front, so that the user is not asked to approve edits that fail.
+                apply_edits(&ctx.fs().read_to_string(&path).await?, edits)?;
+            },
+            FsWrite::ApplyPatch { patch } => {
+                patch_files(ctx, patch)?;
+            },
+            FsWrite::Delete { path } => {
+                let path = sanitize_path_tool_arg(ctx, path);
+                if !path.exists() {
+                    bail!("The provided path must exist in order to delete it")
+                }
+                if path.is_dir() {
+                    bail!("Only files can be deleted")
+                }
+            },
+            FsWrite::Rename { path, new_path } => {
+                let path = sanitize_path_tool_arg(ctx, path);
+                if !path.exists() {
+                    bail!("The provided path must exist in order to rename it")
+                }
+                if path.is_dir() {
+                    bail!("Only files can be renamed")
+                }
+                if new_path.is_empty() {
+                    bail!("New path must not be empty")
+                }
+                if new_path.ends_with(std::path::is_separator) {
+                    bail!("The new path must be a file path, not a directory")
+                }
+                if sanitize_path_tool_arg(ctx, new_path).exists() {
+                    bail!("The new path already exists")
+                }
+            },
         }
 
         Ok(())
     }
 
+    /// Returns the paths of every file that will be modified when this command is invoked.
+    pub fn affected_paths(&self, ctx: &Context) -> Vec<PathBuf> {
+        match self {
+            FsWrite::ApplyPatch { patch } => {
+                let mut paths = Vec::new();
+                for file in parse_patch(patch).unwrap_or_default() {
+                    for path in [file.old_path, file.new_path].into_iter().flatten() {
+                        let path = sanitize_path_tool_arg(ctx, path);
+                        if !paths.contains(&path) {
+                            paths.push(path);
+                        }
+                    }
+                }
+                paths
+            },
+            FsWrite::Rename { path, new_path } => {
+                vec![sanitize_path_tool_arg(ctx, path), sanitize_path_tool_arg(ctx, new_path)]
+            },
+            _ => self
+                .path()
+                .map(|path| sanitize_path_tool_arg(ctx, path))
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    /// The path of the file that the command applies to, [None] for [FsWrite::ApplyPatch] which
+    /// may apply to several files.
+    fn path(&self) -> Option<&str> {
+        match self {
+            FsWrite::Create { path, .. } => Some(path),
+            FsWrite::StrReplace { path, .. } => Some(path),
+            FsWrite::Insert { path, .. } => Some(path),
+            FsWrite::Append { path, .. } => Some(path),
+            FsWrite::MultiEdit { path, .. } => Some(path),
+            FsWrite::ApplyPatch { .. } => None,
+            FsWrite::Delete { path } => Some(path),
+            FsWrite::Rename { path, .. } => Some(path),
+        }
+    }
+
     fn print_relative_path(&self, ctx: &Context, updates: &mut impl Write) -> Result<()> {
-        let cwd = ctx.env().current_dir()?;
-        let path = match self {
-            FsWrite::Create { path, .. } => path,
-            FsWrite::StrReplace { path, .. } => path,
-            FsWrite::Insert { path, .. } => path,
-            FsWrite::Append { path, .. } => path,
+        let Some(path) = self.path() else {
+            return Ok(());
         };
+        let cwd = ctx.env().current_dir()?;
         let relative_path = format_path(cwd, path);
         queue!(
             updates,
@@ -292,6 +532,135 @@ impl FsWrite {
     }
 }
 
+/// Applies every edit to `file` in order, failing if any `old_str` doesn't occur exactly once at
+/// the time its edit is applied.
+fn apply_edits(file: &str, edits: &[Edit]) -> Result<String> {
+    let mut file = file.to_string();
+    for (i, Edit { old_str, new_str }) in edits.iter().enumerate() {
+        match file.matches(old_str.as_str()).count() {
+            0 => bail!("edit {}: no occurrences of \"{old_str}\" were found", i + 1),
+            1 => file = file.replacen(old_str, new_str, 1),
+            x => bail!(
+                "edit {}: {x} occurrences of old_str were found when only 1 is expected",
+                i + 1
+            ),
+        }
+    }
+    Ok(file)
+}
+
+/// A file changed by [FsWrite::ApplyPatch], with its content before and after the patch.
+struct PatchedFile {
+    /// [None] if the file is created by the patch.
+    old_path: Option<PathBuf>,
+    /// [None] if the file is deleted by the patch.
+    new_path: Option<PathBuf>,
+    old_content: String,
+    new_content: String,
+}
+
+impl PatchedFile {
+    fn path(&self) -> &Path {
+        self.new_path
+            .as_ref()
+            .or(self.old_path.as_ref())
+            .expect("a file has at least one path")
+    }
+}
+
+/// Parses `patch` and applies it to the current content of the files, without writing anything.
+fn patch_files(ctx: &Context, patch: &str) -> Result<Vec<PatchedFile>> {
+    let mut files = Vec::new();
+    let mut touched = HashSet::new();
+    for file in parse_patch(patch)? {
+        let old_path = file.old_path.map(|path| sanitize_path_tool_arg(ctx, path));
+        let new_path = file.new_path.map(|path| sanitize_path_tool_arg(ctx, path));
+        // Every file is patched against its current content, so a second change to the same path
+        // would overwrite the first one.
+        let mut paths = old_path
+            .iter()
+            .chain(new_path.iter().filter(|path| old_path.as_ref() != Some(*path)));
+        if let Some(path) = paths.find(|path| !touched.insert((*path).clone())) {
+            bail!("{} is changed more than once by the patch", path.display());
+        }
+        if let Some(new_path) = &new_path {
+            if old_path.as_ref() != Some(new_path) && ctx.fs().exists(new_path) {
+                bail!("{} already exists", new_path.display());
+            }
+        }
+
+        let old_content = match &old_path {
+            Some(old_path) => ctx
+                .fs()
+                .read_to_string_sync(old_path)
+                .wrap_err_with(|| format!("Failed to read {}", old_path.display()))?,
+            None => String::new(),
+        };
+        let new_content = match &new_path {
+            Some(new_path) => apply_hunks(&old_content, &file.hunks)
+                .wrap_err_with(|| format!("Failed to apply the patch to {}", new_path.display()))?,
+            None => String::new(),
+        };
+        files.push(PatchedFile {
+            old_path,
+            new_path,
+            old_content,
+            new_content,
+        });
+    }
+    Ok(files)
+}
+
+/// Writes the new content of a patched file and removes its old path if it was deleted or renamed.
+async fn write_patched_file(ctx: &Context, file: &PatchedFile) -> Result<()> {
+    let fs = ctx.fs();
+    if let Some(new_path) = &file.new_path {
+        if let Some(parent) = new_path.parent() {
+            fs.create_dir_all(parent).await?;
+        }
+        fs.write(new_path, &file.new_content).await?;
+    }
+    if let Some(old_path) = &file.old_path {
+        if file.new_path.as_ref() != Some(old_path) {
+            fs.remove_file(old_path).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Undoes [write_patched_file], putting back the old content of a patched file.
+async fn restore_patched_file(ctx: &Context, file: &PatchedFile) -> Result<()> {
+    let fs = ctx.fs();
+    if let Some(new_path) = &file.new_path {
+        if file.old_path.as_ref() != Some(new_path) && fs.exists(new_path) {
+            fs.remove_file(new_path).await?;
+        }
+    }
+    if let Some(old_path) = &file.old_path {
+        fs.write(old_path, &file.old_content).await?;
+    }
+    Ok(())
+}
+
+/// Prints the changes between the `old` and `new` content of the file at `path`, with a few lines
+/// of context around each change.
+fn print_file_diff(ctx: &Context, updates: &mut impl Write, path: &str, old: &str, new: &str) -> Result<()> {
+    let old_lines = LinesWithEndings::from(old).collect::<Vec<_>>();
+    let new_lines = LinesWithEndings::from(new).collect::<Vec<_>>();
+    let diff = similar::TextDiff::from_lines(old, new);
+    for group in diff.grouped_ops(3) {
+        let (Some(first), Some(last)) = (group.first(), group.last()) else {
+            continue;
+        };
+        let old_range = first.old_range().start..last.old_range().end;
+        let new_range = first.new_range().start..last.new_range().end;
+        let old_part = stylize_output_if_able(ctx, path, &old_lines[old_range.clone()].concat());
+        let new_part = stylize_output_if_able(ctx, path, &new_lines[new_range].concat());
+        print_diff(updates, &old_part, &new_part, old_range.start + 1)?;
+    }
+    Ok(())
+}
+
 /// Writes `content` to `path`, adding a newline if necessary.
 async fn write_to_file(ctx: &Context, path: impl AsRef<Path>, mut content: String) -> Result<()> {
     if !content.ends_with_newline() {
@@ -661,6 +1030,40 @@ mod tests {
         });
         let fw = serde_json::from_value::<FsWrite>(v).unwrap();
         assert!(matches!(fw, FsWrite::Append { .. }));
+
+        // multi_edit
+        let v = serde_json::json!({
+            "path": path,
+            "command": "multi_edit",
+            "edits": [{ "old_str": "a", "new_str": "b" }],
+        });
+        let fw = serde_json::from_value::<FsWrite>(v).unwrap();
+        assert!(matches!(fw, FsWrite::MultiEdit { edits, .. } if edits.len() == 1));
+
+        // apply_patch
+        let v = serde_json::json!({
+            "command": "apply_patch",
+            "patch": "--- a/x\n+++ b/x\n@@ -1 +1 @@\n-a\n+b\n",
+        });
+        let fw = serde_json::from_value::<FsWrite>(v).unwrap();
+        assert!(matches!(fw, FsWrite::ApplyPatch { .. }));
+
+        // delete
+        let v = serde_json::json!({
+            "path": path,
+            "command": "delete",
+        });
+        let fw = serde_json::from_value::<FsWrite>(v).unwrap();
+        assert!(matches!(fw, FsWrite::Delete { .. }));
+
+        // rename
+        let v = serde_json::json!({
+            "path": path,
+            "command": "rename",
+            "new_path": "/my-other-file",
+        });
+        let fw = serde_json::from_value::<FsWrite>(v).unwrap();
+        assert!(matches!(fw, FsWrite::Rename { .. }));
     }
 
     #[tokio::test]
@@ -929,6 +1332,244 @@ mod tests {
         assert!(result.is_err(), "Appending to non-existent file should fail");
     }
 
+    #[tokio::test]
+    async fn test_fs_write_tool_multi_edit() {
+        let ctx = setup_test_directory().await;
+        let mut stdout = std::io::stdout();
+
+        // The second edit matches twice, so nothing is written.
+        let v = serde_json::json!({
+            "path": TEST_FILE_PATH,
+            "command": "multi_edit",
+            "edits": [
+                { "old_str": "2: This is line 2", "new_str": "2: Edited" },
+                { "old_str": "Hello world!", "new_str": "Goodbye world!" },
+            ],
+        });
+        let mut fw = serde_json::from_value::<FsWrite>(v).unwrap();
+        assert!(fw.validate(&ctx).await.is_err());
+        assert!(fw.invoke(&ctx, &mut stdout).await.is_err());
+        assert_eq!(
+            ctx.fs().read_to_string(TEST_FILE_PATH).await.unwrap(),
+            TEST_FILE_CONTENTS
+        );
+
+        // Edits apply to the result of the previous ones.
+        let v = serde_json::json!({
+            "path": TEST_FILE_PATH,
+            "command": "multi_edit",
+            "edits": [
+                { "old_str": "1: Hello world!", "new_str": "1: Goodbye world!" },
+                { "old_str": "Hello world!", "new_str": "Goodbye world!" },
+                { "old_str": "asdf", "new_str": "qwerty" },
+            ],
+        });
+        let mut fw = serde_json::from_value::<FsWrite>(v).unwrap();
+        fw.validate(&ctx).await.unwrap();
+        fw.invoke(&ctx, &mut stdout).await.unwrap();
+        assert_eq!(
+            ctx.fs().read_to_string(TEST_FILE_PATH).await.unwrap(),
+            TEST_FILE_CONTENTS.replace("Hello", "Goodbye").replace("asdf", "qwerty")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fs_write_tool_apply_patch() {
+        let ctx = setup_test_directory().await;
+        let mut stdout = std::io::stdout();
+        ctx.fs().write("/old_name.txt", "keep\nchange\n").await.unwrap();
+
+        let patch = "\
+--- a/test_file.txt
++++ b/test_file.txt
+@@ -3,2 +3,2 @@
+-3: asdf
++3: qwerty
+ 4: Hello world!
+--- /dev/null
++++ b/aaaa1/new_file.txt
+@@ -0,0 +1,2 @@
++first
++second
+--- a/aaaa2/.hidden
++++ /dev/null
+@@ -1 +0,0 @@
+-this is a hidden file
+--- a/old_name.txt
++++ b/new_name.txt
+@@ -1,2 +1,2 @@
+ keep
+-change
++changed
+";
+        let mut fw = serde_json::from_value::<FsWrite>(serde_json::json!({
+            "command": "apply_patch",
+            "patch": patch,
+        }))
+        .unwrap();
+        let affected = fw.affected_paths(&ctx);
+        assert_eq!(affected.len(), 5);
+        assert!(affected.contains(&sanitize_path_tool_arg(&ctx, "new_name.txt")));
+        fw.validate(&ctx).await.unwrap();
+        let mut description = Vec::new();
+        fw.queue_description(&ctx, &mut description).unwrap();
+        assert!(String::from_utf8(description).unwrap().contains("(renamed from "));
+        fw.invoke(&ctx, &mut stdout).await.unwrap();
+
+        assert_eq!(
+            ctx.fs().read_to_string(TEST_FILE_PATH).await.unwrap(),
+            TEST_FILE_CONTENTS.replace("asdf", "qwerty")
+        );
+        assert_eq!(
+            ctx.fs().read_to_string("/aaaa1/new_file.txt").await.unwrap(),
+            "first\nsecond\n"
+        );
+        assert!(!ctx.fs().exists(TEST_HIDDEN_FILE_PATH));
+        assert!(!ctx.fs().exists("/old_name.txt"));
+        assert_eq!(
+            ctx.fs().read_to_string("/new_name.txt").await.unwrap(),
+            "keep\nchanged\n"
+        );
+
+        // The second file doesn't match, so the first one is not written either.
+        let patch = "\
+--- a/test_file.txt
++++ b/test_file.txt
+@@ -1 +1 @@
+-1: Hello world!
++1: Hi world!
+--- a/new_name.txt
++++ b/new_name.txt
+@@ -1 +1 @@
+-missing
++line
+";
+        let mut fw = serde_json::from_value::<FsWrite>(serde_json::json!({
+            "command": "apply_patch",
+            "patch": patch,
+        }))
+        .unwrap();
+        assert!(fw.validate(&ctx).await.is_err());
+        assert!(fw.invoke(&ctx, &mut stdout).await.is_err());
+        assert_eq!(
+            ctx.fs().read_to_string(TEST_FILE_PATH).await.unwrap(),
+            TEST_FILE_CONTENTS.replace("asdf", "qwerty")
+        );
+
+        // A path changed twice is rejected, since the second change would overwrite the first.
+        let patch = "\
+--- a/test_file.txt
++++ b/test_file.txt
+@@ -1 +1 @@
+-1: Hello world!
++1: Hi world!
+--- a/test_file.txt
++++ b/test_file.txt
+@@ -3 +3 @@
+-3: qwerty
++3: asdf
+";
+        let mut fw = serde_json::from_value::<FsWrite>(serde_json::json!({
+            "command": "apply_patch",
+            "patch": patch,
+        }))
+        .unwrap();
+        let err = fw.validate(&ctx).await.unwrap_err();
+        assert!(err.to_string().contains("changed more than once"), "{err}");
+
+        // The second file can't be written, so the files written before it are restored.
+        let patch = "\
+--- a/test_file.txt
++++ b/test_file.txt
+@@ -1 +1 @@
+-1: Hello world!
++1: Hi world!
+--- a/new_name.txt
++++ b/renamed.txt
+@@ -1 +1 @@
+-keep
++kept
+--- /dev/null
++++ b/test_file.txt/blocked.txt
+@@ -0,0 +1 @@
++blocked
+";
+        let fw = serde_json::from_value::<FsWrite>(serde_json::json!({
+            "command": "apply_patch",
+            "patch": patch,
+        }))
+        .unwrap();
+        assert!(fw.invoke(&ctx, &mut stdout).await.is_err());
+        assert_eq!(
+            ctx.fs().read_to_string(TEST_FILE_PATH).await.unwrap(),
+            TEST_FILE_CONTENTS.replace("asdf", "qwerty")
+        );
+        assert_eq!(
+            ctx.fs().read_to_string("/new_name.txt").await.unwrap(),
+            "keep\nchanged\n"
+        );
+        assert!(!ctx.fs().exists("/renamed.txt"));
+    }
+
+    #[tokio::test]
+    async fn test_fs_write_tool_delete_and_rename() {
+        let ctx = setup_test_directory().await;
+        let mut stdout = std::io::stdout();
+
+        let mut fw = serde_json::from_value::<FsWrite>(serde_json::json!({
+            "path": "/aaaa1",
+            "command": "delete",
+        }))
+        .unwrap();
+        assert!(fw.validate(&ctx).await.is_err());
+
+        let mut fw = serde_json::from_value::<FsWrite>(serde_json::json!({
+            "path": TEST_FILE_PATH,
+            "command": "rename",
+            "new_path": TEST_HIDDEN_FILE_PATH,
+        }))
+        .unwrap();
+        assert!(fw.validate(&ctx).await.is_err());
+
+        // Directories can't be renamed, and files can't be renamed to a directory path.
+        for (path, new_path) in [("/aaaa1", "/renamed"), (TEST_FILE_PATH, "/renamed/")] {
+            let mut fw = serde_json::from_value::<FsWrite>(serde_json::json!({
+                "path": path,
+                "command": "rename",
+                "new_path": new_path,
+            }))
+            .unwrap();
+            assert!(
+                fw.validate(&ctx).await.is_err(),
+                "{path} to {new_path} should be invalid"
+            );
+        }
+
+        let mut fw = serde_json::from_value::<FsWrite>(serde_json::json!({
+            "path": TEST_FILE_PATH,
+            "command": "rename",
+            "new_path": "/aaaa3/renamed.txt",
+        }))
+        .unwrap();
+        assert_eq!(fw.affected_paths(&ctx).len(), 2);
+        fw.validate(&ctx).await.unwrap();
+        fw.invoke(&ctx, &mut stdout).await.unwrap();
+        assert!(!ctx.fs().exists(TEST_FILE_PATH));
+        assert_eq!(
+            ctx.fs().read_to_string("/aaaa3/renamed.txt").await.unwrap(),
+            TEST_FILE_CONTENTS
+        );
+
+        let mut fw = serde_json::from_value::<FsWrite>(serde_json::json!({
+            "path": "/aaaa3/renamed.txt",
+            "command": "delete",
+        }))
+        .unwrap();
+        fw.validate(&ctx).await.unwrap();
+        fw.invoke(&ctx, &mut stdout).await.unwrap();
+        assert!(!ctx.fs().exists("/aaaa3/renamed.txt"));
+    }
+
     #[test]
     fn test_lines_with_context() {
         let content = "Hello\nWorld!\nhow\nare\nyou\ntoday?";
@@ -0,0 +1,138 @@
+use std::io::Write;
+
+use crossterm::queue;
+use crossterm::style::{
+    self,
+    Color,
+};
+use eyre::{
+    Result,
+    bail,
+};
+use semantic_search_client::types::{
+    SearchFilter,
+    SearchOptions,
+};
+use serde::Deserialize;
+
+use super::{
+    InvokeOutput,
+    OutputKind,
+};
+use crate::cli::chat::util::knowledge_store::KnowledgeStore;
+use crate::database::Database;
+use crate::database::settings::Setting;
+use crate::platform::Context;
+
+/// Retrieves the most relevant chunks of the knowledge bases indexed with `/knowledge add`.
+///
+/// This is a beta feature that can be enabled/disabled via settings:
+/// `q settings chat.enableKnowledge true`
+#[derive(Debug, Clone, Deserialize)]
+pub struct KnowledgeSearch {
+    /// The text to search for
+    pub query: String,
+    /// Name or id of a single knowledge base to search, searches all of them if not provided
+    pub knowledge_base: Option<String>,
+    /// Maximum number of results to return per knowledge base
+    pub limit: Option<usize>,
+    /// Glob the paths of the results must match, such as `src/**/*.rs`
+    pub path: Option<String>,
+}
+
+impl KnowledgeSearch {
+    /// Checks if the knowledge feature is enabled in settings
+    pub fn is_enabled(database: &Database) -> bool {
+        database.settings.get_bool(Setting::EnabledKnowledge).unwrap_or(false)
+    }
+
+    pub fn queue_description(&self, updates: &mut impl Write) -> Result<()> {
+        queue!(
+            updates,
+            style::Print("Searching "),
+            style::SetForegroundColor(Color::Green),
+            style::Print(self.knowledge_base.as_deref().unwrap_or("all knowledge bases")),
+            style::ResetColor,
+            style::Print(" for: "),
+            style::SetForegroundColor(Color::Green),
+            style::Print(&self.query),
+            style::ResetColor,
+            style::Print("\n"),
+        )?;
+        Ok(())
+    }
+
+    pub async fn invoke(&self, ctx: &Context, _updates: &mut impl Write) -> Result<InvokeOutput> {
+        let store = KnowledgeStore::get_instance(ctx).await?;
+        let results = store
+            .search(&self.query, self.knowledge_base.clone(), SearchOptions {
+                limit: self.limit,
+                filter: SearchFilter {
+                    path_glob: self.path.clone(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .await?;
+
+        let matches = results
+            .iter()
+            .flat_map(|(context, results)| {
+                results.iter().map(|result| {
+                    serde_json::json!({
+                        "knowledge_base": context.name,
+                        "path": result.point.payload.get("path"),
+                        "location": result.location(),
+                        "score": result.score(),
+                        "text": result.text(),
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Ok(InvokeOutput {
+            output: OutputKind::Json(serde_json::Value::Array(matches)),
+        })
+    }
+
+    pub async fn validate(&mut self, _ctx: &Context) -> Result<()> {
+        if self.query.trim().is_empty() {
+            bail!("Search query must not be empty");
+        }
+        if self.limit == Some(0) {
+            bail!("Limit must be greater than 0");
+        }
+        if let Some(path) = &self.path {
+            if let Err(err) = globset::Glob::new(path) {
+                bail!("Invalid path glob '{}': {}", path, err);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_knowledge_search_deserialize_and_validate() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+
+        let mut tool = serde_json::from_value::<KnowledgeSearch>(serde_json::json!({
+            "query": "how do I deploy",
+            "knowledge_base": "docs",
+            "limit": 3,
+            "path": "docs/**/*.md"
+        }))
+        .unwrap();
+        assert_eq!(tool.knowledge_base.as_deref(), Some("docs"));
+        assert!(tool.validate(&ctx).await.is_ok());
+
+        tool.path = Some("docs/[".to_string());
+        assert!(tool.validate(&ctx).await.is_err());
+
+        let mut tool = serde_json::from_value::<KnowledgeSearch>(serde_json::json!({ "query": "  " })).unwrap();
+        assert!(tool.validate(&ctx).await.is_err());
+    }
+}
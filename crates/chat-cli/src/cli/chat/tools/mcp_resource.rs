@@ -0,0 +1,551 @@
+use std::collections::{
+    BTreeMap,
+    HashMap,
+};
+use std::io::Write;
+use std::sync::Arc;
+
+use crossterm::queue;
+use crossterm::style::{
+    self,
+    Color,
+};
+use eyre::{
+    Result,
+    bail,
+    eyre,
+};
+use percent_encoding::{
+    AsciiSet,
+    NON_ALPHANUMERIC,
+    utf8_percent_encode,
+};
+use regex::Regex;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use super::custom_tool::CustomToolClient;
+use super::{
+    InvokeOutput,
+    MAX_TOOL_RESPONSE_SIZE,
+    OutputKind,
+};
+use crate::mcp_client::{
+    ResourceInfo,
+    ResourceReadResult,
+    ResourceTemplate,
+    ResourceTemplatesListResult,
+    ResourcesListResult,
+};
+use crate::platform::Context;
+
+const RESOURCE_ENTRY_START_HEADER: &str = "--- RESOURCE ENTRY BEGIN ---\n";
+const RESOURCE_ENTRY_END_HEADER: &str = "--- RESOURCE ENTRY END ---\n\n";
+
+/// Characters left as is when expanding simple template expressions, as per RFC 6570.
+const UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+/// Lists and reads the resources exposed by the MCP servers of the session.
+#[derive(Debug, Clone, Deserialize)]
+pub struct McpResource {
+    #[serde(flatten)]
+    pub operation: McpResourceOperation,
+    /// Clients of the MCP servers of the session, filled in by the tool manager.
+    #[serde(skip)]
+    pub clients: HashMap<String, Arc<CustomToolClient>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "mode")]
+pub enum McpResourceOperation {
+    List {
+        server_name: Option<String>,
+    },
+    Read {
+        server_name: String,
+        /// Uri of the resource, or a uri template if `arguments` is provided
+        uri: String,
+        arguments: Option<HashMap<String, String>>,
+    },
+}
+
+impl McpResource {
+    pub fn server_name(&self) -> Option<&str> {
+        match &self.operation {
+            McpResourceOperation::List { server_name } => server_name.as_deref(),
+            McpResourceOperation::Read { server_name, .. } => Some(server_name),
+        }
+    }
+
+    pub async fn validate(&mut self, _ctx: &Context) -> Result<()> {
+        if let Some(server_name) = self.server_name() {
+            if !self.clients.contains_key(server_name) {
+                bail!("No MCP server named '{server_name}' is configured");
+            }
+        }
+        if let McpResourceOperation::Read { uri, arguments, .. } = &mut self.operation {
+            if let Some(arguments) = arguments.take() {
+                *uri = expand_uri_template(uri, &arguments)?;
+            }
+            if uri.trim().is_empty() {
+                bail!("Resource uri must not be empty");
+            }
+        }
+        Ok(())
+    }
+
+    pub fn queue_description(&self, updates: &mut impl Write) -> Result<()> {
+        match &self.operation {
+            McpResourceOperation::List { server_name } => queue!(
+                updates,
+                style::Print("Listing resources of "),
+                style::SetForegroundColor(Color::Green),
+                style::Print(server_name.as_deref().unwrap_or("all MCP servers")),
+                style::ResetColor,
+                style::Print("\n"),
+            )?,
+            McpResourceOperation::Read { server_name, uri, .. } => queue!(
+                updates,
+                style::Print("Reading resource "),
+                style::SetForegroundColor(Color::Green),
+                style::Print(uri),
+                style::ResetColor,
+                style::Print(" from "),
+                style::SetForegroundColor(Color::Green),
+                style::Print(server_name),
+                style::ResetColor,
+                style::Print("\n"),
+            )?,
+        }
+        Ok(())
+    }
+
+    pub async fn invoke(&self, _ctx: &Context, _updates: &mut impl Write) -> Result<InvokeOutput> {
+        match &self.operation {
+            McpResourceOperation::List { server_name } => {
+                let resources = list_resources(&self.clients, server_name.as_deref()).await?;
+                Ok(InvokeOutput {
+                    output: OutputKind::Json(serde_json::to_value(resources)?),
+                })
+            },
+            McpResourceOperation::Read { server_name, uri, .. } => {
+                let result = read_resource(&self.clients, server_name, uri).await?;
+                Ok(InvokeOutput {
+                    output: OutputKind::Text(format_resource_contents(&result)),
+                })
+            },
+        }
+    }
+}
+
+/// The resources and resource templates of a single MCP server.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerResources {
+    pub server_name: String,
+    pub resources: Vec<ResourceInfo>,
+    pub resource_templates: Vec<ResourceTemplate>,
+}
+
+impl ServerResources {
+    /// Whether `uri` is either listed by the server or can be constructed from one of its
+    /// templates.
+    pub fn contains(&self, uri: &str) -> bool {
+        self.resources.iter().any(|r| r.uri == uri)
+            || self
+                .resource_templates
+                .iter()
+                .any(|t| match_uri_template(&t.uri_template, uri).is_some())
+    }
+}
+
+/// Lists the resources of `server_name`, or of every server supporting resources if not
+/// provided.
+pub async fn list_resources(
+    clients: &HashMap<String, Arc<CustomToolClient>>,
+    server_name: Option<&str>,
+) -> Result<Vec<ServerResources>> {
+    let clients = match server_name {
+        Some(name) => {
+            let client = clients
+                .get(name)
+                .ok_or_else(|| eyre!("No MCP server named '{name}' is configured"))?;
+            BTreeMap::from([(name, client)])
+        },
+        None => clients.iter().map(|(name, client)| (name.as_str(), client)).collect(),
+    };
+
+    let mut all = Vec::new();
+    for (name, client) in clients {
+        if !client.supports_resources().await {
+            continue;
+        }
+        let resources = client
+            .request("resources/list", None)
+            .await?
+            .result
+            .and_then(|result| serde_json::from_value::<ResourcesListResult>(result).ok())
+            .map(|list| list.resources)
+            .unwrap_or_default();
+        // Templates are optional, servers without any may not implement the method at all.
+        let resource_templates = match client.request("resources/templates/list", None).await {
+            Ok(resp) => resp
+                .result
+                .and_then(|result| serde_json::from_value::<ResourceTemplatesListResult>(result).ok())
+                .map(|list| list.resource_templates)
+                .unwrap_or_default(),
+            Err(_) => vec![],
+        };
+        all.push(ServerResources {
+            server_name: name.to_string(),
+            resources: resources
+                .into_iter()
+                .filter_map(|v| serde_json::from_value(v).ok())
+                .collect(),
+            resource_templates: resource_templates
+                .into_iter()
+                .filter_map(|v| serde_json::from_value(v).ok())
+                .collect(),
+        });
+    }
+    Ok(all)
+}
+
+pub async fn read_resource(
+    clients: &HashMap<String, Arc<CustomToolClient>>,
+    server_name: &str,
+    uri: &str,
+) -> Result<ResourceReadResult> {
+    let client = clients
+        .get(server_name)
+        .ok_or_else(|| eyre!("No MCP server named '{server_name}' is configured"))?;
+    let resp = client
+        .request("resources/read", Some(serde_json::json!({ "uri": uri })))
+        .await?;
+    if let Some(err) = resp.error {
+        bail!("Failed to read {uri} from {server_name}: {}", err.message);
+    }
+    let result = resp
+        .result
+        .ok_or_else(|| eyre!("Result field missing from resources/read response"))?;
+    Ok(serde_json::from_value(result)?)
+}
+
+/// Formats the contents of a resource read for the model, truncating them if needed.
+pub fn format_resource_contents(result: &ResourceReadResult) -> String {
+    let mut formatted = String::new();
+    for contents in &result.contents {
+        formatted.push_str(&format!("uri: {}\n", contents.uri));
+        if let Some(mime_type) = &contents.mime_type {
+            formatted.push_str(&format!("mime type: {mime_type}\n"));
+        }
+        match (&contents.text, &contents.blob) {
+            (Some(text), _) => formatted.push_str(text),
+            (None, Some(blob)) => {
+                formatted.push_str(&format!(
+                    "[binary content of {} base64 encoded bytes omitted]",
+                    blob.len()
+                ));
+            },
+            (None, None) => formatted.push_str("[empty]"),
+        }
+        formatted.push_str("\n\n");
+    }
+    if formatted.len() > MAX_TOOL_RESPONSE_SIZE {
+        let mut end = MAX_TOOL_RESPONSE_SIZE;
+        while !formatted.is_char_boundary(end) {
+            end -= 1;
+        }
+        formatted.truncate(end);
+        formatted.push_str("\n... truncated");
+    }
+    formatted.trim_end().to_string()
+}
+
+/// A reference to a resource in a prompt, written as `@server_name:uri`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceMention {
+    pub server_name: String,
+    pub uri: String,
+}
+
+impl std::fmt::Display for ResourceMention {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "@{}:{}", self.server_name, self.uri)
+    }
+}
+
+impl ResourceMention {
+    /// Parses a single whitespace delimited token, e.g. `@docs:file:///README.md`.
+    pub fn parse(token: &str) -> Option<Self> {
+        let (server_name, uri) = token.strip_prefix('@')?.split_once(':')?;
+        let uri = uri.trim_end_matches(['.', ',', ';', '!', '?', ')', '"', '\'']);
+        let valid_server_name = !server_name.is_empty()
+            && server_name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        (valid_server_name && !uri.is_empty()).then(|| Self {
+            server_name: server_name.to_string(),
+            uri: uri.to_string(),
+        })
+    }
+}
+
+/// Returns the distinct resource mentions of a prompt, in order of appearance.
+pub fn parse_resource_mentions(input: &str) -> Vec<ResourceMention> {
+    let mut mentions = Vec::<ResourceMention>::new();
+    for mention in input.split_whitespace().filter_map(ResourceMention::parse) {
+        if !mentions.contains(&mention) {
+            mentions.push(mention);
+        }
+    }
+    mentions
+}
+
+/// Reads the resources mentioned in `input` and formats them to be attached to the user message.
+///
+/// Mentions of servers that are not configured are left alone. Returns [None] if nothing was
+/// mentioned, otherwise the mentions along with the formatted contents.
+pub async fn attach_resource_mentions(
+    clients: &HashMap<String, Arc<CustomToolClient>>,
+    input: &str,
+) -> Result<Option<(Vec<ResourceMention>, String)>> {
+    let mentions = parse_resource_mentions(input)
+        .into_iter()
+        .filter(|m| clients.contains_key(&m.server_name))
+        .collect::<Vec<_>>();
+    if mentions.is_empty() {
+        return Ok(None);
+    }
+
+    let mut listed = HashMap::<String, ServerResources>::new();
+    let mut attached = String::new();
+    for mention in &mentions {
+        if let Some(template) = unfilled_template_variables(&mention.uri) {
+            bail!("Resource template {mention} needs values for: {}", template.join(", "));
+        }
+        if !listed.contains_key(&mention.server_name) {
+            let resources = list_resources(clients, Some(&mention.server_name))
+                .await?
+                .pop()
+                .ok_or_else(|| eyre!("MCP server '{}' does not provide resources", mention.server_name))?;
+            listed.insert(mention.server_name.clone(), resources);
+        }
+        if !listed[&mention.server_name].contains(&mention.uri) {
+            bail!("Resource {mention} was not found. Use /resources to see the available resources");
+        }
+        let result = read_resource(clients, &mention.server_name, &mention.uri).await?;
+        attached.push_str(RESOURCE_ENTRY_START_HEADER);
+        attached.push_str(&format!("Contents of the resource {mention}:\n\n"));
+        attached.push_str(&format_resource_contents(&result));
+        attached.push('\n');
+        attached.push_str(RESOURCE_ENTRY_END_HEADER);
+    }
+    Ok(Some((mentions, attached)))
+}
+
+/// A single `{...}` expression of a uri template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TemplatePart<'a> {
+    Literal(&'a str),
+    Expression {
+        operator: Option<char>,
+        variables: Vec<&'a str>,
+    },
+}
+
+fn parse_uri_template(template: &str) -> Vec<TemplatePart<'_>> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        if start > 0 {
+            parts.push(TemplatePart::Literal(&rest[..start]));
+        }
+        let expression = &rest[start + 1..start + len];
+        let operator = expression.chars().next().filter(|c| "+#./;?&".contains(*c));
+        let variables = expression[operator.map_or(0, char::len_utf8)..]
+            .split(',')
+            .map(|v| v.trim_end_matches('*'))
+            .filter(|v| !v.is_empty())
+            .collect();
+        parts.push(TemplatePart::Expression { operator, variables });
+        rest = &rest[start + len + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(TemplatePart::Literal(rest));
+    }
+    parts
+}
+
+/// Returns the variables of `uri` if it is a template that was not filled in.
+fn unfilled_template_variables(uri: &str) -> Option<Vec<&str>> {
+    let variables = parse_uri_template(uri)
+        .into_iter()
+        .flat_map(|part| match part {
+            TemplatePart::Expression { variables, .. } => variables,
+            TemplatePart::Literal(_) => vec![],
+        })
+        .collect::<Vec<_>>();
+    (!variables.is_empty()).then_some(variables)
+}
+
+/// Expands an RFC 6570 uri template, e.g. `repo://{owner}/{name}{?ref}`.
+pub fn expand_uri_template(template: &str, arguments: &HashMap<String, String>) -> Result<String> {
+    let mut uri = String::new();
+    for part in parse_uri_template(template) {
+        let (operator, variables) = match part {
+            TemplatePart::Literal(literal) => {
+                uri.push_str(literal);
+                continue;
+            },
+            TemplatePart::Expression { operator, variables } => (operator, variables),
+        };
+        let mut first = true;
+        for variable in variables {
+            let Some(value) = arguments.get(variable) else {
+                match operator {
+                    // Query parameters are optional.
+                    Some('?' | '&' | ';') => continue,
+                    _ => bail!("Missing value for '{variable}' of the resource template {template}"),
+                }
+            };
+            let encoded = match operator {
+                Some('+' | '#') => value.clone(),
+                _ => utf8_percent_encode(value, UNRESERVED).to_string(),
+            };
+            match (operator, first) {
+                (Some('?'), true) => uri.push_str(&format!("?{variable}={encoded}")),
+                (Some('?' | '&'), _) => uri.push_str(&format!("&{variable}={encoded}")),
+                (Some(';'), _) => uri.push_str(&format!(";{variable}={encoded}")),
+                (Some('#'), true) => uri.push_str(&format!("#{encoded}")),
+                (Some('.'), _) => uri.push_str(&format!(".{encoded}")),
+                (Some('/'), _) => uri.push_str(&format!("/{encoded}")),
+                (_, true) => uri.push_str(&encoded),
+                (_, false) => uri.push_str(&format!(",{encoded}")),
+            }
+            first = false;
+        }
+    }
+    Ok(uri)
+}
+
+/// Matches `uri` against an RFC 6570 uri template, returning the values of its variables.
+///
+/// Only expressions holding a single variable are captured, query expressions are matched but
+/// their values are not extracted.
+pub fn match_uri_template(template: &str, uri: &str) -> Option<HashMap<String, String>> {
+    let mut pattern = String::from("^");
+    let mut names = Vec::new();
+    for part in parse_uri_template(template) {
+        match part {
+            TemplatePart::Literal(literal) => pattern.push_str(&regex::escape(literal)),
+            TemplatePart::Expression { operator, variables } => match (operator, variables.as_slice()) {
+                (Some('?' | '&' | ';'), _) => pattern.push_str(r"(?:[?&;].*)?"),
+                (operator, [variable]) => {
+                    names.push(*variable);
+                    let prefix = match operator {
+                        Some(op @ ('#' | '.' | '/')) => regex::escape(&op.to_string()),
+                        _ => String::new(),
+                    };
+                    let value = match operator {
+                        Some('+' | '#') => "(.+?)",
+                        _ => "([^/?#]+?)",
+                    };
+                    pattern.push_str(&format!("{prefix}{value}"));
+                },
+                _ => pattern.push_str("(?:[^/?#]*)"),
+            },
+        }
+    }
+    pattern.push('$');
+
+    let captures = Regex::new(&pattern).ok()?.captures(uri)?;
+    Some(
+        names
+            .into_iter()
+            .zip(captures.iter().skip(1))
+            .filter_map(|(name, value)| Some((name.to_string(), value?.as_str().to_string())))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mcp_resource_deserialize_and_validate() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+
+        let mut tool = serde_json::from_value::<McpResource>(serde_json::json!({ "mode": "List" })).unwrap();
+        assert!(tool.server_name().is_none());
+        assert!(tool.validate(&ctx).await.is_ok());
+
+        let mut tool = serde_json::from_value::<McpResource>(serde_json::json!({
+            "mode": "Read",
+            "server_name": "git",
+            "uri": "repo://{owner}/{name}",
+            "arguments": { "owner": "aws", "name": "q" }
+        }))
+        .unwrap();
+        // The server is not configured
+        assert!(tool.validate(&ctx).await.is_err());
+    }
+
+    #[test]
+    fn test_parse_resource_mentions() {
+        let mentions = parse_resource_mentions(
+            "summarize @docs:file:///README.md, then compare with @git:repo://aws/q. @docs:file:///README.md user@example.com @:x",
+        );
+        assert_eq!(mentions, vec![
+            ResourceMention {
+                server_name: "docs".to_string(),
+                uri: "file:///README.md".to_string(),
+            },
+            ResourceMention {
+                server_name: "git".to_string(),
+                uri: "repo://aws/q".to_string(),
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_expand_uri_template() {
+        let args = HashMap::from([
+            ("owner".to_string(), "aws".to_string()),
+            ("name".to_string(), "q cli".to_string()),
+            ("path".to_string(), "src/main.rs".to_string()),
+        ]);
+        assert_eq!(
+            expand_uri_template("repo://{owner}/{name}/{+path}{?ref,path}", &args).unwrap(),
+            "repo://aws/q%20cli/src/main.rs?path=src%2Fmain.rs"
+        );
+        assert!(expand_uri_template("repo://{owner}/{missing}", &args).is_err());
+    }
+
+    #[test]
+    fn test_match_uri_template() {
+        let values = match_uri_template("repo://{owner}/{name}/{+path}", "repo://aws/q/src/main.rs").unwrap();
+        assert_eq!(values["owner"], "aws");
+        assert_eq!(values["name"], "q");
+        assert_eq!(values["path"], "src/main.rs");
+
+        assert!(match_uri_template("repo://{owner}{?ref}", "repo://aws?ref=main").is_some());
+        assert!(match_uri_template("repo://{owner}", "repo://aws/q").is_none());
+        assert!(match_uri_template("file:///{path}", "repo://aws").is_none());
+    }
+
+    #[test]
+    fn test_unfilled_template_variables() {
+        assert_eq!(
+            unfilled_template_variables("repo://{owner}/{name}"),
+            Some(vec!["owner", "name"])
+        );
+        assert_eq!(unfilled_template_variables("repo://aws/q"), None);
+    }
+}
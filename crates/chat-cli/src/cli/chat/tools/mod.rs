@@ -1,25 +1,42 @@
+pub mod bash_process;
 pub mod custom_tool;
+pub mod delegate;
 pub mod execute_bash;
 pub mod fs_read;
 pub mod fs_write;
 pub mod gh_issue;
+pub mod knowledge_search;
+pub mod mcp_resource;
+pub mod permissions;
+pub mod sandbox;
 pub mod thinking;
 pub mod use_aws;
 
-use std::collections::HashMap;
 use std::io::Write;
 use std::path::{
     Path,
     PathBuf,
 };
 
+use bash_process::BashProcess;
 use crossterm::style::Stylize;
 use custom_tool::CustomTool;
+use delegate::Delegate;
 use execute_bash::ExecuteBash;
 use eyre::Result;
 use fs_read::FsRead;
 use fs_write::FsWrite;
 use gh_issue::GhIssue;
+use knowledge_search::KnowledgeSearch;
+use mcp_resource::McpResource;
+use permissions::{
+    PermissionAction,
+    PermissionPolicy,
+    PermissionRule,
+    RuleScope,
+    ToolUseArgs,
+    hides_commands,
+};
 use serde::{
     Deserialize,
     Serialize,
@@ -42,6 +59,10 @@ pub enum Tool {
     Custom(CustomTool),
     GhIssue(GhIssue),
     Thinking(Thinking),
+    KnowledgeSearch(KnowledgeSearch),
+    McpResource(McpResource),
+    Delegate(Delegate),
+    BashProcess(BashProcess),
 }
 
 impl Tool {
@@ -55,6 +76,10 @@ impl Tool {
             Tool::Custom(custom_tool) => &custom_tool.name,
             Tool::GhIssue(_) => "gh_issue",
             Tool::Thinking(_) => "thinking (prerelease)",
+            Tool::KnowledgeSearch(_) => "knowledge_search (prerelease)",
+            Tool::McpResource(_) => "mcp_resource",
+            Tool::Delegate(_) => "delegate",
+            Tool::BashProcess(_) => "bash_process",
         }
         .to_owned()
     }
@@ -69,6 +94,30 @@ impl Tool {
             Tool::Custom(_) => true,
             Tool::GhIssue(_) => false,
             Tool::Thinking(_) => false,
+            Tool::KnowledgeSearch(_) => false,
+            Tool::McpResource(_) => false,
+            Tool::Delegate(_) => false,
+            Tool::BashProcess(bash_process) => bash_process.requires_acceptance(),
+        }
+    }
+
+    /// Whether the tool can be invoked concurrently with other tool uses from the same response.
+    /// These tools do not modify local state, so the order in which they complete does not matter.
+    /// MCP tools may have any side effect, so they only run concurrently if their server marks them
+    /// as read-only.
+    pub fn is_concurrency_safe(&self) -> bool {
+        match self {
+            Tool::FsRead(_) => true,
+            Tool::FsWrite(_) => false,
+            Tool::ExecuteBash(_) => false,
+            Tool::UseAws(use_aws) => !use_aws.requires_acceptance(),
+            Tool::Custom(custom_tool) => custom_tool.read_only,
+            Tool::GhIssue(_) => false,
+            Tool::Thinking(_) => true,
+            Tool::KnowledgeSearch(_) => true,
+            Tool::McpResource(_) => true,
+            Tool::Delegate(_) => false,
+            Tool::BashProcess(_) => false,
         }
     }
 
@@ -82,6 +131,11 @@ impl Tool {
             Tool::Custom(custom_tool) => custom_tool.invoke(context, updates).await,
             Tool::GhIssue(gh_issue) => gh_issue.invoke(updates).await,
             Tool::Thinking(think) => think.invoke(updates).await,
+            Tool::KnowledgeSearch(knowledge_search) => knowledge_search.invoke(context, updates).await,
+            Tool::McpResource(mcp_resource) => mcp_resource.invoke(context, updates).await,
+            // Boxed since sub-agents invoke tools themselves.
+            Tool::Delegate(delegate) => Box::pin(delegate.invoke(updates)).await,
+            Tool::BashProcess(bash_process) => bash_process.invoke(updates).await,
         }
     }
 
@@ -95,6 +149,10 @@ impl Tool {
             Tool::Custom(custom_tool) => custom_tool.queue_description(updates),
             Tool::GhIssue(gh_issue) => gh_issue.queue_description(updates),
             Tool::Thinking(thinking) => thinking.queue_description(updates),
+            Tool::KnowledgeSearch(knowledge_search) => knowledge_search.queue_description(updates),
+            Tool::McpResource(mcp_resource) => mcp_resource.queue_description(updates),
+            Tool::Delegate(delegate) => delegate.queue_description(updates),
+            Tool::BashProcess(bash_process) => bash_process.queue_description(updates),
         }
     }
 
@@ -108,79 +166,118 @@ impl Tool {
             Tool::Custom(custom_tool) => custom_tool.validate(ctx).await,
             Tool::GhIssue(gh_issue) => gh_issue.validate(ctx).await,
             Tool::Thinking(think) => think.validate(ctx).await,
+            Tool::KnowledgeSearch(knowledge_search) => knowledge_search.validate(ctx).await,
+            Tool::McpResource(mcp_resource) => mcp_resource.validate(ctx).await,
+            Tool::Delegate(delegate) => delegate.validate(ctx).await,
+            Tool::BashProcess(bash_process) => bash_process.validate(ctx).await,
         }
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct ToolPermission {
-    pub trusted: bool,
+/// The outcome of checking a tool use against the [ToolPermissions].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PermissionDecision {
+    Allow,
+    Ask,
+    /// Denied by the contained rule.
+    Deny(PermissionRule),
 }
 
 #[derive(Debug, Clone)]
 /// Holds overrides for tool permissions.
-/// Tools that do not have an associated ToolPermission should use
-/// their default logic to determine to permission.
+/// Tool uses that do not match any [PermissionRule] should use their default logic to
+/// determine the permission.
 pub struct ToolPermissions {
     // We need this field for any stragglers
     pub trust_all: bool,
-    pub permissions: HashMap<String, ToolPermission>,
+    /// Session rules (`/tools`, `--trust-tools`) along with the workspace and profile policy
+    /// files.
+    pub policy: PermissionPolicy,
 }
 
 impl ToolPermissions {
     pub fn new(capacity: usize) -> Self {
         Self {
             trust_all: false,
-            permissions: HashMap::with_capacity(capacity),
+            policy: PermissionPolicy::with_capacity(capacity),
+        }
+    }
+
+    /// Checks a tool use against the permission rules, falling back to the tool's default
+    /// logic if no rule matches.
+    ///
+    /// Commands that hide other commands from the rules, e.g. with `$(...)` or `bash -c`, match
+    /// every deny and ask command rule, and need approval if a policy rule allows them. Otherwise,
+    /// tools trusted as a whole with `--trust-all-tools`, `--trust-tools` or `/tools trust` run
+    /// them without asking.
+    pub fn evaluate(&self, ctx: &Context, tool_name: &str, tool: &Tool) -> PermissionDecision {
+        let args = ToolUseArgs::from_tool(ctx, tool_name, tool);
+        match self.policy.evaluate(&args) {
+            Some((scope, rule)) => match rule.action {
+                PermissionAction::Allow if scope == RuleScope::Session && rule.is_bare() => PermissionDecision::Allow,
+                PermissionAction::Allow if args.command.as_deref().is_some_and(hides_commands) => {
+                    PermissionDecision::Ask
+                },
+                PermissionAction::Allow => PermissionDecision::Allow,
+                PermissionAction::Ask => PermissionDecision::Ask,
+                PermissionAction::Deny => PermissionDecision::Deny(rule.clone()),
+            },
+            None if self.trust_all || !tool.requires_acceptance(ctx) => PermissionDecision::Allow,
+            None => PermissionDecision::Ask,
         }
     }
 
     pub fn is_trusted(&self, tool_name: &str) -> bool {
-        self.trust_all || self.permissions.get(tool_name).is_some_and(|perm| perm.trusted)
+        match self.policy.bare_action(tool_name) {
+            Some(action) => action == PermissionAction::Allow,
+            None => self.trust_all,
+        }
     }
 
     /// Returns a label to describe the permission status for a given tool.
     pub fn display_label(&self, tool_name: &str) -> String {
-        if self.has(tool_name) || self.trust_all {
-            if self.is_trusted(tool_name) {
-                format!("  {}", "trusted".dark_green().bold())
-            } else {
-                format!("  {}", "not trusted".dark_grey())
-            }
-        } else {
-            self.default_permission_label(tool_name)
+        let label = match self.policy.bare_action(tool_name) {
+            Some(PermissionAction::Allow) => format!("  {}", "trusted".dark_green().bold()),
+            Some(PermissionAction::Ask) => format!("  {}", "not trusted".dark_grey()),
+            Some(PermissionAction::Deny) => format!("  {}", "denied".dark_red().bold()),
+            None if self.trust_all => format!("  {}", "trusted".dark_green().bold()),
+            None => Self::default_permission_label(tool_name),
+        };
+
+        match self.policy.argument_rule_count(tool_name) {
+            0 => label,
+            1 => format!("{label} {}", "(+1 rule)".dark_grey()),
+            count => format!("{label} {}", format!("(+{count} rules)").dark_grey()),
         }
     }
 
     pub fn trust_tool(&mut self, tool_name: &str) {
-        self.permissions
-            .insert(tool_name.to_string(), ToolPermission { trusted: true });
+        self.policy.set_session_action(tool_name, PermissionAction::Allow);
     }
 
     pub fn untrust_tool(&mut self, tool_name: &str) {
         self.trust_all = false;
-        self.permissions
-            .insert(tool_name.to_string(), ToolPermission { trusted: false });
+        self.policy.set_session_action(tool_name, PermissionAction::Ask);
     }
 
     pub fn reset(&mut self) {
         self.trust_all = false;
-        self.permissions.clear();
+        self.policy.clear_session();
     }
 
     pub fn reset_tool(&mut self, tool_name: &str) {
         self.trust_all = false;
-        self.permissions.remove(tool_name);
+        self.policy.clear_session_tool(tool_name);
     }
 
     pub fn has(&self, tool_name: &str) -> bool {
-        self.permissions.contains_key(tool_name)
+        self.policy.has_session_rules(tool_name)
     }
 
     /// Provide default permission labels for the built-in set of tools.
     /// Unknown tools are assumed to be "Per-request"
     // This "static" way avoids needing to construct a tool instance.
-    fn default_permission_label(&self, tool_name: &str) -> String {
+    fn default_permission_label(tool_name: &str) -> String {
         let label = match tool_name {
             "fs_read" => "trusted".dark_green().bold(),
             "fs_write" => "not trusted".dark_grey(),
@@ -188,7 +285,10 @@ impl ToolPermissions {
             "use_aws" => "trust read-only commands".dark_grey(),
             "report_issue" => "trusted".dark_green().bold(),
             "thinking" => "trusted (prerelease)".dark_green().bold(),
-            _ if self.trust_all => "trusted".dark_grey().bold(),
+            "knowledge_search" => "trusted (prerelease)".dark_green().bold(),
+            "mcp_resource" => "trusted".dark_green().bold(),
+            "delegate" => "trusted".dark_green().bold(),
+            "bash_process" => "trust reading and killing processes".dark_grey(),
             _ => "not trusted".dark_grey(),
         };
 
@@ -206,6 +306,19 @@ pub struct ToolSpec {
     pub input_schema: InputSchema,
     #[serde(skip_serializing, default = "tool_origin")]
     pub tool_origin: ToolOrigin,
+    /// Hints about the behavior of MCP tools, as advertised by their server.
+    #[serde(skip_serializing, default)]
+    pub annotations: ToolAnnotations,
+}
+
+/// Hints that an MCP server gives about the behavior of a tool. Servers are not trusted to report
+/// them accurately, so they never let a tool run without the user's approval.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolAnnotations {
+    /// The tool does not modify its environment.
+    #[serde(default)]
+    pub read_only_hint: bool,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
@@ -257,8 +370,15 @@ fn tool_origin() -> ToolOrigin {
 pub struct QueuedTool {
     pub id: String,
     pub name: String,
+    /// Input of the tool use, as requested by the model or rewritten by a pre_tool_use hook.
+    pub args: serde_json::Value,
     pub accepted: bool,
     pub tool: Tool,
+    /// Whether the pre_tool_use hooks have run. They run once, before permissions are checked,
+    /// even if the user is prompted for several tool uses of the same response.
+    pub pre_tool_use_ran: bool,
+    /// Why a pre_tool_use hook blocked the tool use.
+    pub blocked: Option<String>,
 }
 
 /// The schema specification describing a tool's fields.
@@ -374,6 +494,108 @@ mod tests {
     use super::*;
     use crate::platform::EnvProvider;
 
+    #[tokio::test]
+    async fn test_custom_tool_requires_acceptance() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let config = serde_json::from_value::<custom_tool::CustomToolConfig>(serde_json::json!({
+            "url": "http://localhost:1/mcp"
+        }))
+        .unwrap();
+        let mut custom_tool = CustomTool {
+            name: "tool".to_string(),
+            client: std::sync::Arc::new(
+                custom_tool::CustomToolClient::from_config("server".to_string(), config).unwrap(),
+            ),
+            method: "tools/call".to_string(),
+            params: None,
+            read_only: false,
+        };
+        let tool = Tool::Custom(custom_tool.clone());
+        assert!(tool.requires_acceptance(&ctx));
+        assert!(!tool.is_concurrency_safe());
+
+        custom_tool.read_only = true;
+        let tool = Tool::Custom(custom_tool);
+        assert!(tool.requires_acceptance(&ctx));
+        assert!(tool.is_concurrency_safe());
+
+        let spec = serde_json::from_value::<ToolSpec>(serde_json::json!({
+            "name": "tool",
+            "description": "A read-only tool",
+            "inputSchema": { "type": "object" },
+            "annotations": { "readOnlyHint": true },
+        }))
+        .unwrap();
+        assert!(spec.annotations.read_only_hint);
+    }
+
+    #[tokio::test]
+    async fn test_hidden_commands_need_approval() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let mut permissions = ToolPermissions::new(0);
+        permissions
+            .policy
+            .add(
+                &ctx,
+                RuleScope::Profile,
+                PermissionRule::new("execute_bash", PermissionAction::Allow),
+            )
+            .await
+            .unwrap();
+        let bash = |command: &str| {
+            Tool::ExecuteBash(serde_json::from_value(serde_json::json!({ "command": command })).unwrap())
+        };
+        assert_eq!(
+            permissions.evaluate(&ctx, "execute_bash", &bash("ls")),
+            PermissionDecision::Allow
+        );
+        assert_eq!(
+            permissions.evaluate(&ctx, "execute_bash", &bash("bash -c 'ls'")),
+            PermissionDecision::Ask
+        );
+        assert_eq!(
+            permissions.evaluate(&ctx, "execute_bash", &bash("echo $(ls)")),
+            PermissionDecision::Ask
+        );
+
+        // Trusting the tool for the session runs hidden commands too.
+        permissions.trust_tool("execute_bash");
+        assert_eq!(
+            permissions.evaluate(&ctx, "execute_bash", &bash("echo $(ls)")),
+            PermissionDecision::Allow
+        );
+        let mut permissions = ToolPermissions::new(0);
+        permissions.trust_all = true;
+        assert_eq!(
+            permissions.evaluate(&ctx, "execute_bash", &bash("bash -c 'ls'")),
+            PermissionDecision::Allow
+        );
+
+        // Unless a deny or ask rule could match one of the hidden commands.
+        let deny_rm = PermissionRule {
+            command_regex: Some(r"^rm\s+-\w*r".to_string()),
+            ..PermissionRule::new("execute_bash", PermissionAction::Deny)
+        };
+        permissions
+            .policy
+            .add(&ctx, RuleScope::Profile, deny_rm.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            permissions.evaluate(&ctx, "execute_bash", &bash("bash -c 'rm -rf build'")),
+            PermissionDecision::Deny(deny_rm.clone())
+        );
+        permissions.trust_tool("execute_bash");
+        assert_eq!(
+            permissions.evaluate(&ctx, "execute_bash", &bash("echo $(rm -rf build)")),
+            PermissionDecision::Deny(deny_rm)
+        );
+        assert_eq!(
+            permissions.evaluate(&ctx, "execute_bash", &bash("ls")),
+            PermissionDecision::Allow
+        );
+    }
+
     #[tokio::test]
     async fn test_tilde_path_expansion() {
         let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
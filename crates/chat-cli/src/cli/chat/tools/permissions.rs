@@ -0,0 +1,821 @@
+use std::fmt::Display;
+use std::path::{
+    Component,
+    Path,
+    PathBuf,
+};
+
+use eyre::{
+    Result,
+    bail,
+    eyre,
+};
+use globset::{
+    GlobBuilder,
+    GlobMatcher,
+};
+use regex::Regex;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use tracing::warn;
+
+use super::Tool;
+use super::execute_bash::has_shell_operators;
+use crate::platform::Context;
+use crate::util::directories;
+
+/// Name of the policy file stored in a profile directory or in a workspace's `.amazonq`
+/// directory.
+pub const POLICY_FILE_NAME: &str = "permissions.json";
+
+/// What should happen when a [PermissionRule] matches a tool use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PermissionAction {
+    /// Run the tool without asking for confirmation.
+    Allow,
+    /// Refuse to run the tool, the model is told that the tool use was denied.
+    Deny,
+    /// Ask the user for confirmation before running the tool.
+    Ask,
+}
+
+impl Display for PermissionAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PermissionAction::Allow => write!(f, "allow"),
+            PermissionAction::Deny => write!(f, "deny"),
+            PermissionAction::Ask => write!(f, "ask"),
+        }
+    }
+}
+
+/// Where a [PermissionRule] comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleScope {
+    /// Rules added with `/tools` or `--trust-tools`, lost when the session ends.
+    Session,
+    /// Rules in `.amazonq/permissions.json` under the current working directory. Only deny and ask
+    /// rules are honored, since the file comes with the repository rather than from the user.
+    Workspace,
+    /// Rules in `permissions.json` in the directory of the active profile.
+    Profile,
+}
+
+impl Display for RuleScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuleScope::Session => write!(f, "session"),
+            RuleScope::Workspace => write!(f, "workspace"),
+            RuleScope::Profile => write!(f, "profile"),
+        }
+    }
+}
+
+/// A single allow/deny/ask rule matching on a tool name and, optionally, the arguments of the
+/// tool use.
+///
+/// Every matcher that is set must match for the rule to apply. Argument matchers only apply to
+/// the tools that have such an argument, e.g. a rule with a `command` never matches `fs_write`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PermissionRule {
+    /// Glob matched against the tool name. For MCP tools, this is matched against both the name
+    /// seen by the model and the name advertised by the server.
+    pub tool: String,
+    pub action: PermissionAction,
+    /// Glob matched against every path written or read by `fs_write` and `fs_read`. Relative
+    /// globs are resolved against the current working directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Prefix matched against the commands run by `execute_bash`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Regex matched against the commands run by `execute_bash`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command_regex: Option<String>,
+    /// Glob matched against the service name of `use_aws`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+    /// Glob matched against the operation name of `use_aws`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub operation: Option<String>,
+    /// Glob matched against the name of the MCP server providing the tool.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+}
+
+impl PermissionRule {
+    pub fn new(tool: impl Into<String>, action: PermissionAction) -> Self {
+        Self {
+            tool: tool.into(),
+            action,
+            path: None,
+            command: None,
+            command_regex: None,
+            service: None,
+            operation: None,
+            server: None,
+        }
+    }
+
+    /// Whether the rule only matches on the tool name.
+    pub fn is_bare(&self) -> bool {
+        self.path.is_none()
+            && self.command.is_none()
+            && self.command_regex.is_none()
+            && self.service.is_none()
+            && self.operation.is_none()
+            && self.server.is_none()
+    }
+
+    /// Checks that every glob and regex of the rule compiles.
+    pub fn validate(&self) -> Result<()> {
+        if self.tool.is_empty() {
+            bail!("A permission rule must specify a tool");
+        }
+        let globs = [&self.path, &self.service, &self.operation, &self.server];
+        for glob in std::iter::once(&self.tool).chain(globs.into_iter().flatten()) {
+            compile_glob(glob, false)?;
+        }
+        if let Some(regex) = &self.command_regex {
+            Regex::new(regex).map_err(|e| eyre!("Invalid command regex '{}': {}", regex, e))?;
+        }
+        Ok(())
+    }
+
+    /// Whether the rule applies to the given tool use.
+    pub fn matches(&self, args: &ToolUseArgs) -> bool {
+        let names_match = glob_matches(&self.tool, &args.name, false)
+            || args
+                .server_tool_name
+                .as_ref()
+                .is_some_and(|name| glob_matches(&self.tool, name, false));
+        if !names_match {
+            return false;
+        }
+
+        if let Some(server) = &self.server {
+            if !args.server.as_ref().is_some_and(|s| glob_matches(server, s, false)) {
+                return false;
+            }
+        }
+
+        if let Some(path) = &self.path {
+            let pattern = resolve_path(path, &args.cwd, args.home.as_deref());
+            let path_matches = |p: &PathBuf| glob_matches(&pattern.to_string_lossy(), &p.to_string_lossy(), true);
+            // Like command matchers, allow rules must cover every path the tool touches while deny
+            // and ask rules match if any of them does.
+            let paths_match = match self.action {
+                PermissionAction::Allow => args.paths.iter().all(path_matches),
+                PermissionAction::Deny | PermissionAction::Ask => args.paths.iter().any(path_matches),
+            };
+            if args.paths.is_empty() || !paths_match {
+                return false;
+            }
+        }
+
+        if self.command.is_some() || self.command_regex.is_some() {
+            let Some(command) = &args.command else {
+                return false;
+            };
+            if !self.matches_command(command) {
+                return false;
+            }
+        }
+
+        if let Some(service) = &self.service {
+            if !args.service.as_ref().is_some_and(|s| glob_matches(service, s, false)) {
+                return false;
+            }
+        }
+
+        if let Some(operation) = &self.operation {
+            if !args
+                .operation
+                .as_ref()
+                .is_some_and(|o| glob_matches(operation, o, false))
+            {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Command matchers are applied to each command of a pipeline or list separately.
+    ///
+    /// Allow rules never match a command that chains, pipes, or substitutes other commands since
+    /// only the first one would have been checked. Deny and ask rules match if any of the commands
+    /// match, and always match a command that [hides_commands] since the hidden commands can't be
+    /// checked.
+    fn matches_command(&self, command: &str) -> bool {
+        if hides_commands(command) {
+            return self.action != PermissionAction::Allow;
+        }
+        let regex = match self.command_regex.as_deref().map(Regex::new) {
+            Some(Ok(regex)) => Some(regex),
+            Some(Err(_)) => return false,
+            None => None,
+        };
+        let segment_matches = |segment: &str| {
+            let prefix_matches = self.command.as_ref().is_none_or(|prefix| {
+                segment == prefix
+                    || segment
+                        .strip_prefix(prefix.as_str())
+                        .is_some_and(|rest| rest.starts_with(char::is_whitespace))
+            });
+            prefix_matches && regex.as_ref().is_none_or(|r| r.is_match(segment))
+        };
+
+        match self.action {
+            PermissionAction::Allow => !has_shell_operators(command) && segment_matches(command.trim()),
+            PermissionAction::Deny | PermissionAction::Ask => command
+                .split(['|', ';', '&', '\n'])
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .any(segment_matches),
+        }
+    }
+}
+
+impl Display for PermissionRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.action, self.tool)?;
+        let matchers = [
+            ("server", &self.server),
+            ("path", &self.path),
+            ("command", &self.command),
+            ("regex", &self.command_regex),
+            ("service", &self.service),
+            ("operation", &self.operation),
+        ];
+        for (label, value) in matchers {
+            if let Some(value) = value {
+                write!(f, " {}={:?}", label, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The parts of a tool use that permission rules can match against.
+#[derive(Debug, Clone, Default)]
+pub struct ToolUseArgs {
+    /// Name of the tool as seen by the model.
+    pub name: String,
+    /// For MCP tools, the name of the server providing the tool.
+    pub server: Option<String>,
+    /// For MCP tools, the name of the tool as advertised by its server.
+    pub server_tool_name: Option<String>,
+    /// Absolute, normalized paths that the tool use reads or writes.
+    pub paths: Vec<PathBuf>,
+    pub command: Option<String>,
+    pub service: Option<String>,
+    pub operation: Option<String>,
+    pub cwd: PathBuf,
+    pub home: Option<PathBuf>,
+}
+
+impl ToolUseArgs {
+    pub fn from_tool(ctx: &Context, name: &str, tool: &Tool) -> Self {
+        let cwd = ctx.fs().chroot_path(ctx.env().current_dir().unwrap_or_default());
+        let home = ctx.env().home().map(|home| ctx.fs().chroot_path(home));
+        let mut args = Self {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        match tool {
+            Tool::FsRead(fs_read) => args.paths = fs_read.affected_paths(ctx),
+            Tool::FsWrite(fs_write) => args.paths = fs_write.affected_paths(ctx),
+            Tool::ExecuteBash(execute_bash) => args.command = Some(execute_bash.command.clone()),
+            Tool::UseAws(use_aws) => {
+                args.service = Some(use_aws.service_name.clone());
+                args.operation = Some(use_aws.operation_name.clone());
+            },
+            Tool::Custom(custom_tool) => {
+                args.server = Some(custom_tool.client.get_server_name().to_string());
+                args.server_tool_name = Some(custom_tool.name.clone());
+            },
+            Tool::McpResource(mcp_resource) => args.server = mcp_resource.server_name().map(str::to_string),
+            Tool::GhIssue(_)
+            | Tool::Thinking(_)
+            | Tool::KnowledgeSearch(_)
+            | Tool::Delegate(_)
+            | Tool::BashProcess(_) => (),
+        }
+        args.paths = args.paths.iter().map(|p| normalize_path(&cwd.join(p))).collect();
+        args.cwd = cwd;
+        args.home = home;
+        args
+    }
+}
+
+/// The permission rules in effect for a chat session.
+///
+/// Rules are evaluated in order: session rules from the most recently added, then workspace rules,
+/// then profile rules. A matching deny rule always wins, otherwise the first matching rule
+/// decides.
+#[derive(Debug, Clone, Default)]
+pub struct PermissionPolicy {
+    session: Vec<PermissionRule>,
+    workspace: Vec<PermissionRule>,
+    profile: Vec<PermissionRule>,
+    profile_name: Option<String>,
+}
+
+/// The content of a policy file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PolicyFile {
+    #[serde(default)]
+    pub rules: Vec<PermissionRule>,
+}
+
+impl PermissionPolicy {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            session: Vec::with_capacity(capacity),
+            ..Default::default()
+        }
+    }
+
+    /// Loads the workspace policy file and the policy file of `profile`.
+    ///
+    /// Invalid rules are skipped with a warning so that a typo does not prevent the chat from
+    /// starting. So are allow rules of the workspace policy file, since a cloned repository must
+    /// not be able to grant its own tool uses permissions.
+    pub async fn load(&mut self, ctx: &Context, profile: &str) -> Result<()> {
+        let path = workspace_policy_path(ctx)?;
+        self.workspace = load_policy_file(ctx, &path).await?;
+        self.workspace.retain(|rule| {
+            if rule.action == PermissionAction::Allow {
+                warn!("Ignoring allow rule '{}' in {}", rule, path.display());
+            }
+            rule.action != PermissionAction::Allow
+        });
+        self.load_profile(ctx, profile).await
+    }
+
+    /// Replaces the profile rules with the ones of `profile`, e.g. after switching profiles.
+    pub async fn load_profile(&mut self, ctx: &Context, profile: &str) -> Result<()> {
+        self.profile = load_policy_file(ctx, &profile_policy_path(ctx, profile)?).await?;
+        self.profile_name = Some(profile.to_string());
+        Ok(())
+    }
+
+    /// Every rule along with its scope, in evaluation order.
+    pub fn rules(&self) -> impl Iterator<Item = (RuleScope, &PermissionRule)> {
+        self.session
+            .iter()
+            .rev()
+            .map(|r| (RuleScope::Session, r))
+            .chain(self.workspace.iter().map(|r| (RuleScope::Workspace, r)))
+            .chain(self.profile.iter().map(|r| (RuleScope::Profile, r)))
+    }
+
+    /// Returns the rule deciding the permission of the tool use, if any, along with its scope.
+    pub fn evaluate(&self, args: &ToolUseArgs) -> Option<(RuleScope, &PermissionRule)> {
+        let mut matching = self.rules().filter(|(_, r)| r.matches(args));
+        let first = matching.next()?;
+        if first.1.action == PermissionAction::Deny {
+            return Some(first);
+        }
+        Some(
+            matching
+                .find(|(_, r)| r.action == PermissionAction::Deny)
+                .unwrap_or(first),
+        )
+    }
+
+    /// The action of the bare rule that decides the permission of `tool_name` regardless of its
+    /// arguments, if any.
+    pub fn bare_action(&self, tool_name: &str) -> Option<PermissionAction> {
+        let mut bare = self
+            .rules()
+            .map(|(_, r)| r)
+            .filter(|r| r.is_bare() && glob_matches(&r.tool, tool_name, false));
+        let first = bare.next()?.action;
+        if bare.any(|r| r.action == PermissionAction::Deny) {
+            return Some(PermissionAction::Deny);
+        }
+        Some(first)
+    }
+
+    /// Number of rules for `tool_name` that match on arguments.
+    pub fn argument_rule_count(&self, tool_name: &str) -> usize {
+        self.rules()
+            .filter(|(_, r)| !r.is_bare() && glob_matches(&r.tool, tool_name, false))
+            .count()
+    }
+
+    /// Replaces every bare session rule for `tool_name` with a single one with the given action.
+    pub fn set_session_action(&mut self, tool_name: &str, action: PermissionAction) {
+        self.session.retain(|r| !(r.is_bare() && r.tool == tool_name));
+        self.session.push(PermissionRule::new(tool_name, action));
+    }
+
+    pub fn has_session_rules(&self, tool_name: &str) -> bool {
+        self.session.iter().any(|r| r.tool == tool_name)
+    }
+
+    pub fn clear_session(&mut self) {
+        self.session.clear();
+    }
+
+    pub fn clear_session_tool(&mut self, tool_name: &str) {
+        self.session.retain(|r| r.tool != tool_name);
+    }
+
+    /// Adds a rule, persisting it to the matching policy file unless it is a session rule.
+    ///
+    /// The rule takes precedence over every existing rule of the same scope.
+    pub async fn add(&mut self, ctx: &Context, scope: RuleScope, rule: PermissionRule) -> Result<()> {
+        rule.validate()?;
+        if scope == RuleScope::Workspace && rule.action == PermissionAction::Allow {
+            bail!("Allow rules are not read from the workspace, save the rule to the profile instead");
+        }
+        match scope {
+            RuleScope::Session => self.session.push(rule),
+            RuleScope::Workspace => {
+                let mut rules = self.workspace.clone();
+                rules.insert(0, rule);
+                save_policy_file(ctx, &workspace_policy_path(ctx)?, &rules).await?;
+                self.workspace = rules;
+            },
+            RuleScope::Profile => {
+                let mut rules = self.profile.clone();
+                rules.insert(0, rule);
+                save_policy_file(ctx, &self.current_profile_policy_path(ctx)?, &rules).await?;
+                self.profile = rules;
+            },
+        }
+        Ok(())
+    }
+
+    /// Removes the rule at `index`, as numbered by [Self::rules] starting from 1.
+    pub async fn remove(&mut self, ctx: &Context, index: usize) -> Result<(RuleScope, PermissionRule)> {
+        let (scope, rule) = self
+            .rules()
+            .nth(index.wrapping_sub(1))
+            .map(|(scope, rule)| (scope, rule.clone()))
+            .ok_or(eyre!("No permission rule with number {}", index))?;
+        let position_in_scope = self.rules().take(index - 1).filter(|(s, _)| *s == scope).count();
+        match scope {
+            RuleScope::Session => {
+                let len = self.session.len();
+                self.session.remove(len - 1 - position_in_scope);
+            },
+            RuleScope::Workspace => {
+                let mut rules = self.workspace.clone();
+                rules.remove(position_in_scope);
+                save_policy_file(ctx, &workspace_policy_path(ctx)?, &rules).await?;
+                self.workspace = rules;
+            },
+            RuleScope::Profile => {
+                let mut rules = self.profile.clone();
+                rules.remove(position_in_scope);
+                save_policy_file(ctx, &self.current_profile_policy_path(ctx)?, &rules).await?;
+                self.profile = rules;
+            },
+        }
+        Ok((scope, rule))
+    }
+
+    fn current_profile_policy_path(&self, ctx: &Context) -> Result<PathBuf> {
+        profile_policy_path(ctx, self.profile_name.as_deref().unwrap_or("default"))
+    }
+}
+
+/// Whether `command` runs commands that can't be matched against the command matchers of a
+/// [PermissionRule]: command substitutions, and scripts given to a shell with `-c` or to `eval`.
+pub fn hides_commands(command: &str) -> bool {
+    const SHELLS: &[&str] = &["sh", "bash", "zsh", "dash", "ksh", "fish", "csh", "tcsh"];
+    if command.contains("$(") || command.contains('`') || command.contains("<(") || command.contains(">(") {
+        return true;
+    }
+    let words = shlex::split(command).unwrap_or_else(|| command.split_whitespace().map(str::to_string).collect());
+    let mut words = words.iter().map(|word| {
+        word.trim_start_matches(['(', '{'])
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+    });
+    while let Some(word) = words.next() {
+        if word == "eval" {
+            return true;
+        }
+        if SHELLS.contains(&word)
+            && words
+                .clone()
+                .take_while(|arg| arg.starts_with('-'))
+                .any(|arg| !arg.starts_with("--") && arg.contains('c'))
+        {
+            return true;
+        }
+    }
+    false
+}
+
+/// Path to the policy file of the workspace in the current working directory.
+pub fn workspace_policy_path(ctx: &Context) -> Result<PathBuf> {
+    Ok(ctx.env().current_dir()?.join(".amazonq").join(POLICY_FILE_NAME))
+}
+
+/// Path to the policy file of `profile_name`.
+pub fn profile_policy_path(ctx: &Context, profile_name: &str) -> Result<PathBuf> {
+    Ok(directories::chat_profiles_dir(ctx)?
+        .join(profile_name)
+        .join(POLICY_FILE_NAME))
+}
+
+async fn load_policy_file(ctx: &Context, path: &Path) -> Result<Vec<PermissionRule>> {
+    if !ctx.fs().exists(path) {
+        return Ok(Vec::new());
+    }
+    let contents = ctx.fs().read_to_string(path).await?;
+    let file: PolicyFile = serde_json::from_str(&contents)
+        .map_err(|e| eyre!("Failed to parse permission rules at {}: {}", path.display(), e))?;
+    Ok(file
+        .rules
+        .into_iter()
+        .filter(|rule| match rule.validate() {
+            Ok(()) => true,
+            Err(err) => {
+                warn!(?err, "Skipping invalid permission rule in {}", path.display());
+                false
+            },
+        })
+        .collect())
+}
+
+async fn save_policy_file(ctx: &Context, path: &Path, rules: &[PermissionRule]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        ctx.fs().create_dir_all(parent).await?;
+    }
+    let file = PolicyFile { rules: rules.to_vec() };
+    ctx.fs().write(path, serde_json::to_string_pretty(&file)?).await?;
+    Ok(())
+}
+
+fn compile_glob(pattern: &str, literal_separator: bool) -> Result<GlobMatcher> {
+    Ok(GlobBuilder::new(pattern)
+        .literal_separator(literal_separator)
+        .build()
+        .map_err(|e| eyre!("Invalid glob '{}': {}", pattern, e))?
+        .compile_matcher())
+}
+
+fn glob_matches(pattern: &str, value: &str, literal_separator: bool) -> bool {
+    compile_glob(pattern, literal_separator).is_ok_and(|glob| glob.is_match(value))
+}
+
+/// Expands `~` and resolves a relative path glob against `cwd`.
+fn resolve_path(pattern: &str, cwd: &Path, home: Option<&Path>) -> PathBuf {
+    match (pattern.strip_prefix("~/"), home) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ if pattern.starts_with("**") => PathBuf::from(pattern),
+        _ => cwd.join(pattern),
+    }
+}
+
+/// Lexically removes `.` and `..` components so that a path like `src/../../etc` can not escape a
+/// glob like `src/**`.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                normalized.pop();
+            },
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bash(command: &str) -> ToolUseArgs {
+        ToolUseArgs {
+            name: "execute_bash".to_string(),
+            command: Some(command.to_string()),
+            cwd: PathBuf::from("/repo"),
+            ..Default::default()
+        }
+    }
+
+    fn write(path: &str) -> ToolUseArgs {
+        ToolUseArgs {
+            name: "fs_write".to_string(),
+            paths: vec![normalize_path(&Path::new("/repo").join(path))],
+            cwd: PathBuf::from("/repo"),
+            home: Some(PathBuf::from("/home/user")),
+            ..Default::default()
+        }
+    }
+
+    fn rule(tool: &str, action: PermissionAction) -> PermissionRule {
+        PermissionRule::new(tool, action)
+    }
+
+    #[test]
+    fn test_path_rules() {
+        let allow_src = PermissionRule {
+            path: Some("src/**".to_string()),
+            ..rule("fs_write", PermissionAction::Allow)
+        };
+        assert!(allow_src.matches(&write("src/main.rs")));
+        assert!(allow_src.matches(&write("src/cli/mod.rs")));
+        assert!(!allow_src.matches(&write("Cargo.toml")));
+        assert!(!allow_src.matches(&write("src/../../etc/passwd")));
+        assert!(!allow_src.matches(&bash("ls src")));
+
+        let deny_ssh = PermissionRule {
+            path: Some("~/.ssh/**".to_string()),
+            ..rule("fs_*", PermissionAction::Deny)
+        };
+        assert!(deny_ssh.matches(&write("/home/user/.ssh/id_rsa")));
+        assert!(!deny_ssh.matches(&write("/home/user/notes.txt")));
+
+        // A tool use touching several paths is denied if any of them is, but only allowed if all
+        // of them are
+        let mut rename = write("src/main.rs");
+        rename.paths.push(PathBuf::from("/home/user/.ssh/id_rsa"));
+        assert!(deny_ssh.matches(&rename));
+        assert!(!allow_src.matches(&rename));
+        let ask_secrets = PermissionRule {
+            path: Some("secrets/**".to_string()),
+            ..rule("fs_write", PermissionAction::Ask)
+        };
+        let mut patch = write("src/main.rs");
+        patch.paths.push(PathBuf::from("/repo/secrets/token"));
+        assert!(ask_secrets.matches(&patch));
+        assert!(!ask_secrets.matches(&write("src/main.rs")));
+    }
+
+    #[test]
+    fn test_command_rules() {
+        let allow_git = PermissionRule {
+            command: Some("git status".to_string()),
+            ..rule("execute_bash", PermissionAction::Allow)
+        };
+        assert!(allow_git.matches(&bash("git status")));
+        assert!(allow_git.matches(&bash("git status --short")));
+        assert!(!allow_git.matches(&bash("git statusx")));
+        assert!(!allow_git.matches(&bash("git status && rm -rf /")));
+        assert!(!allow_git.matches(&bash("git status | sh")));
+
+        let deny_rm = PermissionRule {
+            command_regex: Some(r"^rm\s+-\w*r".to_string()),
+            ..rule("execute_bash", PermissionAction::Deny)
+        };
+        assert!(deny_rm.matches(&bash("rm -rf build")));
+        assert!(deny_rm.matches(&bash("ls; rm -fr /")));
+        assert!(!deny_rm.matches(&bash("rm file.txt")));
+
+        // Commands hidden from the matchers match every deny and ask rule, and no allow rule.
+        let ask_git_push = PermissionRule {
+            command: Some("git push".to_string()),
+            ..rule("execute_bash", PermissionAction::Ask)
+        };
+        for command in [
+            "echo $(rm -rf build)",
+            "echo `rm -rf build`",
+            "bash -c 'rm -rf build'",
+            "/bin/sh -ec 'rm -rf build'",
+            "ls && eval \"rm -rf build\"",
+        ] {
+            assert!(hides_commands(command), "{command}");
+            assert!(deny_rm.matches(&bash(command)), "{command}");
+            assert!(ask_git_push.matches(&bash(command)), "{command}");
+        }
+        assert!(!allow_git.matches(&bash("bash -c 'git status'")));
+        assert!(!hides_commands("bash script.sh -c"));
+        assert!(!hides_commands("git commit -m 'fix bash'"));
+    }
+
+    #[test]
+    fn test_aws_and_mcp_rules() {
+        let allow_s3_reads = PermissionRule {
+            service: Some("s3".to_string()),
+            operation: Some("list-*".to_string()),
+            ..rule("use_aws", PermissionAction::Allow)
+        };
+        let aws = |service: &str, operation: &str| ToolUseArgs {
+            name: "use_aws".to_string(),
+            service: Some(service.to_string()),
+            operation: Some(operation.to_string()),
+            ..Default::default()
+        };
+        assert!(allow_s3_reads.matches(&aws("s3", "list-buckets")));
+        assert!(!allow_s3_reads.matches(&aws("s3", "delete-bucket")));
+        assert!(!allow_s3_reads.matches(&aws("ec2", "list-instances")));
+
+        let allow_git_server = PermissionRule {
+            server: Some("git".to_string()),
+            ..rule("*", PermissionAction::Allow)
+        };
+        let mcp = |server: &str, tool: &str| ToolUseArgs {
+            name: format!("{server}___{tool}"),
+            server: Some(server.to_string()),
+            server_tool_name: Some(tool.to_string()),
+            ..Default::default()
+        };
+        assert!(allow_git_server.matches(&mcp("git", "git_log")));
+        assert!(!allow_git_server.matches(&mcp("github", "create_issue")));
+        assert!(rule("create_issue", PermissionAction::Deny).matches(&mcp("github", "create_issue")));
+    }
+
+    #[test]
+    fn test_policy_precedence() {
+        let mut policy = PermissionPolicy {
+            session: vec![PermissionRule {
+                command: Some("terraform".to_string()),
+                ..rule("execute_bash", PermissionAction::Deny)
+            }],
+            workspace: vec![PermissionRule {
+                command: Some("git push".to_string()),
+                ..rule("execute_bash", PermissionAction::Ask)
+            }],
+            profile: vec![rule("execute_bash", PermissionAction::Allow)],
+            profile_name: None,
+        };
+
+        let action = |policy: &PermissionPolicy, command: &str| policy.evaluate(&bash(command)).map(|(_, r)| r.action);
+        assert_eq!(action(&policy, "ls"), Some(PermissionAction::Allow));
+        assert_eq!(action(&policy, "git push origin"), Some(PermissionAction::Ask));
+        assert_eq!(action(&policy, "terraform apply"), Some(PermissionAction::Deny));
+        assert_eq!(policy.bare_action("execute_bash"), Some(PermissionAction::Allow));
+        assert_eq!(policy.argument_rule_count("execute_bash"), 2);
+
+        // A deny rule wins even when a rule with a higher precedence allows the tool use.
+        policy.set_session_action("execute_bash", PermissionAction::Allow);
+        assert_eq!(action(&policy, "terraform apply"), Some(PermissionAction::Deny));
+        assert_eq!(policy.rules().next().map(|(s, _)| s), Some(RuleScope::Session));
+    }
+
+    #[tokio::test]
+    async fn test_add_and_remove_persisted_rules() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let mut policy = PermissionPolicy::default();
+        policy.load(&ctx, "default").await.unwrap();
+
+        let deny = PermissionRule {
+            command: Some("rm".to_string()),
+            ..rule("execute_bash", PermissionAction::Deny)
+        };
+        policy.add(&ctx, RuleScope::Profile, deny.clone()).await.unwrap();
+        assert!(
+            policy
+                .add(&ctx, RuleScope::Workspace, rule("fs_write", PermissionAction::Allow))
+                .await
+                .is_err()
+        );
+        policy
+            .add(&ctx, RuleScope::Session, rule("fs_write", PermissionAction::Allow))
+            .await
+            .unwrap();
+        assert!(
+            policy
+                .add(&ctx, RuleScope::Session, PermissionRule {
+                    command_regex: Some("(".to_string()),
+                    ..rule("execute_bash", PermissionAction::Deny)
+                })
+                .await
+                .is_err()
+        );
+
+        let mut reloaded = PermissionPolicy::default();
+        reloaded.load(&ctx, "default").await.unwrap();
+        assert_eq!(reloaded.rules().map(|(_, r)| r).collect::<Vec<_>>(), vec![&deny]);
+
+        assert_eq!(
+            policy.remove(&ctx, 2).await.unwrap(),
+            (RuleScope::Profile, deny.clone())
+        );
+        assert!(policy.remove(&ctx, 2).await.is_err());
+        reloaded.load(&ctx, "default").await.unwrap();
+        assert_eq!(reloaded.rules().count(), 0);
+
+        // Allow rules of the workspace policy file are ignored.
+        let workspace = PolicyFile {
+            rules: vec![rule("execute_bash", PermissionAction::Allow), deny.clone()],
+        };
+        let path = workspace_policy_path(&ctx).unwrap();
+        ctx.fs().create_dir_all(path.parent().unwrap()).await.unwrap();
+        ctx.fs()
+            .write(&path, serde_json::to_string(&workspace).unwrap())
+            .await
+            .unwrap();
+        reloaded.load(&ctx, "default").await.unwrap();
+        assert_eq!(reloaded.rules().collect::<Vec<_>>(), vec![(
+            RuleScope::Workspace,
+            &deny
+        )]);
+    }
+}
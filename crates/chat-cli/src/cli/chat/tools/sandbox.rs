@@ -0,0 +1,367 @@
+use std::path::{
+    Path,
+    PathBuf,
+};
+
+use eyre::Result;
+
+use crate::database::settings::{
+    Setting,
+    Settings,
+};
+
+/// Restrictions applied to the commands run by `execute_bash`, enabled with the
+/// `chat.sandbox.enabled` setting.
+///
+/// Only supported on Linux, where file system access is confined with Landlock and the network is
+/// blocked by running commands in new user and network namespaces. Commands can only read the
+/// current working directory, the system directories needed to run programs, such as `/usr` and
+/// `/etc`, and `/proc`.
+///
+/// In particular, the home directory, `/opt` and `/nix` can't be read, so programs installed there,
+/// e.g. under `~/.cargo/bin`, can't be run and configuration files such as `~/.gitconfig` are
+/// ignored. Such paths can be made readable with `chat.sandbox.readPaths`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SandboxPolicy {
+    /// Allow writes under the current working directory and the temporary directory, set with
+    /// `chat.sandbox.allowWrites`. Otherwise, only devices such as `/dev/null` can be written to.
+    pub allow_writes: bool,
+    /// Allow network access, set with `chat.sandbox.allowNetwork`.
+    pub allow_network: bool,
+    /// Additional paths that can be read, set with `chat.sandbox.readPaths`. Paths that don't exist
+    /// are skipped.
+    pub read_paths: Vec<PathBuf>,
+}
+
+impl SandboxPolicy {
+    /// Returns the sandbox configured in `settings`, if it is enabled.
+    pub fn from_settings(settings: &Settings) -> Option<Self> {
+        settings
+            .get_bool(Setting::ChatSandboxEnabled)
+            .unwrap_or(false)
+            .then(|| Self {
+                allow_writes: settings.get_bool(Setting::ChatSandboxAllowWrites).unwrap_or(false),
+                allow_network: settings.get_bool(Setting::ChatSandboxAllowNetwork).unwrap_or(false),
+                read_paths: settings
+                    .get(Setting::ChatSandboxReadPaths)
+                    .and_then(|value| value.as_array())
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|path| path.as_str())
+                    .map(|path| PathBuf::from(shellexpand::tilde(path).as_ref()))
+                    .collect(),
+            })
+    }
+
+    /// Confines `command`, which runs from `cwd`, to the sandbox. Fails if the sandbox is not
+    /// supported rather than running the command unconfined.
+    #[cfg(target_os = "linux")]
+    pub fn apply(&self, command: &mut tokio::process::Command, cwd: &Path) -> Result<()> {
+        linux::apply(self, command, cwd)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn apply(&self, _command: &mut tokio::process::Command, _cwd: &Path) -> Result<()> {
+        eyre::bail!("The sandbox is only supported on Linux, disable it with `q settings chat.sandbox.enabled false`")
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::ffi::CStr;
+    use std::io;
+    use std::path::Path;
+
+    use eyre::Result;
+    use landlock::{
+        ABI,
+        Access,
+        AccessFs,
+        CompatLevel,
+        Compatible,
+        PathBeneath,
+        PathFd,
+        Ruleset,
+        RulesetAttr,
+        RulesetCreatedAttr,
+        RulesetStatus,
+    };
+
+    use super::SandboxPolicy;
+
+    /// Directories that programs need to read and execute besides the working directory. Those
+    /// that don't exist on the host are skipped. `/proc` is needed by tools such as `ps`, and for
+    /// process substitution through `/dev/fd`.
+    const SYSTEM_DIRS: &[&str] = &[
+        "/usr", "/bin", "/sbin", "/lib", "/lib32", "/lib64", "/libx32", "/etc", "/proc",
+    ];
+    /// Devices that commands commonly read from.
+    const READ_DEVICES: &[&str] = &["/dev/zero", "/dev/random", "/dev/urandom"];
+    /// Devices that commands commonly read from and write to. Other devices, such as `/dev/shm`,
+    /// cannot be written to.
+    const WRITE_DEVICES: &[&str] = &["/dev/null", "/dev/tty"];
+
+    pub fn apply(policy: &SandboxPolicy, command: &mut tokio::process::Command, cwd: &Path) -> Result<()> {
+        let read = AccessFs::from_read(ABI::V3);
+        let all = AccessFs::from_all(ABI::V3);
+        let mut ruleset = Ruleset::default()
+            // Rights added in later versions of Landlock are restricted if the kernel supports them.
+            .set_compatibility(CompatLevel::HardRequirement)
+            .handle_access(AccessFs::from_all(ABI::V1))?
+            .set_compatibility(CompatLevel::BestEffort)
+            .handle_access(all)?
+            .create()?
+            .add_rule(PathBeneath::new(PathFd::new(cwd)?, read))?;
+        let existing = |paths: &'static [&'static str]| paths.iter().filter_map(|path| PathFd::new(path).ok());
+        for fd in existing(SYSTEM_DIRS) {
+            ruleset = ruleset.add_rule(PathBeneath::new(fd, read))?;
+        }
+        for fd in policy.read_paths.iter().filter_map(|path| PathFd::new(path).ok()) {
+            ruleset = ruleset.add_rule(PathBeneath::new(fd, read))?;
+        }
+        for fd in existing(READ_DEVICES) {
+            ruleset = ruleset.add_rule(PathBeneath::new(fd, AccessFs::ReadFile))?;
+        }
+        for fd in existing(WRITE_DEVICES) {
+            ruleset = ruleset.add_rule(PathBeneath::new(fd, AccessFs::ReadFile | AccessFs::WriteFile))?;
+        }
+        if policy.allow_writes {
+            for path in [cwd, &std::env::temp_dir()] {
+                ruleset = ruleset.add_rule(PathBeneath::new(PathFd::new(path)?, all))?;
+            }
+        }
+
+        let mut ruleset = Some(ruleset);
+        let network = (!policy.allow_network).then(IsolatedNetwork::new);
+        // SAFETY: the closure runs in the forked child before exec, so it only makes system calls
+        // and does not allocate. Everything it needs is prepared beforehand.
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(network) = &network {
+                    network.enter()?;
+                }
+                match ruleset.take().map(|ruleset| ruleset.restrict_self()) {
+                    Some(Ok(status)) if status.ruleset != RulesetStatus::NotEnforced => Ok(()),
+                    _ => Err(io::Error::from_raw_os_error(libc::EPERM)),
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// New user and network namespaces that map the current user to itself. The only interface of
+    /// the network namespace is a loopback interface that is down, so no connection can be made.
+    struct IsolatedNetwork {
+        uid_map: String,
+        gid_map: String,
+    }
+
+    impl IsolatedNetwork {
+        fn new() -> Self {
+            // SAFETY: getuid and getgid always succeed.
+            let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+            Self {
+                uid_map: format!("{uid} {uid} 1"),
+                gid_map: format!("{gid} {gid} 1"),
+            }
+        }
+
+        /// Moves the calling process into the namespaces.
+        fn enter(&self) -> io::Result<()> {
+            // SAFETY: unshare has no memory safety requirements.
+            if unsafe { libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // Unprivileged processes have to give up setgroups before mapping their group.
+            write_proc_file(c"/proc/self/setgroups", b"deny")?;
+            write_proc_file(c"/proc/self/uid_map", self.uid_map.as_bytes())?;
+            write_proc_file(c"/proc/self/gid_map", self.gid_map.as_bytes())
+        }
+    }
+
+    fn write_proc_file(path: &CStr, content: &[u8]) -> io::Result<()> {
+        // SAFETY: `path` is nul terminated, and `content` is valid for `content.len()` bytes.
+        unsafe {
+            let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let written = libc::write(fd, content.as_ptr().cast(), content.len());
+            let result = match written {
+                n if n < 0 => Err(io::Error::last_os_error()),
+                _ => Ok(()),
+            };
+            libc::close(fd);
+            result
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use std::io;
+    use std::os::unix::process::CommandExt;
+    use std::process::Stdio;
+
+    use super::*;
+
+    /// Returns why the kernel can't run the sandbox, if it can't.
+    fn unsupported_reason() -> Option<&'static str> {
+        /// Makes `landlock_create_ruleset` return the highest ABI version supported by the kernel.
+        const LANDLOCK_CREATE_RULESET_VERSION: libc::c_uint = 1;
+        // SAFETY: with this flag, the attributes must be null and their size 0.
+        let abi = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                std::ptr::null::<libc::c_void>(),
+                0usize,
+                LANDLOCK_CREATE_RULESET_VERSION,
+            )
+        };
+        if abi < 1 {
+            return Some("Landlock is not supported");
+        }
+
+        // Unprivileged user namespaces can be disabled, so try to create one in a child process.
+        let mut command = std::process::Command::new("true");
+        // SAFETY: unshare has no memory safety requirements.
+        unsafe {
+            command.pre_exec(|| match libc::unshare(libc::CLONE_NEWUSER) {
+                0 => Ok(()),
+                _ => Err(io::Error::last_os_error()),
+            });
+        }
+        if !command.status().is_ok_and(|status| status.success()) {
+            return Some("unprivileged user namespaces are not supported");
+        }
+        None
+    }
+
+    /// Runs `script` with bash in the sandbox, returning whether it succeeded. Panics if the
+    /// kernel doesn't support the sandbox.
+    async fn run(policy: &SandboxPolicy, cwd: &Path, script: &str) -> bool {
+        let mut command = tokio::process::Command::new("bash");
+        command
+            .arg("-c")
+            .arg(script)
+            .current_dir(cwd)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        policy.apply(&mut command, cwd).unwrap();
+        command
+            .status()
+            .await
+            .expect("the sandbox is not supported by the kernel")
+            .success()
+    }
+
+    #[tokio::test]
+    async fn test_sandbox_writes() {
+        if let Some(reason) = unsupported_reason() {
+            eprintln!("skipping test_sandbox_writes: {reason}");
+            return;
+        }
+        let cwd = tempfile::tempdir().unwrap();
+        let read_only = SandboxPolicy {
+            allow_writes: false,
+            allow_network: true,
+            read_paths: vec![],
+        };
+        assert!(!run(&read_only, cwd.path(), "echo hi > file").await);
+        assert!(
+            run(
+                &read_only,
+                cwd.path(),
+                "ls /usr/bin > /dev/null && head -c 4 /dev/urandom > /dev/null"
+            )
+            .await
+        );
+        assert!(
+            run(
+                &read_only,
+                cwd.path(),
+                "cat /proc/self/status > /dev/null && cat <(echo hi)"
+            )
+            .await
+        );
+
+        // Only the working directory, system directories and the configured paths can be read
+        std::fs::write(cwd.path().join("notes.txt"), "notes").unwrap();
+        assert!(run(&read_only, cwd.path(), "cat notes.txt").await);
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(outside.path().join("credentials"), "secret").unwrap();
+        let read_outside = format!("cat {}", outside.path().join("credentials").display());
+        assert!(!run(&read_only, cwd.path(), &read_outside).await);
+        let readable = SandboxPolicy {
+            read_paths: vec![outside.path().to_path_buf(), "/does/not/exist".into()],
+            ..read_only.clone()
+        };
+        assert!(run(&readable, cwd.path(), &read_outside).await);
+        assert!(
+            !run(
+                &readable,
+                cwd.path(),
+                &format!("touch {}/file", outside.path().display())
+            )
+            .await
+        );
+
+        let writable = SandboxPolicy {
+            allow_writes: true,
+            allow_network: true,
+            read_paths: vec![],
+        };
+        assert!(run(&writable, cwd.path(), "echo hi > file").await);
+        assert_eq!(std::fs::read_to_string(cwd.path().join("file")).unwrap(), "hi\n");
+        assert!(!run(&writable, cwd.path(), "touch /file").await);
+        if Path::new("/dev/shm").is_dir() {
+            assert!(!run(&writable, cwd.path(), "touch /dev/shm/q_sandbox_test").await);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sandbox_network() {
+        if let Some(reason) = unsupported_reason() {
+            eprintln!("skipping test_sandbox_network: {reason}");
+            return;
+        }
+        let cwd = tempfile::tempdir().unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let connect = format!("echo hi > /dev/tcp/127.0.0.1/{}", listener.local_addr().unwrap().port());
+
+        let offline = SandboxPolicy {
+            allow_writes: false,
+            allow_network: false,
+            read_paths: vec![],
+        };
+        assert!(!run(&offline, cwd.path(), &connect).await);
+
+        let online = SandboxPolicy {
+            allow_writes: false,
+            allow_network: true,
+            read_paths: vec![],
+        };
+        assert!(run(&online, cwd.path(), &connect).await);
+    }
+
+    #[tokio::test]
+    async fn test_from_settings() {
+        let mut settings = Settings::new().await.unwrap();
+        assert_eq!(SandboxPolicy::from_settings(&settings), None);
+
+        settings.set(Setting::ChatSandboxEnabled, true).await.unwrap();
+        settings.set(Setting::ChatSandboxAllowWrites, true).await.unwrap();
+        settings
+            .set(Setting::ChatSandboxReadPaths, serde_json::json!(["/opt"]))
+            .await
+            .unwrap();
+        assert_eq!(
+            SandboxPolicy::from_settings(&settings),
+            Some(SandboxPolicy {
+                allow_writes: true,
+                allow_network: false,
+                read_paths: vec![PathBuf::from("/opt")],
+            })
+        );
+    }
+}
@@ -0,0 +1,262 @@
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::sync::{
+    Arc,
+    Mutex,
+};
+
+use eyre::{
+    Result,
+    eyre,
+};
+use semantic_search_client::embedding::EmbeddingType;
+use semantic_search_client::types::{
+    SearchOptions,
+    SearchResults,
+};
+use semantic_search_client::{
+    MemoryContext,
+    SemanticSearchClient,
+    UpdateSummary,
+};
+use tokio::sync::OnceCell;
+
+use crate::platform::Context;
+use crate::util::directories;
+
+static INSTANCE: OnceCell<KnowledgeStore> = OnceCell::const_new();
+
+/// Knowledge bases indexed with the [SemanticSearchClient].
+///
+/// The store is shared between the `/knowledge` command and the `knowledge_search` tool. Indexed
+/// contexts are persisted under `~/.aws/amazonq/knowledge_bases` so that they only need to be
+/// indexed once.
+#[derive(Clone)]
+pub struct KnowledgeStore {
+    // The client is not async aware and indexing / embedding is CPU bound, so every operation is
+    // run on the blocking thread pool.
+    client: Arc<Mutex<SemanticSearchClient>>,
+}
+
+impl std::fmt::Debug for KnowledgeStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KnowledgeStore").finish_non_exhaustive()
+    }
+}
+
+impl KnowledgeStore {
+    /// Returns the store for the current process, initializing it on first use.
+    pub async fn get_instance(ctx: &Context) -> Result<Self> {
+        let base_dir = directories::chat_knowledge_bases_dir(ctx)?;
+        INSTANCE
+            .get_or_try_init(|| Self::new(base_dir, EmbeddingType::default()))
+            .await
+            .cloned()
+    }
+
+    pub async fn new(base_dir: PathBuf, embedding_type: EmbeddingType) -> Result<Self> {
+        let client =
+            tokio::task::spawn_blocking(move || SemanticSearchClient::with_embedding_type(base_dir, embedding_type))
+                .await??;
+        Ok(Self {
+            client: Arc::new(Mutex::new(client)),
+        })
+    }
+
+    /// Runs `f` with exclusive access to the underlying client on the blocking thread pool.
+    async fn with_client<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut SemanticSearchClient) -> Result<T> + Send + 'static,
+    {
+        let client = Arc::clone(&self.client);
+        tokio::task::spawn_blocking(move || {
+            let mut client = client
+                .lock()
+                .map_err(|e| eyre!("Failed to acquire lock on the knowledge store: {}", e))?;
+            f(&mut client)
+        })
+        .await?
+    }
+
+    /// Indexes the file or directory at `path` as a new persistent context.
+    ///
+    /// Returns the id of the created context.
+    pub async fn add(&self, path: impl AsRef<Path>, name: Option<String>) -> Result<String> {
+        let path = path.as_ref().to_path_buf();
+        let name = name.unwrap_or_else(|| {
+            path.file_name()
+                .map_or_else(|| path.to_string_lossy(), |n| n.to_string_lossy())
+                .to_string()
+        });
+        self.with_client(move |client| {
+            if client
+                .get_contexts()
+                .iter()
+                .any(|c| c.source_path.as_deref() == Some(&*path.to_string_lossy()))
+            {
+                return Err(eyre!("{} has already been indexed", path.display()));
+            }
+            let description = format!("Knowledge base for {}", path.display());
+            Ok(client.add_context_from_path(
+                &path,
+                &name,
+                &description,
+                true,
+                None::<fn(semantic_search_client::ProgressStatus)>,
+            )?)
+        })
+        .await
+    }
+
+    /// Removes a context by id, name, or the path it was indexed from.
+    pub async fn remove(&self, target: impl Into<String>) -> Result<()> {
+        let target = target.into();
+        self.with_client(move |client| {
+            if client.remove_context(&target, true).is_ok() {
+                return Ok(());
+            }
+            client
+                .remove_context_by_path(&target, true)
+                .map_err(|_err| eyre!("No knowledge base found matching '{}'", target))
+        })
+        .await
+    }
+
+    /// Re-indexes the files that were added, modified or removed since a context was indexed.
+    ///
+    /// Updates the context matching `target` by id, name, or path, or every context if `target`
+    /// is [None]. Returns the updated contexts along with what changed.
+    pub async fn update(&self, target: Option<String>) -> Result<Vec<(MemoryContext, UpdateSummary)>> {
+        self.with_client(move |client| {
+            let contexts: Vec<_> = client
+                .get_contexts()
+                .into_iter()
+                .filter(|c| {
+                    target.as_ref().is_none_or(|target| {
+                        &c.id == target || &c.name == target || c.source_path.as_ref() == Some(target)
+                    })
+                })
+                .collect();
+            if let Some(target) = target.as_ref().filter(|_| contexts.is_empty()) {
+                return Err(eyre!("No knowledge base found matching '{}'", target));
+            }
+            let mut updated = Vec::new();
+            for context in contexts {
+                let summary = client.update_context(&context.id)?;
+                let context = client
+                    .get_contexts()
+                    .into_iter()
+                    .find(|c| c.id == context.id)
+                    .unwrap_or(context);
+                updated.push((context, summary));
+            }
+            updated.sort_by(|(a, _), (b, _)| a.created_at.cmp(&b.created_at));
+            Ok(updated)
+        })
+        .await
+    }
+
+    /// Returns every persistent context in the store.
+    pub async fn contexts(&self) -> Result<Vec<MemoryContext>> {
+        self.with_client(|client| {
+            let mut contexts = client.get_contexts();
+            contexts.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+            Ok(contexts)
+        })
+        .await
+    }
+
+    /// Searches either a single context or all of them, ranking results with hybrid search by
+    /// default.
+    ///
+    /// Returns a list of (context, results) pairs.
+    pub async fn search(
+        &self,
+        query: impl Into<String>,
+        context_id: Option<String>,
+        options: SearchOptions,
+    ) -> Result<Vec<(MemoryContext, SearchResults)>> {
+        let query = query.into();
+        self.with_client(move |client| {
+            let contexts = client.get_contexts();
+            let results = match context_id {
+                Some(id) => {
+                    let context = contexts
+                        .iter()
+                        .find(|c| c.id == id || c.name == id)
+                        .ok_or(eyre!("No knowledge base found matching '{}'", id))?;
+                    vec![(
+                        context.id.clone(),
+                        client.search_context_with_options(&context.id, &query, &options)?,
+                    )]
+                },
+                None => client.search_all_with_options(&query, &options)?,
+            };
+            Ok(results
+                .into_iter()
+                .filter_map(|(id, results)| {
+                    let context = contexts.iter().find(|c| c.id == id)?;
+                    Some((context.clone(), results))
+                })
+                .collect())
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_knowledge_store_add_search_remove() {
+        let dir = tempfile::tempdir().unwrap();
+        let docs = dir.path().join("docs");
+        std::fs::create_dir_all(&docs).unwrap();
+        std::fs::write(
+            docs.join("deploy.md"),
+            "Run the deploy script to ship the release to production.",
+        )
+        .unwrap();
+        std::fs::write(
+            docs.join("cooking.txt"),
+            "Boil the pasta for eight minutes in salted water.",
+        )
+        .unwrap();
+
+        let store = KnowledgeStore::new(dir.path().join("kb"), EmbeddingType::BM25)
+            .await
+            .unwrap();
+        let id = store.add(&docs, None).await.unwrap();
+        assert!(
+            store.add(&docs, None).await.is_err(),
+            "indexing the same path twice should fail"
+        );
+
+        let contexts = store.contexts().await.unwrap();
+        assert_eq!(contexts.len(), 1);
+        assert_eq!(contexts[0].id, id);
+        assert_eq!(contexts[0].name, "docs");
+
+        let results = store
+            .search("deploy release", Some("docs".to_string()), SearchOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(!results[0].1.is_empty());
+
+        std::fs::write(docs.join("release.md"), "Tag the release before deploying it.").unwrap();
+        let updated = store.update(Some("docs".to_string())).await.unwrap();
+        assert_eq!(updated.len(), 1);
+        assert_eq!(updated[0].1.added, 1);
+        assert_eq!(updated[0].0.item_count, 3);
+        assert!(store.update(Some("does_not_exist".to_string())).await.is_err());
+
+        assert!(store.remove("does_not_exist").await.is_err());
+        store.remove(docs.to_string_lossy()).await.unwrap();
+        assert!(store.contexts().await.unwrap().is_empty());
+    }
+}
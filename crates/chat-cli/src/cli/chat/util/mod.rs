@@ -1,5 +1,7 @@
 pub mod images;
 pub mod issue;
+pub mod knowledge_store;
+pub mod patch;
 pub mod shared_writer;
 pub mod ui;
 
@@ -0,0 +1,478 @@
+//! Parsing and applying unified diffs, as produced by `diff -u` and `git diff`.
+
+use eyre::{
+    Result,
+    bail,
+};
+
+/// Number of context lines that may be ignored at the start and at the end of a hunk that can't
+/// be found otherwise, like the fuzz factor of `patch`.
+const MAX_FUZZ: usize = 2;
+
+/// The changes to a single file in a unified diff.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilePatch {
+    /// [None] if the file is created by the patch.
+    pub old_path: Option<String>,
+    /// [None] if the file is deleted by the patch.
+    pub new_path: Option<String>,
+    pub hunks: Vec<Hunk>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    /// 1-indexed line of the original file that the hunk starts at, if given in its header.
+    pub old_start: Option<usize>,
+    pub lines: Vec<HunkLine>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HunkLine {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+/// Parses the file patches of a unified diff. Lines outside of file patches, such as the
+/// `diff --git` and `index` lines of git, are ignored.
+///
+/// Hunks end after the number of lines given in their header, so that removed and added lines
+/// such as `-- x` and `++ y` are not mistaken for a file header. Hunks whose header has no line
+/// counts end at the next header instead.
+pub fn parse_patch(patch: &str) -> Result<Vec<FilePatch>> {
+    let lines = patch.lines().collect::<Vec<_>>();
+    let is_file_header =
+        |i: usize| lines[i].starts_with("--- ") && lines.get(i + 1).is_some_and(|next| next.starts_with("+++ "));
+    let is_header = |i: usize| lines[i].starts_with("@@") || lines[i].starts_with("diff ") || is_file_header(i);
+
+    let mut files: Vec<FilePatch> = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        if is_file_header(i) {
+            files.push(FilePatch {
+                old_path: parse_path(&lines[i][4..], "a/"),
+                new_path: parse_path(&lines[i + 1][4..], "b/"),
+                hunks: Vec::new(),
+            });
+            i += 2;
+            continue;
+        }
+        if !lines[i].starts_with("@@") {
+            i += 1;
+            continue;
+        }
+
+        let Some(file) = files.last_mut() else {
+            bail!(
+                "Found a hunk before any file header, every file must start with `--- <path>` and `+++ <path>` lines"
+            );
+        };
+        let hunk_number = file.hunks.len() + 1;
+        let header = parse_hunk_header(lines[i]);
+        let mut hunk = Hunk {
+            old_start: header.as_ref().map(|header| header.old_start),
+            lines: Vec::new(),
+        };
+        // Lines of the original and of the new file that are left in the hunk, if counted.
+        let mut remaining = header.and_then(|header| header.counts);
+        // Blank lines are usually empty context lines whose leading space was trimmed, except at
+        // the end of a hunk without line counts.
+        let mut trailing_blank_lines = 0;
+        i += 1;
+        while i < lines.len() {
+            let line = lines[i];
+            match remaining {
+                // "\ No newline at end of file" follows the last line of the hunk.
+                Some((0, 0)) if line.starts_with('\\') => {
+                    i += 1;
+                    continue;
+                },
+                Some((0, 0)) => break,
+                // Lines starting with `@@` or `diff ` can't be hunk lines.
+                Some(_) if line.starts_with("@@") || line.starts_with("diff ") => break,
+                Some(_) => (),
+                None if is_header(i) => break,
+                None => trailing_blank_lines = if line.is_empty() { trailing_blank_lines + 1 } else { 0 },
+            }
+            let (hunk_line, old_len, new_len) = match line.chars().next() {
+                None => (HunkLine::Context(String::new()), 1, 1),
+                Some(' ') => (HunkLine::Context(line[1..].to_string()), 1, 1),
+                Some('-') => (HunkLine::Remove(line[1..].to_string()), 1, 0),
+                Some('+') => (HunkLine::Add(line[1..].to_string()), 0, 1),
+                Some('\\') => {
+                    i += 1;
+                    continue;
+                },
+                Some(_) => bail!(
+                    "Invalid line in hunk {hunk_number}: `{line}`, every line of a hunk must start with ' ', '-' or '+'"
+                ),
+            };
+            if let Some((old, new)) = remaining.as_mut() {
+                if *old < old_len || *new < new_len {
+                    bail!("Hunk {hunk_number} has more lines than the counts in its header, `{line}` is not expected");
+                }
+                *old -= old_len;
+                *new -= new_len;
+            }
+            hunk.lines.push(hunk_line);
+            i += 1;
+        }
+        match remaining {
+            // Trailing empty context lines may have been trimmed along with the end of the patch.
+            Some((old, new)) if old != new => bail!(
+                "Hunk {hunk_number} has fewer lines than the counts in its header, {old} original and {new} new lines are missing"
+            ),
+            Some(_) => (),
+            None => hunk.lines.truncate(hunk.lines.len() - trailing_blank_lines),
+        }
+        if hunk.lines.iter().all(|line| matches!(line, HunkLine::Context(_))) {
+            bail!("Hunk {hunk_number} does not change anything");
+        }
+        file.hunks.push(hunk);
+    }
+
+    if files.is_empty() {
+        bail!(
+            "The patch does not contain any file, expected a unified diff with `--- <path>` and `+++ <path>` lines followed by `@@` hunks"
+        );
+    }
+    if let Some(file) = files
+        .iter()
+        .find(|file| file.old_path.is_none() && file.new_path.is_none())
+    {
+        bail!(
+            "Invalid file header, both paths are /dev/null ({} hunks)",
+            file.hunks.len()
+        );
+    }
+    Ok(files)
+}
+
+/// Applies `hunks` to `content` in order.
+///
+/// Hunks that are not at the line given in their header are searched for in the rest of the
+/// file, starting with the closest lines. Hunks that still can't be found are matched while
+/// ignoring trailing whitespace, and then while ignoring up to [MAX_FUZZ] lines of context at
+/// their start and end. Context lines are kept as they are in `content`.
+pub fn apply_hunks(content: &str, hunks: &[Hunk]) -> Result<String> {
+    let line_ending = if content.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut lines = content.lines().map(str::to_string).collect::<Vec<_>>();
+    // Start of the part of the file that the next hunk may change.
+    let mut min_start = 0;
+    // Number of lines added by the previous hunks, minus the number of lines removed.
+    let mut offset = 0isize;
+
+    for (i, hunk) in hunks.iter().enumerate() {
+        let hint = match hunk.old_start {
+            // Hunks that only add lines give the line that they are inserted after.
+            Some(start) if hunk.lines.iter().all(|line| matches!(line, HunkLine::Add(_))) => {
+                (start as isize + offset).max(0) as usize
+            },
+            Some(start) => (start.saturating_sub(1) as isize + offset).max(0) as usize,
+            None => min_start,
+        };
+        let Some((start, hunk_lines)) = locate_hunk(&lines, &hunk.lines, hint, min_start) else {
+            bail!(
+                "Hunk {} could not be found in the file, make sure that its context and removed lines match the current content of the file",
+                i + 1
+            );
+        };
+
+        let mut replacement = Vec::new();
+        let mut old_len = 0;
+        for line in hunk_lines {
+            match line {
+                HunkLine::Context(_) => {
+                    replacement.push(lines[start + old_len].clone());
+                    old_len += 1;
+                },
+                HunkLine::Remove(_) => old_len += 1,
+                HunkLine::Add(text) => replacement.push(text.clone()),
+            }
+        }
+        min_start = start + replacement.len();
+        offset += replacement.len() as isize - old_len as isize;
+        lines.splice(start..start + old_len, replacement);
+    }
+
+    let mut patched = lines.join(line_ending);
+    if !patched.is_empty() && (content.is_empty() || content.ends_with('\n')) {
+        patched.push_str(line_ending);
+    }
+    Ok(patched)
+}
+
+/// Returns the line that `hunk` starts at in `lines`, along with the part of the hunk that
+/// matched.
+fn locate_hunk<'a>(
+    lines: &[String],
+    hunk: &'a [HunkLine],
+    hint: usize,
+    min_start: usize,
+) -> Option<(usize, &'a [HunkLine])> {
+    for fuzz in 0..=MAX_FUZZ {
+        let leading = hunk
+            .iter()
+            .take(fuzz)
+            .take_while(|line| matches!(line, HunkLine::Context(_)))
+            .count();
+        let trailing = hunk[leading..]
+            .iter()
+            .rev()
+            .take(fuzz)
+            .take_while(|line| matches!(line, HunkLine::Context(_)))
+            .count();
+        if fuzz > 0 && leading + trailing == 0 {
+            break;
+        }
+
+        let hunk = &hunk[leading..hunk.len() - trailing];
+        let old = hunk
+            .iter()
+            .filter_map(|line| match line {
+                HunkLine::Context(text) | HunkLine::Remove(text) => Some(text.as_str()),
+                HunkLine::Add(_) => None,
+            })
+            .collect::<Vec<_>>();
+        let hint = (hint + leading).clamp(min_start, lines.len());
+        if old.is_empty() {
+            return Some((hint, hunk));
+        }
+        if lines.len() < old.len() + min_start {
+            continue;
+        }
+
+        // Candidate starts, from the closest to the hint.
+        let mut candidates = (min_start..=lines.len() - old.len()).collect::<Vec<_>>();
+        candidates.sort_by_key(|start| start.abs_diff(hint));
+
+        // Lines that only differ in indentation are not matched, since indentation is significant
+        // in languages such as Python and YAML.
+        let normalizers: [fn(&str) -> &str; 2] = [|s| s, str::trim_end];
+        for normalize in normalizers {
+            let matches_at = |start: &usize| {
+                old.iter()
+                    .zip(&lines[*start..])
+                    .all(|(old, line)| normalize(old) == normalize(line))
+            };
+            if let Some(start) = candidates.iter().copied().find(matches_at) {
+                return Some((start, hunk));
+            }
+        }
+    }
+    None
+}
+
+fn parse_path(path: &str, prefix: &str) -> Option<String> {
+    // Timestamps are separated from the path by a tab.
+    let path = path.split('\t').next().unwrap_or_default().trim();
+    match path {
+        "/dev/null" => None,
+        path => Some(path.strip_prefix(prefix).unwrap_or(path).to_string()),
+    }
+}
+
+/// The ranges of a hunk header such as `@@ -12,7 +12,8 @@`.
+#[derive(Debug, PartialEq, Eq)]
+struct HunkHeader {
+    /// 1-indexed line of the original file that the hunk starts at.
+    old_start: usize,
+    /// Number of lines of the original and of the new file in the hunk, [None] if the header
+    /// does not have both ranges.
+    counts: Option<(usize, usize)>,
+}
+
+/// Parses a hunk header. Counts that are omitted, as in `@@ -5 +5,2 @@`, are 1.
+///
+/// Returns [None] if the header does not start with the range of the original file.
+fn parse_hunk_header(header: &str) -> Option<HunkHeader> {
+    let mut ranges = header.trim_start_matches('@').split_whitespace();
+    let parse_range = |range: &str| -> Option<(usize, usize)> {
+        match range.split_once(',') {
+            Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+            None => Some((range.parse().ok()?, 1)),
+        }
+    };
+    let (old_start, old_len) = parse_range(ranges.next()?.strip_prefix('-')?)?;
+    let new_len = ranges
+        .next()
+        .and_then(|range| range.strip_prefix('+'))
+        .and_then(parse_range)
+        .map(|(_, len)| len);
+    Some(HunkHeader {
+        old_start,
+        counts: new_len.map(|new_len| (old_len, new_len)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = "fn main() {\n    let a = 1;\n    let b = 2;\n    println!(\"{}\", a + b);\n}\n";
+
+    #[test]
+    fn test_parse_patch() {
+        let patch = "diff --git a/src/main.rs b/src/main.rs
+index 1111111..2222222 100644
+--- a/src/main.rs\t2024-01-01 00:00:00
++++ b/src/main.rs
+@@ -1,2 +1,2 @@ fn main() {
+ fn main() {
+-    let a = 1;
++    let a = 3;
+
+@@ -5 +5,2 @@
+ }
++// end
+\\ No newline at end of file
+--- /dev/null
++++ b/new.txt
+@@ -0,0 +1 @@
++hello
+
+";
+        let files = parse_patch(patch).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].old_path.as_deref(), Some("src/main.rs"));
+        assert_eq!(files[0].new_path.as_deref(), Some("src/main.rs"));
+        assert_eq!(files[0].hunks.len(), 2);
+        assert_eq!(files[0].hunks[0].old_start, Some(1));
+        assert_eq!(files[0].hunks[0].lines, vec![
+            HunkLine::Context("fn main() {".to_string()),
+            HunkLine::Remove("    let a = 1;".to_string()),
+            HunkLine::Add("    let a = 3;".to_string()),
+        ]);
+        assert_eq!(files[0].hunks[1].old_start, Some(5));
+        assert_eq!(files[0].hunks[1].lines.len(), 2);
+        assert_eq!(files[1].old_path, None);
+        assert_eq!(files[1].new_path.as_deref(), Some("new.txt"));
+        assert_eq!(files[1].hunks[0].lines, vec![HunkLine::Add("hello".to_string())]);
+
+        assert!(parse_patch("just some text").is_err());
+        assert!(parse_patch("@@ -1 +1 @@\n-a\n+b\n").is_err());
+        assert!(parse_patch("--- a/x\n+++ b/x\n@@ -1 +1 @@\n a\n").is_err());
+        assert!(parse_patch("--- a/x\n+++ b/x\n@@ -1 +1 @@\n-a\nb\n").is_err());
+        // Line counts that don't match the hunk.
+        assert!(parse_patch("--- a/x\n+++ b/x\n@@ -1 +1 @@\n-a\n-b\n+c\n").is_err());
+        assert!(parse_patch("--- a/x\n+++ b/x\n@@ -1,3 +1 @@\n-a\n+c\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_patch_line_counts() {
+        // Removing `-- x` and adding `++ y` looks like a file header without the line counts.
+        let patch = "--- a/notes.md
++++ b/notes.md
+@@ -1,2 +1,2 @@
+ # Notes
+--- x
++++ y
+--- a/other.md
++++ b/other.md
+@@ -1 +1,2 @@
+ a
++b
+";
+        let files = parse_patch(patch).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].hunks[0].lines, vec![
+            HunkLine::Context("# Notes".to_string()),
+            HunkLine::Remove("-- x".to_string()),
+            HunkLine::Add("++ y".to_string()),
+        ]);
+        assert_eq!(files[1].new_path.as_deref(), Some("other.md"));
+        assert_eq!(files[1].hunks[0].lines.len(), 2);
+
+        // Counted empty context lines whose leading space was trimmed.
+        let files = parse_patch("--- a/x\n+++ b/x\n@@ -1,3 +1,3 @@\n a\n\n-b\n+c\n").unwrap();
+        assert_eq!(files[0].hunks[0].lines[1], HunkLine::Context(String::new()));
+
+        assert_eq!(
+            parse_hunk_header("@@ -12,7 +12,8 @@ fn main() {"),
+            Some(HunkHeader {
+                old_start: 12,
+                counts: Some((7, 8)),
+            })
+        );
+        assert_eq!(
+            parse_hunk_header("@@ -5 @@"),
+            Some(HunkHeader {
+                old_start: 5,
+                counts: None,
+            })
+        );
+        assert_eq!(parse_hunk_header("@@"), None);
+    }
+
+    fn hunk(old_start: Option<usize>, patch: &str) -> Hunk {
+        let patch = format!("--- a/f\n+++ b/f\n@@ -{} @@\n{patch}", old_start.unwrap_or(0));
+        let mut hunk = parse_patch(&patch).unwrap().remove(0).hunks.remove(0);
+        hunk.old_start = old_start;
+        hunk
+    }
+
+    #[test]
+    fn test_apply_hunks() {
+        // Exact position.
+        let hunks = [hunk(Some(2), "     let a = 1;\n-    let b = 2;\n+    let b = 3;\n")];
+        assert_eq!(
+            apply_hunks(FILE, &hunks).unwrap(),
+            FILE.replace("let b = 2", "let b = 3")
+        );
+
+        // Wrong line number, no line number, and several hunks.
+        let hunks = [
+            hunk(Some(40), "-    let a = 1;\n+    let a = 10;\n+    let c = 0;\n"),
+            hunk(
+                None,
+                "     let b = 2;\n-    println!(\"{}\", a + b);\n+    println!(\"{}\", a + b + c);\n",
+            ),
+        ];
+        assert_eq!(
+            apply_hunks(FILE, &hunks).unwrap(),
+            "fn main() {\n    let a = 10;\n    let c = 0;\n    let b = 2;\n    println!(\"{}\", a + b + c);\n}\n"
+        );
+
+        // Trailing whitespace, the original context lines are kept.
+        let hunks = [hunk(Some(1), " fn main() { \n-    let a = 1;\t\n+\tlet a = 5;\n")];
+        assert_eq!(
+            apply_hunks(FILE, &hunks).unwrap(),
+            FILE.replace("    let a = 1", "\tlet a = 5")
+        );
+
+        // Different indentation is significant.
+        let hunks = [hunk(Some(1), " fn main() {\n-let a = 1;\n+\tlet a = 5;\n")];
+        assert!(apply_hunks(FILE, &hunks).is_err());
+
+        // Fuzz, the first context line is outdated.
+        let hunks = [hunk(Some(1), " fn start() {\n     let a = 1;\n-    let b = 2;\n")];
+        assert_eq!(apply_hunks(FILE, &hunks).unwrap(), FILE.replace("    let b = 2;\n", ""));
+
+        // Removed lines that don't match.
+        let hunks = [hunk(Some(2), "-    let a = 2;\n+    let a = 3;\n")];
+        assert!(apply_hunks(FILE, &hunks).is_err());
+
+        // Hunks are applied in order.
+        let hunks = [
+            hunk(Some(4), "-    println!(\"{}\", a + b);\n"),
+            hunk(Some(2), "-    let a = 1;\n"),
+        ];
+        assert!(apply_hunks(FILE, &hunks).is_err());
+    }
+
+    #[test]
+    fn test_apply_hunks_line_endings() {
+        let hunks = [hunk(None, "+hello\n+world\n")];
+        assert_eq!(apply_hunks("", &hunks).unwrap(), "hello\nworld\n");
+
+        let hunks = [hunk(Some(1), "-a\n+b\n")];
+        assert_eq!(apply_hunks("a\r\nc\r\n", &hunks).unwrap(), "b\r\nc\r\n");
+        assert_eq!(apply_hunks("c\na", &hunks).unwrap(), "c\nb");
+
+        let hunks = [hunk(Some(1), "-a\n")];
+        assert_eq!(apply_hunks("a\n", &hunks).unwrap(), "");
+
+        let hunks = [hunk(Some(1), "+b\n")];
+        assert_eq!(apply_hunks("a\nc\n", &hunks).unwrap(), "a\nb\nc\n");
+    }
+}
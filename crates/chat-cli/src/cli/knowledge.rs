@@ -0,0 +1,223 @@
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::process::ExitCode;
+
+use anstream::println;
+use clap::Subcommand;
+use crossterm::style::Stylize;
+use eyre::{
+    Result,
+    bail,
+    eyre,
+};
+use semantic_search_client::types::SearchOptions;
+use serde_json::json;
+
+use crate::cli::chat::util::knowledge_store::KnowledgeStore;
+use crate::database::Database;
+use crate::database::settings::Setting;
+use crate::platform::Context;
+use crate::util::CLI_BINARY_NAME;
+
+/// Knowledge base subcommands, sharing the knowledge bases used by `/knowledge` in chat
+#[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
+pub enum Knowledge {
+    /// Index a file or directory as a new knowledge base
+    Index {
+        /// Path of the file or directory to index
+        path: PathBuf,
+        /// Name of the knowledge base, defaults to the file or directory name
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// List the indexed knowledge bases
+    List {
+        /// Print the knowledge bases as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Search the knowledge bases
+    Search {
+        /// Search query
+        query: String,
+        /// Only search the knowledge base with this name or id
+        #[arg(long)]
+        context: Option<String>,
+        /// Maximum number of results per knowledge base
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Print the results as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Remove a knowledge base by name, id, or the path it was indexed from
+    #[command(alias("rm"))]
+    Remove {
+        /// Name, id, or path of the knowledge base
+        target: String,
+    },
+    /// Show index statistics of the knowledge bases
+    Stats {
+        /// Print the statistics as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+impl Knowledge {
+    pub async fn execute(self, database: &Database) -> Result<ExitCode> {
+        if !database.settings.get_bool(Setting::EnabledKnowledge).unwrap_or(false) {
+            bail!(
+                "Knowledge bases are a beta feature. Enable them with: {}",
+                format!("{CLI_BINARY_NAME} settings chat.enableKnowledge true").green()
+            );
+        }
+
+        let ctx = Context::new();
+        let store = KnowledgeStore::get_instance(&ctx)
+            .await
+            .map_err(|err| eyre!("Failed to load knowledge bases: {}", err))?;
+
+        match self {
+            Self::Index { path, name } => {
+                let path = path
+                    .canonicalize()
+                    .map_err(|err| eyre!("Failed to index {}: {}", path.display(), err))?;
+                let id = store.add(&path, name).await?;
+                println!("Added {} to knowledge bases (id: {})", path.display(), id);
+            },
+            Self::List { json } => {
+                let contexts = store.contexts().await?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&contexts)?);
+                } else if contexts.is_empty() {
+                    println!(
+                        "No knowledge bases have been added. Use {CLI_BINARY_NAME} knowledge index <path> to index one."
+                    );
+                } else {
+                    for context in contexts {
+                        println!(
+                            "{} ({} items)\n  id: {}\n  path: {}",
+                            context.name.bold(),
+                            context.item_count,
+                            context.id,
+                            context.source_path.as_deref().unwrap_or("-"),
+                        );
+                    }
+                }
+            },
+            Self::Search {
+                query,
+                context,
+                limit,
+                json,
+            } => {
+                if query.trim().is_empty() {
+                    bail!("Search query must not be empty");
+                }
+                let results = store
+                    .search(query, context, SearchOptions {
+                        limit,
+                        ..Default::default()
+                    })
+                    .await?;
+                let mut matches = results
+                    .iter()
+                    .flat_map(|(context, results)| results.iter().map(move |result| (context, result)))
+                    .collect::<Vec<_>>();
+                matches.sort_by(|(_, a), (_, b)| b.score().total_cmp(&a.score()));
+
+                if json {
+                    let matches = matches
+                        .iter()
+                        .map(|(context, result)| {
+                            json!({
+                                "knowledge_base": context.name,
+                                "path": result.point.payload.get("path"),
+                                "location": result.location(),
+                                "score": result.score(),
+                                "text": result.text(),
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    println!("{}", serde_json::to_string_pretty(&matches)?);
+                } else if matches.is_empty() {
+                    println!("No results found");
+                } else {
+                    for (context, result) in matches {
+                        println!(
+                            "{} {} (score: {:.3})",
+                            context.name.as_str().bold(),
+                            result.location().unwrap_or_default(),
+                            result.score()
+                        );
+                        if let Some(snippet) = result.text() {
+                            let snippet = snippet.lines().take(5).collect::<Vec<_>>().join("\n    ");
+                            println!("    {}", snippet);
+                        }
+                    }
+                }
+            },
+            Self::Remove { target } => {
+                // Paths are stored absolute, so retry with the canonical path if the target is a
+                // relative path
+                let result = match store.remove(target.clone()).await {
+                    Err(err) => match Path::new(&target).canonicalize() {
+                        Ok(path) => store.remove(path.to_string_lossy()).await.map_err(|_err| err),
+                        Err(_) => Err(err),
+                    },
+                    result => result,
+                };
+                result?;
+                println!("Removed {} from knowledge bases", target);
+            },
+            Self::Stats { json } => {
+                let contexts = store.contexts().await?;
+                if json {
+                    let stats = contexts
+                        .iter()
+                        .map(|context| {
+                            json!({
+                                "id": context.id,
+                                "name": context.name,
+                                "item_count": context.item_count,
+                                "index_stats": context.index_stats,
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    println!("{}", serde_json::to_string_pretty(&stats)?);
+                } else if contexts.is_empty() {
+                    println!(
+                        "No knowledge bases have been added. Use {CLI_BINARY_NAME} knowledge index <path> to index one."
+                    );
+                } else {
+                    for context in contexts {
+                        println!("{} ({} items)", context.name.as_str().bold(), context.item_count);
+                        match context.index_stats {
+                            Some(stats) => {
+                                println!("  live vectors: {}", stats.live_vectors);
+                                println!("  deleted vectors: {}", stats.deleted_vectors);
+                                println!(
+                                    "  compactions: {}{}",
+                                    stats.compactions,
+                                    stats
+                                        .last_compacted_at
+                                        .map(|at| format!(" (last: {})", at.format("%Y-%m-%d %H:%M:%S UTC")))
+                                        .unwrap_or_default()
+                                );
+                                println!("  saved size: {} bytes", stats.saved_size);
+                            },
+                            None => println!(
+                                "  no statistics yet, they are collected when the knowledge base is next updated"
+                            ),
+                        }
+                    }
+                }
+            },
+        }
+
+        Ok(ExitCode::SUCCESS)
+    }
+}
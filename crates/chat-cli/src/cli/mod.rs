@@ -3,6 +3,7 @@ mod debug;
 mod diagnostics;
 mod feed;
 mod issue;
+mod knowledge;
 mod settings;
 mod user;
 
@@ -109,6 +110,9 @@ pub enum CliRootCommands {
     /// Model Context Protocol (MCP)
     #[command(subcommand)]
     Mcp(Mcp),
+    /// Manage and search knowledge bases
+    #[command(subcommand)]
+    Knowledge(knowledge::Knowledge),
 }
 
 impl CliRootCommands {
@@ -124,6 +128,7 @@ impl CliRootCommands {
             CliRootCommands::Version { .. } => "version",
             CliRootCommands::Chat { .. } => "chat",
             CliRootCommands::Mcp(_) => "mcp",
+            CliRootCommands::Knowledge(_) => "knowledge",
         }
     }
 }
@@ -213,6 +218,7 @@ impl Cli {
                 CliRootCommands::Version { changelog } => Self::print_version(changelog),
                 CliRootCommands::Chat(args) => chat::launch_chat(&mut database, &telemetry, args).await,
                 CliRootCommands::Mcp(args) => mcp::execute_mcp(args).await,
+                CliRootCommands::Knowledge(args) => args.execute(&database).await,
             },
             // Root command
             None => chat::launch_chat(&mut database, &telemetry, chat::cli::Chat::default()).await,
@@ -317,12 +323,15 @@ impl Cli {
 mod test {
     use super::*;
     use crate::cli::chat::cli::{
+        ChatInputFormat,
+        ChatOutputFormat,
         McpAdd,
         McpImport,
         McpList,
         McpRemove,
         Scope,
     };
+    use crate::cli::knowledge::Knowledge;
 
     #[test]
     fn debug_assert() {
@@ -369,11 +378,17 @@ mod test {
             subcommand: Some(CliRootCommands::Chat(Chat {
                 accept_all: false,
                 no_interactive: false,
-                resume: false,
+                output_format: ChatOutputFormat::Text,
+                input_format: ChatInputFormat::Text,
+                resume: None,
+                list: false,
+                search: None,
                 input: None,
                 profile: None,
                 trust_all_tools: false,
                 trust_tools: None,
+                model_endpoint: None,
+                model: None,
             })),
             verbose: 2,
             help_all: false,
@@ -408,11 +423,17 @@ mod test {
             CliRootCommands::Chat(Chat {
                 accept_all: false,
                 no_interactive: false,
-                resume: false,
+                output_format: ChatOutputFormat::Text,
+                input_format: ChatInputFormat::Text,
+                resume: None,
+                list: false,
+                search: None,
                 input: None,
                 profile: Some("my-profile".to_string()),
                 trust_all_tools: false,
                 trust_tools: None,
+                model_endpoint: None,
+                model: None,
             })
         );
     }
@@ -424,11 +445,17 @@ mod test {
             CliRootCommands::Chat(Chat {
                 accept_all: false,
                 no_interactive: false,
-                resume: false,
+                output_format: ChatOutputFormat::Text,
+                input_format: ChatInputFormat::Text,
+                resume: None,
+                list: false,
+                search: None,
                 input: Some("Hello".to_string()),
                 profile: Some("my-profile".to_string()),
                 trust_all_tools: false,
                 trust_tools: None,
+                model_endpoint: None,
+                model: None,
             })
         );
     }
@@ -440,11 +467,17 @@ mod test {
             CliRootCommands::Chat(Chat {
                 accept_all: true,
                 no_interactive: false,
-                resume: false,
+                output_format: ChatOutputFormat::Text,
+                input_format: ChatInputFormat::Text,
+                resume: None,
+                list: false,
+                search: None,
                 input: None,
                 profile: Some("my-profile".to_string()),
                 trust_all_tools: false,
                 trust_tools: None,
+                model_endpoint: None,
+                model: None,
             })
         );
     }
@@ -456,11 +489,17 @@ mod test {
             CliRootCommands::Chat(Chat {
                 accept_all: false,
                 no_interactive: true,
-                resume: true,
+                output_format: ChatOutputFormat::Text,
+                input_format: ChatInputFormat::Text,
+                resume: Some(None),
+                list: false,
+                search: None,
                 input: None,
                 profile: None,
                 trust_all_tools: false,
                 trust_tools: None,
+                model_endpoint: None,
+                model: None,
             })
         );
         assert_parse!(
@@ -468,13 +507,127 @@ mod test {
             CliRootCommands::Chat(Chat {
                 accept_all: false,
                 no_interactive: true,
-                resume: true,
+                output_format: ChatOutputFormat::Text,
+                input_format: ChatInputFormat::Text,
+                resume: Some(None),
+                list: false,
+                search: None,
+                input: None,
+                profile: None,
+                trust_all_tools: false,
+                trust_tools: None,
+                model_endpoint: None,
+                model: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_chat_with_history() {
+        assert_parse!(
+            ["chat", "--resume=abc123"],
+            CliRootCommands::Chat(Chat {
+                accept_all: false,
+                no_interactive: false,
+                output_format: ChatOutputFormat::Text,
+                input_format: ChatInputFormat::Text,
+                resume: Some(Some("abc123".to_string())),
+                list: false,
+                search: None,
+                input: None,
+                profile: None,
+                trust_all_tools: false,
+                trust_tools: None,
+                model_endpoint: None,
+                model: None,
+            })
+        );
+        // The value of --resume must be given with an equals sign, so that it can be followed by
+        // the first question
+        assert_parse!(
+            ["chat", "--resume", "fix the bug"],
+            CliRootCommands::Chat(Chat {
+                accept_all: false,
+                no_interactive: false,
+                output_format: ChatOutputFormat::Text,
+                input_format: ChatInputFormat::Text,
+                resume: Some(None),
+                list: false,
+                search: None,
+                input: Some("fix the bug".to_string()),
+                profile: None,
+                trust_all_tools: false,
+                trust_tools: None,
+                model_endpoint: None,
+                model: None,
+            })
+        );
+        assert_parse!(
+            ["chat", "--list"],
+            CliRootCommands::Chat(Chat {
+                accept_all: false,
+                no_interactive: false,
+                output_format: ChatOutputFormat::Text,
+                input_format: ChatInputFormat::Text,
+                resume: None,
+                list: true,
+                search: None,
+                input: None,
+                profile: None,
+                trust_all_tools: false,
+                trust_tools: None,
+                model_endpoint: None,
+                model: None,
+            })
+        );
+        assert_parse!(
+            ["chat", "--search", "deploy script"],
+            CliRootCommands::Chat(Chat {
+                accept_all: false,
+                no_interactive: false,
+                output_format: ChatOutputFormat::Text,
+                input_format: ChatInputFormat::Text,
+                resume: None,
+                list: false,
+                search: Some("deploy script".to_string()),
                 input: None,
                 profile: None,
                 trust_all_tools: false,
                 trust_tools: None,
+                model_endpoint: None,
+                model: None,
             })
         );
+        assert!(Cli::try_parse_from([CHAT_BINARY_NAME, "chat", "--list", "--resume"]).is_err());
+    }
+
+    #[test]
+    fn test_chat_with_output_format() {
+        assert_parse!(
+            [
+                "chat",
+                "--output-format",
+                "stream-json",
+                "--input-format",
+                "stream-json"
+            ],
+            CliRootCommands::Chat(Chat {
+                accept_all: false,
+                no_interactive: false,
+                output_format: ChatOutputFormat::StreamJson,
+                input_format: ChatInputFormat::StreamJson,
+                resume: None,
+                list: false,
+                search: None,
+                input: None,
+                profile: None,
+                trust_all_tools: false,
+                trust_tools: None,
+                model_endpoint: None,
+                model: None,
+            })
+        );
+        assert!(Cli::try_parse_from([CHAT_BINARY_NAME, "chat", "--output-format", "yaml"]).is_err());
     }
 
     #[test]
@@ -484,11 +637,17 @@ mod test {
             CliRootCommands::Chat(Chat {
                 accept_all: false,
                 no_interactive: false,
-                resume: false,
+                output_format: ChatOutputFormat::Text,
+                input_format: ChatInputFormat::Text,
+                resume: None,
+                list: false,
+                search: None,
                 input: None,
                 profile: None,
                 trust_all_tools: true,
                 trust_tools: None,
+                model_endpoint: None,
+                model: None,
             })
         );
     }
@@ -500,11 +659,17 @@ mod test {
             CliRootCommands::Chat(Chat {
                 accept_all: false,
                 no_interactive: false,
-                resume: false,
+                output_format: ChatOutputFormat::Text,
+                input_format: ChatInputFormat::Text,
+                resume: None,
+                list: false,
+                search: None,
                 input: None,
                 profile: None,
                 trust_all_tools: false,
                 trust_tools: Some(vec!["".to_string()]),
+                model_endpoint: None,
+                model: None,
             })
         );
     }
@@ -516,11 +681,17 @@ mod test {
             CliRootCommands::Chat(Chat {
                 accept_all: false,
                 no_interactive: false,
-                resume: false,
+                output_format: ChatOutputFormat::Text,
+                input_format: ChatInputFormat::Text,
+                resume: None,
+                list: false,
+                search: None,
                 input: None,
                 profile: None,
                 trust_all_tools: false,
                 trust_tools: Some(vec!["fs_read".to_string(), "fs_write".to_string()]),
+                model_endpoint: None,
+                model: None,
             })
         );
     }
@@ -539,7 +710,9 @@ mod test {
             ],
             CliRootCommands::Mcp(Mcp::Add(McpAdd {
                 name: "test_server".to_string(),
-                command: "test_command".to_string(),
+                command: Some("test_command".to_string()),
+                url: None,
+                header: vec![],
                 scope: None,
                 env: vec![
                     [
@@ -555,6 +728,32 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_mcp_subcomman_add_url() {
+        assert_parse!(
+            [
+                "mcp",
+                "add",
+                "--name",
+                "remote",
+                "--url",
+                "https://example.com/mcp",
+                "--header",
+                "Authorization: Bearer token"
+            ],
+            CliRootCommands::Mcp(Mcp::Add(McpAdd {
+                name: "remote".to_string(),
+                command: None,
+                url: Some("https://example.com/mcp".to_string()),
+                header: vec![("Authorization".to_string(), "Bearer token".to_string())],
+                scope: None,
+                env: vec![],
+                timeout: None,
+                force: false,
+            }))
+        );
+    }
+
     #[test]
     fn test_mcp_subcomman_remove_workspace() {
         assert_parse!(
@@ -595,4 +794,46 @@ mod test {
             }))
         );
     }
+
+    #[test]
+    fn test_knowledge_subcommand_index() {
+        assert_parse!(
+            ["knowledge", "index", "docs", "--name", "team-docs"],
+            CliRootCommands::Knowledge(Knowledge::Index {
+                path: "docs".into(),
+                name: Some("team-docs".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_knowledge_subcommand_search() {
+        assert_parse!(
+            ["knowledge", "search", "how do I deploy", "--context", "docs", "--json"],
+            CliRootCommands::Knowledge(Knowledge::Search {
+                query: "how do I deploy".to_string(),
+                context: Some("docs".to_string()),
+                limit: None,
+                json: true,
+            })
+        );
+    }
+
+    #[test]
+    fn test_knowledge_subcommand_list_remove_stats() {
+        assert_parse!(
+            ["knowledge", "list"],
+            CliRootCommands::Knowledge(Knowledge::List { json: false })
+        );
+        assert_parse!(
+            ["knowledge", "rm", "docs"],
+            CliRootCommands::Knowledge(Knowledge::Remove {
+                target: "docs".to_string()
+            })
+        );
+        assert_parse!(
+            ["knowledge", "stats", "--json"],
+            CliRootCommands::Knowledge(Knowledge::Stats { json: true })
+        );
+    }
 }
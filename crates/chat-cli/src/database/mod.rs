@@ -15,6 +15,7 @@ use rusqlite::{
     Error,
     ToSql,
     params,
+    params_from_iter,
 };
 use serde::de::DeserializeOwned;
 use serde::{
@@ -66,7 +67,8 @@ const MIGRATIONS: &[Migration] = migrations![
     "004_state_table",
     "005_auth_table",
     "006_make_state_blob",
-    "007_conversations_table"
+    "007_conversations_table",
+    "008_conversation_history_table"
 ];
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -92,6 +94,39 @@ impl From<amzn_codewhisperer_client::types::Profile> for AuthProfile {
     }
 }
 
+/// Metadata of a conversation kept in the conversation history.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConversationMetadata {
+    pub conversation_id: String,
+    /// The first prompt of the conversation.
+    pub title: String,
+    /// The directory the conversation was last saved from.
+    pub cwd: String,
+    pub profile: Option<String>,
+    /// Unix timestamp, in seconds.
+    pub created_at: i64,
+    /// Unix timestamp, in seconds.
+    pub updated_at: i64,
+    /// Estimated number of tokens used by the conversation history.
+    pub token_count: u64,
+}
+
+impl ConversationMetadata {
+    fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            conversation_id: row.get(0)?,
+            title: row.get(1)?,
+            cwd: row.get(2)?,
+            profile: row.get(3)?,
+            created_at: row.get(4)?,
+            updated_at: row.get(5)?,
+            token_count: row.get(6)?,
+        })
+    }
+}
+
+const CONVERSATION_METADATA_COLUMNS: &str = "conversation_id, title, cwd, profile, created_at, updated_at, token_count";
+
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
 #[serde(transparent)]
 pub struct Secret(pub String);
@@ -333,6 +368,87 @@ impl Database {
         self.set_json_entry(Table::Conversations, path, state)
     }
 
+    /// Save a chat conversation to the conversation history, keyed by its conversation id. The
+    /// title and creation time of a conversation are kept from the first time it was saved.
+    ///
+    /// The title and `transcript` are stored lowercased for [Self::search_conversations], since
+    /// SQLite's `lower()` only folds ASCII letters.
+    pub fn save_conversation(
+        &mut self,
+        metadata: &ConversationMetadata,
+        transcript: &str,
+        state: &ConversationState,
+    ) -> Result<usize, DatabaseError> {
+        Ok(self.pool.get()?.execute(
+            "INSERT INTO conversation_history
+                (conversation_id, title, cwd, profile, created_at, updated_at, token_count, search_text, value)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+            ON CONFLICT (conversation_id) DO UPDATE SET
+                cwd = excluded.cwd,
+                profile = excluded.profile,
+                updated_at = excluded.updated_at,
+                token_count = excluded.token_count,
+                search_text = excluded.search_text,
+                value = excluded.value",
+            params![
+                metadata.conversation_id,
+                metadata.title,
+                metadata.cwd,
+                metadata.profile,
+                metadata.created_at,
+                metadata.updated_at,
+                metadata.token_count,
+                format!("{}\n{}", metadata.title.to_lowercase(), transcript.to_lowercase()),
+                serde_json::to_string(state)?,
+            ],
+        )?)
+    }
+
+    /// Get a chat conversation from the conversation history given its conversation id.
+    pub fn get_conversation_by_id(&self, conversation_id: &str) -> Result<Option<ConversationState>, DatabaseError> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT value FROM conversation_history WHERE conversation_id = ?1")?;
+        match stmt.query_row([conversation_id], |row| row.get::<_, String>(0)) {
+            Ok(value) => Ok(Some(serde_json::from_str(&value)?)),
+            Err(Error::QueryReturnedNoRows) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// List the most recently updated conversations in the conversation history.
+    pub fn list_conversations(&self, limit: usize) -> Result<Vec<ConversationMetadata>, DatabaseError> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {CONVERSATION_METADATA_COLUMNS} FROM conversation_history ORDER BY updated_at DESC LIMIT ?1"
+        ))?;
+        let rows = stmt.query_map([limit], ConversationMetadata::from_row)?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Search the transcripts and titles of the conversation history, returning the most recently
+    /// updated conversations that contain every word of `query`, ignoring case.
+    pub fn search_conversations(&self, query: &str, limit: usize) -> Result<Vec<ConversationMetadata>, DatabaseError> {
+        let terms = query
+            .split_whitespace()
+            .map(|term| term.to_lowercase())
+            .collect::<Vec<_>>();
+        if terms.is_empty() {
+            return self.list_conversations(limit);
+        }
+
+        // The search text is already lowercased, see [Self::save_conversation].
+        let conditions = (1..=terms.len())
+            .map(|i| format!("instr(search_text, ?{i}) > 0"))
+            .collect::<Vec<_>>()
+            .join(" AND ");
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {CONVERSATION_METADATA_COLUMNS} FROM conversation_history WHERE {conditions} ORDER BY updated_at DESC LIMIT {limit}"
+        ))?;
+        let rows = stmt.query_map(params_from_iter(&terms), ConversationMetadata::from_row)?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
     pub async fn get_secret(&self, key: &str) -> Result<Option<Secret>, DatabaseError> {
         Ok(self.get_entry::<String>(Table::Auth, key)?.map(Into::into))
     }
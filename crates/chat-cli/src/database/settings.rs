@@ -21,13 +21,27 @@ pub enum Setting {
     OldClientId,
     ShareCodeWhispererContent,
     EnabledThinking,
+    EnabledKnowledge,
     SkimCommandKey,
     ChatGreetingEnabled,
     ApiTimeout,
+    ApiRetryMaxAttempts,
+    ApiRetryInitialBackoff,
+    ApiRetryMaxBackoff,
     ChatEditMode,
     ChatEnableNotifications,
+    ChatMaxConcurrentTools,
+    ChatSandboxEnabled,
+    ChatSandboxAllowWrites,
+    ChatSandboxAllowNetwork,
+    ChatSandboxReadPaths,
+    ChatCompactionStrategy,
+    ChatCompactionTurns,
+    ChatCompactionThreshold,
     ApiCodeWhispererService,
     ApiQService,
+    ApiModelEndpointUrl,
+    ApiModelEndpointModel,
     McpInitTimeout,
     McpLoadedBefore,
 }
@@ -39,13 +53,27 @@ impl AsRef<str> for Setting {
             Self::OldClientId => "telemetryClientId",
             Self::ShareCodeWhispererContent => "codeWhisperer.shareCodeWhispererContentWithAWS",
             Self::EnabledThinking => "chat.enableThinking",
+            Self::EnabledKnowledge => "chat.enableKnowledge",
             Self::SkimCommandKey => "chat.skimCommandKey",
             Self::ChatGreetingEnabled => "chat.greeting.enabled",
             Self::ApiTimeout => "api.timeout",
+            Self::ApiRetryMaxAttempts => "api.retry.maxAttempts",
+            Self::ApiRetryInitialBackoff => "api.retry.initialBackoffMs",
+            Self::ApiRetryMaxBackoff => "api.retry.maxBackoffMs",
             Self::ChatEditMode => "chat.editMode",
             Self::ChatEnableNotifications => "chat.enableNotifications",
+            Self::ChatMaxConcurrentTools => "chat.maxConcurrentTools",
+            Self::ChatSandboxEnabled => "chat.sandbox.enabled",
+            Self::ChatSandboxAllowWrites => "chat.sandbox.allowWrites",
+            Self::ChatSandboxAllowNetwork => "chat.sandbox.allowNetwork",
+            Self::ChatSandboxReadPaths => "chat.sandbox.readPaths",
+            Self::ChatCompactionStrategy => "chat.compaction.strategy",
+            Self::ChatCompactionTurns => "chat.compaction.turns",
+            Self::ChatCompactionThreshold => "chat.compaction.threshold",
             Self::ApiCodeWhispererService => "api.codewhisperer.service",
             Self::ApiQService => "api.q.service",
+            Self::ApiModelEndpointUrl => "api.modelEndpoint.url",
+            Self::ApiModelEndpointModel => "api.modelEndpoint.model",
             Self::McpInitTimeout => "mcp.initTimeout",
             Self::McpLoadedBefore => "mcp.loadedBefore",
         }
@@ -67,13 +95,27 @@ impl TryFrom<&str> for Setting {
             "telemetryClientId" => Ok(Self::OldClientId),
             "codeWhisperer.shareCodeWhispererContentWithAWS" => Ok(Self::ShareCodeWhispererContent),
             "chat.enableThinking" => Ok(Self::EnabledThinking),
+            "chat.enableKnowledge" => Ok(Self::EnabledKnowledge),
             "chat.skimCommandKey" => Ok(Self::SkimCommandKey),
             "chat.greeting.enabled" => Ok(Self::ChatGreetingEnabled),
             "api.timeout" => Ok(Self::ApiTimeout),
+            "api.retry.maxAttempts" => Ok(Self::ApiRetryMaxAttempts),
+            "api.retry.initialBackoffMs" => Ok(Self::ApiRetryInitialBackoff),
+            "api.retry.maxBackoffMs" => Ok(Self::ApiRetryMaxBackoff),
             "chat.editMode" => Ok(Self::ChatEditMode),
             "chat.enableNotifications" => Ok(Self::ChatEnableNotifications),
+            "chat.maxConcurrentTools" => Ok(Self::ChatMaxConcurrentTools),
+            "chat.sandbox.enabled" => Ok(Self::ChatSandboxEnabled),
+            "chat.sandbox.allowWrites" => Ok(Self::ChatSandboxAllowWrites),
+            "chat.sandbox.allowNetwork" => Ok(Self::ChatSandboxAllowNetwork),
+            "chat.sandbox.readPaths" => Ok(Self::ChatSandboxReadPaths),
+            "chat.compaction.strategy" => Ok(Self::ChatCompactionStrategy),
+            "chat.compaction.turns" => Ok(Self::ChatCompactionTurns),
+            "chat.compaction.threshold" => Ok(Self::ChatCompactionThreshold),
             "api.codewhisperer.service" => Ok(Self::ApiCodeWhispererService),
             "api.q.service" => Ok(Self::ApiQService),
+            "api.modelEndpoint.url" => Ok(Self::ApiModelEndpointUrl),
+            "api.modelEndpoint.model" => Ok(Self::ApiModelEndpointModel),
             "mcp.initTimeout" => Ok(Self::McpInitTimeout),
             "mcp.loadedBefore" => Ok(Self::McpLoadedBefore),
             _ => Err(DatabaseError::InvalidSetting(value.to_string())),
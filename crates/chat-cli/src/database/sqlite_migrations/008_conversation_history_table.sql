@@ -0,0 +1,13 @@
+CREATE TABLE conversation_history (
+    conversation_id TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    cwd TEXT NOT NULL,
+    profile TEXT,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    token_count INTEGER NOT NULL DEFAULT 0,
+    search_text TEXT NOT NULL DEFAULT '',
+    value TEXT NOT NULL
+);
+
+CREATE INDEX conversation_history_updated_at ON conversation_history (updated_at);
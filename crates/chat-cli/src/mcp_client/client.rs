@@ -22,11 +22,13 @@ use tokio::time;
 use tokio::time::error::Elapsed;
 
 use super::transport::base_protocol::{
+    JsonRpcError,
     JsonRpcMessage,
     JsonRpcNotification,
     JsonRpcRequest,
     JsonRpcVersion,
 };
+use super::transport::http::JsonRpcHttpTransport;
 use super::transport::stdio::JsonRpcStdioTransport;
 use super::transport::{
     self,
@@ -43,12 +45,14 @@ use super::{
     PromptsListResult,
     ResourceTemplatesListResult,
     ResourcesListResult,
+    SamplingCreateMessageParams,
     ServerCapabilities,
     ToolsListResult,
 };
 
 pub type ClientInfo = serde_json::Value;
 pub type StdioTransport = JsonRpcStdioTransport;
+pub type HttpTransport = JsonRpcHttpTransport;
 
 /// Represents the capabilities of a client in the Model Context Protocol.
 /// This structure is sent to the server during initialization to communicate
@@ -82,6 +86,16 @@ pub struct ClientConfig {
     pub env: Option<HashMap<String, String>>,
 }
 
+/// Config for servers that are reached over HTTP rather than launched as a child process.
+#[derive(Debug, Deserialize)]
+pub struct HttpClientConfig {
+    pub server_name: String,
+    pub url: String,
+    pub headers: HashMap<String, String>,
+    pub timeout: u64,
+    pub client_info: serde_json::Value,
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Error)]
 pub enum ClientError {
@@ -197,6 +211,30 @@ impl Client<StdioTransport> {
     }
 }
 
+impl Client<HttpTransport> {
+    pub fn from_http_config(config: HttpClientConfig) -> Result<Self, ClientError> {
+        let HttpClientConfig {
+            server_name,
+            url,
+            headers,
+            timeout,
+            client_info,
+        } = config;
+        let transport = Arc::new(JsonRpcHttpTransport::new(&url, headers)?);
+        Ok(Self {
+            server_name,
+            transport,
+            timeout,
+            server_process_id: None,
+            client_info,
+            current_id: Arc::new(AtomicU64::new(0)),
+            messenger: None,
+            prompt_gets: Arc::new(SyncRwLock::new(HashMap::new())),
+            is_prompts_out_of_date: Arc::new(AtomicBool::new(false)),
+        })
+    }
+}
+
 impl<T> Drop for Client<T>
 where
     T: Transport,
@@ -244,7 +282,17 @@ where
         });
 
         let init_params = Some({
-            let client_cap = ClientCapabilities::from(self.client_info.clone());
+            let mut client_cap = ClientCapabilities::from(self.client_info.clone());
+            // Requests from the server are answered by the messenger, so there is nothing to
+            // advertise without one.
+            if self.messenger.is_some() {
+                client_cap
+                    .capabilities
+                    .insert("roots".to_string(), serde_json::json!({ "listChanged": true }));
+                client_cap
+                    .capabilities
+                    .insert("sampling".to_string(), serde_json::json!({}));
+            }
             serde_json::json!(client_cap)
         });
         let init_resp = self.request("initialize", init_params).await?;
@@ -302,7 +350,19 @@ where
                 match listener.recv().await {
                     Ok(msg) => {
                         match msg {
-                            JsonRpcMessage::Request(_req) => {},
+                            JsonRpcMessage::Request(req) => {
+                                // Requests such as sampling can take a while to be answered, so
+                                // they should not hold up the processing of other messages.
+                                let transport_ref = transport_ref.clone();
+                                let server_name = server_name.clone();
+                                let messenger = messenger_ref.as_ref().map(|m| m.duplicate());
+                                tokio::spawn(async move {
+                                    let resp = handle_server_request(req, messenger.as_deref()).await;
+                                    if let Err(e) = transport_ref.send(&JsonRpcMessage::Response(resp)).await {
+                                        tracing::error!("Failed to respond to request from {}: {:?}", server_name, e);
+                                    }
+                                });
+                            },
                             JsonRpcMessage::Notification(notif) => {
                                 let JsonRpcNotification { method, params, .. } = notif;
                                 match method.as_str() {
@@ -387,10 +447,12 @@ where
         };
         tracing::trace!(target: "mcp", "To {}:\n{:#?}", self.server_name, request);
         let msg = JsonRpcMessage::Request(request);
+        // The listener has to exist before the request is sent, since some transports deliver the
+        // response as part of sending the request.
+        let mut listener = self.transport.get_listener();
         time::timeout(Duration::from_millis(self.timeout), self.transport.send(&msg))
             .await
             .map_err(send_map_err)??;
-        let mut listener = self.transport.get_listener();
         let mut resp = time::timeout(Duration::from_millis(self.timeout), async {
             // we want to ignore all other messages sent by the server at this point and let the
             // background loop handle them
@@ -510,6 +572,52 @@ where
     }
 }
 
+/// Answers a request sent by the server.
+async fn handle_server_request(req: JsonRpcRequest, messenger: Option<&dyn Messenger>) -> JsonRpcResponse {
+    const METHOD_NOT_FOUND: i32 = -32601;
+    const INVALID_PARAMS: i32 = -32602;
+    const INTERNAL_ERROR: i32 = -32603;
+
+    let JsonRpcRequest { id, method, params, .. } = req;
+    let result = match (method.as_str(), messenger) {
+        ("ping", _) => Ok(serde_json::json!({})),
+        ("roots/list", Some(messenger)) => messenger
+            .list_roots()
+            .await
+            .map_err(|e| (INTERNAL_ERROR, e.to_string()))
+            .and_then(|result| serde_json::to_value(result).map_err(|e| (INTERNAL_ERROR, e.to_string()))),
+        ("sampling/createMessage", Some(messenger)) => {
+            match serde_json::from_value::<SamplingCreateMessageParams>(params.unwrap_or_default()) {
+                Ok(params) => messenger
+                    .create_message(params)
+                    .await
+                    .map_err(|e| (INTERNAL_ERROR, e.to_string()))
+                    .and_then(|result| serde_json::to_value(result).map_err(|e| (INTERNAL_ERROR, e.to_string()))),
+                Err(e) => Err((INVALID_PARAMS, e.to_string())),
+            }
+        },
+        (method, _) => Err((METHOD_NOT_FOUND, format!("Method not found: {method}"))),
+    };
+    match result {
+        Ok(result) => JsonRpcResponse {
+            jsonrpc: JsonRpcVersion::default(),
+            id,
+            result: Some(result),
+            error: None,
+        },
+        Err((code, message)) => JsonRpcResponse {
+            jsonrpc: JsonRpcVersion::default(),
+            id,
+            result: None,
+            error: Some(JsonRpcError {
+                code,
+                message,
+                data: None,
+            }),
+        },
+    }
+}
+
 fn examine_server_capabilities(ser_cap: &JsonRpcResponse) -> Result<(), ClientError> {
     // Check the jrpc version.
     // Currently we are only proceeding if the versions are EXACTLY the same.
@@ -614,6 +722,27 @@ mod tests {
     const TEST_BIN_OUT_DIR: &str = "target/debug";
     const TEST_SERVER_NAME: &str = "test_mcp_server";
 
+    #[tokio::test]
+    async fn test_handle_server_request() {
+        let request = |method: &str| JsonRpcRequest {
+            jsonrpc: JsonRpcVersion::default(),
+            id: 7,
+            method: method.to_string(),
+            params: None,
+        };
+        let messenger = crate::mcp_client::NullMessenger;
+
+        let resp = handle_server_request(request("roots/list"), Some(&messenger)).await;
+        assert_eq!(resp.id, 7);
+        assert_eq!(resp.result, Some(serde_json::json!({ "roots": [] })));
+
+        let resp = handle_server_request(request("sampling/createMessage"), Some(&messenger)).await;
+        assert_eq!(resp.error.unwrap().code, -32602);
+
+        let resp = handle_server_request(request("roots/list"), None).await;
+        assert_eq!(resp.error.unwrap().code, -32601);
+    }
+
     fn get_workspace_root() -> PathBuf {
         let output = std::process::Command::new("cargo")
             .args(["metadata", "--format-version=1", "--no-deps"])
@@ -4,6 +4,9 @@ use super::{
     PromptsListResult,
     ResourceTemplatesListResult,
     ResourcesListResult,
+    RootsListResult,
+    SamplingCreateMessageParams,
+    SamplingCreateMessageResult,
     ToolsListResult,
 };
 
@@ -36,6 +39,16 @@ pub trait Messenger: std::fmt::Debug + Send + Sync + 'static {
     /// Signals to the orchestrator that a server has started initializing
     async fn send_init_msg(&self) -> Result<(), MessengerError>;
 
+    /// Answers a `roots/list` request from the server with the directories in scope
+    async fn list_roots(&self) -> Result<RootsListResult, MessengerError>;
+
+    /// Answers a `sampling/createMessage` request from the server, which asks the consumer to
+    /// run a completion with its model
+    async fn create_message(
+        &self,
+        params: SamplingCreateMessageParams,
+    ) -> Result<SamplingCreateMessageResult, MessengerError>;
+
     /// Creates a duplicate of the messenger object
     /// This function is used to create a new instance of the messenger with the same configuration
     fn duplicate(&self) -> Box<dyn Messenger>;
@@ -75,6 +88,17 @@ impl Messenger for NullMessenger {
         Ok(())
     }
 
+    async fn list_roots(&self) -> Result<RootsListResult, MessengerError> {
+        Ok(RootsListResult { roots: vec![] })
+    }
+
+    async fn create_message(
+        &self,
+        _params: SamplingCreateMessageParams,
+    ) -> Result<SamplingCreateMessageResult, MessengerError> {
+        Err(MessengerError::Custom("Sampling is not supported".to_string()))
+    }
+
     fn duplicate(&self) -> Box<dyn Messenger> {
         Box::new(NullMessenger)
     }
@@ -105,4 +105,5 @@ pub enum TransportType {
     #[default]
     Stdio,
     Websocket,
+    Http,
 }
@@ -0,0 +1,735 @@
+use std::collections::HashMap;
+use std::sync::{
+    Arc,
+    RwLock as SyncRwLock,
+};
+
+use reqwest::header::{
+    ACCEPT,
+    CONTENT_TYPE,
+    HeaderMap,
+    HeaderName,
+    HeaderValue,
+};
+use reqwest::{
+    Response,
+    StatusCode,
+};
+use tokio::sync::{
+    Mutex,
+    broadcast,
+    oneshot,
+};
+use tokio::task::JoinHandle;
+use url::Url;
+
+use super::base_protocol::JsonRpcMessage;
+use super::{
+    Listener,
+    LogListener,
+    Transport,
+    TransportError,
+};
+
+const SESSION_ID_HEADER: &str = "mcp-session-id";
+const EVENT_STREAM: &str = "text/event-stream";
+
+/// Transport for MCP servers reachable over HTTP.
+///
+/// Implements the streamable HTTP transport, see
+/// https://modelcontextprotocol.io/specification/2025-03-26/basic/transports#streamable-http
+///
+/// Servers that only support the older HTTP+SSE transport are detected when the first POST is
+/// rejected, in which case the transport falls back to listening on the SSE stream and posting
+/// messages to the endpoint advertised by the server, see
+/// https://modelcontextprotocol.io/specification/2024-11-05/basic/transports#http-with-sse
+#[derive(Debug)]
+pub struct JsonRpcHttpTransport {
+    client: reqwest::Client,
+    url: Url,
+    headers: HeaderMap,
+    mode: Mutex<Option<HttpMode>>,
+    session_id: Arc<SyncRwLock<Option<String>>>,
+    tx: broadcast::Sender<Result<JsonRpcMessage, TransportError>>,
+    receiver: broadcast::Receiver<Result<JsonRpcMessage, TransportError>>,
+    log_tx: broadcast::Sender<String>,
+    log_receiver: broadcast::Receiver<String>,
+    tasks: std::sync::Mutex<Vec<JoinHandle<()>>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum HttpMode {
+    /// Every message is posted to the server's url, responses come back in the POST response
+    /// either as plain JSON or as an SSE stream.
+    Streamable,
+    /// Messages are posted to `endpoint` and every message from the server comes back on a single
+    /// long lived SSE stream.
+    Sse { endpoint: Url },
+}
+
+impl JsonRpcHttpTransport {
+    pub fn new(url: &str, headers: HashMap<String, String>) -> Result<Self, TransportError> {
+        let url = Url::parse(url).map_err(|e| TransportError::Custom(format!("Invalid url '{}': {}", url, e)))?;
+        let mut header_map = HeaderMap::new();
+        for (name, value) in headers {
+            let header_name = HeaderName::try_from(name.as_str())
+                .map_err(|e| TransportError::Custom(format!("Invalid header name '{}': {}", name, e)))?;
+            let header_value = HeaderValue::try_from(value.as_str())
+                .map_err(|e| TransportError::Custom(format!("Invalid value for header '{}': {}", name, e)))?;
+            header_map.insert(header_name, header_value);
+        }
+        let client = reqwest::Client::builder()
+            .build()
+            .map_err(|e| TransportError::Http(e.to_string()))?;
+        let (tx, receiver) = broadcast::channel(100);
+        let (log_tx, log_receiver) = broadcast::channel(100);
+        Ok(Self {
+            client,
+            url,
+            headers: header_map,
+            mode: Mutex::new(None),
+            session_id: Arc::new(SyncRwLock::new(None)),
+            tx,
+            receiver,
+            log_tx,
+            log_receiver,
+            tasks: std::sync::Mutex::new(Vec::new()),
+        })
+    }
+
+    fn session_id(&self) -> Option<String> {
+        self.session_id.read().ok().and_then(|id| id.clone())
+    }
+
+    fn spawn(&self, task: JoinHandle<()>) {
+        if let Ok(mut tasks) = self.tasks.lock() {
+            tasks.retain(|t| !t.is_finished());
+            tasks.push(task);
+        }
+    }
+
+    async fn post(&self, url: &Url, msg: &JsonRpcMessage) -> Result<Response, TransportError> {
+        let mut request = self
+            .client
+            .post(url.clone())
+            .headers(self.headers.clone())
+            .header(ACCEPT, format!("application/json, {EVENT_STREAM}"))
+            .header(CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(msg)?);
+        if let Some(session_id) = self.session_id() {
+            request = request.header(SESSION_ID_HEADER, session_id);
+        }
+        Ok(request.send().await?)
+    }
+
+    /// Sends a message with the streamable HTTP transport.
+    ///
+    /// Returns the response back if the server rejected the POST in a way that indicates that it
+    /// only supports the HTTP+SSE transport.
+    async fn send_streamable(
+        &self,
+        msg: &JsonRpcMessage,
+        negotiating: bool,
+    ) -> Result<Option<Response>, TransportError> {
+        let response = self.post(&self.url, msg).await?;
+        let status = response.status();
+        if negotiating
+            && matches!(
+                status,
+                StatusCode::BAD_REQUEST | StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED
+            )
+        {
+            return Ok(Some(response));
+        }
+        if !status.is_success() {
+            return Err(TransportError::Http(format!(
+                "Server responded with {}: {}",
+                status,
+                response.text().await.unwrap_or_default()
+            )));
+        }
+
+        if let Some(session_id) = response.headers().get(SESSION_ID_HEADER) {
+            if let (Ok(session_id), Ok(mut lock)) = (session_id.to_str(), self.session_id.write()) {
+                lock.replace(session_id.to_string());
+            }
+        }
+
+        if status == StatusCode::ACCEPTED {
+            // The server has no response for notifications and responses.
+            if is_initialized_notification(msg) {
+                self.open_server_stream();
+            }
+            return Ok(None);
+        }
+
+        let tx = self.tx.clone();
+        if is_event_stream(&response) {
+            let log_tx = self.log_tx.clone();
+            self.spawn(tokio::spawn(async move {
+                read_event_stream(response, tx, log_tx, None).await;
+            }));
+        } else {
+            // Read the body before returning so that the response is never broadcasted before
+            // the caller is listening for it.
+            let body = response.bytes().await?;
+            broadcast_body(&body, &tx);
+        }
+        Ok(None)
+    }
+
+    /// Opens the optional stream for messages initiated by the server. Servers that do not
+    /// support it respond with 405.
+    fn open_server_stream(&self) {
+        let mut request = self
+            .client
+            .get(self.url.clone())
+            .headers(self.headers.clone())
+            .header(ACCEPT, EVENT_STREAM);
+        if let Some(session_id) = self.session_id() {
+            request = request.header(SESSION_ID_HEADER, session_id);
+        }
+        let tx = self.tx.clone();
+        let log_tx = self.log_tx.clone();
+        self.spawn(tokio::spawn(async move {
+            match request.send().await {
+                Ok(response) if response.status().is_success() && is_event_stream(&response) => {
+                    read_event_stream(response, tx, log_tx, None).await;
+                },
+                Ok(response) => {
+                    let _ = log_tx.send(format!("Server stream is not available ({})", response.status()));
+                },
+                Err(err) => {
+                    let _ = log_tx.send(format!("Failed to open server stream: {}", err));
+                },
+            }
+        }));
+    }
+
+    /// Connects to the SSE stream of a server using the HTTP+SSE transport and waits for the
+    /// endpoint that messages should be posted to. The endpoint must be on the same origin as the
+    /// server, since the configured headers are sent along with every message.
+    async fn connect_sse(&self) -> Result<Url, TransportError> {
+        let response = self
+            .client
+            .get(self.url.clone())
+            .headers(self.headers.clone())
+            .header(ACCEPT, EVENT_STREAM)
+            .send()
+            .await?;
+        if !response.status().is_success() || !is_event_stream(&response) {
+            return Err(TransportError::Http(format!(
+                "Server does not support the streamable HTTP or the SSE transport ({})",
+                response.status()
+            )));
+        }
+
+        let (endpoint_tx, endpoint_rx) = oneshot::channel();
+        let tx = self.tx.clone();
+        let log_tx = self.log_tx.clone();
+        self.spawn(tokio::spawn(async move {
+            read_event_stream(response, tx, log_tx, Some(endpoint_tx)).await;
+        }));
+        let endpoint = endpoint_rx
+            .await
+            .map_err(|_err| TransportError::Http("SSE stream closed before an endpoint was received".to_string()))?;
+        let url = self
+            .url
+            .join(&endpoint)
+            .map_err(|e| TransportError::Http(format!("Invalid endpoint '{}': {}", endpoint, e)))?;
+        if url.origin() != self.url.origin() {
+            return Err(TransportError::Http(format!(
+                "Endpoint '{}' is not on the same origin as the server",
+                endpoint
+            )));
+        }
+        Ok(url)
+    }
+}
+
+#[async_trait::async_trait]
+impl Transport for JsonRpcHttpTransport {
+    async fn send(&self, msg: &JsonRpcMessage) -> Result<(), TransportError> {
+        // The lock is not held across requests so that sends are not serialized. Only the first
+        // message, `initialize`, detects the mode, so no other send should be racing it.
+        let mode = self.mode.lock().await.clone();
+        match mode {
+            Some(HttpMode::Streamable) => {
+                self.send_streamable(msg, false).await?;
+            },
+            Some(HttpMode::Sse { endpoint }) => {
+                let response = self.post(&endpoint, msg).await?;
+                if !response.status().is_success() {
+                    return Err(TransportError::Http(format!(
+                        "Server responded with {}",
+                        response.status()
+                    )));
+                }
+            },
+            None => match self.send_streamable(msg, true).await? {
+                None => {
+                    self.mode.lock().await.replace(HttpMode::Streamable);
+                },
+                Some(rejected) => {
+                    let _ = self.log_tx.send(format!(
+                        "Streamable HTTP was rejected ({}), falling back to SSE",
+                        rejected.status()
+                    ));
+                    let endpoint = self.connect_sse().await?;
+                    let response = self.post(&endpoint, msg).await?;
+                    if !response.status().is_success() {
+                        return Err(TransportError::Http(format!(
+                            "Server responded with {}",
+                            response.status()
+                        )));
+                    }
+                    self.mode.lock().await.replace(HttpMode::Sse { endpoint });
+                },
+            },
+        }
+        Ok(())
+    }
+
+    fn get_listener(&self) -> impl Listener {
+        HttpListener {
+            receiver: self.receiver.resubscribe(),
+        }
+    }
+
+    async fn shutdown(&self) -> Result<(), TransportError> {
+        if let Ok(mut tasks) = self.tasks.lock() {
+            for task in tasks.drain(..) {
+                task.abort();
+            }
+        }
+        // Let the server know that the session can be cleaned up.
+        if let Some(session_id) = self.session_id() {
+            self.client
+                .delete(self.url.clone())
+                .headers(self.headers.clone())
+                .header(SESSION_ID_HEADER, session_id)
+                .send()
+                .await?;
+        }
+        Ok(())
+    }
+
+    fn get_log_listener(&self) -> impl LogListener {
+        HttpLogListener {
+            receiver: self.log_receiver.resubscribe(),
+        }
+    }
+}
+
+impl Drop for JsonRpcHttpTransport {
+    fn drop(&mut self) {
+        if let Ok(mut tasks) = self.tasks.lock() {
+            for task in tasks.drain(..) {
+                task.abort();
+            }
+        }
+    }
+}
+
+pub struct HttpListener {
+    pub receiver: broadcast::Receiver<Result<JsonRpcMessage, TransportError>>,
+}
+
+#[async_trait::async_trait]
+impl Listener for HttpListener {
+    async fn recv(&mut self) -> Result<JsonRpcMessage, TransportError> {
+        self.receiver.recv().await?
+    }
+}
+
+pub struct HttpLogListener {
+    pub receiver: broadcast::Receiver<String>,
+}
+
+#[async_trait::async_trait]
+impl LogListener for HttpLogListener {
+    async fn recv(&mut self) -> Result<String, TransportError> {
+        Ok(self.receiver.recv().await?)
+    }
+}
+
+fn is_event_stream(response: &Response) -> bool {
+    response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with(EVENT_STREAM))
+}
+
+fn is_initialized_notification(msg: &JsonRpcMessage) -> bool {
+    matches!(msg, JsonRpcMessage::Notification(notif) if notif.method == "notifications/initialized")
+}
+
+/// Broadcasts a JSON body containing either a single message or a batch of messages.
+fn broadcast_body(body: &[u8], tx: &broadcast::Sender<Result<JsonRpcMessage, TransportError>>) {
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return;
+    }
+    match serde_json::from_slice::<serde_json::Value>(body) {
+        Ok(serde_json::Value::Array(batch)) => {
+            for msg in batch {
+                let _ = tx.send(serde_json::from_value::<JsonRpcMessage>(msg).map_err(Into::into));
+            }
+        },
+        Ok(msg) => {
+            let _ = tx.send(serde_json::from_value::<JsonRpcMessage>(msg).map_err(Into::into));
+        },
+        Err(e) => {
+            let _ = tx.send(Err(e.into()));
+        },
+    }
+}
+
+/// Reads server sent events until the stream ends, broadcasting every message received.
+///
+/// If `endpoint_tx` is provided, the data of the first `endpoint` event is sent through it.
+async fn read_event_stream(
+    mut response: Response,
+    tx: broadcast::Sender<Result<JsonRpcMessage, TransportError>>,
+    log_tx: broadcast::Sender<String>,
+    mut endpoint_tx: Option<oneshot::Sender<String>>,
+) {
+    let mut parser = SseParser::default();
+    loop {
+        let chunk = match response.chunk().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(err) => {
+                let _ = log_tx.send(format!("Error reading from SSE stream: {}", err));
+                break;
+            },
+        };
+        for event in parser.feed(&chunk) {
+            match event.event.as_deref() {
+                Some("endpoint") => {
+                    if let Some(endpoint_tx) = endpoint_tx.take() {
+                        let _ = endpoint_tx.send(event.data);
+                    }
+                },
+                None | Some("message") => broadcast_body(event.data.as_bytes(), &tx),
+                Some(other) => {
+                    let _ = log_tx.send(format!("Ignoring SSE event '{}'", other));
+                },
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SseEvent {
+    pub event: Option<String>,
+    pub data: String,
+}
+
+/// Incremental parser for the `text/event-stream` format.
+///
+/// See https://html.spec.whatwg.org/multipage/server-sent-events.html#event-stream-interpretation
+#[derive(Debug, Default)]
+pub(crate) struct SseParser {
+    buffer: Vec<u8>,
+    event: Option<String>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    /// Feeds a chunk of the stream, returning the events completed by it.
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line = self.buffer.drain(..=pos).collect::<Vec<_>>();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line.is_empty() {
+                if !self.data.is_empty() {
+                    events.push(SseEvent {
+                        event: self.event.take(),
+                        data: self.data.join("\n"),
+                    });
+                }
+                self.event = None;
+                self.data.clear();
+                continue;
+            }
+            if line.starts_with(':') {
+                // Comments are used as keep-alives.
+                continue;
+            }
+            let (field, value) = line.split_once(':').unwrap_or((line, ""));
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            match field {
+                "event" => self.event = Some(value.to_string()),
+                "data" => self.data.push(value.to_string()),
+                _ => (),
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::sync::atomic::{
+        AtomicBool,
+        Ordering,
+    };
+
+    use bytes::Bytes;
+    use futures::StreamExt;
+    use http_body_util::combinators::BoxBody;
+    use http_body_util::{
+        BodyExt,
+        Full,
+        StreamBody,
+    };
+    use hyper::body::{
+        Frame,
+        Incoming,
+    };
+    use hyper::server::conn::http1;
+    use hyper::service::service_fn;
+    use hyper::{
+        Method,
+        Request,
+    };
+    use hyper_util::rt::TokioIo;
+    use serde_json::json;
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    type Body = BoxBody<Bytes, Infallible>;
+
+    fn full(body: impl Into<Bytes>) -> Body {
+        Full::new(body.into()).boxed()
+    }
+
+    fn response(status: u16, content_type: &str, body: Body) -> hyper::Response<Body> {
+        hyper::Response::builder()
+            .status(status)
+            .header(CONTENT_TYPE, content_type)
+            .header(SESSION_ID_HEADER, "session-1")
+            .body(body)
+            .unwrap()
+    }
+
+    fn reply_to(msg: &serde_json::Value) -> serde_json::Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": msg["id"],
+            "result": { "method": msg["method"] },
+        })
+    }
+
+    struct MockServer {
+        url: String,
+        /// Whether the session was terminated with a DELETE request.
+        deleted: Arc<AtomicBool>,
+        /// The `x-api-key` header of every request received.
+        api_keys: Arc<std::sync::Mutex<Vec<Option<String>>>>,
+    }
+
+    /// A mock server for the streamable HTTP transport, or for the HTTP+SSE transport if
+    /// `sse_endpoint` is given, which is then sent as the endpoint to post messages to.
+    async fn spawn_mock_server(sse_endpoint: Option<&'static str>) -> MockServer {
+        let legacy = sse_endpoint.is_some();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        // Legacy servers send every message on the SSE stream opened by the GET request.
+        let (sse_tx, sse_rx) = mpsc::unbounded_channel::<String>();
+        let sse_rx = Arc::new(Mutex::new(Some(sse_rx)));
+        let deleted = Arc::new(AtomicBool::new(false));
+        let api_keys = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let server = MockServer {
+            url: format!("http://{addr}/mcp"),
+            deleted: Arc::clone(&deleted),
+            api_keys: Arc::clone(&api_keys),
+        };
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let sse_tx = sse_tx.clone();
+                let sse_rx = Arc::clone(&sse_rx);
+                let deleted = Arc::clone(&deleted);
+                let api_keys = Arc::clone(&api_keys);
+                tokio::spawn(async move {
+                    let service = service_fn(move |req: Request<Incoming>| {
+                        let sse_tx = sse_tx.clone();
+                        let sse_rx = Arc::clone(&sse_rx);
+                        let deleted = Arc::clone(&deleted);
+                        let api_keys = Arc::clone(&api_keys);
+                        async move {
+                            let api_key = req
+                                .headers()
+                                .get("x-api-key")
+                                .map(|value| value.to_str().unwrap().to_string());
+                            api_keys.lock().unwrap().push(api_key);
+                            let method = req.method().clone();
+                            let path = req.uri().path().to_string();
+                            let body = req.into_body().collect().await.unwrap().to_bytes();
+                            let res = match (legacy, method, path.as_str()) {
+                                (false, Method::POST, "/mcp") => {
+                                    let msg: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                                    match msg["method"].as_str() {
+                                        _ if msg.get("id").is_none() => response(202, "application/json", full("")),
+                                        Some("initialize") => {
+                                            response(200, "application/json", full(reply_to(&msg).to_string()))
+                                        },
+                                        // Reply with an event stream, including a notification
+                                        // before the actual response.
+                                        _ => response(
+                                            200,
+                                            EVENT_STREAM,
+                                            full(format!(
+                                                ": keep-alive\n\nevent: message\ndata: {}\n\ndata: {}\n\n",
+                                                json!({"jsonrpc": "2.0", "method": "notifications/message", "params": {}}),
+                                                reply_to(&msg)
+                                            )),
+                                        ),
+                                    }
+                                },
+                                (false, Method::GET, "/mcp") => response(405, "text/plain", full("")),
+                                (false, Method::DELETE, "/mcp") => {
+                                    deleted.store(true, Ordering::SeqCst);
+                                    response(200, "text/plain", full(""))
+                                },
+                                (true, Method::GET, "/mcp") => {
+                                    let rx = sse_rx.lock().await.take().unwrap();
+                                    let stream = futures::stream::once(async move {
+                                        format!("event: endpoint\ndata: {}\n\n", sse_endpoint.unwrap())
+                                    })
+                                    .chain(futures::stream::unfold(rx, |mut rx| async move {
+                                        rx.recv()
+                                            .await
+                                            .map(|msg| (format!("event: message\ndata: {msg}\n\n"), rx))
+                                    }))
+                                    .map(|s| Ok::<_, Infallible>(Frame::data(Bytes::from(s))));
+                                    response(200, EVENT_STREAM, BodyExt::boxed(StreamBody::new(stream)))
+                                },
+                                (true, Method::POST, "/messages") => {
+                                    let msg: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                                    if msg.get("id").is_some() {
+                                        sse_tx.send(reply_to(&msg).to_string()).unwrap();
+                                    }
+                                    response(202, "text/plain", full(""))
+                                },
+                                _ => response(404, "text/plain", full("")),
+                            };
+                            Ok::<_, Infallible>(res)
+                        }
+                    });
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        server
+    }
+
+    fn request(id: u64, method: &str) -> JsonRpcMessage {
+        serde_json::from_value(json!({ "jsonrpc": "2.0", "id": id, "method": method })).unwrap()
+    }
+
+    async fn recv_response(listener: &mut impl Listener, id: u64) -> JsonRpcMessage {
+        loop {
+            let msg = listener.recv().await.unwrap();
+            if matches!(msg, JsonRpcMessage::Response(_)) && msg.id() == Some(id) {
+                return msg;
+            }
+        }
+    }
+
+    async fn assert_round_trip(legacy: bool) {
+        let server = spawn_mock_server(legacy.then_some("/messages?session=1")).await;
+        let url = &server.url;
+        let transport =
+            JsonRpcHttpTransport::new(url, HashMap::from([("x-api-key".to_string(), "secret".to_string())])).unwrap();
+
+        for (id, method) in [(0, "initialize"), (1, "tools/list")] {
+            let mut listener = transport.get_listener();
+            transport.send(&request(id, method)).await.unwrap();
+            let JsonRpcMessage::Response(resp) = recv_response(&mut listener, id).await else {
+                unreachable!()
+            };
+            assert_eq!(resp.result.unwrap()["method"], method);
+        }
+        let notification = serde_json::from_value::<JsonRpcMessage>(
+            json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }),
+        )
+        .unwrap();
+        transport.send(&notification).await.unwrap();
+
+        let expected_mode = if legacy {
+            HttpMode::Sse {
+                endpoint: Url::parse(url).unwrap().join("/messages?session=1").unwrap(),
+            }
+        } else {
+            HttpMode::Streamable
+        };
+        assert_eq!(transport.mode.lock().await.clone(), Some(expected_mode));
+        assert_eq!(transport.session_id().is_some(), !legacy);
+        transport.shutdown().await.unwrap();
+        // Only streamable HTTP sessions are terminated.
+        assert_eq!(server.deleted.load(Ordering::SeqCst), !legacy);
+
+        let api_keys = server.api_keys.lock().unwrap();
+        assert!(!api_keys.is_empty());
+        assert!(
+            api_keys.iter().all(|key| key.as_deref() == Some("secret")),
+            "{api_keys:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_streamable_http_transport() {
+        assert_round_trip(false).await;
+    }
+
+    #[tokio::test]
+    async fn test_sse_fallback_transport() {
+        assert_round_trip(true).await;
+    }
+
+    #[tokio::test]
+    async fn test_sse_endpoint_on_other_origin() {
+        let server = spawn_mock_server(Some("http://127.0.0.1:1/messages")).await;
+        let transport = JsonRpcHttpTransport::new(
+            &server.url,
+            HashMap::from([("x-api-key".to_string(), "secret".to_string())]),
+        )
+        .unwrap();
+        let err = transport.send(&request(0, "initialize")).await.unwrap_err();
+        assert!(err.to_string().contains("not on the same origin"), "{err}");
+        assert_eq!(transport.mode.lock().await.clone(), None);
+        transport.shutdown().await.unwrap();
+    }
+
+    #[test]
+    fn test_sse_parser() {
+        let mut parser = SseParser::default();
+        assert!(parser.feed(b"event: endpoint\r\nda").is_empty());
+        assert_eq!(
+            parser.feed(b"ta: /messages\r\n\r\n: ping\n\ndata: {\"a\":\ndata: 1}\n\n"),
+            vec![
+                SseEvent {
+                    event: Some("endpoint".to_string()),
+                    data: "/messages".to_string(),
+                },
+                SseEvent {
+                    event: None,
+                    data: "{\"a\":\n1}".to_string(),
+                },
+            ]
+        );
+    }
+}
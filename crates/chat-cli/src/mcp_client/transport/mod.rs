@@ -1,4 +1,5 @@
 pub mod base_protocol;
+pub mod http;
 pub mod stdio;
 
 use std::fmt::Debug;
@@ -13,6 +14,8 @@ pub enum TransportError {
     Serialization(String),
     #[error("IO error: {0}")]
     Stdio(String),
+    #[error("HTTP error: {0}")]
+    Http(String),
     #[error("{0}")]
     Custom(String),
     #[error(transparent)]
@@ -25,6 +28,12 @@ impl From<serde_json::Error> for TransportError {
     }
 }
 
+impl From<reqwest::Error> for TransportError {
+    fn from(err: reqwest::Error) -> Self {
+        TransportError::Http(err.to_string())
+    }
+}
+
 impl From<std::io::Error> for TransportError {
     fn from(err: std::io::Error) -> Self {
         TransportError::Stdio(err.to_string())
@@ -36,8 +45,8 @@ impl From<std::io::Error> for TransportError {
 pub trait Transport: Send + Sync + Debug + 'static {
     /// Sends a message over the transport layer.
     async fn send(&self, msg: &JsonRpcMessage) -> Result<(), TransportError>;
-    /// Listens to awaits for a response. This is a call that should be used after `send` is called
-    /// to listen for a response from the message recipient.
+    /// Listens to awaits for a response. The listener should be obtained before `send` is called
+    /// so that responses delivered as part of the send (as is the case for HTTP) are not missed.
     fn get_listener(&self) -> impl Listener;
     /// Gracefully terminates the transport connection, cleaning up any resources.
     /// This should be called when the transport is no longer needed to ensure proper cleanup.
@@ -123,6 +123,11 @@ pub fn chat_profiles_dir(ctx: &Context) -> Result<PathBuf> {
     Ok(home_dir(ctx)?.join(".aws").join("amazonq").join("profiles"))
 }
 
+/// The directory containing the knowledge bases indexed by the `/knowledge` feature in `q chat`.
+pub fn chat_knowledge_bases_dir(ctx: &Context) -> Result<PathBuf> {
+    Ok(home_dir(ctx)?.join(".aws").join("amazonq").join("knowledge_bases"))
+}
+
 /// The path to the fig settings file
 pub fn settings_path() -> Result<PathBuf> {
     Ok(fig_data_dir()?.join("settings.json"))
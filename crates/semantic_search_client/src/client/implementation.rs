@@ -1,4 +1,7 @@
-use std::collections::HashMap;
+use std::collections::{
+    HashMap,
+    HashSet,
+};
 use std::fs;
 use std::path::{
     Path,
@@ -30,9 +33,14 @@ use crate::types::{
     ContextId,
     ContextMap,
     DataPoint,
+    FileState,
+    IndexStats,
     MemoryContext,
     ProgressStatus,
+    SearchMode,
+    SearchOptions,
     SearchResults,
+    UpdateSummary,
 };
 
 /// Semantic search client for managing semantic memory
@@ -304,6 +312,9 @@ impl SemanticSearchClient {
             callback(ProgressStatus::StartingIndexing(1));
         }
 
+        // Record the state of the file before processing it, so that later edits are noticed
+        let file_state = utils::file_state(file_path)?;
+
         // Process the file
         let items = process_file(file_path)?;
 
@@ -313,7 +324,8 @@ impl SemanticSearchClient {
         }
 
         // Create a semantic context from the items
-        let semantic_context = self.create_semantic_context(&context_dir, &items, &progress_callback)?;
+        let mut semantic_context = self.create_semantic_context(&context_dir, &items, &progress_callback)?;
+        semantic_context.set_file_states(HashMap::from([(file_path.to_string_lossy().to_string(), file_state)]));
 
         // Notify progress: Finalizing
         if let Some(ref callback) = progress_callback {
@@ -374,10 +386,11 @@ impl SemanticSearchClient {
         let file_count = Self::count_files_in_directory(dir_path, &progress_callback)?;
 
         // Process files
-        let items = Self::process_directory_files(dir_path, file_count, &progress_callback)?;
+        let (items, file_states) = Self::process_directory_files(dir_path, file_count, &progress_callback)?;
 
         // Create and populate semantic context
-        let semantic_context = self.create_semantic_context(&context_dir, &items, &progress_callback)?;
+        let mut semantic_context = self.create_semantic_context(&context_dir, &items, &progress_callback)?;
+        semantic_context.set_file_states(file_states);
 
         // Save and store context
         self.save_and_store_context(
@@ -406,11 +419,13 @@ impl SemanticSearchClient {
     }
 
     /// Process files in a directory
+    ///
+    /// Returns the items of every file along with the state of the files, by path
     fn process_directory_files<F>(
         dir_path: &Path,
         file_count: usize,
         progress_callback: &Option<F>,
-    ) -> Result<Vec<Value>>
+    ) -> Result<(Vec<Value>, HashMap<String, FileState>)>
     where
         F: Fn(ProgressStatus) + Send + 'static,
     {
@@ -422,26 +437,20 @@ impl SemanticSearchClient {
         // Process all files in the directory with progress updates
         let mut processed_files = 0;
         let mut items = Vec::new();
-
-        for entry in walkdir::WalkDir::new(dir_path)
-            .follow_links(true)
-            .into_iter()
-            .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().is_file())
-        {
-            let path = entry.path();
-
-            // Skip hidden files
-            if path
-                .file_name()
-                .and_then(|n| n.to_str())
-                .is_some_and(|s| s.starts_with('.'))
-            {
-                continue;
+        let mut file_states = HashMap::new();
+
+        for path in utils::collect_files(dir_path) {
+            // Record the state of the file, including files that fail to process so that they
+            // are only retried once they change
+            match utils::file_state(&path) {
+                Ok(state) => {
+                    file_states.insert(path.to_string_lossy().to_string(), state);
+                },
+                Err(_) => continue,
             }
 
             // Process the file
-            match process_file(path) {
+            match process_file(&path) {
                 Ok(mut file_items) => items.append(&mut file_items),
                 Err(_) => continue, // Skip files that fail to process
             }
@@ -454,7 +463,7 @@ impl SemanticSearchClient {
             }
         }
 
-        Ok(items)
+        Ok((items, file_states))
     }
 
     /// Create a semantic context from items
@@ -524,7 +533,7 @@ impl SemanticSearchClient {
         semantic_context: SemanticContext,
     ) -> Result<()> {
         // Notify progress: Finalizing (90% progress point)
-        let item_count = semantic_context.get_data_points().len();
+        let item_count = semantic_context.item_count();
 
         // Save to disk if persistent
         if persistent {
@@ -532,7 +541,8 @@ impl SemanticSearchClient {
         }
 
         // Create the context metadata
-        let context = MemoryContext::new(id.to_string(), name, description, persistent, source_path, item_count);
+        let mut context = MemoryContext::new(id.to_string(), name, description, persistent, source_path, item_count);
+        context.index_stats = Some(semantic_context.index_stats());
 
         // Store the context
         if persistent {
@@ -696,6 +706,8 @@ impl SemanticSearchClient {
 
     /// Search across all contexts
     ///
+    /// Results are ranked with hybrid search, see [`SearchMode::Hybrid`].
+    ///
     /// # Arguments
     ///
     /// * `query_text` - Search query
@@ -706,6 +718,27 @@ impl SemanticSearchClient {
     ///
     /// A vector of (context_id, results) pairs
     pub fn search_all(&self, query_text: &str, result_limit: Option<usize>) -> Result<Vec<(ContextId, SearchResults)>> {
+        self.search_all_with_options(query_text, &SearchOptions {
+            limit: result_limit,
+            ..Default::default()
+        })
+    }
+
+    /// Search across all contexts with the given ranking mode, filters and minimum score
+    ///
+    /// # Arguments
+    ///
+    /// * `query_text` - Search query
+    /// * `options` - Search options
+    ///
+    /// # Returns
+    ///
+    /// A vector of (context_id, results) pairs, sorted by best match
+    pub fn search_all_with_options(
+        &self,
+        query_text: &str,
+        options: &SearchOptions,
+    ) -> Result<Vec<(ContextId, SearchResults)>> {
         // Validate inputs
         if query_text.is_empty() {
             return Err(SemanticSearchError::InvalidArgument(
@@ -714,10 +747,10 @@ impl SemanticSearchClient {
         }
 
         // Use the configured default_results if limit is None
-        let effective_limit = result_limit.unwrap_or_else(|| config::get_config().default_results);
+        let effective_limit = options.limit.unwrap_or_else(|| config::get_config().default_results);
 
         // Generate an embedding for the query
-        let query_vector = self.embedder.embed(query_text)?;
+        let query_vector = self.embed_query(query_text, options.mode)?;
 
         let mut all_results = Vec::new();
 
@@ -727,12 +760,13 @@ impl SemanticSearchClient {
                 SemanticSearchError::OperationFailed(format!("Failed to acquire lock on context: {}", e))
             })?;
 
-            match context_guard.search(&query_vector, effective_limit) {
+            match context_guard.search_with_options(query_vector.as_deref(), query_text, options, effective_limit) {
                 Ok(results) => {
                     if !results.is_empty() {
                         all_results.push((context_id.clone(), results));
                     }
                 },
+                Err(e @ SemanticSearchError::InvalidArgument(_)) => return Err(e),
                 Err(e) => {
                     tracing::warn!("Failed to search context {}: {}", context_id, e);
                     continue; // Skip contexts that fail to search
@@ -758,6 +792,8 @@ impl SemanticSearchClient {
 
     /// Search in a specific context
     ///
+    /// Results are ranked with hybrid search, see [`SearchMode::Hybrid`].
+    ///
     /// # Arguments
     ///
     /// * `context_id` - ID of the context to search in
@@ -773,6 +809,29 @@ impl SemanticSearchClient {
         context_id: &str,
         query_text: &str,
         result_limit: Option<usize>,
+    ) -> Result<SearchResults> {
+        self.search_context_with_options(context_id, query_text, &SearchOptions {
+            limit: result_limit,
+            ..Default::default()
+        })
+    }
+
+    /// Search in a specific context with the given ranking mode, filters and minimum score
+    ///
+    /// # Arguments
+    ///
+    /// * `context_id` - ID of the context to search in
+    /// * `query_text` - Search query
+    /// * `options` - Search options
+    ///
+    /// # Returns
+    ///
+    /// A vector of search results
+    pub fn search_context_with_options(
+        &self,
+        context_id: &str,
+        query_text: &str,
+        options: &SearchOptions,
     ) -> Result<SearchResults> {
         // Validate inputs
         if context_id.is_empty() {
@@ -788,10 +847,10 @@ impl SemanticSearchClient {
         }
 
         // Use the configured default_results if limit is None
-        let effective_limit = result_limit.unwrap_or_else(|| config::get_config().default_results);
+        let effective_limit = options.limit.unwrap_or_else(|| config::get_config().default_results);
 
         // Generate an embedding for the query
-        let query_vector = self.embedder.embed(query_text)?;
+        let query_vector = self.embed_query(query_text, options.mode)?;
 
         let context = self
             .volatile_contexts
@@ -802,7 +861,15 @@ impl SemanticSearchClient {
             .lock()
             .map_err(|e| SemanticSearchError::OperationFailed(format!("Failed to acquire lock on context: {}", e)))?;
 
-        context_guard.search(&query_vector, effective_limit)
+        context_guard.search_with_options(query_vector.as_deref(), query_text, options, effective_limit)
+    }
+
+    /// Embed a search query, unless searching by keyword only
+    fn embed_query(&self, query_text: &str, mode: SearchMode) -> Result<Option<Vec<f32>>> {
+        match mode {
+            SearchMode::Keyword => Ok(None),
+            SearchMode::Vector | SearchMode::Hybrid => Ok(Some(self.embedder.embed(query_text)?)),
+        }
     }
 
     /// Get all contexts
@@ -850,10 +917,13 @@ impl SemanticSearchClient {
         fs::create_dir_all(&persistent_dir)?;
 
         // Get the context data
-        let context_guard = context
+        let mut context_guard = context
             .lock()
             .map_err(|e| SemanticSearchError::OperationFailed(format!("Failed to acquire lock on context: {}", e)))?;
 
+        // Only the data points are saved, so drop the deleted ones first
+        context_guard.compact()?;
+
         // Save the data to the persistent directory
         let data_path = persistent_dir.join("data.json");
         utils::save_json_to_file(&data_path, context_guard.get_data_points())?;
@@ -1037,6 +1107,199 @@ impl SemanticSearchClient {
         Ok(())
     }
 
+    /// Update a persistent context with the changes made to its source path since it was indexed
+    ///
+    /// Only the files that were added, modified or removed are processed and embedded again.
+    /// Files are compared by modification time and size first, and by content hash when those
+    /// differ, so touching a file without editing it does not embed it again.
+    ///
+    /// # Arguments
+    ///
+    /// * `context_id` - ID of the context to update
+    ///
+    /// # Returns
+    ///
+    /// A summary of the files that changed
+    pub fn update_context(&mut self, context_id: &str) -> Result<UpdateSummary> {
+        let source_path = self
+            .persistent_contexts
+            .get(context_id)
+            .ok_or_else(|| SemanticSearchError::ContextNotFound(context_id.to_string()))?
+            .source_path
+            .clone()
+            .map(PathBuf::from)
+            .ok_or_else(|| {
+                SemanticSearchError::InvalidArgument(format!("Context {} was not indexed from a path", context_id))
+            })?;
+
+        if !source_path.exists() {
+            return Err(SemanticSearchError::InvalidPath(format!(
+                "Path does not exist: {}",
+                source_path.display()
+            )));
+        }
+
+        let context = self
+            .volatile_contexts
+            .get(context_id)
+            .cloned()
+            .ok_or_else(|| SemanticSearchError::ContextNotFound(context_id.to_string()))?;
+        let mut context = context
+            .lock()
+            .map_err(|e| SemanticSearchError::OperationFailed(format!("Failed to acquire lock on context: {}", e)))?;
+
+        let files = if source_path.is_dir() {
+            utils::collect_files(&source_path)
+        } else {
+            vec![source_path]
+        };
+
+        // Find the files that changed since the last update
+        let previous = context.file_states().clone();
+        let mut summary = UpdateSummary::default();
+        let mut file_states = HashMap::new();
+        let mut changed = Vec::new();
+        for path in files {
+            let key = path.to_string_lossy().to_string();
+            let Ok(mut state) = utils::file_metadata(&path) else {
+                continue;
+            };
+            let old_state = previous.get(&key);
+            if let Some(old_state) = old_state {
+                if old_state.modified == state.modified && old_state.size == state.size {
+                    summary.unchanged += 1;
+                    file_states.insert(key, old_state.clone());
+                    continue;
+                }
+            }
+
+            state.hash = match utils::hash_file(&path) {
+                Ok(hash) => hash,
+                Err(_) => continue,
+            };
+            match old_state {
+                Some(old_state) if old_state.hash == state.hash => summary.unchanged += 1,
+                Some(_) => {
+                    summary.modified += 1;
+                    changed.push(path);
+                },
+                None => {
+                    summary.added += 1;
+                    changed.push(path);
+                },
+            }
+            file_states.insert(key, state);
+        }
+
+        if file_states == previous {
+            return Ok(summary);
+        }
+
+        // Files that are gone, including files indexed before their state was recorded
+        let removed: HashSet<String> = previous
+            .keys()
+            .cloned()
+            .chain(
+                context
+                    .live_data_points()
+                    .filter_map(|(_, point)| point.payload.get("path").and_then(|v| v.as_str()))
+                    .map(str::to_string),
+            )
+            .filter(|path| !file_states.contains_key(path))
+            .collect();
+        summary.removed = removed.len();
+
+        // Drop the chunks of the removed and changed files, then embed the changed files again
+        let mut outdated = removed;
+        outdated.extend(changed.iter().map(|path| path.to_string_lossy().to_string()));
+        context.remove_files(&outdated)?;
+
+        let mut items = Vec::new();
+        for path in &changed {
+            match process_file(path) {
+                Ok(mut file_items) => items.append(&mut file_items),
+                Err(e) => tracing::debug!("Skipping {}: {}", path.display(), e),
+            }
+        }
+        let offset = context.get_data_points().len();
+        let data_points = items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| self.create_data_point_from_item(item, offset + i))
+            .collect::<Result<Vec<_>>>()?;
+        context.add_data_points(data_points)?;
+        context.set_file_states(file_states);
+        context.save()?;
+        let item_count = context.item_count();
+        let index_stats = context.index_stats();
+        drop(context);
+
+        if let Some(memory_context) = self.persistent_contexts.get_mut(context_id) {
+            memory_context.item_count = item_count;
+            memory_context.index_stats = Some(index_stats);
+            if summary.has_changes() {
+                memory_context.updated_at = chrono::Utc::now();
+            }
+        }
+        self.save_contexts_metadata()?;
+
+        Ok(summary)
+    }
+
+    /// Compact the index of a context, dropping the data points deleted since the last compaction
+    ///
+    /// Contexts are compacted automatically once more than a quarter of their data points are
+    /// deleted, so this is only needed to reclaim space sooner.
+    ///
+    /// # Arguments
+    ///
+    /// * `context_id` - ID of the context to compact
+    ///
+    /// # Returns
+    ///
+    /// The number of data points dropped
+    pub fn compact_context(&mut self, context_id: &str) -> Result<usize> {
+        let context = self
+            .volatile_contexts
+            .get(context_id)
+            .cloned()
+            .ok_or_else(|| SemanticSearchError::ContextNotFound(context_id.to_string()))?;
+        let mut context = context
+            .lock()
+            .map_err(|e| SemanticSearchError::OperationFailed(format!("Failed to acquire lock on context: {}", e)))?;
+
+        let dropped = context.compact()?;
+        if let Some(memory_context) = self.persistent_contexts.get_mut(context_id) {
+            if dropped > 0 {
+                context.save()?;
+            }
+            memory_context.index_stats = Some(context.index_stats());
+            self.save_contexts_metadata()?;
+        }
+
+        Ok(dropped)
+    }
+
+    /// Get the statistics of the index of a context
+    ///
+    /// # Arguments
+    ///
+    /// * `context_id` - ID of the context
+    ///
+    /// # Returns
+    ///
+    /// The statistics of the index
+    pub fn get_index_stats(&self, context_id: &str) -> Result<IndexStats> {
+        let context = self
+            .volatile_contexts
+            .get(context_id)
+            .ok_or_else(|| SemanticSearchError::ContextNotFound(context_id.to_string()))?;
+        let context = context
+            .lock()
+            .map_err(|e| SemanticSearchError::OperationFailed(format!("Failed to acquire lock on context: {}", e)))?;
+        Ok(context.index_stats())
+    }
+
     /// Save contexts metadata to disk
     fn save_contexts_metadata(&self) -> Result<()> {
         let contexts_file = self.base_dir.join("contexts.json");
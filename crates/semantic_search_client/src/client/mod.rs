@@ -6,6 +6,9 @@ mod implementation;
 pub mod semantic_context;
 /// Utility functions for semantic search operations
 pub mod utils;
+/// Watching source paths to keep persistent contexts up to date
+mod watcher;
 
 pub use implementation::SemanticSearchClient;
 pub use semantic_context::SemanticContext;
+pub use watcher::ContextWatcher;
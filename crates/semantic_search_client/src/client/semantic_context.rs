@@ -1,3 +1,7 @@
+use std::collections::{
+    HashMap,
+    HashSet,
+};
 use std::fs::{
     self,
     File,
@@ -6,23 +10,85 @@ use std::io::{
     BufReader,
     BufWriter,
 };
-use std::path::PathBuf;
+use std::path::{
+    Path,
+    PathBuf,
+};
 
-use crate::error::Result;
-use crate::index::VectorIndex;
+use chrono::{
+    DateTime,
+    Utc,
+};
+use globset::{
+    GlobBuilder,
+    GlobMatcher,
+};
+use tracing::debug;
+
+use crate::client::utils;
+use crate::error::{
+    Result,
+    SemanticSearchError,
+};
+use crate::index::{
+    KeywordIndex,
+    VectorIndex,
+};
 use crate::types::{
     DataPoint,
+    FileState,
+    IndexStats,
+    SearchFilter,
+    SearchMode,
+    SearchOptions,
     SearchResult,
 };
 
+/// Name of the file storing the state of the indexed files, next to the data points
+const FILES_FILE_NAME: &str = "files.json";
+
+/// Name of the file storing the tombstones and compaction history of the index
+const INDEX_STATE_FILE_NAME: &str = "index.json";
+
+/// Base name of the files storing the graph and vectors of the index
+const INDEX_BASENAME: &str = "index";
+
+/// Fraction of deleted data points above which the index is compacted
+const COMPACTION_THRESHOLD: f32 = 0.25;
+
+/// Constant of reciprocal rank fusion, damping the weight of the top ranks
+const RRF_K: f32 = 60.0;
+
+/// Number of candidates taken from each ranking for every result of a hybrid search
+const HYBRID_CANDIDATES_PER_RESULT: usize = 4;
+
 /// A semantic context containing data points and a vector index
 pub struct SemanticContext {
     /// The data points stored in the index
     pub(crate) data_points: Vec<DataPoint>,
     /// The vector index for fast approximate nearest neighbor search
     index: Option<VectorIndex>,
+    /// The keyword index for BM25 search
+    keywords: Option<KeywordIndex>,
     /// Path to save/load the data points
     data_path: PathBuf,
+    /// State of the indexed files by path, saved next to the data points
+    files: HashMap<String, FileState>,
+    /// Compaction history of the index
+    compactions: usize,
+    /// When the index was last compacted
+    last_compacted_at: Option<DateTime<Utc>>,
+}
+
+/// Index state saved next to the data points, which the saved graph does not include
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct IndexState {
+    /// Positions of the deleted data points
+    deleted: Vec<usize>,
+    /// Number of times the index was compacted
+    compactions: usize,
+    /// When the index was last compacted
+    last_compacted_at: Option<DateTime<Utc>>,
 }
 
 impl SemanticContext {
@@ -37,7 +103,11 @@ impl SemanticContext {
         let mut context = Self {
             data_points: Vec::new(),
             index: None,
+            keywords: None,
             data_path: data_path.clone(),
+            files: HashMap::new(),
+            compactions: 0,
+            last_compacted_at: None,
         };
 
         // Load data points if the file exists
@@ -46,27 +116,160 @@ impl SemanticContext {
             let reader = BufReader::new(file);
             context.data_points = serde_json::from_reader(reader)?;
         }
+        context.files = utils::load_json_from_file(&context.files_path())?;
+        let state: IndexState = utils::load_json_from_file(&context.dir().join(INDEX_STATE_FILE_NAME))?;
+        context.compactions = state.compactions;
+        context.last_compacted_at = state.last_compacted_at;
 
-        // If we have data points, rebuild the index
+        // If we have data points, load the saved index, or rebuild it if it is missing or out of date
         if !context.data_points.is_empty() {
-            context.rebuild_index()?;
+            let deleted: HashSet<usize> = state.deleted.into_iter().collect();
+            if let Err(e) = context.load_index(&deleted) {
+                debug!("Rebuilding the index of {}: {}", context.data_path.display(), e);
+                context.rebuild_index_without(&deleted)?;
+            }
         }
 
         Ok(context)
     }
 
+    /// Directory the context is saved to
+    fn dir(&self) -> &Path {
+        self.data_path.parent().unwrap_or(Path::new("."))
+    }
+
+    /// Load the index saved with the data points
+    fn load_index(&mut self, deleted: &HashSet<usize>) -> Result<()> {
+        let mut index = VectorIndex::load(self.dir(), INDEX_BASENAME)?;
+        if index.total_len() != self.data_points.len() {
+            return Err(SemanticSearchError::OperationFailed(format!(
+                "The saved index has {} vectors but there are {} data points",
+                index.total_len(),
+                self.data_points.len()
+            )));
+        }
+        for id in deleted {
+            index.delete(*id);
+        }
+        self.index = Some(index);
+        self.rebuild_keyword_index();
+        Ok(())
+    }
+
     /// Save data points to disk
+    ///
+    /// The graph of the index is saved along with them, so that loading the context does not
+    /// insert every data point again.
     pub fn save(&self) -> Result<()> {
         // Save the data points as JSON
         let file = File::create(&self.data_path)?;
         let writer = BufWriter::new(file);
         serde_json::to_writer(writer, &self.data_points)?;
 
+        utils::save_json_to_file(&self.files_path(), &self.files)?;
+
+        if let Some(index) = self.index.as_ref().filter(|index| index.total_len() > 0) {
+            index.save(self.dir(), INDEX_BASENAME)?;
+        }
+        let mut deleted: Vec<usize> = self.index.iter().flat_map(|index| index.deleted_ids()).collect();
+        deleted.sort_unstable();
+        utils::save_json_to_file(&self.dir().join(INDEX_STATE_FILE_NAME), &IndexState {
+            deleted,
+            compactions: self.compactions,
+            last_compacted_at: self.last_compacted_at,
+        })?;
+
         Ok(())
     }
 
-    /// Rebuild the index from the current data points
+    /// Path of the file storing the state of the indexed files
+    fn files_path(&self) -> PathBuf {
+        self.data_path.with_file_name(FILES_FILE_NAME)
+    }
+
+    /// Get the state of the indexed files by path
+    pub fn file_states(&self) -> &HashMap<String, FileState> {
+        &self.files
+    }
+
+    /// Replace the state of the indexed files
+    pub fn set_file_states(&mut self, files: HashMap<String, FileState>) {
+        self.files = files;
+    }
+
+    /// Delete the data points chunked from the given files
+    ///
+    /// The data points are tombstoned, and the index is compacted once more than a quarter of
+    /// them are deleted.
+    ///
+    /// # Returns
+    ///
+    /// The number of data points deleted
+    pub fn remove_files(&mut self, paths: &HashSet<String>) -> Result<usize> {
+        let ids: Vec<usize> = self
+            .live_data_points()
+            .filter(|(_, point)| {
+                point
+                    .payload
+                    .get("path")
+                    .and_then(|v| v.as_str())
+                    .is_some_and(|path| paths.contains(path))
+            })
+            .map(|(i, _)| i)
+            .collect();
+        let (Some(index), Some(keywords)) = (self.index.as_mut(), self.keywords.as_mut()) else {
+            return Ok(0);
+        };
+        for id in &ids {
+            index.delete(*id);
+            keywords.remove(*id);
+        }
+
+        if index.deleted_len() as f32 > index.total_len() as f32 * COMPACTION_THRESHOLD {
+            self.compact()?;
+        }
+        Ok(ids.len())
+    }
+
+    /// Compact the index, dropping the deleted data points
+    ///
+    /// The ids of the remaining data points are renumbered to match their position.
+    ///
+    /// # Returns
+    ///
+    /// The number of data points dropped
+    pub fn compact(&mut self) -> Result<usize> {
+        let deleted = self.index.as_ref().map_or(0, |index| index.deleted_len());
+        if deleted > 0 {
+            self.rebuild_index()?;
+        }
+        Ok(deleted)
+    }
+
+    /// Rebuild the index from the current data points, dropping the deleted ones
     pub fn rebuild_index(&mut self) -> Result<()> {
+        let deleted: HashSet<usize> = self.index.iter().flat_map(|index| index.deleted_ids()).collect();
+        self.rebuild_index_without(&deleted)?;
+        if !deleted.is_empty() {
+            self.compactions += 1;
+            self.last_compacted_at = Some(Utc::now());
+        }
+        Ok(())
+    }
+
+    /// Rebuild the index from the current data points, dropping the given ones
+    fn rebuild_index_without(&mut self, deleted: &HashSet<usize>) -> Result<()> {
+        if !deleted.is_empty() {
+            let mut i = 0;
+            self.data_points.retain(|_| {
+                i += 1;
+                !deleted.contains(&(i - 1))
+            });
+            for (i, point) in self.data_points.iter_mut().enumerate() {
+                point.id = i;
+            }
+        }
+
         // Create a new index with the current data points
         let index = VectorIndex::new(self.data_points.len().max(100));
 
@@ -77,10 +280,51 @@ impl SemanticContext {
 
         // Set the new index
         self.index = Some(index);
+        self.rebuild_keyword_index();
 
         Ok(())
     }
 
+    /// Rebuild the keyword index from the data points that are not deleted
+    fn rebuild_keyword_index(&mut self) {
+        let keywords = KeywordIndex::new(self.live_data_points().map(|(i, point)| (i, point_text(point))));
+        self.keywords = Some(keywords);
+    }
+
+    /// Check if the data point at the given position was deleted
+    fn is_deleted(&self, id: usize) -> bool {
+        self.index.as_ref().is_some_and(|index| index.is_deleted(id))
+    }
+
+    /// Get the data points that are not deleted, with their positions
+    pub fn live_data_points(&self) -> impl Iterator<Item = (usize, &DataPoint)> {
+        self.data_points
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !self.is_deleted(*i))
+    }
+
+    /// Get the number of data points that are not deleted
+    pub fn item_count(&self) -> usize {
+        self.data_points.len() - self.index.as_ref().map_or(0, |index| index.deleted_len())
+    }
+
+    /// Get the statistics of the index
+    pub fn index_stats(&self) -> IndexStats {
+        let saved_size = ["hnsw.graph", "hnsw.data"]
+            .iter()
+            .filter_map(|ext| fs::metadata(self.dir().join(format!("{INDEX_BASENAME}.{ext}"))).ok())
+            .map(|metadata| metadata.len())
+            .sum();
+        IndexStats {
+            live_vectors: self.item_count(),
+            deleted_vectors: self.index.as_ref().map_or(0, |index| index.deleted_len()),
+            compactions: self.compactions,
+            last_compacted_at: self.last_compacted_at,
+            saved_size,
+        }
+    }
+
     /// Add data points to the context
     pub fn add_data_points(&mut self, data_points: Vec<DataPoint>) -> Result<usize> {
         // Store the count before extending the data points
@@ -116,6 +360,9 @@ impl SemanticContext {
         // Add only the points in the specified range to the index
         for i in start_idx..end_idx {
             index.insert(&self.data_points[i].vector, i);
+            if let Some(keywords) = self.keywords.as_mut() {
+                keywords.insert(point_text(&self.data_points[i]), i);
+            }
         }
 
         Ok(())
@@ -143,8 +390,227 @@ impl SemanticContext {
         Ok(search_results)
     }
 
-    /// Get the data points for serialization
+    /// Search with the given options
+    ///
+    /// # Arguments
+    ///
+    /// * `query_vector` - Embedding of the query, required unless searching by keyword only
+    /// * `query_text` - Text of the query
+    /// * `options` - Ranking mode, filters and minimum score of the results
+    /// * `limit` - Maximum number of results to return
+    ///
+    /// # Returns
+    ///
+    /// The results, best first
+    pub fn search_with_options(
+        &self,
+        query_vector: Option<&[f32]>,
+        query_text: &str,
+        options: &SearchOptions,
+        limit: usize,
+    ) -> Result<Vec<SearchResult>> {
+        let filter = PointFilter::new(&options.filter)?;
+        let candidates = match options.mode {
+            SearchMode::Hybrid => limit * HYBRID_CANDIDATES_PER_RESULT,
+            _ => limit,
+        };
+
+        let vector_ranking = match (options.mode, query_vector) {
+            (SearchMode::Keyword, _) => Vec::new(),
+            (_, Some(query_vector)) => self.vector_ranking(query_vector, &filter, candidates),
+            (_, None) => {
+                return Err(SemanticSearchError::InvalidArgument(
+                    "A query embedding is required for vector search".to_string(),
+                ));
+            },
+        };
+        let keyword_ranking = match (options.mode, &self.keywords) {
+            (SearchMode::Vector, _) | (_, None) => Vec::new(),
+            (_, Some(keywords)) => keywords
+                .search(query_text)
+                .into_iter()
+                .filter(|(id, _)| filter.matches(&self.data_points[*id], &self.files))
+                .take(candidates)
+                .collect(),
+        };
+
+        // Scores between 0 and 1, best first
+        let scored: Vec<(usize, f32)> = match options.mode {
+            SearchMode::Vector => vector_ranking
+                .into_iter()
+                .map(|(id, distance)| (id, 1.0 - distance))
+                .collect(),
+            SearchMode::Keyword => {
+                let best = keyword_ranking.first().map_or(1.0, |(_, score)| *score);
+                keyword_ranking
+                    .into_iter()
+                    .map(|(id, score)| (id, if best > 0.0 { score / best } else { 0.0 }))
+                    .collect()
+            },
+            SearchMode::Hybrid => reciprocal_rank_fusion(&[vector_ranking, keyword_ranking]),
+        };
+
+        Ok(scored
+            .into_iter()
+            .filter(|(_, score)| options.min_score.is_none_or(|min_score| *score >= min_score))
+            .take(limit)
+            .map(|(id, score)| SearchResult::new(self.data_points[id].clone(), 1.0 - score))
+            .collect())
+    }
+
+    /// Rank the data points matching a filter by cosine distance to the query, closest first
+    fn vector_ranking(&self, query_vector: &[f32], filter: &PointFilter<'_>, limit: usize) -> Vec<(usize, f32)> {
+        if filter.is_empty() {
+            return match &self.index {
+                Some(index) => index.search(query_vector, limit, limit.max(100)),
+                None => Vec::new(),
+            };
+        }
+
+        // The approximate index cannot be filtered, so compare the matching points exhaustively
+        let mut ranking: Vec<(usize, f32)> = self
+            .live_data_points()
+            .filter(|(_, point)| filter.matches(point, &self.files))
+            .map(|(i, point)| (i, cosine_distance(query_vector, &point.vector)))
+            .collect();
+        ranking.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        ranking.truncate(limit);
+        ranking
+    }
+
+    /// Get the data points for serialization, including the deleted ones until the index is
+    /// compacted
     pub fn get_data_points(&self) -> &Vec<DataPoint> {
         &self.data_points
     }
 }
+
+/// Get the text of a data point
+fn point_text(point: &DataPoint) -> &str {
+    point.payload.get("text").and_then(|v| v.as_str()).unwrap_or_default()
+}
+
+/// Cosine distance between two vectors, as computed by the vector index
+fn cosine_distance(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 1.0;
+    }
+    1.0 - dot / (norm_a * norm_b)
+}
+
+/// Fuse rankings with reciprocal rank fusion
+///
+/// Each result scores `1 / (RRF_K + rank)` in every ranking it appears in. Scores are divided by
+/// the score of a result ranked first in every non-empty ranking, so that they fall between 0 and
+/// 1.
+///
+/// # Returns
+///
+/// The fused (id, score) pairs, best first
+fn reciprocal_rank_fusion(rankings: &[Vec<(usize, f32)>]) -> Vec<(usize, f32)> {
+    let non_empty = rankings.iter().filter(|r| !r.is_empty()).count();
+    if non_empty == 0 {
+        return Vec::new();
+    }
+    let best = non_empty as f32 / (RRF_K + 1.0);
+
+    let mut scores: HashMap<usize, f32> = HashMap::new();
+    for ranking in rankings {
+        for (rank, (id, _)) in ranking.iter().enumerate() {
+            *scores.entry(*id).or_default() += 1.0 / (RRF_K + rank as f32 + 1.0);
+        }
+    }
+
+    let mut fused: Vec<(usize, f32)> = scores.into_iter().map(|(id, score)| (id, score / best)).collect();
+    fused.sort_by(|a, b| {
+        b.1.partial_cmp(&a.1)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a.0.cmp(&b.0))
+    });
+    fused
+}
+
+/// A [SearchFilter] ready to be matched against data points
+struct PointFilter<'a> {
+    filter: &'a SearchFilter,
+    glob: Option<GlobMatcher>,
+}
+
+impl<'a> PointFilter<'a> {
+    fn new(filter: &'a SearchFilter) -> Result<Self> {
+        let glob = match &filter.path_glob {
+            Some(pattern) => {
+                let pattern = if pattern.starts_with('/') || pattern.starts_with("**") {
+                    pattern.clone()
+                } else {
+                    format!("**/{pattern}")
+                };
+                let glob = GlobBuilder::new(&pattern)
+                    .literal_separator(true)
+                    .build()
+                    .map_err(|e| SemanticSearchError::InvalidArgument(format!("Invalid path glob: {}", e)))?;
+                Some(glob.compile_matcher())
+            },
+            None => None,
+        };
+        Ok(Self { filter, glob })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.filter.is_empty()
+    }
+
+    fn matches(&self, point: &DataPoint, files: &HashMap<String, FileState>) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        let Some(path) = point.payload.get("path").and_then(|v| v.as_str()) else {
+            return false;
+        };
+        if self.glob.as_ref().is_some_and(|glob| !glob.is_match(path)) {
+            return false;
+        }
+        if let Some(file_type) = self.filter.file_type {
+            if point.payload.get("file_type").and_then(|v| v.as_str()) != Some(&format!("{:?}", file_type)) {
+                return false;
+            }
+        }
+        if self.filter.modified_after.is_some() || self.filter.modified_before.is_some() {
+            let Some(modified) = files
+                .get(path)
+                .and_then(|state| chrono::DateTime::<chrono::Utc>::from_timestamp_millis(state.modified as i64))
+            else {
+                return false;
+            };
+            if self.filter.modified_after.is_some_and(|after| modified < after)
+                || self.filter.modified_before.is_some_and(|before| modified >= before)
+            {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reciprocal_rank_fusion() {
+        let vector = vec![(1, 0.1), (2, 0.2), (3, 0.3)];
+        let keyword = vec![(3, 9.0), (1, 5.0)];
+        let fused = reciprocal_rank_fusion(&[vector.clone(), keyword]);
+        let ids: Vec<_> = fused.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, vec![1, 3, 2]);
+        assert!(fused.iter().all(|(_, score)| *score > 0.0 && *score <= 1.0));
+
+        // A result ranked first by every non-empty ranking scores 1
+        let fused = reciprocal_rank_fusion(&[vector, Vec::new()]);
+        assert_eq!(fused[0], (1, 1.0));
+        assert!(reciprocal_rank_fusion(&[Vec::new(), Vec::new()]).is_empty());
+    }
+}
@@ -1,13 +1,21 @@
+use std::fmt::Write;
 use std::fs;
 use std::path::{
     Path,
     PathBuf,
 };
 
+use sha2::{
+    Digest,
+    Sha256,
+};
 use uuid::Uuid;
 
 use crate::error::Result;
-use crate::types::ProgressStatus;
+use crate::types::{
+    FileState,
+    ProgressStatus,
+};
 
 /// Create a context directory based on persistence setting
 ///
@@ -88,6 +96,87 @@ where
     Ok(file_count)
 }
 
+/// Whether a path is hidden, i.e. its file name starts with a dot
+pub fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|s| s.starts_with('.'))
+}
+
+/// Collect the files to index in a directory, skipping hidden files
+///
+/// # Arguments
+///
+/// * `dir_path` - Path to the directory
+///
+/// # Returns
+///
+/// The paths of the files, in walk order
+pub fn collect_files(dir_path: &Path) -> Vec<PathBuf> {
+    walkdir::WalkDir::new(dir_path)
+        .follow_links(true)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && !is_hidden(e.path()))
+        .map(|e| e.into_path())
+        .collect()
+}
+
+/// Get the modification time and size of a file, leaving its hash empty
+///
+/// # Arguments
+///
+/// * `path` - Path to the file
+///
+/// # Returns
+///
+/// The state of the file without its hash
+pub fn file_metadata(path: &Path) -> Result<FileState> {
+    let metadata = fs::metadata(path)?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_millis() as u64);
+    Ok(FileState {
+        hash: String::new(),
+        modified,
+        size: metadata.len(),
+    })
+}
+
+/// Get the state of a file, hashing its content
+///
+/// # Arguments
+///
+/// * `path` - Path to the file
+///
+/// # Returns
+///
+/// The state of the file
+pub fn file_state(path: &Path) -> Result<FileState> {
+    let mut state = file_metadata(path)?;
+    state.hash = hash_file(path)?;
+    Ok(state)
+}
+
+/// Hash the content of a file with SHA-256
+///
+/// # Arguments
+///
+/// * `path` - Path to the file
+///
+/// # Returns
+///
+/// The hash as a hex string
+pub fn hash_file(path: &Path) -> Result<String> {
+    let digest = Sha256::digest(fs::read(path)?);
+    Ok(digest.iter().fold(String::new(), |mut hex, b| {
+        let _ = write!(hex, "{b:02x}");
+        hex
+    }))
+}
+
 /// Save JSON data to a file
 ///
 /// # Arguments
@@ -0,0 +1,152 @@
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::sync::mpsc::{
+    self,
+    RecvTimeoutError,
+};
+use std::sync::{
+    Arc,
+    Mutex,
+};
+use std::thread;
+use std::time::Duration;
+
+use notify::{
+    RecommendedWatcher,
+    RecursiveMode,
+    Watcher,
+};
+
+use crate::client::SemanticSearchClient;
+use crate::error::{
+    Result,
+    SemanticSearchError,
+};
+
+/// Keeps a persistent context up to date with its source path in the background
+///
+/// File system events are collected until none has been received for the debounce delay, and the
+/// context is then updated with [`SemanticSearchClient::update_context`]. Watching stops when the
+/// watcher is dropped or the context is removed.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::sync::{
+///     Arc,
+///     Mutex,
+/// };
+/// use std::time::Duration;
+///
+/// use semantic_search_client::{
+///     ContextWatcher,
+///     ProgressStatus,
+///     SemanticSearchClient,
+/// };
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let mut client = SemanticSearchClient::new_with_default_dir()?;
+/// let id = client.add_context_from_path(
+///     "./docs",
+///     "Docs",
+///     "Project docs",
+///     true,
+///     None::<fn(ProgressStatus)>,
+/// )?;
+/// let client = Arc::new(Mutex::new(client));
+/// let _watcher = ContextWatcher::new(Arc::clone(&client), &id, Duration::from_secs(2))?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ContextWatcher {
+    /// Dropping the file system watcher closes the event channel, which stops the update thread
+    _watcher: RecommendedWatcher,
+    /// Path being watched
+    source_path: PathBuf,
+}
+
+impl ContextWatcher {
+    /// Start watching the source path of a persistent context
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - Client holding the context
+    /// * `context_id` - ID of the context to keep up to date
+    /// * `debounce` - How long to wait for changes to settle before updating the context
+    ///
+    /// # Returns
+    ///
+    /// A watcher that updates the context until it is dropped
+    pub fn new(client: Arc<Mutex<SemanticSearchClient>>, context_id: &str, debounce: Duration) -> Result<Self> {
+        let source_path = {
+            let client = client.lock().map_err(|e| {
+                SemanticSearchError::OperationFailed(format!("Failed to acquire lock on client: {}", e))
+            })?;
+            client
+                .get_contexts()
+                .into_iter()
+                .find(|c| c.id == context_id)
+                .ok_or_else(|| SemanticSearchError::ContextNotFound(context_id.to_string()))?
+                .source_path
+                .map(PathBuf::from)
+                .ok_or_else(|| {
+                    SemanticSearchError::InvalidArgument(format!("Context {} was not indexed from a path", context_id))
+                })?
+        };
+
+        let (tx, rx) = mpsc::channel::<notify::Result<notify::Event>>();
+        let mut watcher = notify::recommended_watcher(tx).map_err(|e| {
+            SemanticSearchError::OperationFailed(format!("Failed to create file system watcher: {}", e))
+        })?;
+        watcher.watch(&source_path, RecursiveMode::Recursive).map_err(|e| {
+            SemanticSearchError::OperationFailed(format!("Failed to watch {}: {}", source_path.display(), e))
+        })?;
+
+        let context_id = context_id.to_string();
+        // Reading files while updating the context raises access events, which must not trigger
+        // another update
+        let is_change = |event: &notify::Result<notify::Event>| !matches!(event, Ok(event) if event.kind.is_access());
+        thread::spawn(move || {
+            loop {
+                match rx.recv() {
+                    Ok(event) if is_change(&event) => {},
+                    Ok(_) => continue,
+                    Err(_) => return,
+                }
+
+                // Wait for the changes to settle
+                loop {
+                    match rx.recv_timeout(debounce) {
+                        Ok(_) => continue,
+                        Err(RecvTimeoutError::Timeout) => break,
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
+                }
+
+                let Ok(mut client) = client.lock() else {
+                    return;
+                };
+                match client.update_context(&context_id) {
+                    Ok(summary) if summary.has_changes() => {
+                        tracing::info!("Updated context {}: {:?}", context_id, summary);
+                    },
+                    Ok(_) => {},
+                    Err(SemanticSearchError::ContextNotFound(_)) => return,
+                    Err(e) => tracing::warn!("Failed to update context {}: {}", context_id, e),
+                }
+            }
+        });
+
+        Ok(Self {
+            _watcher: watcher,
+            source_path,
+        })
+    }
+
+    /// Get the path being watched
+    pub fn source_path(&self) -> &Path {
+        &self.source_path
+    }
+}
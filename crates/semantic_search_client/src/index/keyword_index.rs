@@ -0,0 +1,145 @@
+use bm25::{
+    Embedder,
+    EmbedderBuilder,
+    Language,
+    LanguageMode,
+    Scorer,
+};
+
+/// Keyword index ranking texts by BM25 score
+///
+/// Used alongside the [`VectorIndex`](super::VectorIndex) for hybrid search, so that queries for
+/// exact terms such as symbol names match even when their embedding does not.
+pub struct KeywordIndex {
+    /// Turns texts into sparse term vectors, fit to the average length of the indexed texts
+    embedder: Embedder,
+    /// Scores the indexed texts against a query
+    scorer: Scorer<usize>,
+}
+
+impl KeywordIndex {
+    /// Create a keyword index of the given texts
+    ///
+    /// # Arguments
+    ///
+    /// * `texts` - The texts to index, with their IDs
+    ///
+    /// # Returns
+    ///
+    /// A new KeywordIndex instance
+    pub fn new<'a>(texts: impl IntoIterator<Item = (usize, &'a str)>) -> Self {
+        let texts: Vec<_> = texts
+            .into_iter()
+            .map(|(id, text)| (id, expand_identifiers(text)))
+            .collect();
+        let corpus: Vec<&str> = texts.iter().map(|(_, text)| text.as_str()).collect();
+        let embedder = EmbedderBuilder::with_fit_to_corpus(LanguageMode::Fixed(Language::English), &corpus).build();
+
+        let mut scorer = Scorer::new();
+        for (id, text) in &texts {
+            scorer.upsert(id, embedder.embed(text));
+        }
+
+        Self { embedder, scorer }
+    }
+
+    /// Insert a text into the index
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The text to insert
+    /// * `id` - The ID associated with the text
+    pub fn insert(&mut self, text: &str, id: usize) {
+        self.scorer.upsert(&id, self.embedder.embed(&expand_identifiers(text)));
+    }
+
+    /// Remove a text from the index
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID associated with the text
+    pub fn remove(&mut self, id: usize) {
+        self.scorer.remove(&id);
+    }
+
+    /// Search for the texts matching the terms of a query
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The query text
+    ///
+    /// # Returns
+    ///
+    /// A vector of (id, score) pairs for every text sharing a term with the query, best first
+    pub fn search(&self, query: &str) -> Vec<(usize, f32)> {
+        self.scorer
+            .matches(&self.embedder.embed(&expand_identifiers(query)))
+            .into_iter()
+            .map(|doc| (doc.id, doc.score))
+            .collect()
+    }
+}
+
+/// Append the words of identifiers such as `parse_retry_after` or `RetryPolicy` to a text, so that
+/// both the identifier and its words match
+fn expand_identifiers(text: &str) -> String {
+    let mut expanded = text.to_string();
+    for word in text.split(|c: char| !(c.is_alphanumeric() || c == '_')) {
+        let parts = split_identifier(word);
+        if parts.len() > 1 {
+            for part in parts {
+                expanded.push(' ');
+                expanded.push_str(part);
+            }
+        }
+    }
+    expanded
+}
+
+/// Split an identifier at underscores and at lowercase to uppercase transitions
+fn split_identifier(word: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    for segment in word.split('_').filter(|s| !s.is_empty()) {
+        let mut start = 0;
+        let mut prev_lowercase = false;
+        for (i, c) in segment.char_indices() {
+            if c.is_uppercase() && prev_lowercase {
+                parts.push(&segment[start..i]);
+                start = i;
+            }
+            prev_lowercase = c.is_lowercase() || c.is_ascii_digit();
+        }
+        parts.push(&segment[start..]);
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_identifier() {
+        assert_eq!(split_identifier("parse_retry_after"), vec!["parse", "retry", "after"]);
+        assert_eq!(split_identifier("RetryPolicy"), vec!["Retry", "Policy"]);
+        assert_eq!(split_identifier("HTTPServer2Go"), vec!["HTTPServer2", "Go"]);
+        assert_eq!(split_identifier("plain"), vec!["plain"]);
+    }
+
+    #[test]
+    fn test_search() {
+        let mut index = KeywordIndex::new([
+            (0, "The retry policy backs off exponentially."),
+            (1, "fn parse_retry_after(value: &str) -> Option<Duration>"),
+            (2, "Boil the pasta in salted water."),
+        ]);
+        index.insert("struct RetryPolicy { max_attempts: u32 }", 3);
+        index.remove(2);
+
+        let ids = |query| index.search(query).into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+        assert_eq!(ids("parse_retry_after").first(), Some(&1));
+        assert_eq!(ids("RetryPolicy").first(), Some(&3));
+        assert!(ids("pasta").is_empty());
+        assert!(ids("unrelated").is_empty());
+    }
+}
@@ -1,3 +1,5 @@
+mod keyword_index;
 mod vector_index;
 
+pub use keyword_index::KeywordIndex;
 pub use vector_index::VectorIndex;
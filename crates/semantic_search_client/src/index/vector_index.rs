@@ -1,14 +1,30 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use hnsw_rs::api::AnnT;
 use hnsw_rs::hnsw::Hnsw;
+use hnsw_rs::hnswio::HnswIo;
 use hnsw_rs::prelude::DistCosine;
 use tracing::{
     debug,
     info,
 };
 
+use crate::error::{
+    Result,
+    SemanticSearchError,
+};
+
 /// Vector index for fast approximate nearest neighbor search
+///
+/// Vectors cannot be removed from the HNSW graph, so deleted vectors are tombstoned: they are
+/// skipped by searches until the index is rebuilt without them.
 pub struct VectorIndex {
     /// The HNSW index
     index: Hnsw<'static, f32, DistCosine>,
+    /// IDs of the deleted vectors
+    deleted: HashSet<usize>,
 }
 
 impl VectorIndex {
@@ -33,7 +49,54 @@ impl VectorIndex {
         );
 
         debug!("Vector index created successfully");
-        Self { index }
+        Self {
+            index,
+            deleted: HashSet::new(),
+        }
+    }
+
+    /// Load an index saved with [`VectorIndex::save`]
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Directory the index was saved to
+    /// * `basename` - Base name of the saved files
+    ///
+    /// # Returns
+    ///
+    /// The loaded index, without tombstones
+    pub fn load(dir: &Path, basename: &str) -> Result<Self> {
+        // The loaded graph borrows from its loader for its whole lifetime. Since the vectors are
+        // read into memory rather than memory mapped, the loader only holds the file names and
+        // load options, so it is leaked (a few hundred bytes per load) rather than kept alongside
+        // the graph in a self-referencing struct.
+        let loader: &'static mut HnswIo = Box::leak(Box::new(HnswIo::new(dir, basename)));
+        let index = loader
+            .load_hnsw::<f32, DistCosine>()
+            .map_err(|e| SemanticSearchError::OperationFailed(format!("Failed to load vector index: {}", e)))?;
+
+        debug!("Loaded vector index with {} vectors", index.get_nb_point());
+        Ok(Self {
+            index,
+            deleted: HashSet::new(),
+        })
+    }
+
+    /// Save the graph and vectors of the index, as `<basename>.hnsw.graph` and
+    /// `<basename>.hnsw.data`
+    ///
+    /// Tombstones are not saved, and must be restored with [`VectorIndex::delete`] after loading.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Directory to save the index to
+    /// * `basename` - Base name of the saved files
+    pub fn save(&self, dir: &Path, basename: &str) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        self.index
+            .file_dump(dir, basename)
+            .map_err(|e| SemanticSearchError::OperationFailed(format!("Failed to save vector index: {}", e)))?;
+        Ok(())
     }
 
     /// Insert a vector into the index
@@ -46,6 +109,29 @@ impl VectorIndex {
         self.index.insert((vector, id));
     }
 
+    /// Delete a vector from the index
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID associated with the vector
+    ///
+    /// # Returns
+    ///
+    /// `true` if the vector was not already deleted
+    pub fn delete(&mut self, id: usize) -> bool {
+        self.deleted.insert(id)
+    }
+
+    /// Check if a vector was deleted
+    pub fn is_deleted(&self, id: usize) -> bool {
+        self.deleted.contains(&id)
+    }
+
+    /// Get the IDs of the deleted vectors, in no particular order
+    pub fn deleted_ids(&self) -> impl Iterator<Item = usize> + '_ {
+        self.deleted.iter().copied()
+    }
+
     /// Search for nearest neighbors
     ///
     /// # Arguments
@@ -56,9 +142,14 @@ impl VectorIndex {
     ///
     /// # Returns
     ///
-    /// A vector of (id, distance) pairs
+    /// A vector of (id, distance) pairs, excluding deleted vectors
     pub fn search(&self, query: &[f32], limit: usize, ef_search: usize) -> Vec<(usize, f32)> {
-        let results = self.index.search(query, limit, ef_search);
+        let results = if self.deleted.is_empty() {
+            self.index.search(query, limit, ef_search)
+        } else {
+            let is_live = |id: &usize| !self.deleted.contains(id);
+            self.index.search_filter(query, limit, ef_search, Some(&is_live))
+        };
 
         results
             .into_iter()
@@ -66,15 +157,23 @@ impl VectorIndex {
             .collect()
     }
 
-    /// Get the number of elements in the index
+    /// Get the number of vectors in the index, excluding deleted vectors
     ///
     /// # Returns
     ///
     /// The number of elements in the index
     pub fn len(&self) -> usize {
-        // Since HNSW doesn't provide a direct way to get the count,
-        // we'll use a simple counter that's updated when items are inserted
-        self.index.get_ef_construction()
+        self.total_len() - self.deleted.len()
+    }
+
+    /// Get the number of vectors in the graph, including deleted vectors
+    pub fn total_len(&self) -> usize {
+        self.index.get_nb_point()
+    }
+
+    /// Get the number of deleted vectors still in the graph
+    pub fn deleted_len(&self) -> usize {
+        self.deleted.len()
     }
 
     /// Check if the index is empty
@@ -83,7 +182,6 @@ impl VectorIndex {
     ///
     /// `true` if the index is empty, `false` otherwise
     pub fn is_empty(&self) -> bool {
-        // For simplicity, we'll assume it's empty if ef_construction is at default value
-        self.index.get_ef_construction() == 100
+        self.len() == 0
     }
 }
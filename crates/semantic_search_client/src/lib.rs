@@ -22,7 +22,10 @@ pub mod types;
 /// Text embedding functionality
 pub mod embedding;
 
-pub use client::SemanticSearchClient;
+pub use client::{
+    ContextWatcher,
+    SemanticSearchClient,
+};
 pub use config::SemanticSearchConfig;
 pub use error::{
     Result,
@@ -31,7 +34,12 @@ pub use error::{
 pub use types::{
     DataPoint,
     FileType,
+    IndexStats,
     MemoryContext,
     ProgressStatus,
+    SearchFilter,
+    SearchMode,
+    SearchOptions,
     SearchResult,
+    UpdateSummary,
 };
@@ -7,7 +7,7 @@ use crate::error::{
     Result,
     SemanticSearchError,
 };
-use crate::processing::text_chunker::chunk_text;
+use crate::processing::structured_chunker::chunk_file;
 use crate::types::FileType;
 
 /// Determine the file type based on extension
@@ -65,10 +65,16 @@ pub fn process_file(path: &Path) -> Result<Vec<Value>> {
     })?;
 
     match file_type {
-        FileType::Text | FileType::Markdown | FileType::Code => {
-            // For text-based files, chunk the content and create multiple data points
-            // Use the configured chunk size and overlap
-            let chunks = chunk_text(&content, None, None);
+        FileType::Text | FileType::Markdown | FileType::Code | FileType::Json => {
+            // Make sure JSON files are valid before splitting them at their top-level entries
+            if file_type == FileType::Json {
+                serde_json::from_str::<Value>(&content)
+                    .map_err(|e| SemanticSearchError::SerializationError(e.to_string()))?;
+            }
+
+            // For text-based files, chunk the content following its structure and create multiple
+            // data points. Use the configured chunk size and overlap
+            let chunks = chunk_file(&content, file_type, None, None);
             let path_str = path.to_string_lossy().to_string();
             let file_type_str = format!("{:?}", file_type);
 
@@ -76,11 +82,16 @@ pub fn process_file(path: &Path) -> Result<Vec<Value>> {
 
             for (i, chunk) in chunks.iter().enumerate() {
                 let mut metadata = serde_json::Map::new();
-                metadata.insert("text".to_string(), Value::String(chunk.clone()));
+                metadata.insert("text".to_string(), Value::String(chunk.text.clone()));
                 metadata.insert("path".to_string(), Value::String(path_str.clone()));
                 metadata.insert("file_type".to_string(), Value::String(file_type_str.clone()));
                 metadata.insert("chunk_index".to_string(), Value::Number((i as u64).into()));
                 metadata.insert("total_chunks".to_string(), Value::Number((chunks.len() as u64).into()));
+                metadata.insert(
+                    "start_line".to_string(),
+                    Value::Number((chunk.start_line as u64).into()),
+                );
+                metadata.insert("end_line".to_string(), Value::Number((chunk.end_line as u64).into()));
 
                 // For code files, add additional metadata
                 if file_type == FileType::Code {
@@ -112,22 +123,6 @@ pub fn process_file(path: &Path) -> Result<Vec<Value>> {
 
             Ok(results)
         },
-        FileType::Json => {
-            // For JSON files, parse the content
-            let json: Value =
-                serde_json::from_str(&content).map_err(|e| SemanticSearchError::SerializationError(e.to_string()))?;
-
-            match json {
-                Value::Array(items) => {
-                    // If it's an array, return each item
-                    Ok(items)
-                },
-                _ => {
-                    // Otherwise, return the whole object
-                    Ok(vec![json])
-                },
-            }
-        },
         FileType::Unknown => {
             // For unknown file types, just store the path
             let mut metadata = serde_json::Map::new();
@@ -1,5 +1,7 @@
 /// File processing utilities for handling different file types and extracting content
 pub mod file_processor;
+/// Structure-aware chunking that keeps code definitions, Markdown sections and JSON entries whole
+pub mod structured_chunker;
 /// Text chunking utilities for breaking down text into manageable pieces for embedding
 pub mod text_chunker;
 
@@ -8,4 +10,8 @@ pub use file_processor::{
     process_directory,
     process_file,
 };
+pub use structured_chunker::{
+    Chunk,
+    chunk_file,
+};
 pub use text_chunker::chunk_text;
@@ -0,0 +1,445 @@
+use std::ops::Range;
+
+use crate::config;
+use crate::types::FileType;
+
+/// A chunk of a file along with the lines it spans
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    /// The text of the chunk
+    pub text: String,
+    /// First line of the chunk, starting at 1
+    pub start_line: usize,
+    /// Last line of the chunk, inclusive
+    pub end_line: usize,
+}
+
+/// Chunk the content of a file following its structure
+///
+/// Code is split at definition boundaries, found from the indentation of the lines, Markdown at
+/// headings and JSON at top-level keys or array elements. Chunks are only split further when they
+/// are larger than `chunk_size`. Other files are split into overlapping word windows like
+/// [chunk_text](super::chunk_text).
+///
+/// # Arguments
+///
+/// * `content` - The content of the file
+/// * `file_type` - The type of the file
+/// * `chunk_size` - Optional chunk size in words (if None, uses config value)
+/// * `overlap` - Optional overlap in words for plain text (if None, uses config value)
+///
+/// # Returns
+///
+/// A vector of chunks, in the order they appear in the file
+pub fn chunk_file(content: &str, file_type: FileType, chunk_size: Option<usize>, overlap: Option<usize>) -> Vec<Chunk> {
+    let chunk_size = chunk_size.unwrap_or_else(|| config::get_config().chunk_size).max(1);
+    let overlap = overlap
+        .unwrap_or_else(|| config::get_config().chunk_overlap)
+        .min(chunk_size - 1);
+
+    let lines: Vec<&str> = content.lines().collect();
+    match file_type {
+        FileType::Code => {
+            let units = code_units(&lines, 0..lines.len());
+            merge_units(&lines, units, chunk_size, split_code_unit)
+        },
+        FileType::Markdown => chunk_markdown(&lines, chunk_size),
+        FileType::Json => {
+            let units = json_units(content, lines.len());
+            merge_units(&lines, units, chunk_size, split_lines)
+        },
+        FileType::Text | FileType::Unknown => chunk_words(&lines, chunk_size, overlap),
+    }
+}
+
+/// Splits a range of code lines into blocks that start at the lines with the smallest
+/// indentation, such as top-level definitions. Comments, attributes and decorators are kept with
+/// the definition that follows them.
+fn code_units(lines: &[&str], range: Range<usize>) -> Vec<Range<usize>> {
+    let Some(indent) = range
+        .clone()
+        .filter(|&i| !is_blank(lines[i]))
+        .map(|i| indentation(lines[i]))
+        .min()
+    else {
+        return Vec::new();
+    };
+
+    let mut units = Vec::new();
+    let mut start = range.start;
+    let mut after_prefix = false;
+    for i in range.clone() {
+        let line = lines[i];
+        if is_blank(line) || indentation(line) != indent || is_closing(line.trim_start()) {
+            continue;
+        }
+        if i > start && !after_prefix {
+            units.push(start..i);
+            start = i;
+        }
+        after_prefix = is_prefix(line.trim_start());
+    }
+    units.push(start..range.end);
+    units
+}
+
+/// Splits a code block that is too large at the definitions of its body, e.g. the methods of a
+/// class. The first line of the block stays with the first definition.
+fn split_code_unit(lines: &[&str], unit: Range<usize>, chunk_size: usize) -> Vec<Chunk> {
+    let mut units = code_units(lines, unit.start + 1..unit.end);
+    if units.len() <= 1 {
+        return split_lines(lines, unit, chunk_size);
+    }
+    units[0].start = unit.start;
+    merge_units(lines, units, chunk_size, split_code_unit)
+}
+
+/// Splits Markdown into the sections under each heading. Sections that only hold a heading are
+/// kept with the following section, and sections that are too large are split at paragraphs.
+fn chunk_markdown(lines: &[&str], chunk_size: usize) -> Vec<Chunk> {
+    let mut sections: Vec<Range<usize>> = Vec::new();
+    let mut start = 0;
+    let mut in_fence = false;
+    for (i, line) in lines.iter().enumerate() {
+        if is_fence(line) {
+            in_fence = !in_fence;
+        } else if !in_fence && is_heading(line) && i > start {
+            let heading_only = is_heading(lines[start]) && lines[start + 1..i].iter().all(|l| is_blank(l));
+            if !heading_only {
+                sections.push(start..i);
+                start = i;
+            }
+        }
+    }
+    sections.push(start..lines.len());
+
+    let mut chunks = Vec::new();
+    for section in sections {
+        let Some(section) = trim_blank_lines(lines, section) else {
+            continue;
+        };
+        if word_count(lines, section.clone()) <= chunk_size {
+            chunks.push(chunk(lines, section));
+        } else {
+            let paragraphs = markdown_paragraphs(lines, section);
+            chunks.extend(merge_units(lines, paragraphs, chunk_size, split_lines));
+        }
+    }
+    chunks
+}
+
+/// Splits a range of Markdown lines at blank lines, keeping fenced code blocks whole.
+fn markdown_paragraphs(lines: &[&str], range: Range<usize>) -> Vec<Range<usize>> {
+    let mut paragraphs = Vec::new();
+    let mut start = range.start;
+    let mut in_fence = false;
+    for i in range.clone() {
+        if is_fence(lines[i]) {
+            in_fence = !in_fence;
+        } else if !in_fence && is_blank(lines[i]) && i > start {
+            paragraphs.push(start..i);
+            start = i;
+        }
+    }
+    paragraphs.push(start..range.end);
+    paragraphs
+}
+
+/// Splits JSON at the top-level keys of an object or the elements of an array, returning
+/// the whole document if it is neither.
+fn json_units(content: &str, line_count: usize) -> Vec<Range<usize>> {
+    let mut starts: Vec<usize> = Vec::new();
+    let mut end = line_count;
+    let mut line = 0;
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut expect_entry = false;
+    for byte in content.bytes() {
+        if byte == b'\n' {
+            line += 1;
+            continue;
+        }
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {},
+            }
+            continue;
+        }
+        if byte.is_ascii_whitespace() {
+            continue;
+        }
+        if expect_entry && depth == 1 && !matches!(byte, b'}' | b']') {
+            if starts.last() != Some(&line) {
+                starts.push(line);
+            }
+            expect_entry = false;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'{' | b'[' => {
+                depth += 1;
+                if depth == 1 {
+                    expect_entry = true;
+                }
+            },
+            b'}' | b']' => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    end = line;
+                    break;
+                }
+            },
+            b',' if depth == 1 => expect_entry = true,
+            _ => {},
+        }
+    }
+
+    if starts.is_empty() {
+        return std::iter::once(0..line_count).collect();
+    }
+    let mut units: Vec<Range<usize>> = starts.windows(2).map(|w| w[0]..w[1]).collect();
+    let last = *starts.last().unwrap();
+    units.push(last..end.max(last + 1).min(line_count));
+    // Keep the opening bracket with the first entry.
+    units[0].start = 0;
+    units
+}
+
+/// Splits plain text into overlapping windows of `chunk_size` words.
+fn chunk_words(lines: &[&str], chunk_size: usize, overlap: usize) -> Vec<Chunk> {
+    let words: Vec<(&str, usize)> = lines
+        .iter()
+        .enumerate()
+        .flat_map(|(i, line)| line.split_whitespace().map(move |word| (word, i)))
+        .collect();
+
+    let mut chunks = Vec::new();
+    let mut i = 0;
+    while i < words.len() {
+        let window = &words[i..(i + chunk_size).min(words.len())];
+        chunks.push(Chunk {
+            text: window.iter().map(|(word, _)| *word).collect::<Vec<_>>().join(" "),
+            start_line: window[0].1 + 1,
+            end_line: window[window.len() - 1].1 + 1,
+        });
+        if i + chunk_size >= words.len() {
+            break;
+        }
+        i += chunk_size - overlap;
+    }
+    chunks
+}
+
+/// Merges consecutive units into chunks of up to `chunk_size` words, splitting the units that are
+/// larger with `split`.
+fn merge_units(
+    lines: &[&str],
+    units: Vec<Range<usize>>,
+    chunk_size: usize,
+    split: fn(&[&str], Range<usize>, usize) -> Vec<Chunk>,
+) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut pending: Option<Range<usize>> = None;
+    for unit in units.into_iter().filter_map(|unit| trim_blank_lines(lines, unit)) {
+        if word_count(lines, unit.clone()) > chunk_size {
+            chunks.extend(pending.take().map(|range| chunk(lines, range)));
+            chunks.extend(split(lines, unit, chunk_size));
+            continue;
+        }
+        pending = match pending {
+            Some(range) if word_count(lines, range.start..unit.end) <= chunk_size => Some(range.start..unit.end),
+            Some(range) => {
+                chunks.push(chunk(lines, range));
+                Some(unit)
+            },
+            None => Some(unit),
+        };
+    }
+    chunks.extend(pending.map(|range| chunk(lines, range)));
+    chunks
+}
+
+/// Splits a range of lines into chunks of up to `chunk_size` words without breaking lines, unless
+/// a single line is larger than `chunk_size`.
+fn split_lines(lines: &[&str], range: Range<usize>, chunk_size: usize) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut start = range.start;
+    let mut words = 0;
+    for i in range.clone() {
+        let line_words = lines[i].split_whitespace().count();
+        if line_words > chunk_size {
+            if let Some(range) = trim_blank_lines(lines, start..i) {
+                chunks.push(chunk(lines, range));
+            }
+            chunks.extend(chunk_words(&lines[i..=i], chunk_size, 0).into_iter().map(|c| Chunk {
+                start_line: i + 1,
+                end_line: i + 1,
+                ..c
+            }));
+            start = i + 1;
+            words = 0;
+        } else if words + line_words > chunk_size {
+            if let Some(range) = trim_blank_lines(lines, start..i) {
+                chunks.push(chunk(lines, range));
+            }
+            start = i;
+            words = line_words;
+        } else {
+            words += line_words;
+        }
+    }
+    if let Some(range) = trim_blank_lines(lines, start..range.end) {
+        chunks.push(chunk(lines, range));
+    }
+    chunks
+}
+
+fn chunk(lines: &[&str], range: Range<usize>) -> Chunk {
+    Chunk {
+        text: lines[range.clone()].join("\n"),
+        start_line: range.start + 1,
+        end_line: range.end,
+    }
+}
+
+/// Removes the blank lines at both ends of `range`, returning [None] if only blank lines are left.
+fn trim_blank_lines(lines: &[&str], range: Range<usize>) -> Option<Range<usize>> {
+    let start = range.clone().find(|&i| !is_blank(lines[i]))?;
+    let end = range.rev().find(|&i| !is_blank(lines[i]))? + 1;
+    Some(start..end)
+}
+
+fn word_count(lines: &[&str], range: Range<usize>) -> usize {
+    lines[range].iter().map(|line| line.split_whitespace().count()).sum()
+}
+
+fn is_blank(line: &str) -> bool {
+    line.trim().is_empty()
+}
+
+fn indentation(line: &str) -> usize {
+    line.chars()
+        .take_while(|c| c.is_whitespace())
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum()
+}
+
+/// Whether the line closes a block, like `}` or `end`, rather than starting a new one.
+fn is_closing(line: &str) -> bool {
+    line.starts_with(['}', ')', ']'])
+        || line.starts_with("</")
+        || ["end", "fi", "done", "esac"].iter().any(|keyword| {
+            line.strip_prefix(keyword)
+                .is_some_and(|rest| !rest.starts_with(is_word_char))
+        })
+}
+
+/// Whether the line belongs to the definition that follows it, like comments and attributes.
+fn is_prefix(line: &str) -> bool {
+    ["//", "#", "/*", "*", "@", "--", "<!--"]
+        .iter()
+        .any(|prefix| line.starts_with(prefix))
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn is_heading(line: &str) -> bool {
+    let trimmed = line.trim_start_matches(' ');
+    let level = trimmed.chars().take_while(|&c| c == '#').count();
+    line.len() - trimmed.len() < 4
+        && (1..=6).contains(&level)
+        && trimmed[level..].chars().next().is_none_or(char::is_whitespace)
+}
+
+fn is_fence(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    trimmed.starts_with("```") || trimmed.starts_with("~~~")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(chunks: &[Chunk]) -> Vec<&str> {
+        chunks.iter().map(|chunk| chunk.text.as_str()).collect()
+    }
+
+    fn lines(chunks: &[Chunk]) -> Vec<(usize, usize)> {
+        chunks.iter().map(|chunk| (chunk.start_line, chunk.end_line)).collect()
+    }
+
+    #[test]
+    fn test_chunk_code_at_definitions() {
+        let code = "use std::fmt;\n\n/// Adds numbers\n#[inline]\nfn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n\nfn sub(a: i32, b: i32) -> i32 {\n    a - b\n}\n";
+
+        // The functions do not fit in one chunk together, the import is kept with the first one.
+        let chunks = chunk_file(code, FileType::Code, Some(20), Some(0));
+        assert_eq!(texts(&chunks), vec![
+            "use std::fmt;\n\n/// Adds numbers\n#[inline]\nfn add(a: i32, b: i32) -> i32 {\n    a + b\n}",
+            "fn sub(a: i32, b: i32) -> i32 {\n    a - b\n}",
+        ]);
+        assert_eq!(lines(&chunks), vec![(1, 7), (9, 11)]);
+
+        let chunks = chunk_file(code, FileType::Code, Some(100), Some(0));
+        assert_eq!(lines(&chunks), vec![(1, 11)]);
+    }
+
+    #[test]
+    fn test_chunk_large_class_at_methods() {
+        let code = "class Greeter:\n    def __init__(self, name):\n        self.name = name\n\n    def greet(self):\n        print('hello', self.name)\n\n    @property\n    def upper(self):\n        return self.name.upper()\n";
+        let chunks = chunk_file(code, FileType::Code, Some(8), Some(0));
+        assert_eq!(lines(&chunks), vec![(1, 3), (5, 6), (8, 10)]);
+        assert!(chunks[2].text.starts_with("    @property"));
+    }
+
+    #[test]
+    fn test_chunk_markdown_at_headings() {
+        let markdown =
+            "# Title\n\n## Install\n\nRun the installer.\n\n```sh\n# not a heading\n```\n\n## Usage\n\nRun it.\n";
+        let chunks = chunk_file(markdown, FileType::Markdown, Some(100), Some(0));
+        assert_eq!(texts(&chunks), vec![
+            "# Title\n\n## Install\n\nRun the installer.\n\n```sh\n# not a heading\n```",
+            "## Usage\n\nRun it.",
+        ]);
+        assert_eq!(lines(&chunks), vec![(1, 9), (11, 13)]);
+    }
+
+    #[test]
+    fn test_chunk_json_at_top_level_keys() {
+        let json = "{\n  \"name\": \"app\",\n  \"scripts\": {\n    \"build\": \"cargo build\",\n    \"test\": \"cargo test\"\n  },\n  \"private\": true\n}\n";
+        let chunks = chunk_file(json, FileType::Json, Some(10), Some(0));
+        assert_eq!(lines(&chunks), vec![(1, 2), (3, 6), (7, 7)]);
+        assert!(chunks[1].text.contains("cargo test"));
+
+        let array = "[\n  {\"id\": 1},\n  {\"id\": 2}\n]";
+        let chunks = chunk_file(array, FileType::Json, Some(3), Some(0));
+        assert_eq!(lines(&chunks), vec![(1, 2), (3, 3)]);
+    }
+
+    #[test]
+    fn test_chunk_text_tracks_lines() {
+        let text = "one two three\nfour five\n\nsix seven eight nine";
+        let chunks = chunk_file(text, FileType::Text, Some(4), Some(1));
+        assert_eq!(texts(&chunks), vec![
+            "one two three four",
+            "four five six seven",
+            "seven eight nine"
+        ]);
+        assert_eq!(lines(&chunks), vec![(1, 2), (2, 4), (4, 4)]);
+        assert!(chunk_file("", FileType::Text, Some(4), Some(1)).is_empty());
+    }
+
+    #[test]
+    fn test_split_long_lines() {
+        let minified = "a b c d e f g";
+        let chunks = chunk_file(minified, FileType::Code, Some(3), Some(0));
+        assert_eq!(texts(&chunks), vec!["a b c", "d e f", "g"]);
+        assert_eq!(lines(&chunks), vec![(1, 1), (1, 1), (1, 1)]);
+    }
+}
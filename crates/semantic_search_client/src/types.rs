@@ -50,6 +50,29 @@ pub struct MemoryContext {
 
     /// Number of items in the context
     pub item_count: usize,
+
+    /// Statistics of the vector index, if the context has been saved since they were introduced
+    #[serde(default)]
+    pub index_stats: Option<IndexStats>,
+}
+
+/// Statistics of the vector index of a context
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexStats {
+    /// Number of vectors returned by searches
+    pub live_vectors: usize,
+
+    /// Number of deleted vectors still in the graph, until the next compaction
+    pub deleted_vectors: usize,
+
+    /// Number of times the index was compacted
+    pub compactions: usize,
+
+    /// When the index was last compacted
+    pub last_compacted_at: Option<DateTime<Utc>>,
+
+    /// Size of the saved graph and vectors, in bytes
+    pub saved_size: u64,
 }
 
 impl MemoryContext {
@@ -72,10 +95,47 @@ impl MemoryContext {
             source_path,
             persistent,
             item_count,
+            index_stats: None,
         }
     }
 }
 
+/// State of an indexed file, used to find the files that changed since they were indexed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileState {
+    /// SHA-256 of the file content, as hex
+    pub hash: String,
+
+    /// Last modification time, in milliseconds since the Unix epoch
+    pub modified: u64,
+
+    /// Size of the file in bytes
+    pub size: u64,
+}
+
+/// Summary of an incremental update of a context
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UpdateSummary {
+    /// Files that were not indexed before
+    pub added: usize,
+
+    /// Files whose content changed since they were indexed
+    pub modified: usize,
+
+    /// Indexed files that no longer exist
+    pub removed: usize,
+
+    /// Files left as they were
+    pub unchanged: usize,
+}
+
+impl UpdateSummary {
+    /// Whether any file was added, modified or removed
+    pub fn has_changes(&self) -> bool {
+        self.added + self.modified + self.removed > 0
+    }
+}
+
 /// A data point in the semantic index
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DataPoint {
@@ -105,10 +165,90 @@ impl SearchResult {
         Self { point, distance }
     }
 
+    /// Get the relevance score of this result, between 0 and 1 (higher is better)
+    ///
+    /// This is `1 - distance`. See [`SearchOptions::mode`] for how it is computed for each mode.
+    pub fn score(&self) -> f32 {
+        1.0 - self.distance
+    }
+
     /// Get the text content of this result
     pub fn text(&self) -> Option<&str> {
         self.point.payload.get("text").and_then(|v| v.as_str())
     }
+
+    /// Get the lines of the file this result was chunked from, starting at 1
+    pub fn line_range(&self) -> Option<(usize, usize)> {
+        let line = |key: &str| self.point.payload.get(key).and_then(|v| v.as_u64()).map(|v| v as usize);
+        Some((line("start_line")?, line("end_line")?))
+    }
+
+    /// Get the location of this result as `path:line` or `path:start-end`, if it came from a file
+    pub fn location(&self) -> Option<String> {
+        let path = self.point.payload.get("path").and_then(|v| v.as_str())?;
+        Some(match self.line_range() {
+            Some((start, end)) if start == end => format!("{path}:{start}"),
+            Some((start, end)) => format!("{path}:{start}-{end}"),
+            None => path.to_string(),
+        })
+    }
+}
+
+/// How search results are ranked
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchMode {
+    /// Rank by similarity of the embeddings, scored by cosine similarity
+    Vector,
+    /// Rank by BM25 score of the query terms, scored relative to the best match
+    Keyword,
+    /// Fuse the vector and keyword rankings with reciprocal rank fusion, scored relative to a
+    /// result ranked first by both
+    #[default]
+    Hybrid,
+}
+
+/// Filters on the metadata of search results
+#[derive(Debug, Clone, Default)]
+pub struct SearchFilter {
+    /// Glob the path of the indexed file must match. Relative globs such as `src/**/*.rs` match
+    /// anywhere in the path
+    pub path_glob: Option<String>,
+
+    /// Type of the indexed file
+    pub file_type: Option<FileType>,
+
+    /// Only match files last modified at or after this time
+    pub modified_after: Option<DateTime<Utc>>,
+
+    /// Only match files last modified before this time
+    pub modified_before: Option<DateTime<Utc>>,
+}
+
+impl SearchFilter {
+    /// Whether the filter matches every data point
+    pub fn is_empty(&self) -> bool {
+        self.path_glob.is_none()
+            && self.file_type.is_none()
+            && self.modified_after.is_none()
+            && self.modified_before.is_none()
+    }
+}
+
+/// Options for searching contexts
+#[derive(Debug, Clone, Default)]
+pub struct SearchOptions {
+    /// Maximum number of results to return per context (if None, uses default_results from
+    /// config)
+    pub limit: Option<usize>,
+
+    /// How results are ranked
+    pub mode: SearchMode,
+
+    /// Filters on the metadata of the results
+    pub filter: SearchFilter,
+
+    /// Minimum [`SearchResult::score`] of the results
+    pub min_score: Option<f32>,
 }
 
 /// File type for processing
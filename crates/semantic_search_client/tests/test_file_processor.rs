@@ -70,6 +70,32 @@ fn test_process_markdown_file() {
     fs::remove_dir_all(temp_dir).unwrap_or(());
 }
 
+#[test]
+fn test_process_code_file_line_ranges() {
+    // Create a temporary directory for the test
+    let temp_dir = env::temp_dir().join("memory_bank_test_process_code");
+    fs::create_dir_all(&temp_dir).unwrap();
+
+    // Initialize config
+    config::init_config(&temp_dir).unwrap();
+
+    // Create a test code file
+    let test_file = temp_dir.join("test.py");
+    fs::write(&test_file, "import os\n\n\ndef main():\n    print(os.getcwd())\n").unwrap();
+
+    // Process the file
+    let items = process_file(&test_file).unwrap();
+
+    // Verify the chunk records the lines it spans
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].get("start_line").and_then(|v| v.as_u64()), Some(1));
+    assert_eq!(items[0].get("end_line").and_then(|v| v.as_u64()), Some(5));
+    assert_eq!(items[0].get("language").and_then(|v| v.as_str()), Some("py"));
+
+    // Clean up
+    fs::remove_dir_all(temp_dir).unwrap_or(());
+}
+
 #[test]
 fn test_process_nonexistent_file() {
     // Create a temporary directory for the test
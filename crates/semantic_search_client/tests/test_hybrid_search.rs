@@ -0,0 +1,136 @@
+use std::{
+    env,
+    fs,
+};
+
+use chrono::{
+    Duration,
+    Utc,
+};
+use semantic_search_client::embedding::EmbeddingType;
+use semantic_search_client::types::ProgressStatus;
+use semantic_search_client::{
+    FileType,
+    SearchFilter,
+    SearchMode,
+    SearchOptions,
+    SemanticSearchClient,
+};
+
+fn paths(results: &[semantic_search_client::SearchResult]) -> Vec<String> {
+    results
+        .iter()
+        .filter_map(|r| r.point.payload.get("path").and_then(|v| v.as_str()))
+        .map(|p| p.rsplit('/').next().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn test_hybrid_search_with_filters() {
+    let temp_dir = env::temp_dir().join("semantic_search_test_hybrid_search");
+    fs::remove_dir_all(&temp_dir).unwrap_or(());
+    let base_dir = temp_dir.join("semantic_search");
+    let docs = temp_dir.join("docs");
+    fs::create_dir_all(docs.join("src")).unwrap();
+    fs::write(
+        docs.join("src").join("retry.rs"),
+        "pub fn parse_retry_after(value: &str) -> Option<u64> {\n    value.trim().parse().ok()\n}\n",
+    )
+    .unwrap();
+    fs::write(
+        docs.join("retries.md"),
+        "# Retries\n\nFailed requests are retried after the delay given by the service.\n",
+    )
+    .unwrap();
+    fs::write(docs.join("cooking.txt"), "Boil the pasta in salted water.").unwrap();
+
+    let mut client = SemanticSearchClient::with_embedding_type(&base_dir, EmbeddingType::BM25).unwrap();
+    let id = client
+        .add_context_from_path(&docs, "Docs", "Test docs", true, None::<fn(ProgressStatus)>)
+        .unwrap();
+
+    // Symbol names match by keyword in every mode that uses the keyword index
+    for mode in [SearchMode::Hybrid, SearchMode::Keyword] {
+        let results = client
+            .search_context_with_options(&id, "parse_retry_after", &SearchOptions {
+                mode,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(
+            paths(&results).first().map(String::as_str),
+            Some("retry.rs"),
+            "{mode:?}"
+        );
+        assert!(results.iter().all(|r| (0.0..=1.0).contains(&r.score())));
+    }
+    let results = client
+        .search_context_with_options(&id, "parse_retry_after", &SearchOptions {
+            mode: SearchMode::Keyword,
+            ..Default::default()
+        })
+        .unwrap();
+    assert!((results[0].score() - 1.0).abs() < 1e-6);
+
+    let search = |filter: SearchFilter| {
+        client
+            .search_all_with_options("retry the request", &SearchOptions {
+                filter,
+                ..Default::default()
+            })
+            .unwrap()
+            .into_iter()
+            .flat_map(|(_, results)| paths(&results))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        search(SearchFilter {
+            path_glob: Some("src/**/*.rs".to_string()),
+            ..Default::default()
+        }),
+        vec!["retry.rs"]
+    );
+    assert!(
+        search(SearchFilter {
+            file_type: Some(FileType::Markdown),
+            ..Default::default()
+        })
+        .iter()
+        .all(|p| p == "retries.md")
+    );
+    assert!(
+        search(SearchFilter {
+            modified_after: Some(Utc::now() + Duration::hours(1)),
+            ..Default::default()
+        })
+        .is_empty()
+    );
+    assert!(
+        !search(SearchFilter {
+            modified_after: Some(Utc::now() - Duration::hours(1)),
+            ..Default::default()
+        })
+        .is_empty()
+    );
+
+    // Results below the minimum score are dropped
+    let results = client
+        .search_context_with_options(&id, "pasta", &SearchOptions {
+            mode: SearchMode::Keyword,
+            min_score: Some(0.99),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(paths(&results), vec!["cooking.txt"]);
+
+    let invalid = client.search_context_with_options(&id, "retry", &SearchOptions {
+        filter: SearchFilter {
+            path_glob: Some("src/[".to_string()),
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    assert!(invalid.is_err());
+
+    fs::remove_dir_all(temp_dir).unwrap_or(());
+}
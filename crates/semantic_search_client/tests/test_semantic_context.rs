@@ -1,4 +1,7 @@
-use std::collections::HashMap;
+use std::collections::{
+    HashMap,
+    HashSet,
+};
 use std::{
     env,
     fs,
@@ -98,3 +101,76 @@ fn test_add_data_points() {
     // Clean up
     fs::remove_dir_all(temp_dir).unwrap_or(());
 }
+
+#[test]
+fn test_remove_files_and_compact() {
+    let temp_dir = env::temp_dir().join("memory_bank_test_remove_files");
+    fs::remove_dir_all(&temp_dir).unwrap_or(());
+    fs::create_dir_all(&temp_dir).unwrap();
+    let data_path = temp_dir.join("data.json");
+
+    // Eight data points, two per file, each pointing along its own axis
+    let data_points = (0..8)
+        .map(|i| {
+            let mut vector = vec![0.01; 384];
+            vector[i] = 1.0;
+            let mut payload = HashMap::new();
+            payload.insert("text".to_string(), Value::String(format!("chunk {i} of file{}", i / 2)));
+            payload.insert("path".to_string(), Value::String(format!("file{}.txt", i / 2)));
+            DataPoint { id: i, payload, vector }
+        })
+        .collect();
+    let mut semantic_context = SemanticContext::new(data_path.clone()).unwrap();
+    semantic_context.add_data_points(data_points).unwrap();
+
+    // Removing a quarter of the data points only tombstones them
+    let removed = semantic_context
+        .remove_files(&HashSet::from(["file0.txt".to_string()]))
+        .unwrap();
+    assert_eq!(removed, 2);
+    assert_eq!(semantic_context.item_count(), 6);
+    assert_eq!(semantic_context.get_data_points().len(), 8);
+    let stats = semantic_context.index_stats();
+    assert_eq!(
+        (stats.live_vectors, stats.deleted_vectors, stats.compactions),
+        (6, 2, 0)
+    );
+
+    let mut query = vec![0.01; 384];
+    query[0] = 1.0;
+    let results = semantic_context.search(&query, 8).unwrap();
+    assert!(results.iter().all(|r| r.point.payload["path"] != "file0.txt"));
+
+    // The graph and the tombstones are saved and loaded with the data points
+    semantic_context.save().unwrap();
+    assert!(temp_dir.join("index.hnsw.graph").exists());
+    let mut semantic_context = SemanticContext::new(data_path.clone()).unwrap();
+    assert_eq!(semantic_context.item_count(), 6);
+    assert_eq!(semantic_context.index_stats().deleted_vectors, 2);
+    let results = semantic_context.search(&query, 8).unwrap();
+    assert!(results.iter().all(|r| r.point.payload["path"] != "file0.txt"));
+
+    // Going over the threshold compacts the index
+    semantic_context
+        .remove_files(&HashSet::from(["file1.txt".to_string()]))
+        .unwrap();
+    let stats = semantic_context.index_stats();
+    assert_eq!(
+        (stats.live_vectors, stats.deleted_vectors, stats.compactions),
+        (4, 0, 1)
+    );
+    assert!(stats.last_compacted_at.is_some());
+    let data_points = semantic_context.get_data_points();
+    assert_eq!(data_points.len(), 4);
+    assert!(data_points.iter().enumerate().all(|(i, point)| point.id == i));
+    assert_eq!(data_points[0].payload["path"], "file2.txt");
+
+    // A missing or outdated graph is rebuilt from the data points
+    semantic_context.save().unwrap();
+    fs::remove_file(temp_dir.join("index.hnsw.graph")).unwrap();
+    let semantic_context = SemanticContext::new(data_path).unwrap();
+    assert_eq!(semantic_context.item_count(), 4);
+    assert_eq!(semantic_context.index_stats().compactions, 1);
+
+    fs::remove_dir_all(temp_dir).unwrap_or(());
+}
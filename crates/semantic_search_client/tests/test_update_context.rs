@@ -0,0 +1,117 @@
+use std::sync::{
+    Arc,
+    Mutex,
+};
+use std::time::{
+    Duration,
+    Instant,
+};
+use std::{
+    env,
+    fs,
+};
+
+use semantic_search_client::embedding::EmbeddingType;
+use semantic_search_client::types::ProgressStatus;
+use semantic_search_client::{
+    ContextWatcher,
+    SemanticSearchClient,
+    UpdateSummary,
+};
+
+#[test]
+fn test_update_context() {
+    let temp_dir = env::temp_dir().join("semantic_search_test_update_context");
+    fs::remove_dir_all(&temp_dir).unwrap_or(());
+    let base_dir = temp_dir.join("semantic_search");
+    let docs = temp_dir.join("docs");
+    fs::create_dir_all(&docs).unwrap();
+    fs::write(docs.join("deploy.md"), "Run the deploy script to ship the release.").unwrap();
+    fs::write(docs.join("cooking.txt"), "Boil the pasta in salted water.").unwrap();
+    fs::write(docs.join("garden.txt"), "Water the tomatoes every morning.").unwrap();
+
+    let mut client = SemanticSearchClient::with_embedding_type(&base_dir, EmbeddingType::BM25).unwrap();
+    let id = client
+        .add_context_from_path(&docs, "Docs", "Test docs", true, None::<fn(ProgressStatus)>)
+        .unwrap();
+    assert!(base_dir.join(&id).join("files.json").exists());
+
+    // Nothing changed yet
+    assert_eq!(client.update_context(&id).unwrap(), UpdateSummary {
+        unchanged: 3,
+        ..Default::default()
+    });
+
+    // Rewriting a file with the same content is not a change
+    fs::write(docs.join("garden.txt"), "Water the tomatoes every morning.").unwrap();
+    fs::write(
+        docs.join("deploy.md"),
+        "Run the deploy script to ship the release, then tag it.",
+    )
+    .unwrap();
+    fs::remove_file(docs.join("cooking.txt")).unwrap();
+    fs::write(docs.join("travel.txt"), "Book the train tickets early.").unwrap();
+
+    assert_eq!(client.update_context(&id).unwrap(), UpdateSummary {
+        added: 1,
+        modified: 1,
+        removed: 1,
+        unchanged: 1,
+    });
+
+    let contexts = client.get_contexts();
+    assert_eq!(contexts[0].item_count, 3);
+    // Deleting the chunks of two files out of three compacts the index
+    let stats = contexts[0].index_stats.as_ref().unwrap();
+    assert_eq!(
+        (stats.live_vectors, stats.deleted_vectors, stats.compactions),
+        (3, 0, 1)
+    );
+    assert!(stats.saved_size > 0);
+    let results = client.search_context(&id, "train tickets", Some(10)).unwrap();
+    let paths: Vec<_> = results
+        .iter()
+        .filter_map(|r| r.point.payload.get("path").and_then(|v| v.as_str()))
+        .collect();
+    assert!(paths.iter().any(|p| p.ends_with("travel.txt")));
+    assert!(!paths.iter().any(|p| p.ends_with("cooking.txt")));
+
+    // The file states are saved with the context
+    drop(client);
+    let mut client = SemanticSearchClient::with_embedding_type(&base_dir, EmbeddingType::BM25).unwrap();
+    assert!(!client.update_context(&id).unwrap().has_changes());
+
+    assert!(client.update_context("does_not_exist").is_err());
+
+    fs::remove_dir_all(temp_dir).unwrap_or(());
+}
+
+#[test]
+fn test_context_watcher() {
+    let temp_dir = env::temp_dir().join("semantic_search_test_context_watcher");
+    fs::remove_dir_all(&temp_dir).unwrap_or(());
+    let base_dir = temp_dir.join("semantic_search");
+    let docs = temp_dir.join("docs");
+    fs::create_dir_all(&docs).unwrap();
+    fs::write(docs.join("deploy.md"), "Run the deploy script to ship the release.").unwrap();
+
+    let mut client = SemanticSearchClient::with_embedding_type(&base_dir, EmbeddingType::BM25).unwrap();
+    let id = client
+        .add_context_from_path(&docs, "Docs", "Test docs", true, None::<fn(ProgressStatus)>)
+        .unwrap();
+    let client = Arc::new(Mutex::new(client));
+    let watcher = ContextWatcher::new(Arc::clone(&client), &id, Duration::from_millis(100)).unwrap();
+    assert_eq!(watcher.source_path(), docs);
+
+    fs::write(docs.join("travel.txt"), "Book the train tickets early.").unwrap();
+
+    let item_count = || client.lock().unwrap().get_contexts()[0].item_count;
+    let start = Instant::now();
+    while item_count() < 2 && start.elapsed() < Duration::from_secs(10) {
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    assert_eq!(item_count(), 2);
+
+    drop(watcher);
+    fs::remove_dir_all(temp_dir).unwrap_or(());
+}
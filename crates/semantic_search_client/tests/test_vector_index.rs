@@ -53,3 +53,56 @@ fn test_search() {
         assert!(results[0].0 <= 2);
     }
 }
+
+/// A vector pointing mostly along the given axis
+fn axis_vector(axis: usize) -> Vec<f32> {
+    let mut vector = vec![0.01; 384];
+    vector[axis] = 1.0;
+    vector
+}
+
+#[test]
+fn test_delete() {
+    let mut index = VectorIndex::new(384);
+    for i in 0..3 {
+        index.insert(&axis_vector(i), i);
+    }
+
+    assert!(index.delete(0));
+    assert!(!index.delete(0), "deleting twice should be a no-op");
+    assert!(index.is_deleted(0));
+    assert_eq!(index.len(), 2);
+    assert_eq!(index.total_len(), 3);
+    assert_eq!(index.deleted_len(), 1);
+
+    // Deleted vectors are skipped, even when they are the closest
+    let results = index.search(&axis_vector(0), 3, 100);
+    assert!(!results.is_empty());
+    assert!(results.iter().all(|(id, _)| *id != 0));
+}
+
+#[test]
+fn test_save_and_load() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let index = VectorIndex::new(384);
+    for i in 0..3 {
+        index.insert(&axis_vector(i), i);
+    }
+    index.save(temp_dir.path(), "index").unwrap();
+
+    let loaded = VectorIndex::load(temp_dir.path(), "index").unwrap();
+    assert_eq!(loaded.total_len(), 3);
+    assert_eq!(loaded.search(&axis_vector(1), 1, 100)[0].0, 1);
+
+    // The loaded index can still be extended
+    loaded.insert(&axis_vector(3), 3);
+    assert_eq!(loaded.search(&axis_vector(3), 1, 100)[0].0, 3);
+
+    // Loaded indexes can be dropped and moved to other threads
+    drop(loaded);
+    let loaded = VectorIndex::load(temp_dir.path(), "index").unwrap();
+    let total_len = std::thread::spawn(move || loaded.total_len()).join().unwrap();
+    assert_eq!(total_len, 3);
+
+    assert!(VectorIndex::load(&temp_dir.path().join("missing"), "index").is_err());
+}